@@ -0,0 +1,207 @@
+use crate::{frontmatter, link, zettel, zettelkasten::Zettelkasten};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A local link whose target does not exist
+#[derive(Debug, PartialEq)]
+pub struct MissingTarget {
+    pub id: zettel::Id,
+    /// path of the linking zettel
+    pub path: String,
+    /// line of the link in the file, counting the frontmatter
+    pub line: usize,
+    pub target: String,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    /// files in the assets directory no zettel links to
+    pub unreferenced: Vec<PathBuf>,
+    pub missing: Vec<MissingTarget>,
+    /// `[[id#anchor]]` links whose heading or block is gone from the target,
+    /// with the target written as `id#anchor`
+    pub broken_anchors: Vec<MissingTarget>,
+    /// zettels that could not be read, so their references are unknown
+    pub unreadable: Vec<String>,
+}
+
+/// compare local links in every zettel against the files in the assets
+/// directory, and anchored wikilinks against the headings of their targets
+pub fn scan(zk: &Zettelkasten, root_dir: &Path) -> Result<Report> {
+    let mut report = Report::default();
+    let mut referenced = HashSet::new();
+    let mut bodies: HashMap<&str, Option<String>> = HashMap::new();
+    let mut ids: Vec<_> = zk.zettels.keys().collect();
+    ids.sort();
+    for id in ids {
+        let meta = &zk.zettels[id];
+        let path = meta.full_path(root_dir);
+        // where the body starts in the file, found once a link is reported
+        let (body, mut first_line) = match frontmatter::parse_yaml_path(&path) {
+            Ok((_, body)) => (body, None),
+            // links may still be found in a file with broken frontmatter
+            Err(frontmatter::Error::IoError(_)) => {
+                report.unreadable.push(meta.path.clone());
+                continue;
+            }
+            Err(_) => (std::fs::read_to_string(&path)?, Some(1)),
+        };
+        for link in link::markdown_links(&body) {
+            if !link.is_local() {
+                continue;
+            }
+            let target = link.resolve(&path);
+            if !target.exists() {
+                let first_line = *first_line
+                    .get_or_insert_with(|| frontmatter::first_body_line(&path).unwrap_or(1));
+                report.missing.push(MissingTarget {
+                    id: id.clone(),
+                    path: meta.path.clone(),
+                    line: link.line + first_line - 1,
+                    target: link.target.clone(),
+                });
+            }
+            referenced.insert(target);
+        }
+        for link in link::wikilinks(&body) {
+            let (anchor, target) = match (&link.anchor, zk.zettels.get(&link.target)) {
+                (Some(anchor), Some(target)) => (anchor, target),
+                _ => continue,
+            };
+            let target_body = bodies.entry(&target.id).or_insert_with(|| {
+                frontmatter::parse_yaml_path(target.full_path(root_dir))
+                    .ok()
+                    .map(|(_, body)| body)
+            });
+            let found = match target_body {
+                Some(body) => crate::outline::anchored(body, anchor).is_some(),
+                // unreadable targets are reported on their own
+                None => true,
+            };
+            if !found {
+                let first_line = *first_line
+                    .get_or_insert_with(|| frontmatter::first_body_line(&path).unwrap_or(1));
+                report.broken_anchors.push(MissingTarget {
+                    id: id.clone(),
+                    path: meta.path.clone(),
+                    line: link.line + first_line - 1,
+                    target: format!("{}#{}", link.target, anchor),
+                });
+            }
+        }
+    }
+    let assets_dir = root_dir.join(zk.config.assets_dir());
+    if assets_dir.is_dir() {
+        let mut files = Vec::new();
+        walk(&assets_dir, &mut files)?;
+        files.sort();
+        report.unreferenced = files
+            .into_iter()
+            .filter(|file| !referenced.contains(file))
+            .map(|file| file.strip_prefix(root_dir).unwrap().to_path_buf())
+            .collect();
+    }
+    Ok(report)
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            walk(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn unreferenced_and_missing() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_assets_test")?;
+        let root_dir = tmp_dir.path().canonicalize()?;
+        let db = Database::new(root_dir.clone());
+        let mut zk = Zettelkasten::default();
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let zettel = db.new_zettel(&Default::default(), "with images", "abc", dt)?;
+        zk.add(db.root_dir(), &zettel)?;
+        let path = zettel.meta.full_path(&root_dir);
+        let mut data = std::fs::read_to_string(&path)?;
+        // lines are those of the file, after the frontmatter
+        let frontmatter_lines = data.lines().count();
+        data.push_str("![used](assets/used.png)\n![gone](assets/gone.png)\n");
+        std::fs::write(&path, data)?;
+        std::fs::create_dir_all(root_dir.join("assets/old"))?;
+        std::fs::write(root_dir.join("assets/used.png"), "")?;
+        std::fs::write(root_dir.join("assets/old/unused.png"), "")?;
+        let report = scan(&zk, &root_dir)?;
+        assert_eq!(
+            report.unreferenced,
+            vec![PathBuf::from("assets/old/unused.png")]
+        );
+        assert_eq!(report.missing.len(), 1);
+        assert_eq!(report.missing[0].target, "assets/gone.png");
+        assert_eq!(report.missing[0].line, frontmatter_lines + 2);
+        assert!(report.broken_anchors.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn broken_anchors() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_anchors_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        for (id, body) in [
+            ("a", "# Kept\nquote ^q\n"),
+            (
+                "b",
+                "[[a#Kept]] [[a#^q]]\n[[a#Renamed]] [[a#^gone]] [[missing#x]]\n",
+            ),
+        ] {
+            let mut zettel = db.new_zettel(&Default::default(), id, id, dt)?;
+            zettel.content = body.to_owned();
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        let report = scan(&zk, tmp_dir.path())?;
+        let broken: Vec<_> = report
+            .broken_anchors
+            .iter()
+            .map(|m| (m.id.as_str(), m.line, m.target.as_str()))
+            .collect();
+        // the frontmatter takes the first 6 lines
+        assert_eq!(broken, [("b", 8, "a#Renamed"), ("b", 8, "a#^gone")]);
+        Ok(())
+    }
+}
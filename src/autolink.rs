@@ -0,0 +1,173 @@
+use crate::{frontmatter, link, query, zettel, zettelkasten::Zettelkasten};
+use regex::Regex;
+use std::{collections::BTreeMap, ops::Range, path::Path, path::PathBuf};
+
+/// A keyword mentioned in a zettel, to be linked to the zettel of its rule
+#[derive(Debug, PartialEq)]
+pub struct Mention {
+    /// line number starting at 1
+    pub line: usize,
+    /// the mention as written
+    pub text: String,
+    pub target: zettel::Id,
+}
+
+/// A zettel whose mentions of keywords are turned into links
+#[derive(Debug)]
+pub struct Change {
+    pub id: zettel::Id,
+    pub path: PathBuf,
+    pub mentions: Vec<Mention>,
+    pub body: String,
+    /// new contents of the file
+    pub contents: String,
+}
+
+/// parts of `line` mentions aren't looked for in: links, urls and inline
+/// code
+fn protected(line: &str) -> Vec<Range<usize>> {
+    let re = Regex::new(r"!?\[[^\]]*\]\([^)]*\)|`[^`]*`|<?https?://\S+").unwrap();
+    let mut ranges: Vec<Range<usize>> = re.find_iter(line).map(|m| m.range()).collect();
+    ranges.extend(link::wikilinks(line).into_iter().map(|l| l.span));
+    ranges
+}
+
+/// `body` of zettel `id` with the first plain mention of each keyword of
+/// `rules` turned into `[[target|mention]]`, matching whole words in any
+/// case
+///
+/// keywords are skipped in code, headings, existing links and when the body
+/// already links to their target or is the target itself
+pub fn link_mentions(
+    body: &str,
+    id: &str,
+    rules: &BTreeMap<String, zettel::Id>,
+) -> (String, Vec<Mention>) {
+    let linked: Vec<zettel::Id> = link::wikilinks(body)
+        .into_iter()
+        .map(|l| l.target)
+        .collect();
+    let mut rules: Vec<(Regex, &zettel::Id)> = rules
+        .iter()
+        .filter(|(keyword, target)| {
+            !keyword.trim().is_empty() && *target != id && !linked.contains(target)
+        })
+        .filter_map(|(keyword, target)| {
+            let re = Regex::new(&format!(r"(?i)\b{}\b", regex::escape(keyword.trim()))).ok()?;
+            Some((re, target))
+        })
+        .collect();
+    let mut mentions = Vec::new();
+    let mut out = String::new();
+    let mut fenced = false;
+    for (n, line) in body.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim_start();
+        let fence = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+        fenced ^= fence;
+        if fenced || fence || trimmed.starts_with('#') || rules.is_empty() {
+            out.push_str(line);
+            continue;
+        }
+        // earliest mention of any rule on the line, one at a time so that
+        // links just made are protected too
+        let mut line = line.to_owned();
+        loop {
+            let taken = protected(&line);
+            let found = rules
+                .iter()
+                .enumerate()
+                .filter_map(|(i, (re, _))| {
+                    re.find_iter(&line)
+                        .find(|m| !taken.iter().any(|r| r.start < m.end() && m.start() < r.end))
+                        .map(|m| (m.range(), i))
+                })
+                .min_by_key(|(range, _)| range.start);
+            let (range, i) = match found {
+                Some(found) => found,
+                None => break,
+            };
+            let (_, target) = rules.remove(i);
+            let text = line[range.clone()].to_owned();
+            line.replace_range(range, &format!("[[{}|{}]]", target, text));
+            mentions.push(Mention {
+                line: n + 1,
+                text,
+                target: target.clone(),
+            });
+        }
+        out.push_str(&line);
+    }
+    (out, mentions)
+}
+
+/// changes from linking mentions of `rules` in every zettel matching
+/// `query`, in order of creation; zettels without mentions are left out
+pub fn plan(
+    zk: &Zettelkasten,
+    root_dir: &Path,
+    query: &query::Query,
+    rules: &BTreeMap<String, zettel::Id>,
+) -> Result<Vec<Change>, frontmatter::Error> {
+    let mut ids: Vec<&zettel::Id> = zk
+        .zettels
+        .iter()
+        .filter(|(id, meta)| query.matches(id, meta, root_dir))
+        .map(|(id, _)| id)
+        .collect();
+    ids.sort_by_key(|id| (zk.zettels[*id].created, *id));
+    let mut changes = Vec::new();
+    for id in ids {
+        let path = zk.zettels[id].full_path(root_dir);
+        let (fm, body) = frontmatter::parse_yaml_path(&path)?;
+        let (body, mentions) = link_mentions(&body, id, rules);
+        if mentions.is_empty() {
+            continue;
+        }
+        changes.push(Change {
+            id: id.clone(),
+            contents: frontmatter::write_for(&path, &fm, &body)?,
+            path,
+            mentions,
+            body,
+        });
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn links_first_plain_mentions() {
+        let rules = BTreeMap::from([
+            ("CRDT".to_owned(), "crdt".to_owned()),
+            ("vector clock".to_owned(), "clocks".to_owned()),
+            ("Lamport".to_owned(), "lamport".to_owned()),
+        ]);
+        let body = "# CRDTs and CRDT\n\
+                    Merging with `crdt` code and crdts: a crdt, then a CRDT.\n\
+                    [Vector clock](https://example.com/vector-clock) and vector clocks, a Vector Clock.\n\
+                    ```\nCRDT\n```\n\
+                    See [[lamport]] on Lamport.\n";
+        let (linked, mentions) = link_mentions(body, "clocks", &rules);
+        assert_eq!(
+            linked,
+            "# CRDTs and CRDT\n\
+             Merging with `crdt` code and crdts: a [[crdt|crdt]], then a CRDT.\n\
+             [Vector clock](https://example.com/vector-clock) and vector clocks, a Vector Clock.\n\
+             ```\nCRDT\n```\n\
+             See [[lamport]] on Lamport.\n"
+        );
+        assert_eq!(
+            mentions,
+            [Mention {
+                line: 2,
+                text: "crdt".to_owned(),
+                target: "crdt".to_owned()
+            }]
+        );
+        let (linked, _) = link_mentions("vector clocks and a vector clock", "x", &rules);
+        assert_eq!(linked, "vector clocks and a [[clocks|vector clock]]");
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    /// malformed entry starting at the given line
+    SyntaxError(usize),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::SyntaxError(line) => write!(f, "malformed bibtex entry at line {}", line),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A single reference from a `.bib` file
+#[derive(Debug, PartialEq, Clone)]
+pub struct Entry {
+    /// entry type such as `book` or `article`, lowercased
+    pub kind: String,
+    /// citation key
+    pub key: String,
+    /// fields with lowercased names and braces stripped from values
+    pub fields: BTreeMap<String, String>,
+}
+
+impl Entry {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(|v| v.as_str())
+    }
+}
+
+pub fn parse_path(path: impl AsRef<Path>) -> Result<Vec<Entry>> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+/// parse entries from a bibtex document
+///
+/// `@comment`, `@preamble` and `@string` blocks are skipped; string
+/// macros are not expanded
+pub fn parse(input: &str) -> Result<Vec<Entry>> {
+    let mut parser = Parser {
+        chars: input.chars().collect(),
+        pos: 0,
+    };
+    let mut entries = Vec::new();
+    while parser.skip_to('@') {
+        let line = parser.line();
+        let err = || Error::SyntaxError(line);
+        parser.pos += 1;
+        let kind = parser.take_while(|c| c.is_alphanumeric()).to_lowercase();
+        parser.skip_whitespace();
+        if !matches!(parser.peek(), Some('{') | Some('(')) {
+            return Err(err());
+        }
+        if ["comment", "preamble", "string"].contains(&kind.as_str()) {
+            parser.braced().ok_or_else(err)?;
+            continue;
+        }
+        parser.pos += 1;
+        let key = parser.take_while(|c| c != ',' && c != '}' && c != ')');
+        let key = key.trim().to_owned();
+        let mut fields = BTreeMap::new();
+        loop {
+            parser.skip_whitespace();
+            match parser.peek() {
+                Some(',') => parser.pos += 1,
+                Some('}') | Some(')') => {
+                    parser.pos += 1;
+                    break;
+                }
+                None => return Err(err()),
+                _ => {
+                    let name = parser.take_while(|c| c != '=' && c != ',' && c != '}');
+                    if parser.peek() != Some('=') {
+                        return Err(err());
+                    }
+                    parser.pos += 1;
+                    parser.skip_whitespace();
+                    let value = parser.value().ok_or_else(err)?;
+                    fields.insert(name.trim().to_lowercase(), value);
+                }
+            }
+        }
+        entries.push(Entry { kind, key, fields });
+    }
+    Ok(entries)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn line(&self) -> usize {
+        self.chars[..self.pos]
+            .iter()
+            .filter(|c| **c == '\n')
+            .count()
+            + 1
+    }
+
+    fn skip_to(&mut self, target: char) -> bool {
+        while let Some(c) = self.peek() {
+            if c == target {
+                return true;
+            }
+            self.pos += 1;
+        }
+        false
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.take_while(char::is_whitespace);
+    }
+
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&pred) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// consume a balanced `{...}` or `(...)` group and return its contents
+    fn braced(&mut self) -> Option<String> {
+        let close = match self.peek()? {
+            '{' => '}',
+            '(' => ')',
+            _ => return None,
+        };
+        let open = self.peek()?;
+        self.pos += 1;
+        let start = self.pos;
+        let mut depth = 0;
+        loop {
+            let c = self.peek()?;
+            self.pos += 1;
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                if depth == 0 {
+                    return Some(self.chars[start..self.pos - 1].iter().collect());
+                }
+                depth -= 1;
+            }
+        }
+    }
+
+    fn value(&mut self) -> Option<String> {
+        let raw = match self.peek()? {
+            '{' => self.braced()?,
+            '"' => {
+                self.pos += 1;
+                let start = self.pos;
+                let mut depth = 0;
+                loop {
+                    match self.peek()? {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        '"' if depth == 0 => break,
+                        _ => (),
+                    }
+                    self.pos += 1;
+                }
+                self.pos += 1;
+                self.chars[start..self.pos - 1].iter().collect()
+            }
+            _ => self
+                .take_while(|c| c != ',' && c != '}' && c != ')')
+                .trim()
+                .to_owned(),
+        };
+        let value: String = raw.chars().filter(|c| *c != '{' && *c != '}').collect();
+        Some(value.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_entries() -> Result<()> {
+        let bib = r#"
+@comment{ignored {nested}}
+@book{knuth1984,
+  author = {Donald E. Knuth},
+  title = {The {\TeX}book},
+  year = 1984,
+  publisher = "Addison-Wesley",
+}
+@Article{ahrens2017, title={How to Take
+    Smart Notes}}
+"#;
+        let entries = parse(bib)?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].kind, "book");
+        assert_eq!(entries[0].key, "knuth1984");
+        assert_eq!(entries[0].field("title"), Some("The \\TeXbook"));
+        assert_eq!(entries[0].field("year"), Some("1984"));
+        assert_eq!(entries[0].field("publisher"), Some("Addison-Wesley"));
+        assert_eq!(entries[1].kind, "article");
+        assert_eq!(entries[1].field("title"), Some("How to Take Smart Notes"));
+        assert!(matches!(
+            parse("@book{broken, title = {oops}"),
+            Err(Error::SyntaxError(1))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+use crate::{frontmatter, query, zettel, zettelkasten::Zettelkasten};
+use serde::Serialize;
+use std::path::Path;
+
+/// widest a column of [`Board::to_text`] gets, in characters
+const MAX_WIDTH: usize = 30;
+
+/// Formats of `zk board`
+#[derive(Debug, Clone, Copy, PartialEq, Default, clap::ValueEnum)]
+pub enum Format {
+    /// columns side by side
+    #[default]
+    Text,
+    Json,
+}
+
+/// What puts a zettel in a column
+#[derive(Debug, Clone, PartialEq)]
+pub enum By {
+    /// the value of a frontmatter key, like `status: doing`
+    Key(String),
+    /// what follows a prefix in its tags, like `doing` in `status/doing`
+    TagPrefix(String),
+}
+
+impl std::str::FromStr for By {
+    type Err = std::convert::Infallible;
+
+    /// `tag:<prefix>` groups by tags, anything else names a key
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.strip_prefix("tag:") {
+            Some(prefix) => Self::TagPrefix(prefix.to_owned()),
+            None => Self::Key(s.to_owned()),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Card {
+    pub id: zettel::Id,
+    pub title: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Column {
+    pub name: String,
+    /// oldest first
+    pub zettels: Vec<Card>,
+}
+
+/// Zettels grouped into columns
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Board {
+    pub columns: Vec<Column>,
+}
+
+/// group the zettels matching `query` into columns `by` a key or tag
+/// prefix; `columns` come first in that order even when empty, followed by
+/// any other values in alphabetical order
+///
+/// zettels without the key or a tag with the prefix are left out
+pub fn board(
+    zk: &Zettelkasten,
+    root_dir: &Path,
+    query: &query::Query,
+    by: &By,
+    columns: &[String],
+) -> Result<Board, frontmatter::Error> {
+    let mut ids: Vec<&zettel::Id> = zk
+        .zettels
+        .iter()
+        .filter(|(id, meta)| query.matches(id, meta, root_dir))
+        .map(|(id, _)| id)
+        .collect();
+    ids.sort_by_key(|id| (zk.zettels[*id].created, *id));
+    let mut board = Board {
+        columns: columns
+            .iter()
+            .map(|name| Column {
+                name: name.clone(),
+                zettels: Vec::new(),
+            })
+            .collect(),
+    };
+    let mut extra: Vec<Column> = Vec::new();
+    for id in ids {
+        let meta = &zk.zettels[id];
+        let names: Vec<String> = match by {
+            By::Key(key) => {
+                let (fm, _) = frontmatter::parse_yaml_path(meta.full_path(root_dir))?;
+                match fm.get(&key.as_str().into()) {
+                    Some(serde_yaml::Value::String(s)) => vec![s.clone()],
+                    Some(serde_yaml::Value::Bool(b)) => vec![b.to_string()],
+                    Some(serde_yaml::Value::Number(n)) => vec![n.to_string()],
+                    _ => Vec::new(),
+                }
+            }
+            By::TagPrefix(prefix) => meta
+                .tags
+                .iter()
+                .filter_map(|tag| tag.strip_prefix(prefix.as_str()))
+                .filter(|name| !name.is_empty())
+                .map(str::to_owned)
+                .collect(),
+        };
+        for name in names {
+            let card = Card {
+                id: id.clone(),
+                title: meta.title.clone(),
+            };
+            match board
+                .columns
+                .iter_mut()
+                .chain(extra.iter_mut())
+                .find(|c| c.name == name)
+            {
+                Some(column) => column.zettels.push(card),
+                None => extra.push(Column {
+                    name,
+                    zettels: vec![card],
+                }),
+            }
+        }
+    }
+    extra.sort_by(|a, b| a.name.cmp(&b.name));
+    board.columns.extend(extra);
+    Ok(board)
+}
+
+/// `s` cut to `width` characters, marking the cut with `…`
+fn fit(s: &str, width: usize) -> String {
+    if s.chars().count() <= width {
+        return s.to_owned();
+    }
+    let mut cut: String = s.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+impl Board {
+    /// columns side by side, each headed by its name and count
+    pub fn to_text(&self) -> String {
+        let heads: Vec<String> = self
+            .columns
+            .iter()
+            .map(|c| format!("{} ({})", c.name, c.zettels.len()))
+            .collect();
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .zip(&heads)
+            .map(|(c, head)| {
+                c.zettels
+                    .iter()
+                    .map(|card| card.title.chars().count())
+                    .chain([head.chars().count()])
+                    .max()
+                    .unwrap_or(0)
+                    .min(MAX_WIDTH)
+            })
+            .collect();
+        let rows = self.columns.iter().map(|c| c.zettels.len()).max();
+        let line = |cells: Vec<String>| {
+            let padded: Vec<String> = cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{:width$}", fit(cell, *width), width = width))
+                .collect();
+            format!("{}\n", padded.join(" | ").trim_end())
+        };
+        let mut out = line(heads.clone());
+        out.push_str(&line(widths.iter().map(|w| "-".repeat(*w)).collect()));
+        for row in 0..rows.unwrap_or(0) {
+            out.push_str(&line(
+                self.columns
+                    .iter()
+                    .map(|c| c.zettels.get(row).map_or("", |card| &card.title).to_owned())
+                    .collect(),
+            ));
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("boards serialize")
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Text => self.to_text(),
+            Format::Json => self.to_json() + "\n",
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn group_by_status_and_tag() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_board_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for (n, (id, status, tag)) in [
+            ("a", "done", "status/done"),
+            ("b", "doing", "status/doing"),
+            ("c", "blocked", "other"),
+            ("d", "", "status/"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let date = dt + chrono::Duration::hours(n as i64);
+            let mut zettel = db.new_zettel(&zk.config, id.to_uppercase(), id, date)?;
+            if !status.is_empty() {
+                zettel
+                    .extra_frontmatter
+                    .insert("status".to_owned(), status.to_owned());
+            }
+            zettel.meta.tags = vec![tag.to_owned()];
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        let names = |board: &Board| -> Vec<(String, usize)> {
+            board
+                .columns
+                .iter()
+                .map(|c| (c.name.clone(), c.zettels.len()))
+                .collect()
+        };
+        let columns = ["todo", "doing", "done"].map(str::to_owned);
+        let by_status = board(
+            &zk,
+            tmp_dir.path(),
+            &query::Query::All,
+            &"status".parse()?,
+            &columns,
+        )?;
+        assert_eq!(
+            names(&by_status),
+            [
+                ("todo".to_owned(), 0),
+                ("doing".to_owned(), 1),
+                ("done".to_owned(), 1),
+                ("blocked".to_owned(), 1)
+            ]
+        );
+        let by_tag = board(
+            &zk,
+            tmp_dir.path(),
+            &query::Query::All,
+            &"tag:status/".parse()?,
+            &[],
+        )?;
+        assert_eq!(
+            names(&by_tag),
+            [("doing".to_owned(), 1), ("done".to_owned(), 1)]
+        );
+        assert_eq!(
+            by_tag.to_text(),
+            "doing (1) | done (1)\n--------- | --------\nB         | A\n"
+        );
+        assert!(by_tag.to_json().contains("\"name\": \"doing\""));
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use std::process::{Command, Stdio};
+
+/// programs printing the clipboard, tried in order until one works
+#[cfg(target_os = "macos")]
+const PASTE_COMMANDS: &[&[&str]] = &[&["pbpaste"]];
+#[cfg(windows)]
+const PASTE_COMMANDS: &[&[&str]] = &[&["powershell", "-NoProfile", "-Command", "Get-Clipboard"]];
+#[cfg(not(any(target_os = "macos", windows)))]
+const PASTE_COMMANDS: &[&[&str]] = &[
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-o"],
+    &["xsel", "--clipboard", "--output"],
+];
+
+/// text on the system clipboard, read with the platform's paste command
+pub fn read_clipboard() -> std::io::Result<String> {
+    let mut last_error = None;
+    for command in PASTE_COMMANDS {
+        let output = Command::new(command[0])
+            .args(&command[1..])
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output();
+        match output {
+            Ok(output) if output.status.success() => {
+                return Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+            }
+            Ok(output) => {
+                last_error = Some(std::io::Error::other(format!(
+                    "{} exited with {}",
+                    command[0], output.status
+                )))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        let tried: Vec<&str> = PASTE_COMMANDS.iter().map(|c| c[0]).collect();
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("no clipboard program found; tried {}", tried.join(", ")),
+        )
+    }))
+}
+
+/// longest title taken from captured text, in characters
+const MAX_TITLE: usize = 60;
+
+/// title for captured text: its first non-blank line without markdown
+/// markers, cut at a word boundary if it is long
+pub fn title_from(text: &str) -> Option<String> {
+    let line = text.lines().map(str::trim).find(|line| !line.is_empty())?;
+    let line = line
+        .trim_start_matches(['#', '>', '-', '*'])
+        .trim()
+        .to_owned();
+    if line.chars().count() <= MAX_TITLE {
+        return Some(line).filter(|line| !line.is_empty());
+    }
+    let cut: String = line.chars().take(MAX_TITLE).collect();
+    let title = match cut.rfind(char::is_whitespace) {
+        Some(end) => cut[..end].trim_end(),
+        None => &cut,
+    };
+    Some(format!("{}…", title))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn titles_from_first_line() {
+        assert_eq!(
+            title_from("\n  # A heading \nbody").as_deref(),
+            Some("A heading")
+        );
+        assert_eq!(
+            title_from("> quoted words").as_deref(),
+            Some("quoted words")
+        );
+        assert_eq!(title_from(" \n---\n"), None);
+        let long = "word ".repeat(20);
+        let title = title_from(&long).unwrap();
+        assert!(title.ends_with("word…"));
+        assert!(title.chars().count() <= MAX_TITLE + 1);
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{zettel, zettelkasten::Zettelkasten, ZettelMeta};
+
+/// literature notes `meta` cites, whether by their id or by the citation key
+/// they are about, ordered by id
+pub fn cited_notes(zk: &Zettelkasten, meta: &ZettelMeta) -> Vec<zettel::Id> {
+    let mut ids: Vec<zettel::Id> = zk
+        .zettels
+        .iter()
+        .filter(|(id, lit)| {
+            meta.cites
+                .iter()
+                .any(|cited| cited == *id || lit.cite.as_ref().is_some_and(|key| key == cited))
+        })
+        .map(|(id, _)| id.clone())
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// zettels citing `source`, a citation key or the id of a literature note,
+/// ordered by id
+///
+/// citing a literature note counts as citing the key it is about and the
+/// other way around
+pub fn cited_by(zk: &Zettelkasten, source: &str) -> Vec<zettel::Id> {
+    let key = zk
+        .zettels
+        .get(source)
+        .and_then(|meta| meta.cite.as_deref())
+        .unwrap_or(source);
+    let mut names = vec![source, key];
+    names.extend(
+        zk.zettels
+            .iter()
+            .filter(|(_, meta)| meta.cite.as_deref() == Some(key))
+            .map(|(id, _)| id.as_str()),
+    );
+    let mut ids: Vec<zettel::Id> = zk
+        .zettels
+        .iter()
+        .filter(|(_, meta)| meta.cites.iter().any(|c| names.contains(&c.as_str())))
+        .map(|(id, _)| id.clone())
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn citations_by_key_and_id() {
+        let mut zk = Zettelkasten::default();
+        let now = chrono::Local::now();
+        for (id, cite, cites) in [
+            ("lit", Some("knuth1984"), vec![]),
+            ("by-key", None, vec!["knuth1984"]),
+            ("by-id", None, vec!["lit", "other2000"]),
+            ("unrelated", None, vec!["other2000"]),
+        ] {
+            let mut meta = ZettelMeta::new(id, id, "z.md", now);
+            meta.cite = cite.map(str::to_owned);
+            meta.cites = cites.into_iter().map(str::to_owned).collect();
+            zk.zettels.insert(id.to_owned(), meta);
+        }
+        assert_eq!(cited_by(&zk, "knuth1984"), ["by-id", "by-key"]);
+        assert_eq!(cited_by(&zk, "lit"), ["by-id", "by-key"]);
+        assert_eq!(cited_by(&zk, "other2000"), ["by-id", "unrelated"]);
+        assert_eq!(cited_notes(&zk, &zk.zettels["by-key"]), ["lit"]);
+        assert!(cited_notes(&zk, &zk.zettels["unrelated"]).is_empty());
+    }
+}
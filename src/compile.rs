@@ -0,0 +1,256 @@
+use crate::{frontmatter, link, outline, sequence, transclude, zettel, zettelkasten::Zettelkasten};
+use std::{collections::HashMap, path::Path};
+
+#[derive(Debug)]
+pub enum Error {
+    FrontmatterError(frontmatter::Error),
+    TranscludeError(transclude::Error),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::TranscludeError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl From<transclude::Error> for Error {
+    fn from(e: transclude::Error) -> Self {
+        Self::TranscludeError(e)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// frontmatter keys describing a single zettel, left out of the document
+const OWN_KEYS: &[&str] = &[
+    "id",
+    "title",
+    "date",
+    "created",
+    "modified",
+    "review",
+    "follows",
+    "tags",
+    "publish",
+    "visibility",
+];
+
+/// How `zk compile` orders the zettels of a document
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Order {
+    /// as given on the command line
+    List,
+    /// along the sequences they belong to, sequences started earliest first
+    Sequence,
+    /// by creation
+    Date,
+}
+
+/// `ids` in `order`
+pub fn order(zk: &Zettelkasten, mut ids: Vec<zettel::Id>, order: Order) -> Vec<zettel::Id> {
+    match order {
+        Order::List => ids,
+        Order::Date => {
+            ids.sort_by_key(|id| (zk.zettels[id].created, id.clone()));
+            ids
+        }
+        Order::Sequence => {
+            ids.sort_by_key(|id| (zk.zettels[id].created, id.clone()));
+            let mut ordered: Vec<zettel::Id> = Vec::new();
+            for id in &ids {
+                if ordered.contains(id) {
+                    continue;
+                }
+                for entry in sequence::chain(zk, id) {
+                    if ids.contains(&entry.id) && !ordered.contains(&entry.id) {
+                        ordered.push(entry.id);
+                    }
+                }
+                // a zettel in a cycle may be missing from its own chain
+                if !ordered.contains(id) {
+                    ordered.push(id.clone());
+                }
+            }
+            ordered
+        }
+    }
+}
+
+/// `body` with its headings moved so the shallowest is at `level`, and a
+/// leading heading repeating `title` dropped
+pub fn shift_headings(body: &str, title: &str, level: usize) -> String {
+    let mut headings = outline::headings(body);
+    let mut skip = None;
+    if let Some(first) = headings.first() {
+        let before = body.lines().take(first.line - 1);
+        if first.text.trim() == title.trim() && before.clone().all(|l| l.trim().is_empty()) {
+            skip = Some(first.line);
+            headings.remove(0);
+        }
+    }
+    let shallowest = headings.iter().map(|h| h.level).min().unwrap_or(level);
+    let lines_at: HashMap<usize, usize> = headings.iter().map(|h| (h.line, h.level)).collect();
+    let mut out = String::new();
+    for (n, line) in body.lines().enumerate() {
+        if skip == Some(n + 1) {
+            continue;
+        }
+        match lines_at.get(&(n + 1)) {
+            Some(old) if line.trim_start().starts_with('#') => {
+                let new = (old + level).saturating_sub(shallowest).clamp(1, 6);
+                let text = line.trim_start().trim_start_matches('#');
+                out.push_str(&"#".repeat(new));
+                out.push_str(text);
+            }
+            _ => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    out.trim_start_matches('\n').to_owned()
+}
+
+/// wikilinks to zettels in the document become links to their sections,
+/// others become their labels
+fn resolve_links(body: &str, zk: &Zettelkasten, included: &[zettel::Id]) -> String {
+    let links = link::wikilinks(body);
+    let mut out = String::new();
+    for (n, line) in body.lines().enumerate() {
+        let mut last = 0;
+        for l in links.iter().filter(|l| l.line == n + 1 && !l.embed) {
+            let meta = zk.zettels.get(&l.target);
+            let title = meta.map(|meta| meta.title.as_str());
+            let label = l.label.as_deref().or(title).unwrap_or(&l.target);
+            out.push_str(&line[last..l.span.start]);
+            last = l.span.end;
+            match title.filter(|_| included.contains(&l.target)) {
+                Some(title) => {
+                    let anchor = l.anchor.as_deref().unwrap_or(title);
+                    out.push_str(&format!("[{}](#{})", label, link::slug(anchor)));
+                }
+                None => out.push_str(label),
+            }
+        }
+        out.push_str(&line[last..]);
+        out.push('\n');
+    }
+    out
+}
+
+/// one markdown document of the zettels `ids` in that order, each under a
+/// heading of its title, with embeds expanded and frontmatter merged
+///
+/// the document's frontmatter has `title`, the tags of every zettel and
+/// other keys with the value of the first zettel that has them
+pub fn compile(
+    zk: &Zettelkasten,
+    root_dir: &Path,
+    ids: &[zettel::Id],
+    title: Option<&str>,
+) -> Result<String> {
+    let transcluder = transclude::Transcluder::new(zk, root_dir);
+    let mut merged = serde_yaml::Mapping::new();
+    let mut tags: Vec<String> = Vec::new();
+    let mut body = String::new();
+    for id in ids {
+        let meta = &zk.zettels[id];
+        let (fm, text) = frontmatter::parse_yaml_path(meta.full_path(root_dir))?;
+        for tag in zettel::tags(&fm) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        for (key, value) in fm {
+            let own = key.as_str().is_some_and(|key| OWN_KEYS.contains(&key));
+            if !own && !merged.contains_key(&key) {
+                merged.insert(key, value);
+            }
+        }
+        let text = transcluder.expand(&text, &mut vec![id.clone()])?;
+        let text = resolve_links(&text, zk, ids);
+        if !body.is_empty() {
+            body.push('\n');
+        }
+        body.push_str(&format!("## {}\n\n", meta.title));
+        body.push_str(shift_headings(&text, &meta.title, 3).trim_end());
+        body.push('\n');
+    }
+    let title = title
+        .map(str::to_owned)
+        .or_else(|| ids.first().map(|id| zk.zettels[id].title.clone()))
+        .unwrap_or_default();
+    let mut fm = serde_yaml::Mapping::new();
+    fm.insert("title".into(), title.as_str().into());
+    if !tags.is_empty() {
+        fm.insert("tags".into(), tags.into());
+    }
+    fm.extend(merged);
+    Ok(frontmatter::write_yaml(
+        &fm,
+        &format!("# {}\n\n{}", title, body),
+    )?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn headings_shift() {
+        let body = "# Title\n\nintro\n# One\n## Deeper\n";
+        assert_eq!(
+            shift_headings(body, "Title", 3),
+            "intro\n### One\n#### Deeper\n"
+        );
+        assert_eq!(shift_headings("text\n", "Title", 3), "text\n");
+    }
+
+    #[test]
+    fn compile_in_sequence() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_compile_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        for (n, (id, body)) in [
+            ("b", "Second, see [[a]] and [[x]].\n# Part\ntext"),
+            ("a", "First ![[x]]"),
+            ("x", "embedded"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let date = dt + chrono::Duration::hours(n as i64);
+            let mut zettel = db.new_zettel(&zk.config, id.to_uppercase(), id, date)?;
+            zettel.content = body.to_owned();
+            zettel
+                .extra_frontmatter
+                .insert("author".to_owned(), format!("author of {}", id));
+            zettel
+                .extra_frontmatter
+                .insert("tags".to_owned(), id.to_owned());
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        zk.zettels.get_mut("b").unwrap().follows = Some("a".to_owned());
+        let ids = order(&zk, vec!["b".to_owned(), "a".to_owned()], Order::Sequence);
+        assert_eq!(ids, ["a", "b"]);
+        let doc = compile(&zk, tmp_dir.path(), &ids, Some("Draft"))?;
+        assert_eq!(
+            doc,
+            "---\ntitle: Draft\ntags:\n  - a\n  - b\nauthor: author of a\n---\n\
+             # Draft\n\n## A\n\nFirst embedded\n\n## B\n\nSecond, see [A](#a) and X.\n### Part\ntext\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+use crate::{crypt, dates, frontmatter, fsutil, hooks, reconcile, zettel, DateTime};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+/// Settings of a zettelkasten, stored alongside its metadata
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Config {
+    /// BibTeX file used for literature notes, relative to the root directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bibliography: Option<PathBuf>,
+    /// directory holding images and other files referenced by zettels,
+    /// relative to the root directory; defaults to `assets`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub assets_dir: Option<PathBuf>,
+    /// other zettelkastens that `[[name:id]]` links point into, by name;
+    /// paths are relative to the root directory
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub kastens: BTreeMap<String, PathBuf>,
+    /// how `sync` settles fields that differ between frontmatter and
+    /// database; defaults to taking the frontmatter's value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conflict_policy: Option<reconcile::Policy>,
+    /// how ids of new zettels are generated; defaults to random ids
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_scheme: Option<zettel::IdScheme>,
+    /// what `new` and `meta set` do when a zettel would get a title another
+    /// zettel already has, in any case; defaults to warning
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title_policy: Option<TitlePolicy>,
+    /// whether `sync` follows symbolic links; defaults to following them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symlinks: Option<SymlinkPolicy>,
+    /// timezone timestamps are shown and written to frontmatter in, as
+    /// `local`, `utc` or `+HH:MM`; defaults to the local timezone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<dates::Zone>,
+    /// language of month and weekday names in `{{date:...}}` titles,
+    /// `@created:...` frontmatter and `list --dates`: `en`, `de`, `es`,
+    /// `fr`, `it` or `nl`; defaults to English
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<dates::Locale>,
+    /// strftime format of the dates `list --dates` shows, like
+    /// `%-d. %B %Y`; defaults to `%Y-%m-%d`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date_format: Option<String>,
+    /// name of the files of new zettels without extension, with `{date}`,
+    /// `{title}` and `{id}` filled in; defaults to `{date}-{title}`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename_template: Option<String>,
+    /// put the id in the file names of new zettels instead of following
+    /// `filename_template`, `prefix` for `{id}-{title}` or `only` for
+    /// `{id}`; `sync` then recovers the ids of files whose frontmatter lost
+    /// them from their names
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filename_id: Option<FilenameId>,
+    /// refuse to add zettels to directories that don't exist yet instead of
+    /// creating them; defaults to creating them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict_dirs: Option<bool>,
+    /// format of the files of new zettels, `markdown` or `org`; defaults
+    /// to markdown. `sync` reads either kind of file whatever this says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_format: Option<frontmatter::Format>,
+    /// titles of recurring notes by name, for `zk new --title-template`;
+    /// like any title given to `new` they can hold date variables such as
+    /// `{{date:%Y-%m-%d}}`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub title_templates: BTreeMap<String, String>,
+    /// kinds of notes by name, like `literature` or `journal`, for
+    /// `zk new --kind`; literature notes made with `--cite` are of the
+    /// `literature` kind when there is one
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub kinds: BTreeMap<String, Kind>,
+    /// names files use for zk's own frontmatter keys, like `id: uid` or
+    /// `created: date`; keys are read and written under their alias
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub key_aliases: BTreeMap<String, String>,
+    /// keywords `zk autolink` turns mentions of into links to a zettel,
+    /// like `CRDT: <id>`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub autolink: BTreeMap<String, zettel::Id>,
+    /// keys for `zk encrypt` and for reading encrypted zettels
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub age: Option<crypt::Age>,
+    #[serde(default, skip_serializing_if = "hooks::Hooks::is_empty")]
+    pub hooks: hooks::Hooks,
+}
+
+/// A kind of note, with its own subdirectory and frontmatter
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Kind {
+    /// subdirectory of the root directory new notes of the kind are put in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    /// frontmatter of new notes of the kind, added to and overriding the
+    /// default frontmatter; values are filled in like the defaults
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub frontmatter: HashMap<String, String>,
+}
+
+/// What `sync` does with symbolic links
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    Skip,
+    /// follow links that stay inside the root directory, visiting every
+    /// file and directory once
+    #[default]
+    Follow,
+}
+
+/// What to do about zettels sharing a title, which links by title can't
+/// tell apart
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TitlePolicy {
+    Allow,
+    #[default]
+    Warn,
+    Refuse,
+}
+
+/// How the id goes in the file names of new zettels
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilenameId {
+    /// `<id>-<title>`
+    Prefix,
+    /// `<id>` alone
+    Only,
+}
+
+/// Where the value of a setting comes from, from the weakest to the
+/// strongest
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Source {
+    /// built into zk
+    Default,
+    /// the config stored with the zettelkasten
+    Kasten,
+    /// an environment variable
+    Env,
+    /// a command line flag
+    Flag,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Default => "default",
+            Self::Kasten => "kasten",
+            Self::Env => "env",
+            Self::Flag => "flag",
+        })
+    }
+}
+
+/// format of the dates `list --dates` shows when none is configured
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// file name template used when none is configured
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "{date}-{title}";
+
+impl Config {
+    /// name of the file for a new zettel
+    pub fn file_name(&self, title: &str, id: &str, date: DateTime) -> String {
+        let date = self.timezone.unwrap_or_default().show(date);
+        let template = match self.filename_id {
+            Some(FilenameId::Prefix) => "{id}-{title}",
+            Some(FilenameId::Only) => "{id}",
+            None => self
+                .filename_template
+                .as_deref()
+                .unwrap_or(DEFAULT_FILENAME_TEMPLATE),
+        };
+        // the title goes in last so braces in it are left alone
+        let name = template
+            .replace("{date}", &date.format("%Y-%m-%d").to_string())
+            .replace("{id}", id)
+            .replace("{title}", &title.replace(' ', "-"));
+        let extension = self.file_format.unwrap_or_default().extension();
+        format!("{}.{}", fsutil::sanitize_file_name(&name), extension)
+    }
+
+    /// id in the name of the file at `path` when `filename_id` is set, the
+    /// longest of the file stem and its parts before a `-` that `known`
+    /// accepts; ids can hold dashes themselves so only known ones count
+    pub fn id_in_file_name(&self, path: &Path, known: impl Fn(&str) -> bool) -> Option<String> {
+        self.filename_id?;
+        let stem = path.file_stem()?.to_str()?;
+        let prefixes = stem.match_indices('-').map(|(at, _)| &stem[..at]);
+        std::iter::once(stem)
+            .chain(prefixes.rev())
+            .find(|id| known(id))
+            .map(str::to_owned)
+    }
+
+    /// every setting that has a value, by key, with zk's defaults in place
+    /// of the settings left out
+    pub fn resolved(&self) -> Vec<(String, serde_yaml::Value, Source)> {
+        let defaults = Self {
+            assets_dir: Some(self.assets_dir().to_owned()),
+            conflict_policy: Some(self.conflict_policy.unwrap_or_default()),
+            id_scheme: Some(self.id_scheme.unwrap_or_default()),
+            title_policy: Some(self.title_policy.unwrap_or_default()),
+            symlinks: Some(self.symlinks.unwrap_or_default()),
+            timezone: Some(self.timezone.unwrap_or_default()),
+            locale: Some(self.locale.unwrap_or_default()),
+            date_format: Some(DEFAULT_DATE_FORMAT.to_owned()),
+            filename_template: Some(DEFAULT_FILENAME_TEMPLATE.to_owned()),
+            file_format: Some(self.file_format.unwrap_or_default()),
+            strict_dirs: Some(self.strict_dirs.unwrap_or_default()),
+            ..Default::default()
+        };
+        let mapping = |config: &Self| match serde_yaml::to_value(config) {
+            Ok(serde_yaml::Value::Mapping(mapping)) => mapping,
+            _ => serde_yaml::Mapping::new(),
+        };
+        let mut resolved = BTreeMap::new();
+        let layers = [
+            (mapping(&defaults), Source::Default),
+            (mapping(self), Source::Kasten),
+        ];
+        for (layer, source) in layers {
+            for (key, value) in layer {
+                if let Some(key) = key.as_str() {
+                    resolved.insert(key.to_owned(), (value, source));
+                }
+            }
+        }
+        resolved
+            .into_iter()
+            .map(|(key, (value, source))| (key, value, source))
+            .collect()
+    }
+
+    pub fn assets_dir(&self) -> &Path {
+        self.assets_dir
+            .as_deref()
+            .unwrap_or_else(|| Path::new("assets"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::prelude::*;
+
+    #[test]
+    fn file_name_template() {
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut config = Config::default();
+        assert_eq!(
+            config.file_name("a new post", "abc", dt),
+            "2015-05-14-a-new-post.md"
+        );
+        config.filename_template = Some("{id} {title}?".to_owned());
+        assert_eq!(config.file_name("{date}", "abc", dt), "abc {date}-.md");
+        config.filename_id = Some(FilenameId::Prefix);
+        assert_eq!(config.file_name("a post", "abc", dt), "abc-a-post.md");
+        config.filename_id = Some(FilenameId::Only);
+        assert_eq!(config.file_name("a post", "abc", dt), "abc.md");
+    }
+
+    #[test]
+    fn ids_in_file_names() {
+        let mut config = Config::default();
+        let known = |id: &str| ["20150514120000", "20150514120000-2"].contains(&id);
+        let path = Path::new("sub/20150514120000-2-a-post.md");
+        assert_eq!(config.id_in_file_name(path, known), None);
+        config.filename_id = Some(FilenameId::Prefix);
+        assert_eq!(
+            config.id_in_file_name(path, known).as_deref(),
+            Some("20150514120000-2")
+        );
+        let path = Path::new("20150514120000.md");
+        assert_eq!(
+            config.id_in_file_name(path, known).as_deref(),
+            Some("20150514120000")
+        );
+        assert_eq!(config.id_in_file_name(Path::new("other.md"), known), None);
+    }
+
+    #[test]
+    fn resolved_settings() {
+        let config = Config {
+            locale: Some(dates::Locale::De),
+            ..Default::default()
+        };
+        let resolved = config.resolved();
+        let setting = |key: &str| {
+            resolved
+                .iter()
+                .find(|(k, _, _)| k == key)
+                .map(|(_, value, source)| (value.as_str().unwrap().to_owned(), *source))
+        };
+        assert_eq!(setting("locale"), Some(("de".to_owned(), Source::Kasten)));
+        assert_eq!(
+            setting("filename_template"),
+            Some(("{date}-{title}".to_owned(), Source::Default))
+        );
+        assert_eq!(setting("bibliography"), None);
+    }
+}
@@ -0,0 +1,325 @@
+//! Encryption of zettel bodies with [age](https://age-encryption.org)
+//!
+//! bodies are encrypted by running the `age` program, or another one taking
+//! the same arguments like `rage`, and stored ASCII armored under cleartext
+//! frontmatter. Titles, tags and links stay readable in the database.
+
+use crate::{frontmatter, zettel::ZettelHandle};
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    FrontmatterError(frontmatter::Error),
+    /// no recipients configured to encrypt to
+    NoRecipients,
+    /// no identity configured to decrypt with
+    NoIdentity,
+    /// the program exited unsuccessfully, with what it printed to stderr
+    Failed(String, String),
+    /// the program couldn't be found
+    NotInstalled(String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::NoRecipients => write!(f, "no age recipients in the config to encrypt to"),
+            Self::NoIdentity => write!(f, "no age identity in the config to decrypt with"),
+            Self::Failed(program, stderr) => write!(f, "{} failed: {}", program, stderr.trim()),
+            Self::NotInstalled(program) => write!(
+                f,
+                "no {} found; install age or set `program` under `age` in the config",
+                program
+            ),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// first line of an ASCII armored age file
+pub const ARMOR_BEGIN: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+
+/// Keys zettel bodies are encrypted with
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Age {
+    /// public keys, `age1...` or ssh keys, that can decrypt the bodies
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    /// file with a private key for one of the recipients, needed to read
+    /// encrypted bodies
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<PathBuf>,
+    /// program to run; defaults to `age`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub program: Option<String>,
+}
+
+impl Age {
+    fn program(&self) -> &str {
+        self.program.as_deref().unwrap_or("age")
+    }
+
+    /// run the program with `args`, feeding it `input`
+    fn run(&self, args: &[OsString], input: &[u8]) -> Result<Vec<u8>> {
+        let mut child = Command::new(self.program())
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => Error::NotInstalled(self.program().to_owned()),
+                _ => e.into(),
+            })?;
+        // written from another thread so a full stdout pipe can't block us
+        let mut stdin = child.stdin.take().unwrap();
+        let input = input.to_owned();
+        let writer = std::thread::spawn(move || stdin.write_all(&input));
+        let output = child.wait_with_output()?;
+        writer
+            .join()
+            .expect("writing to the age program panicked")?;
+        if !output.status.success() {
+            return Err(Error::Failed(
+                self.program().to_owned(),
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+        Ok(output.stdout)
+    }
+
+    /// arguments naming who can decrypt: the recipients, or the identity
+    /// itself when there are none
+    fn recipient_args(&self) -> Result<Vec<OsString>> {
+        let mut args = Vec::new();
+        for recipient in &self.recipients {
+            args.extend(["-r".into(), recipient.into()]);
+        }
+        match &self.identity {
+            Some(identity) if args.is_empty() => args.extend(["-i".into(), identity.into()]),
+            _ if args.is_empty() => return Err(Error::NoRecipients),
+            _ => {}
+        }
+        Ok(args)
+    }
+
+    /// `data` encrypted in age's binary format
+    pub fn encrypt_bytes(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut args: Vec<OsString> = vec!["--encrypt".into()];
+        args.extend(self.recipient_args()?);
+        self.run(&args, data)
+    }
+
+    /// `data` decrypted with the identity
+    pub fn decrypt_bytes(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let identity = self.identity.as_ref().ok_or(Error::NoIdentity)?;
+        self.run(&["--decrypt".into(), "-i".into(), identity.into()], data)
+    }
+
+    /// `text` encrypted to every recipient, ASCII armored
+    pub fn encrypt(&self, text: &str) -> Result<String> {
+        if self.recipients.is_empty() {
+            return Err(Error::NoRecipients);
+        }
+        let mut args: Vec<OsString> = vec!["--encrypt".into(), "--armor".into()];
+        args.extend(self.recipient_args()?);
+        let armored = self.run(&args, text.as_bytes())?;
+        Ok(String::from_utf8_lossy(&armored).into_owned())
+    }
+
+    /// `armored` decrypted with the identity
+    pub fn decrypt(&self, armored: &str) -> Result<String> {
+        let text = self.decrypt_bytes(armored.as_bytes())?;
+        Ok(String::from_utf8_lossy(&text).into_owned())
+    }
+
+    /// encrypt the body of the zettel at `path` in place, leaving its
+    /// frontmatter readable; `false` if it was encrypted already
+    pub fn encrypt_file(&self, path: &Path) -> Result<bool> {
+        let (fm, body) = frontmatter::parse_yaml_path(path)?;
+        if is_encrypted(&body) {
+            return Ok(false);
+        }
+        let body = self.encrypt(&body)?;
+        std::fs::write(path, frontmatter::write_for(path, &fm, &body)?)?;
+        Ok(true)
+    }
+
+    /// decrypt the body of the zettel at `path` in place, returning it, or
+    /// `None` if it wasn't encrypted
+    pub fn decrypt_file(&self, path: &Path) -> Result<Option<String>> {
+        let (fm, body) = frontmatter::parse_yaml_path(path)?;
+        if !is_encrypted(&body) {
+            return Ok(None);
+        }
+        let body = self.decrypt(&body)?;
+        std::fs::write(path, frontmatter::write_for(path, &fm, &body)?)?;
+        Ok(Some(body))
+    }
+
+    /// the whole file of the zettel at `path` with its body decrypted
+    pub fn read_decrypted(&self, path: &Path) -> Result<String> {
+        let (fm, body) = frontmatter::parse_yaml_path(path)?;
+        Ok(frontmatter::write_for(path, &fm, &self.decrypt(&body)?)?)
+    }
+}
+
+/// whether `body` is an encrypted body
+pub fn is_encrypted(body: &str) -> bool {
+    body.trim_start().starts_with(ARMOR_BEGIN)
+}
+
+/// whether the body of `zettel` is encrypted, reading no further than its
+/// first line
+pub fn is_encrypted_file(zettel: &ZettelHandle) -> Result<bool> {
+    let (_, mut lines) = zettel.body_lines()?;
+    for line in &mut lines {
+        let line = line?;
+        if !line.trim().is_empty() {
+            return Ok(line.trim() == ARMOR_BEGIN);
+        }
+    }
+    Ok(false)
+}
+
+/// A decrypted copy of an encrypted zettel in the temporary directory, for
+/// editing; encrypted back into place by [`Plaintext::finish`] and removed
+/// when dropped
+#[derive(Debug)]
+pub struct Plaintext {
+    pub path: PathBuf,
+    original: PathBuf,
+}
+
+impl Plaintext {
+    pub fn new(age: &Age, original: &Path) -> Result<Self> {
+        let name = original.file_name().unwrap_or_default().to_string_lossy();
+        let path =
+            std::env::temp_dir().join(format!("zk-{}-{}", uuid::Uuid::new_v4().simple(), name));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let contents = age.read_decrypted(original)?;
+        let plaintext = Self {
+            path,
+            original: original.to_path_buf(),
+        };
+        options
+            .open(&plaintext.path)?
+            .write_all(contents.as_bytes())?;
+        Ok(plaintext)
+    }
+
+    /// encrypt the edited copy into the original file
+    pub fn finish(self, age: &Age) -> Result<()> {
+        let (fm, body) = frontmatter::parse_yaml_path(&self.path)?;
+        let body = age.encrypt(&body)?;
+        std::fs::write(
+            &self.original,
+            frontmatter::write_for(&self.original, &fm, &body)?,
+        )?;
+        Ok(())
+    }
+}
+
+impl Drop for Plaintext {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// settings running a stand-in for age that "encrypts" by reversing each
+/// line between armor lines, for tests
+#[cfg(all(test, unix))]
+pub(crate) fn fake_age(dir: &Path) -> std::io::Result<Age> {
+    use std::os::unix::fs::PermissionsExt;
+    let program = dir.join("fake-age");
+    std::fs::write(
+        &program,
+        "#!/bin/sh\ncase \"$1\" in\n--decrypt) sed '1d;$d' | rev ;;\n\
+         *) echo '-----BEGIN AGE ENCRYPTED FILE-----'; rev; echo '-----END AGE ENCRYPTED FILE-----' ;;\n\
+         esac\n",
+    )?;
+    std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755))?;
+    Ok(Age {
+        program: Some(program.to_string_lossy().into_owned()),
+        ..Default::default()
+    })
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_crypt_test")?;
+        let mut age = fake_age(tmp_dir.path())?;
+        let path = tmp_dir.path().join("note.md");
+        std::fs::write(&path, "---\ntitle: Secret\n---\nhidden words\n")?;
+        assert!(matches!(age.encrypt_file(&path), Err(Error::NoRecipients)));
+        age.recipients.push("age1example".to_owned());
+        assert!(age.encrypt_file(&path)?);
+        assert!(!age.encrypt_file(&path)?);
+        let (fm, body) = frontmatter::parse_yaml_path(&path)?;
+        assert_eq!(fm.get(&"title".into()), Some(&"Secret".into()));
+        assert!(is_encrypted(&body));
+        assert!(!body.contains("hidden words"));
+        assert!(matches!(age.decrypt_file(&path), Err(Error::NoIdentity)));
+        age.identity = Some(tmp_dir.path().join("key.txt"));
+        let plaintext = Plaintext::new(&age, &path)?;
+        let copy = std::fs::read_to_string(&plaintext.path)?;
+        assert!(copy.contains("hidden words"));
+        std::fs::write(&plaintext.path, copy.replace("hidden", "edited"))?;
+        let copy_path = plaintext.path.clone();
+        plaintext.finish(&age)?;
+        assert!(!copy_path.exists());
+        assert_eq!(age.decrypt_file(&path)?.as_deref(), Some("edited words\n"));
+        assert_eq!(age.decrypt_file(&path)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_program() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_crypt_test")?;
+        let age = Age {
+            recipients: vec!["age1example".to_owned()],
+            program: Some("zk-no-such-age".to_owned()),
+            ..Default::default()
+        };
+        let path = tmp_dir.path().join("note.md");
+        let note = "---\ntitle: Secret\n---\nhidden words\n";
+        std::fs::write(&path, note)?;
+        assert!(matches!(
+            age.encrypt_file(&path),
+            Err(Error::NotInstalled(program)) if program == "zk-no-such-age"
+        ));
+        assert_eq!(std::fs::read_to_string(&path)?, note);
+        Ok(())
+    }
+}
@@ -0,0 +1,589 @@
+use crate::{
+    crypt, database, database::Database, events, frontmatter, fsutil, hooks, query, zettel,
+    zettelkasten, zettelkasten::Zettelkasten, DateTime, ZettelMeta,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    path::Path,
+    sync::mpsc,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    DatabaseError(database::Error),
+    FrontmatterError(frontmatter::Error),
+    ZettelkastenError(zettelkasten::Error),
+    QueryError(query::Error),
+    RegexError(regex::Error),
+    /// no database in the root directory
+    NotInitialized,
+    UnknownMethod(String),
+    InvalidParams(String),
+    NotFound(String),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::DatabaseError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::ZettelkastenError(e) => e.fmt(f),
+            Self::QueryError(e) => e.fmt(f),
+            Self::RegexError(e) => e.fmt(f),
+            Self::NotInitialized => write!(f, "database does not exist; use `init` first"),
+            Self::UnknownMethod(method) => write!(f, "unknown method {}", method),
+            Self::InvalidParams(message) => write!(f, "invalid params: {}", message),
+            Self::NotFound(what) => write!(f, "{} not found", what),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<database::Error> for Error {
+    fn from(e: database::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl From<zettelkasten::Error> for Error {
+    fn from(e: zettelkasten::Error) -> Self {
+        Self::ZettelkastenError(e)
+    }
+}
+
+impl From<query::Error> for Error {
+    fn from(e: query::Error) -> Self {
+        Self::QueryError(e)
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Self {
+        Self::RegexError(e)
+    }
+}
+
+impl Error {
+    /// JSON-RPC error code
+    fn code(&self) -> i64 {
+        match self {
+            Self::UnknownMethod(_) => -32601,
+            Self::InvalidParams(_) | Self::QueryError(_) | Self::RegexError(_) => -32602,
+            _ => -32000,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// path of the socket under the root directory when none is given
+pub fn default_socket(root_dir: &Path) -> std::path::PathBuf {
+    root_dir.join(".zk").join("daemon.sock")
+}
+
+/// A zettelkasten kept in memory, answering JSON-RPC requests, on a socket
+/// for `zk daemon` or on stdin and stdout for `zk api --stdio`
+///
+/// zettels are only read again when they are synced through the daemon or
+/// on `reload`, so changes made by other zk commands in the meantime aren't
+/// seen until then. Changes made through the daemon are sent as `event`
+/// notifications to the client whose request made them, after its response.
+pub struct Daemon<D: Database> {
+    db: D,
+    zk: Zettelkasten,
+    /// bodies of zettels by id, searched by `search`
+    bodies: HashMap<zettel::Id, String>,
+    events: mpsc::Receiver<events::Event>,
+}
+
+impl<D: Database> Daemon<D> {
+    pub fn new(db: D) -> Result<Self> {
+        let mut daemon = Self {
+            db,
+            zk: Zettelkasten::default(),
+            bodies: HashMap::new(),
+            events: mpsc::channel().1,
+        };
+        daemon.reload()?;
+        Ok(daemon)
+    }
+
+    fn reload(&mut self) -> Result<()> {
+        self.zk = self.db.get_zk()?.ok_or(Error::NotInitialized)?;
+        self.events = self.zk.subscribe();
+        self.bodies = self
+            .zk
+            .zettels
+            .iter()
+            .filter_map(|(id, meta)| {
+                let (_, body) =
+                    frontmatter::parse_yaml_path(meta.full_path(self.db.root_dir())).ok()?;
+                Some((id.clone(), body))
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// response to a request, `None` for notifications
+    pub fn handle(&mut self, request: &Value, now: DateTime) -> Option<Value> {
+        let id = request.get("id").cloned();
+        let method = request
+            .get("method")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let result = self.call(method, &params, now);
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+            Err(e) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": {"code": e.code(), "message": e.to_string()},
+            }),
+        })
+    }
+
+    fn call(&mut self, method: &str, params: &Value, now: DateTime) -> Result<Value> {
+        let param = |name: &str| {
+            params
+                .get(name)
+                .and_then(Value::as_str)
+                .ok_or_else(|| Error::InvalidParams(format!("missing string `{}`", name)))
+        };
+        match method {
+            "list" => {
+                let query = match params.get("query").and_then(Value::as_str) {
+                    Some(query) => query::parse(query)?,
+                    None => query::Query::All,
+                };
+                let mut found: Vec<(&zettel::Id, &ZettelMeta)> = self
+                    .zk
+                    .zettels
+                    .iter()
+                    .filter(|(id, meta)| query.matches(id, meta, self.db.root_dir()))
+                    .collect();
+                found.sort_by_key(|(_, meta)| meta.created);
+                Ok(found
+                    .into_iter()
+                    .map(|(id, meta)| describe(id, meta))
+                    .collect())
+            }
+            "search" => {
+                let re = regex::Regex::new(param("pattern")?)?;
+                let mut ids: Vec<&zettel::Id> = self.bodies.keys().collect();
+                ids.sort();
+                let mut found = Vec::new();
+                for id in ids {
+                    let body = &self.bodies[id];
+                    let lines: Vec<&str> = body.lines().collect();
+                    for n in crate::grep::matching_lines(&re, body) {
+                        found.push(json!({"id": id, "line": n, "text": lines[n - 1]}));
+                    }
+                }
+                Ok(Value::Array(found))
+            }
+            "resolve" => {
+                let target = param("target")?;
+                // `[[id|label]]` links point at ids; titles are tried after
+                let id = target.split('|').next().unwrap_or_default().trim();
+                let found = self.zk.zettels.get_key_value(id).or_else(|| {
+                    self.zk
+                        .zettels
+                        .iter()
+                        .find(|(_, meta)| meta.title.eq_ignore_ascii_case(id))
+                });
+                Ok(found.map_or(Value::Null, |(id, meta)| describe(id, meta)))
+            }
+            "new" => {
+                let title = param("title")?;
+                let id = self.zk.new_id(now);
+                let zettel = self.db.new_zettel(&self.zk.config, title, &id, now)?;
+                self.zk.add(self.db.root_dir(), &zettel)?;
+                self.zk.commit_entry(&self.db, &id)?;
+                self.bodies.insert(id.clone(), String::new());
+                let hooks = &self.zk.config.hooks;
+                hooks::run(
+                    self.db.root_dir(),
+                    hooks,
+                    hooks::Event::New,
+                    &[(&id, &zettel.meta)],
+                );
+                Ok(describe(&id, &self.zk.zettels[&id]))
+            }
+            "sync-file" => {
+                let root_dir = self.db.root_dir().to_path_buf();
+                let given = Path::new(param("path")?);
+                let relative = match given.is_absolute() {
+                    true => given.strip_prefix(&root_dir).ok(),
+                    false => Some(given),
+                };
+                let stored = relative
+                    .and_then(fsutil::to_slash)
+                    .filter(|relative| fsutil::contained(relative).is_some())
+                    .ok_or_else(|| {
+                        let message = format!("{} is outside the kasten", given.display());
+                        Error::InvalidParams(message)
+                    })?;
+                let path = root_dir.join(fsutil::from_slash(&stored));
+                let (mut fm, body) = frontmatter::parse_yaml_path(&path)?;
+                frontmatter::unalias(&mut fm, &self.zk.config.key_aliases);
+                let id = fm
+                    .get(&"id".into())
+                    .and_then(|id| id.as_str())
+                    .ok_or_else(|| {
+                        Error::InvalidParams("file has no `id` in frontmatter".to_owned())
+                    })?
+                    .to_owned();
+                let frontmatter_truth = self.zk.meta.storage == zettelkasten::Storage::Frontmatter;
+                let meta = self
+                    .zk
+                    .zettels
+                    .get_mut(&id)
+                    .ok_or_else(|| Error::NotFound(format!("zettel {}", id)))?;
+                let before = meta.clone();
+                meta.path = stored;
+                meta.update_from_frontmatter(&fm);
+                // encrypted bodies keep what was known from before
+                if !crypt::is_encrypted(&body) {
+                    meta.update_from_body(&body);
+                } else {
+                    meta.update_hash(&body);
+                }
+                let file_modified: DateTime = std::fs::metadata(&path)?.modified()?.into();
+                if frontmatter_truth {
+                    meta.read_state(&fm, file_modified);
+                } else {
+                    meta.modified = file_modified;
+                }
+                for event in events::Event::between(&id, Some(&before), Some(meta)) {
+                    self.zk.notify(&event);
+                }
+                self.bodies.insert(id.clone(), body);
+                self.zk.commit_entry(&self.db, &id)?;
+                Ok(describe(&id, &self.zk.zettels[&id]))
+            }
+            "reload" => {
+                self.reload()?;
+                Ok(Value::Null)
+            }
+            "shutdown" => Ok(Value::Null),
+            _ => Err(Error::UnknownMethod(method.to_owned())),
+        }
+    }
+
+    /// answer one request line with its response and the events it caused,
+    /// and whether it asked for a shutdown
+    ///
+    /// lines that aren't JSON, or not even UTF-8, get a parse error, so a
+    /// broken client can't take the daemon down with it.
+    fn answer(&mut self, line: &[u8]) -> (Vec<Value>, bool) {
+        let parsed = std::str::from_utf8(line)
+            .map_err(|e| e.to_string())
+            .and_then(|line| serde_json::from_str::<Value>(line).map_err(|e| e.to_string()));
+        let (response, shutdown) = match parsed {
+            Ok(request) => (
+                self.handle(&request, chrono::Local::now()),
+                request.get("method") == Some(&"shutdown".into()),
+            ),
+            Err(message) => {
+                let error = json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": {"code": -32700, "message": message},
+                });
+                (Some(error), false)
+            }
+        };
+        let events = self
+            .events
+            .try_iter()
+            .map(|event| json!({"jsonrpc": "2.0", "method": "event", "params": event}));
+        (response.into_iter().chain(events).collect(), shutdown)
+    }
+
+    /// answer newline separated requests from `input` on `output` until
+    /// input ends or a `shutdown` request, returning whether it was the latter
+    pub fn answer_lines(&mut self, input: impl BufRead, output: impl Write) -> Result<bool> {
+        answer_lines(|line| self.answer(line), input, output)
+    }
+
+    /// answer newline separated requests on the socket at `path` until a
+    /// `shutdown` request
+    ///
+    /// every client gets a thread of its own, so an editor that keeps its
+    /// connection open doesn't hold up the others, and a client that breaks
+    /// off or fails is only logged and dropped.
+    #[cfg(unix)]
+    pub fn serve(self, path: &Path) -> Result<()>
+    where
+        D: Send,
+    {
+        use std::{
+            net::Shutdown,
+            os::unix::net::{UnixListener, UnixStream},
+            sync::{
+                atomic::{AtomicBool, Ordering},
+                Mutex, PoisonError,
+            },
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        // a socket left behind by a daemon that didn't shut down cleanly
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        tracing::info!("listening on {}", path.display());
+        let daemon = Mutex::new(self);
+        let clients: Mutex<HashMap<usize, UnixStream>> = Mutex::default();
+        let shutdown = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            for (n, stream) in listener.incoming().enumerate() {
+                if shutdown.load(Ordering::SeqCst) {
+                    break;
+                }
+                let client = match stream.and_then(|s| Ok((s.try_clone()?, s))) {
+                    Ok((client, stream)) => {
+                        clients
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .insert(n, client);
+                        stream
+                    }
+                    Err(e) => {
+                        tracing::warn!("couldn't accept a client: {}", e);
+                        continue;
+                    }
+                };
+                let (daemon, clients, shutdown) = (&daemon, &clients, &shutdown);
+                scope.spawn(move || {
+                    let answer = |line: &[u8]| {
+                        let mut daemon = daemon.lock().unwrap_or_else(PoisonError::into_inner);
+                        daemon.answer(line)
+                    };
+                    let answered = client.try_clone().map_err(Error::from).and_then(|writer| {
+                        answer_lines(answer, std::io::BufReader::new(client), writer)
+                    });
+                    clients
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .remove(&n);
+                    match answered {
+                        Ok(false) => {}
+                        Ok(true) => {
+                            shutdown.store(true, Ordering::SeqCst);
+                            // end the other clients' reads and wake the accept loop
+                            for client in clients
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .values()
+                            {
+                                client.shutdown(Shutdown::Both).ok();
+                            }
+                            UnixStream::connect(path).ok();
+                        }
+                        Err(e) => tracing::warn!("dropped a client: {}", e),
+                    }
+                });
+            }
+        });
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn serve(self, _path: &Path) -> Result<()> {
+        let e = std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "the daemon needs unix sockets",
+        );
+        Err(e.into())
+    }
+}
+
+/// write what `answer` gives for each line of `input` to `output`, until
+/// input ends or a line asks for a shutdown, returning whether it was the latter
+fn answer_lines(
+    mut answer: impl FnMut(&[u8]) -> (Vec<Value>, bool),
+    input: impl BufRead,
+    mut output: impl Write,
+) -> Result<bool> {
+    for line in input.split(b'\n') {
+        let line = line?;
+        if line.trim_ascii().is_empty() {
+            continue;
+        }
+        let (messages, shutdown) = answer(&line);
+        for message in messages {
+            writeln!(output, "{}", message)?;
+        }
+        output.flush()?;
+        if shutdown {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// what requests return about a zettel
+fn describe(id: &str, meta: &ZettelMeta) -> Value {
+    json!({
+        "id": id,
+        "title": meta.title,
+        "path": meta.path,
+        "tags": meta.tags,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::{self, Kasten};
+
+    #[test]
+    fn answers_requests() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = Kasten::new();
+        kasten.add("a", "First", "some text\nabout rust\n");
+        let dt = testutil::date();
+        let mut daemon = Daemon::new(&kasten.db)?;
+        let mut call = |method: &str, params: Value| {
+            let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+            daemon.handle(&request, dt).unwrap()
+        };
+        let found = call("search", json!({"pattern": "rust"}));
+        assert_eq!(found["result"][0]["id"], "a");
+        assert_eq!(found["result"][0]["line"], 2);
+        assert_eq!(
+            call("resolve", json!({"target": "first"}))["result"]["id"],
+            "a"
+        );
+        let created = call("new", json!({"title": "Second"}));
+        let id = created["result"]["id"].as_str().unwrap().to_owned();
+        assert_eq!(
+            call("list", json!({}))["result"].as_array().unwrap().len(),
+            2
+        );
+        let path = kasten.root_dir().join("2015-05-14-Second.md");
+        std::fs::write(
+            &path,
+            format!("---\nid: {}\ntitle: Renamed\n---\nbody\n", id),
+        )?;
+        let synced = call("sync-file", json!({"path": path}));
+        assert_eq!(synced["result"]["title"], "Renamed");
+        let synced = call("sync-file", json!({"path": "2015-05-14-Second.md"}));
+        assert_eq!(synced["result"]["path"], "2015-05-14-Second.md");
+        let outside = kasten.root_dir().join("../outside.md");
+        for path in [json!(outside), json!("../outside.md"), json!("/etc/passwd")] {
+            let refused = call("sync-file", json!({ "path": path }));
+            assert_eq!(refused["error"]["code"], -32602);
+        }
+        assert_eq!(call("nope", Value::Null)["error"]["code"], -32601);
+        let input = "garbage\n{\"id\": 2, \"method\": \"shutdown\"}\n{\"id\": 3}\n";
+        let mut output = Vec::new();
+        assert!(daemon.answer_lines(input.as_bytes(), &mut output)?);
+        let output = String::from_utf8(output)?;
+        let responses: Vec<Value> = output
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<std::result::Result<_, _>>()?;
+        let (notifications, responses): (Vec<Value>, Vec<Value>) =
+            responses.into_iter().partition(|r| r["method"] == "event");
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["error"]["code"], -32700);
+        let events: Vec<&Value> = notifications
+            .iter()
+            .map(|n| &n["params"]["event"])
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "zettel-added",
+                "committed",
+                "title-changed",
+                "committed",
+                "committed"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn survives_malformed_lines() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = Kasten::new();
+        kasten.add("a", "First", "body\n");
+        let mut daemon = Daemon::new(&kasten.db)?;
+        let input: &[u8] =
+            b"\xff\xfe\n{\"id\": 1, \"method\": \"nope\"}\n{\"id\": 2, \"method\": \"list\"}";
+        let mut output = Vec::new();
+        assert!(!daemon.answer_lines(input, &mut output)?);
+        let responses: Vec<Value> = String::from_utf8(output)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<std::result::Result<_, _>>()?;
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["error"]["code"], -32700);
+        assert_eq!(responses[1]["error"]["code"], -32601);
+        assert_eq!(responses[2]["result"][0]["id"], "a");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn serves_clients_side_by_side() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use std::{io::BufReader, os::unix::net::UnixStream};
+        let kasten = Kasten::new();
+        let db = database::file::Database::new(kasten.root_dir().to_path_buf())?;
+        db.commit(&Zettelkasten::default())?;
+        let socket = default_socket(kasten.root_dir());
+        let daemon = Daemon::new(db)?;
+        std::thread::scope(
+            |scope| -> std::result::Result<(), Box<dyn std::error::Error>> {
+                let served = scope.spawn(|| daemon.serve(&socket));
+                let connect = || loop {
+                    match UnixStream::connect(&socket) {
+                        Ok(stream) => break stream,
+                        Err(_) => std::thread::sleep(std::time::Duration::from_millis(10)),
+                    }
+                };
+                // an editor that connects and then says nothing
+                let idle = connect();
+                // a client that breaks off in the middle of a request
+                let mut gone = connect();
+                gone.write_all(b"{\"id\": 1, \"meth")?;
+                drop(gone);
+                let mut client = connect();
+                let mut responses = BufReader::new(client.try_clone()?).lines();
+                client.write_all(b"\xff\n{\"id\": 2, \"method\": \"list\"}\n")?;
+                let error: Value = serde_json::from_str(&responses.next().unwrap()?)?;
+                assert_eq!(error["error"]["code"], -32700);
+                let listed: Value = serde_json::from_str(&responses.next().unwrap()?)?;
+                assert_eq!(listed["result"], json!([]));
+                client.write_all(b"{\"id\": 3, \"method\": \"shutdown\"}\n")?;
+                served.join().unwrap()?;
+                drop(idle);
+                Ok(())
+            },
+        )?;
+        assert!(!socket.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,747 @@
+use super::{lookup, migrate, Error, Result};
+use crate::{
+    crypt, events, fsutil, hooks, undo,
+    zettel::ZettelMeta,
+    zettelkasten::{Storage, Zettelkasten},
+};
+use serde::Serialize;
+use serde_yaml::Value;
+use std::path::{Path, PathBuf};
+
+/// directory holding the metadata of each zettel with sidecar storage
+pub const SIDECAR_DIR: &str = "_zettels";
+
+/// suffix of the database file and sidecars when they are encrypted
+pub const ENCRYPTED_SUFFIX: &str = ".age";
+
+/// environment variable naming the age identity file that encrypted
+/// databases are opened with
+pub const KEY_FILE_VAR: &str = "ZK_KEY_FILE";
+
+/// Formats of the database file
+#[derive(Debug, Default, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum DatabaseKind {
+    #[default]
+    Yaml,
+    Json,
+    Toml,
+    /// compact binary format that is much faster to read for large kastens
+    Cbor,
+}
+
+impl DatabaseKind {
+    const ALL: [Self; 4] = [Self::Yaml, Self::Json, Self::Toml, Self::Cbor];
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Yaml => "_zettel.yaml",
+            Self::Json => "_zettel.json",
+            Self::Toml => "_zettel.toml",
+            Self::Cbor => "_zettel.cbor",
+        }
+    }
+
+    /// extension of sidecar files
+    fn extension(self) -> &'static str {
+        &self.file_name()["_zettel.".len()..]
+    }
+
+    /// format of the database file in `root_dir`, if there is one
+    pub fn detect(root_dir: &Path) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| root_dir.join(kind.file_name()).is_file())
+    }
+
+    /// format of the encrypted database file in `root_dir`, if there is one
+    pub fn detect_encrypted(root_dir: &Path) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| {
+            let name = format!("{}{}", kind.file_name(), ENCRYPTED_SUFFIX);
+            root_dir.join(name).is_file()
+        })
+    }
+
+    /// contents of a database file before they are upgraded to the current
+    /// version
+    fn read_raw(self, data: &[u8]) -> Result<Value> {
+        Ok(match self {
+            Self::Yaml => serde_yaml::from_slice(data)?,
+            Self::Json => serde_json::from_slice(data)?,
+            Self::Toml => toml::from_slice(data)?,
+            Self::Cbor => ciborium::de::from_reader(data)?,
+        })
+    }
+
+    fn write(self, value: &impl Serialize) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::Yaml => serde_yaml::to_vec(value)?,
+            Self::Json => serde_json::to_vec_pretty(value)?,
+            // going through a value puts plain keys before tables, which
+            // toml requires
+            Self::Toml => toml::to_vec(&toml::Value::try_from(value)?)?,
+            Self::Cbor => {
+                let mut data = Vec::new();
+                ciborium::ser::into_writer(value, &mut data)?;
+                data
+            }
+        })
+    }
+}
+
+/// Database stored in a file in the root directory, along with a file per
+/// zettel under [`SIDECAR_DIR`] when the kasten uses [`Storage::Sidecar`]
+///
+/// the files can be encrypted with age, in which case they end in
+/// [`ENCRYPTED_SUFFIX`] and are only decrypted in memory
+#[derive(Debug)]
+pub struct Database {
+    root_dir: PathBuf,
+    kind: DatabaseKind,
+    read_only: bool,
+    encrypted: bool,
+    /// identity to decrypt and encrypt with, from [`KEY_FILE_VAR`]
+    key: Option<crypt::Age>,
+    /// label of the undo entry recorded on commit, if any
+    undo_label: Option<String>,
+}
+
+impl Database {
+    /// open the database in `root_dir` in the format of its file, YAML if
+    /// there is none yet; read-only if the file can't be written to
+    pub fn new(root_dir: PathBuf) -> Result<Self> {
+        let root_dir = std::fs::canonicalize(root_dir)?;
+        let (kind, encrypted) = match DatabaseKind::detect(&root_dir) {
+            Some(kind) => (kind, false),
+            None => match DatabaseKind::detect_encrypted(&root_dir) {
+                Some(kind) => (kind, true),
+                None => (DatabaseKind::default(), false),
+            },
+        };
+        let key = std::env::var_os(KEY_FILE_VAR).map(|path| crypt::Age {
+            identity: Some(path.into()),
+            ..Default::default()
+        });
+        let mut db = Self {
+            root_dir,
+            kind,
+            read_only: false,
+            encrypted,
+            key,
+            undo_label: None,
+        };
+        let path = db.path();
+        db.read_only = path.is_file()
+            && std::fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .is_err();
+        Ok(db)
+    }
+
+    /// store the database in another format from now on
+    pub fn with_kind(mut self, kind: DatabaseKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// encrypt and decrypt with `key` instead of the one from
+    /// [`KEY_FILE_VAR`]
+    pub fn with_key(mut self, key: crypt::Age) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    /// store the database encrypted or in the clear from now on
+    pub fn with_encryption(mut self, encrypted: bool) -> Self {
+        self.encrypted = encrypted;
+        self
+    }
+
+    pub fn kind(&self) -> DatabaseKind {
+        self.kind
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// store the database in `kind`, encrypted or not, from now on,
+    /// checking that it reads back unchanged before the old file is removed
+    pub fn migrate(self, kind: DatabaseKind, encrypted: bool) -> Result<Self> {
+        let old_path = self.path();
+        let old_suffix = self.sidecar_suffix();
+        let zk = match super::Database::get_zk(&self)? {
+            Some(zk) => zk,
+            None => return Ok(self.with_kind(kind).with_encryption(encrypted)),
+        };
+        let db = self.with_kind(kind).with_encryption(encrypted);
+        super::Database::commit(&db, &zk)?;
+        if super::Database::get_zk(&db)?.as_ref() != Some(&zk) {
+            std::fs::remove_file(db.path())?;
+            return Err(Error::LossyMigration(kind));
+        }
+        std::fs::remove_file(old_path)?;
+        if zk.meta.storage == Storage::Sidecar {
+            db.remove_sidecars(|name| name.ends_with(&old_suffix))?;
+        }
+        Ok(db)
+    }
+
+    /// refuse commits from now on
+    pub fn set_read_only(&mut self) {
+        self.read_only = true;
+    }
+
+    /// record an undo entry labelled `label` on each commit from now on
+    pub fn record_undo(&mut self, label: String) {
+        self.undo_label = Some(label);
+    }
+
+    /// log what committing `zk` over the stored zettels `before` changed
+    fn save_undo(&self, label: &str, before: &Zettelkasten, zk: &Zettelkasten) {
+        let saved = undo::entry(
+            &self.root_dir,
+            label,
+            chrono::Local::now(),
+            &before.zettels,
+            zk,
+            zk.take_undo(),
+        )
+        .and_then(|entry| match entry {
+            Some(entry) => undo::save(&self.root_dir, &entry, super::Database::key(self)),
+            None => Ok(()),
+        });
+        if let Err(e) = saved {
+            tracing::warn!("couldn't record undo: {}", e);
+        }
+    }
+
+    /// version of the database file as stored, `None` if there is no file
+    pub fn stored_version(&self) -> Result<Option<u32>> {
+        let path = self.path();
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let contents = self.kind.read_raw(&self.read_file(&path)?)?;
+        Ok(Some(migrate::version(&contents)))
+    }
+
+    /// the database file
+    pub fn path(&self) -> PathBuf {
+        let mut name = self.kind.file_name().to_owned();
+        if self.encrypted {
+            name.push_str(ENCRYPTED_SUFFIX);
+        }
+        self.root_dir.join(name)
+    }
+
+    /// contents of one of the database's files, decrypted
+    fn read_file(&self, path: &Path) -> Result<Vec<u8>> {
+        let data = std::fs::read(path)?;
+        if !self.encrypted {
+            return Ok(data);
+        }
+        let key = self.key.as_ref().ok_or(Error::Locked)?;
+        Ok(key.decrypt_bytes(&data)?)
+    }
+
+    /// write one of the database's files, encrypting it
+    fn write_file(&self, path: &Path, data: &[u8]) -> Result<()> {
+        if !self.encrypted {
+            return Ok(fsutil::write_atomic(path, data)?);
+        }
+        let key = self.key.as_ref().ok_or(Error::Locked)?;
+        Ok(fsutil::write_atomic(path, key.encrypt_bytes(data)?)?)
+    }
+
+    /// end of the names of sidecar files, after the id
+    fn sidecar_suffix(&self) -> String {
+        let suffix = format!(".{}", self.kind.extension());
+        match self.encrypted {
+            true => suffix + ENCRYPTED_SUFFIX,
+            false => suffix,
+        }
+    }
+
+    fn sidecar_path(&self, id: &str) -> PathBuf {
+        self.root_dir
+            .join(SIDECAR_DIR)
+            .join(format!("{}{}", id, self.sidecar_suffix()))
+    }
+
+    /// raw metadata of every zettel stored in sidecars, by id
+    fn read_sidecars(&self) -> Result<serde_yaml::Mapping> {
+        let mut zettels = serde_yaml::Mapping::new();
+        let dir = self.root_dir.join(SIDECAR_DIR);
+        if !dir.is_dir() {
+            return Ok(zettels);
+        }
+        let suffix = self.sidecar_suffix();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap().to_string_lossy();
+            if let Some(id) = name.strip_suffix(&suffix) {
+                let meta = self.kind.read_raw(&self.read_file(&path)?)?;
+                zettels.insert(id.into(), meta);
+            }
+        }
+        Ok(zettels)
+    }
+
+    /// write the metadata of every zettel that changed into its sidecar and
+    /// remove the sidecars of zettels that are gone
+    fn write_sidecars(&self, zk: &Zettelkasten) -> Result<()> {
+        std::fs::create_dir_all(self.root_dir.join(SIDECAR_DIR))?;
+        for (id, meta) in &zk.zettels {
+            let path = self.sidecar_path(id);
+            let data = self.kind.write(meta)?;
+            if self.read_file(&path).ok().as_ref() != Some(&data) {
+                self.write_file(&path, &data)?;
+            }
+        }
+        let suffix = self.sidecar_suffix();
+        self.remove_sidecars(|name| {
+            name.strip_suffix(&suffix)
+                .is_some_and(|id| !zk.zettels.contains_key(id))
+        })
+    }
+
+    /// lock against other processes writing the database
+    fn lock(&self) -> Result<fsutil::Lock> {
+        Ok(fsutil::Lock::acquire(
+            &self.root_dir.join(".zk").join("db.lock"),
+        )?)
+    }
+
+    /// write every zettel of `zk` and the lookup, with the lock held
+    fn store(&self, zk: &Zettelkasten) -> Result<()> {
+        if zk.meta.storage == Storage::Sidecar {
+            self.write_sidecars(zk)?;
+            // the database file is left with settings only
+            let mut index = serde_yaml::to_value(zk)?;
+            if let Some(root) = index.as_mapping_mut() {
+                root.remove(&"zettels".into());
+            }
+            self.write_file(&self.path(), &self.kind.write(&index)?)?;
+        } else {
+            self.write_file(&self.path(), &self.kind.write(zk)?)?;
+        }
+        if self.encrypted {
+            let lookup = lookup::path(&self.root_dir);
+            if lookup.is_file() {
+                std::fs::remove_file(lookup)?;
+            }
+        } else if let Err(e) = lookup::write(&self.root_dir, &self.path(), zk) {
+            // lookups fall back to reading the database
+            tracing::warn!("couldn't write the id lookup: {}", e);
+        }
+        Ok(())
+    }
+
+    fn remove_sidecars(&self, remove: impl Fn(&str) -> bool) -> Result<()> {
+        for entry in std::fs::read_dir(self.root_dir.join(SIDECAR_DIR))? {
+            let path = entry?.path();
+            if remove(&path.file_name().unwrap().to_string_lossy()) {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl super::Database for Database {
+    fn root_dir(&self) -> &Path {
+        self.root_dir.as_path()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn get_zk(&self) -> Result<Option<Zettelkasten>> {
+        let path = self.path();
+        if path.is_file() {
+            let mut contents = self.kind.read_raw(&self.read_file(&path)?)?;
+            let sidecar = contents
+                .get("meta")
+                .and_then(|meta| meta.get("storage"))
+                .and_then(Value::as_str)
+                == Some("sidecar");
+            if let (true, Some(root)) = (sidecar, contents.as_mapping_mut()) {
+                root.insert("zettels".into(), self.read_sidecars()?.into());
+            }
+            migrate::upgrade(&mut contents)?;
+            Ok(Some(serde_yaml::from_value(contents)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// found with the lookup written on commit when it is up to date, so
+    /// large databases aren't read in full
+    fn get(&self, id: &str) -> Result<Option<ZettelMeta>> {
+        if self.encrypted {
+            // there is no lookup, it would give away the metadata
+        } else if let Some(found) = lookup::get(&self.root_dir, &self.path(), id) {
+            return Ok(found);
+        }
+        Ok(self.get_zk()?.and_then(|mut zk| {
+            let mut meta = zk.zettels.remove(id)?;
+            meta.id = id.to_owned();
+            Some(meta)
+        }))
+    }
+
+    fn key(&self) -> Option<&crypt::Age> {
+        self.key.as_ref().filter(|_| self.encrypted)
+    }
+
+    fn commit(&self, zk: &Zettelkasten) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        let _lock = self.lock()?;
+        let before = match &self.undo_label {
+            Some(_) => super::Database::get_zk(self)?,
+            None => None,
+        };
+        self.store(zk)?;
+        if let Some(label) = &self.undo_label {
+            self.save_undo(label, &before.unwrap_or_default(), zk);
+        }
+        zk.notify(&events::Event::Committed);
+        hooks::run(&self.root_dir, &zk.config.hooks, hooks::Event::Commit, &[]);
+        Ok(())
+    }
+
+    /// with sidecar storage only the sidecar of `id` is written; otherwise
+    /// the stored zettels are read again under the lock and the database
+    /// file is rewritten with just `id` changed
+    fn commit_entry(&self, zk: &Zettelkasten, id: &str) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        let _lock = self.lock()?;
+        if zk.meta.storage == Storage::Sidecar && self.path().is_file() {
+            let path = self.sidecar_path(id);
+            match zk.zettels.get(id) {
+                Some(meta) => {
+                    std::fs::create_dir_all(self.root_dir.join(SIDECAR_DIR))?;
+                    self.write_file(&path, &self.kind.write(meta)?)?;
+                }
+                None if path.is_file() => std::fs::remove_file(path)?,
+                None => {}
+            }
+            // the database file didn't change, so the lookup would still
+            // pass for up to date
+            let lookup = lookup::path(&self.root_dir);
+            if lookup.is_file() {
+                std::fs::remove_file(lookup)?;
+            }
+        } else if let Some(mut stored) = super::Database::get_zk(self)? {
+            match zk.zettels.get(id) {
+                Some(meta) => stored.zettels.insert(id.to_owned(), meta.clone()),
+                None => stored.zettels.remove(id),
+            };
+            self.store(&stored)?;
+        } else {
+            self.store(zk)?;
+        }
+        zk.notify(&events::Event::Committed);
+        hooks::run(&self.root_dir, &zk.config.hooks, hooks::Event::Commit, &[]);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::Database as _;
+    use chrono::prelude::*;
+    use std::fs::File;
+    use tempdir::TempDir;
+
+    #[test]
+    fn init_db() -> Result<()> {
+        let tmp_dir = TempDir::new("zk_yaml_test").expect("couldn't create temp dir");
+        let db = Database::new(PathBuf::from(tmp_dir.path())).expect("could not create db");
+        let zk = Zettelkasten::default();
+        db.commit(&zk)?;
+        let mut db_path = PathBuf::from(tmp_dir.path());
+        db_path.push("_zettel.yaml");
+        let file = File::open(db_path).expect("db file wasn't created");
+        let read_db: Zettelkasten = serde_yaml::from_reader(file)?;
+        assert_eq!(zk, read_db);
+        Ok(())
+    }
+
+    #[test]
+    fn read_only_db() -> Result<()> {
+        let tmp_dir = TempDir::new("zk_yaml_test").expect("couldn't create temp dir");
+        let mut db = Database::new(PathBuf::from(tmp_dir.path()))?;
+        assert!(!db.is_read_only());
+        db.commit(&Zettelkasten::default())?;
+        db.set_read_only();
+        assert!(matches!(
+            db.commit(&Zettelkasten::default()),
+            Err(Error::ReadOnly)
+        ));
+        assert!(db.get_zk()?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn every_kind_round_trips() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        for kind in DatabaseKind::ALL {
+            let tmp_dir = TempDir::new("zk_file_test").expect("couldn't create temp dir");
+            let db = Database::new(PathBuf::from(tmp_dir.path()))?.with_kind(kind);
+            let mut zk = Zettelkasten::default();
+            let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+            let mut meta = crate::ZettelMeta::new("abc", "A title", "a.md", dt);
+            meta.tags = vec!["t".to_owned()];
+            // ids are only stored as keys
+            meta.id.clear();
+            zk.zettels.insert("abc".to_owned(), meta);
+            db.commit(&zk)?;
+            assert_eq!(DatabaseKind::detect(tmp_dir.path()), Some(kind));
+            let db = Database::new(PathBuf::from(tmp_dir.path()))?;
+            assert_eq!(db.get_zk()?, Some(zk));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn sidecar_storage() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new("zk_file_test").expect("couldn't create temp dir");
+        let db = Database::new(PathBuf::from(tmp_dir.path()))?;
+        let mut zk = Zettelkasten::default();
+        zk.meta.storage = Storage::Sidecar;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        for id in ["a", "b"] {
+            let mut meta = crate::ZettelMeta::new("", id, "z.md", dt);
+            meta.id.clear();
+            zk.zettels.insert(id.to_owned(), meta);
+        }
+        db.commit(&zk)?;
+        let sidecars = tmp_dir.path().join(SIDECAR_DIR);
+        assert!(sidecars.join("a.yaml").is_file());
+        assert!(!std::fs::read_to_string(tmp_dir.path().join("_zettel.yaml"))?.contains("zettels"));
+        assert_eq!(db.get_zk()?.as_ref(), Some(&zk));
+        zk.zettels.remove("b");
+        db.commit(&zk)?;
+        assert!(!sidecars.join("b.yaml").exists());
+        let db = db.migrate(DatabaseKind::Json, false)?;
+        assert!(!sidecars.join("a.yaml").exists());
+        assert_eq!(db.get_zk()?, Some(zk));
+        Ok(())
+    }
+
+    #[test]
+    fn commit_single_entries() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        for storage in [Storage::Single, Storage::Sidecar] {
+            let tmp_dir = TempDir::new("zk_file_test").expect("couldn't create temp dir");
+            let db = Database::new(PathBuf::from(tmp_dir.path()))?;
+            let mut zk = Zettelkasten::default();
+            zk.meta.storage = storage;
+            let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+            for id in ["a", "b", "c"] {
+                let mut meta = crate::ZettelMeta::new("", id, "z.md", dt);
+                meta.id.clear();
+                zk.zettels.insert(id.to_owned(), meta);
+            }
+            db.commit(&zk)?;
+            // two processes each change a different zettel
+            let mut first = db.get_zk()?.unwrap();
+            let mut second = first.clone();
+            first.zettels.get_mut("a").unwrap().title = "A".to_owned();
+            second.zettels.get_mut("b").unwrap().title = "B".to_owned();
+            second.zettels.remove("c");
+            first.commit_entry(&db, "a")?;
+            second.commit_entry(&db, "b")?;
+            second.commit_entry(&db, "c")?;
+            let stored = db.get_zk()?.unwrap();
+            assert_eq!(stored.zettels["a"].title, "A");
+            assert_eq!(stored.zettels["b"].title, "B");
+            assert!(!stored.zettels.contains_key("c"));
+            assert_eq!(db.get("a")?.map(|meta| meta.title), Some("A".to_owned()));
+            assert!(!tmp_dir.path().join(".zk").join("db.lock").exists());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_between_kinds() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new("zk_file_test").expect("couldn't create temp dir");
+        let db = Database::new(PathBuf::from(tmp_dir.path()))?;
+        let zk = Zettelkasten::default();
+        db.commit(&zk)?;
+        let db = db.migrate(DatabaseKind::Cbor, false)?;
+        assert_eq!(
+            DatabaseKind::detect(tmp_dir.path()),
+            Some(DatabaseKind::Cbor)
+        );
+        assert!(!tmp_dir.path().join("_zettel.yaml").exists());
+        assert_eq!(db.get_zk()?, Some(zk));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn encrypted_database() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new("zk_file_test").expect("couldn't create temp dir");
+        let root_dir = tmp_dir.path().join("kasten");
+        std::fs::create_dir(&root_dir)?;
+        let mut key = crypt::fake_age(tmp_dir.path())?;
+        key.identity = Some(tmp_dir.path().join("key.txt"));
+        let db = Database::new(root_dir.clone())?.with_key(key.clone());
+        let mut zk = Zettelkasten::default();
+        zk.meta.storage = Storage::Sidecar;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut meta = crate::ZettelMeta::new("", "Secret title", "z.md", dt);
+        meta.id.clear();
+        zk.zettels.insert("a".to_owned(), meta);
+        db.commit(&zk)?;
+        let db = db.migrate(DatabaseKind::Yaml, true)?;
+        assert!(!root_dir.join("_zettel.yaml").exists());
+        assert!(!lookup::path(&root_dir).exists());
+        let sidecar = root_dir.join(SIDECAR_DIR).join("a.yaml.age");
+        assert!(!std::fs::read_to_string(sidecar)?.contains("Secret title"));
+        assert_eq!(
+            db.get("a")?.map(|meta| meta.title).as_deref(),
+            Some("Secret title")
+        );
+        let locked = Database::new(root_dir.clone())?;
+        assert!(locked.is_encrypted());
+        assert!(matches!(locked.get_zk(), Err(Error::Locked)));
+        let db = Database::new(root_dir.clone())?.with_key(key);
+        assert_eq!(db.get_zk()?.as_ref(), Some(&zk));
+        let db = db.migrate(DatabaseKind::Yaml, false)?;
+        assert!(!db.is_encrypted());
+        assert!(root_dir.join(SIDECAR_DIR).join("a.yaml").is_file());
+        assert!(!root_dir.join("_zettel.yaml.age").exists());
+        assert_eq!(db.get_zk()?, Some(zk));
+        Ok(())
+    }
+
+    #[test]
+    fn new_zettel() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new("zk_yaml_test").expect("couldn't create temp dir");
+        let root_dir = PathBuf::from(tmp_dir.path());
+        let db = Database::new(root_dir.clone())?;
+        let mut zk = Zettelkasten::default();
+        let id = "123456";
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let title = "a new blog post";
+        let zettel = db.new_zettel(&Default::default(), title, id, dt)?;
+        zk.add(db.root_dir(), &zettel)?;
+        let zettel_path = zettel.meta.full_path(&root_dir);
+        assert!(zettel_path.exists(), "new zettel was not created on fs");
+        let data = std::fs::read_to_string(&zettel_path)?;
+        let (fm, _) = {
+            use extract_frontmatter::{config::Splitter, Extractor};
+            let fm_extractor = Extractor::new(Splitter::EnclosingLines("---"));
+            fm_extractor.extract(&data)
+        };
+        let meta: serde_yaml::Mapping = serde_yaml::from_str(&fm)?;
+        assert_eq!(
+            id,
+            meta.get(&"id".into())
+                .expect("frontmatter should containt field 'id'"),
+            "id in frontmatter does not match"
+        );
+        assert_eq!(
+            "2015-05-14",
+            meta.get(&"date".into())
+                .expect("frontmatter should containt field 'date'"),
+            "date in frontmatter does not match"
+        );
+        assert_eq!(
+            title,
+            meta.get(&"title".into())
+                .expect("frontmatter should containt field 'title'"),
+            "title in frontmatter does not match"
+        );
+        db.commit(&zk)?;
+        let new_zk = db.get_zk()?.unwrap();
+        assert!(
+            new_zk.zettels.len() == 1,
+            "new zettel should be reflected in db"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn new_zettel_of_kind() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new("zk_yaml_test").expect("couldn't create temp dir");
+        let db = Database::new(tmp_dir.path().to_path_buf())?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let config = Default::default();
+        let mut kind = crate::config::Kind {
+            dir: Some("journal/daily".into()),
+            ..Default::default()
+        };
+        let zettel = db.new_zettel_of_kind(&config, &kind, "today", "1", dt)?;
+        assert!(zettel.meta.path.starts_with("journal/daily/"));
+        for dir in ["../outside", "/tmp", "journal/../..", "a\\b"] {
+            kind.dir = Some(dir.into());
+            assert!(matches!(
+                db.new_zettel_of_kind(&config, &kind, "today", "1", dt),
+                Err(Error::UnsafeKindDir(_))
+            ));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn lookup_by_id() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new("zk_lookup_test")?;
+        let db = Database::new(PathBuf::from(tmp_dir.path()))?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for id in ["a", "b", "bb", "c", "d"] {
+            let meta = ZettelMeta::new(id, &format!("title {}", id), &format!("{}.md", id), dt);
+            zk.zettels.insert(id.to_owned(), meta);
+        }
+        db.commit(&zk)?;
+        for id in ["a", "b", "bb", "c", "d"] {
+            let found = lookup::get(&db.root_dir, &db.path(), id).unwrap().unwrap();
+            assert_eq!(found.id, id);
+            assert_eq!(found.title, format!("title {}", id));
+        }
+        assert_eq!(lookup::get(&db.root_dir, &db.path(), "ba"), Some(None));
+        assert_eq!(
+            db.path_to("c")?,
+            Some(tmp_dir.path().canonicalize()?.join("c.md"))
+        );
+        // a database changed behind zk's back is read instead
+        zk.zettels.get_mut("c").unwrap().title = "changed".to_owned();
+        std::fs::write(db.path(), serde_yaml::to_vec(&zk)?)?;
+        assert_eq!(lookup::get(&db.root_dir, &db.path(), "c"), None);
+        assert_eq!(db.get("c")?.unwrap().title, "changed");
+        Ok(())
+    }
+
+    /// run with `cargo test --release -- --ignored large_kasten`
+    #[test]
+    #[ignore]
+    fn large_kasten() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = TempDir::new("zk_large_test")?;
+        let db = Database::new(PathBuf::from(tmp_dir.path()))?.with_kind(DatabaseKind::Cbor);
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for n in 0..100_000 {
+            let id = format!("{:018}", n * 7919 % 100_000);
+            let meta = ZettelMeta::new(&id, "a note", &format!("{}.md", id), dt);
+            zk.zettels.insert(id, meta);
+        }
+        db.commit(&zk)?;
+        let started = std::time::Instant::now();
+        for n in (0..100_000).step_by(9973) {
+            let id = format!("{:018}", n);
+            assert_eq!(db.get(&id)?.unwrap().id, id);
+        }
+        let each = started.elapsed() / 11;
+        assert!(each < std::time::Duration::from_millis(50), "{:?}", each);
+        Ok(())
+    }
+}
@@ -0,0 +1,103 @@
+//! Metadata of every zettel in a file sorted by id, so a single zettel can
+//! be found by binary search without reading the whole database
+//!
+//! each line holds an id and the metadata as JSON, separated by a tab. The
+//! first line records the size and modification time of the database file
+//! the lookup was written for; if the database changed since, the lookup
+//! isn't used.
+
+use crate::{zettelkasten::Zettelkasten, ZettelMeta};
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{BufRead, BufReader, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// version of the lookup layout
+const VERSION: u32 = 1;
+
+/// path of the lookup under the root directory
+pub fn path(root_dir: &Path) -> PathBuf {
+    root_dir.join(".zk").join("index").join("lookup")
+}
+
+/// first line of a lookup for the database file at `db_path`
+fn stamp(db_path: &Path) -> std::io::Result<String> {
+    let meta = std::fs::metadata(db_path)?;
+    let modified = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    Ok(format!("zk-lookup {} {} {}", VERSION, meta.len(), modified))
+}
+
+/// write the lookup for `zk`, just committed to the database file at
+/// `db_path`
+pub fn write(root_dir: &Path, db_path: &Path, zk: &Zettelkasten) -> std::io::Result<()> {
+    let path = path(root_dir);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    let mut ids: Vec<&String> = zk.zettels.keys().collect();
+    ids.sort();
+    let mut out = std::io::BufWriter::new(File::create(&path)?);
+    writeln!(out, "{}", stamp(db_path)?)?;
+    for id in ids {
+        // ids with tabs or newlines can't be looked up; they are found
+        // through the database instead
+        if id.contains(['\t', '\n']) {
+            continue;
+        }
+        let meta = serde_json::to_string(&zk.zettels[id])?;
+        writeln!(out, "{}\t{}", id, meta)?;
+    }
+    out.flush()
+}
+
+/// metadata of zettel `id`, `None` if the lookup is missing, unreadable or
+/// older than the database file at `db_path`, `Some(None)` if it has no
+/// such zettel
+pub fn get(root_dir: &Path, db_path: &Path, id: &str) -> Option<Option<ZettelMeta>> {
+    let file = File::open(path(root_dir)).ok()?;
+    let len = file.metadata().ok()?.len();
+    let mut reader = BufReader::new(file);
+    let mut header = String::new();
+    reader.read_line(&mut header).ok()?;
+    if header.trim_end() != stamp(db_path).ok()? {
+        return None;
+    }
+    // `lo` is always the start of a line
+    let (mut lo, mut hi) = (header.len() as u64, len);
+    let mut line = String::new();
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        let start = if mid == lo {
+            reader.seek(SeekFrom::Start(lo)).ok()?;
+            lo
+        } else {
+            // skip to the first line starting at or after `mid`
+            reader.seek(SeekFrom::Start(mid - 1)).ok()?;
+            let mut skipped = Vec::new();
+            reader.read_until(b'\n', &mut skipped).ok()?;
+            mid - 1 + skipped.len() as u64
+        };
+        if start >= hi {
+            hi = mid;
+            continue;
+        }
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+        let (key, meta) = line.trim_end_matches('\n').split_once('\t')?;
+        match key.cmp(id) {
+            Ordering::Equal => {
+                let mut meta: ZettelMeta = serde_json::from_str(meta).ok()?;
+                meta.id = id.to_owned();
+                return Some(Some(meta));
+            }
+            Ordering::Less => lo = start + line.len() as u64,
+            Ordering::Greater => hi = mid,
+        }
+    }
+    Some(None)
+}
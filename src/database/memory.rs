@@ -0,0 +1,73 @@
+use super::Result;
+use crate::{events, zettelkasten::Zettelkasten};
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+};
+
+/// Database keeping the metadata in memory, for tests and embedding
+///
+/// nothing is read from or written to the filesystem; `root_dir` is only
+/// used to place new zettels
+#[derive(Debug, Default)]
+pub struct Database {
+    root_dir: PathBuf,
+    zk: RefCell<Option<Zettelkasten>>,
+}
+
+impl Database {
+    pub fn new(root_dir: PathBuf) -> Self {
+        Self {
+            root_dir,
+            zk: RefCell::new(None),
+        }
+    }
+}
+
+impl super::Database for Database {
+    fn root_dir(&self) -> &Path {
+        self.root_dir.as_path()
+    }
+
+    fn get_zk(&self) -> Result<Option<Zettelkasten>> {
+        Ok(self.zk.borrow().clone())
+    }
+
+    fn commit(&self, zk: &Zettelkasten) -> Result<()> {
+        *self.zk.borrow_mut() = Some(zk.clone());
+        zk.notify(&events::Event::Committed);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::Database as _;
+
+    #[test]
+    fn keeps_a_copy_of_each_commit() -> Result<()> {
+        let tmp_dir = tempdir::TempDir::new("zk_memory_test").expect("couldn't create temp dir");
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        assert_eq!(db.root_dir(), tmp_dir.path());
+        assert_eq!(db.get_zk()?, None);
+        let mut zk = Zettelkasten::default();
+        let events = zk.subscribe();
+        let zettel = db.new_zettel(&zk.config, "One", "1", crate::testutil::date())?;
+        assert!(zettel
+            .meta
+            .full_path(db.root_dir())
+            .starts_with(tmp_dir.path()));
+        zk.zettels.insert("1".to_owned(), zettel.meta);
+        db.commit(&zk)?;
+        assert_eq!(events.try_recv(), Ok(events::Event::Committed));
+        zk.zettels.clear();
+        let committed = db.get_zk()?.unwrap();
+        assert_eq!(committed.zettels["1"].title, "One");
+        db.commit(&zk)?;
+        assert!(db.get_zk()?.unwrap().zettels.is_empty());
+        // nothing is written to the root directory
+        assert!(std::fs::read_dir(tmp_dir.path())?.next().is_none());
+        Ok(())
+    }
+}
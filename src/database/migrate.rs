@@ -0,0 +1,122 @@
+use super::{Error, Result};
+use crate::dates;
+use serde_yaml::{Mapping, Value};
+
+/// steps upgrading the raw contents of a database, the one at index `n`
+/// going from version `n` to `n + 1`
+///
+/// a step is needed whenever a change to the stored types can't be read
+/// from older files through serde defaults alone
+const MIGRATIONS: &[fn(&mut Mapping)] = &[
+    // version 0 predates versioning; every field added since has a default
+    |_| {},
+    timestamps_to_utc,
+];
+
+/// version 2 stores timestamps in UTC rather than the offset of whichever
+/// machine last wrote them
+///
+/// sidecar files are left as they are; their timestamps still read the same
+/// and are rewritten in UTC with the next change to their zettel
+fn timestamps_to_utc(root: &mut Mapping) {
+    fn convert(map: &mut Mapping, keys: &[&str]) {
+        for key in keys {
+            let key = Value::from(*key);
+            if let Some(utc) = map
+                .get(&key)
+                .and_then(Value::as_str)
+                .and_then(dates::to_utc)
+            {
+                map.insert(key, utc.into());
+            }
+        }
+    }
+    if let Some(meta) = root.get_mut(&"meta".into()).and_then(Value::as_mapping_mut) {
+        convert(meta, &["created", "modified"]);
+    }
+    let zettels = root
+        .get_mut(&"zettels".into())
+        .and_then(Value::as_mapping_mut);
+    for (_, meta) in zettels.into_iter().flat_map(|z| z.iter_mut()) {
+        if let Some(meta) = meta.as_mapping_mut() {
+            convert(meta, &["created", "modified"]);
+            if let Some(review) = meta
+                .get_mut(&"review".into())
+                .and_then(Value::as_mapping_mut)
+            {
+                convert(review, &["last_reviewed"]);
+            }
+        }
+    }
+}
+
+/// version written by this build of zk
+pub const CURRENT_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// version of the raw contents of a database, 0 if it has none
+pub fn version(contents: &Value) -> u32 {
+    contents
+        .get("meta")
+        .and_then(|meta| meta.get("version"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0) as u32
+}
+
+/// bring raw database contents up to [`CURRENT_VERSION`], returning the
+/// version they had
+pub fn upgrade(contents: &mut Value) -> Result<u32> {
+    let version = version(contents);
+    if version > CURRENT_VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    let root = match contents.as_mapping_mut() {
+        Some(root) => root,
+        None => return Ok(version),
+    };
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(root);
+    }
+    if let Some(meta) = root.get_mut(&"meta".into()).and_then(Value::as_mapping_mut) {
+        meta.insert("version".into(), CURRENT_VERSION.into());
+    }
+    Ok(version)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn upgrade_unversioned_contents() -> Result<()> {
+        let mut contents: Value = serde_yaml::from_str("meta: {created: x}\nzettels: {}")?;
+        assert_eq!(version(&contents), 0);
+        assert_eq!(upgrade(&mut contents)?, 0);
+        assert_eq!(version(&contents), CURRENT_VERSION);
+        let mut newer: Value = serde_yaml::from_str("meta: {version: 999}")?;
+        assert!(matches!(
+            upgrade(&mut newer),
+            Err(Error::UnsupportedVersion(999))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn timestamps_move_to_utc() -> Result<()> {
+        let mut contents: Value = serde_yaml::from_str(
+            "meta: {version: 0, created: '2015-05-14T12:00:00+02:00'}\n\
+             zettels: {a: {created: '2015-05-14T12:00:00-05:00', review: {last_reviewed: '2015-05-15T01:00:00+01:00'}}}",
+        )?;
+        upgrade(&mut contents)?;
+        assert_eq!(
+            contents["meta"]["created"],
+            Value::from("2015-05-14T10:00:00Z")
+        );
+        let a = &contents["zettels"]["a"];
+        assert_eq!(a["created"], Value::from("2015-05-14T17:00:00Z"));
+        assert_eq!(
+            a["review"]["last_reviewed"],
+            Value::from("2015-05-15T00:00:00Z")
+        );
+        Ok(())
+    }
+}
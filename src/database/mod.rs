@@ -1 +1,267 @@
-pub mod yaml;
+pub mod file;
+pub mod lookup;
+pub mod memory;
+pub mod migrate;
+
+use crate::{
+    config::{Config, Kind},
+    crypt, events, fsutil,
+    zettel::{Zettel, ZettelMeta},
+    zettelkasten::Zettelkasten,
+    DateTime,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    SerializationError(serde_yaml::Error),
+    JsonSerializationError(serde_json::Error),
+    TomlSerializationError(toml::ser::Error),
+    TomlDeserializationError(toml::de::Error),
+    CborSerializationError(ciborium::ser::Error<std::io::Error>),
+    CborDeserializationError(ciborium::de::Error<std::io::Error>),
+    /// a database written by a newer version of zk
+    UnsupportedVersion(u32),
+    /// converting to another format would lose data
+    LossyMigration(file::DatabaseKind),
+    /// a commit to a database opened read-only
+    ReadOnly,
+    CryptError(crypt::Error),
+    /// an encrypted database opened without a key
+    Locked,
+    /// a path that can't be stored with `/` separators
+    NotUtf8(PathBuf),
+    /// a kind directory leading outside the root directory
+    UnsafeKindDir(PathBuf),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::SerializationError(e) => e.fmt(f),
+            Self::JsonSerializationError(e) => e.fmt(f),
+            Self::TomlSerializationError(e) => e.fmt(f),
+            Self::TomlDeserializationError(e) => e.fmt(f),
+            Self::CborSerializationError(e) => e.fmt(f),
+            Self::CborDeserializationError(e) => e.fmt(f),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "database version {} is newer than this zk supports ({})",
+                version,
+                migrate::CURRENT_VERSION
+            ),
+            Self::LossyMigration(kind) => {
+                write!(f, "database doesn't read back unchanged as {:?}", kind)
+            }
+            Self::ReadOnly => f.write_str("database is read-only"),
+            Self::CryptError(e) => e.fmt(f),
+            Self::Locked => write!(
+                f,
+                "database is encrypted; set {} to an age identity to open it",
+                file::KEY_FILE_VAR
+            ),
+            Self::NotUtf8(path) => write!(f, "{} is not UTF-8", path.display()),
+            Self::UnsafeKindDir(dir) => {
+                write!(
+                    f,
+                    "kind directory {} leads outside the kasten",
+                    dir.display()
+                )
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(e: serde_yaml::Error) -> Self {
+        Self::SerializationError(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::JsonSerializationError(e)
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(e: toml::ser::Error) -> Self {
+        Self::TomlSerializationError(e)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Self::TomlDeserializationError(e)
+    }
+}
+
+impl From<ciborium::ser::Error<std::io::Error>> for Error {
+    fn from(e: ciborium::ser::Error<std::io::Error>) -> Self {
+        Self::CborSerializationError(e)
+    }
+}
+
+impl From<ciborium::de::Error<std::io::Error>> for Error {
+    fn from(e: ciborium::de::Error<std::io::Error>) -> Self {
+        Self::CborDeserializationError(e)
+    }
+}
+
+impl From<crypt::Error> for Error {
+    fn from(e: crypt::Error) -> Self {
+        Self::CryptError(e)
+    }
+}
+type Result<T> = std::result::Result<T, Error>;
+
+/// Storage for the metadata of a zettelkasten whose zettels live under
+/// `root_dir`
+pub trait Database {
+    fn root_dir(&self) -> &Path;
+
+    /// whether commits are refused
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    /// `None` if the zettelkasten wasn't initialized yet
+    fn get_zk(&self) -> Result<Option<Zettelkasten>>;
+
+    fn commit(&self, zk: &Zettelkasten) -> Result<()>;
+
+    /// store the metadata of zettel `id` from `zk`, or drop it when `zk`
+    /// has no such zettel, keeping every other zettel as it is stored
+    ///
+    /// merges into what was stored unless the backend can do better, so
+    /// zettels committed meanwhile by someone else aren't lost
+    fn commit_entry(&self, zk: &Zettelkasten, id: &str) -> Result<()> {
+        let mut stored = match self.get_zk()? {
+            Some(stored) => stored,
+            None => return self.commit(zk),
+        };
+        match zk.zettels.get(id) {
+            Some(meta) => stored.zettels.insert(id.to_owned(), meta.clone()),
+            None => stored.zettels.remove(id),
+        };
+        self.commit(&stored)?;
+        // copies don't carry the subscribers along
+        zk.notify(&events::Event::Committed);
+        Ok(())
+    }
+
+    /// metadata of zettel `id`, `None` if there is no such zettel or the
+    /// zettelkasten wasn't initialized
+    ///
+    /// reads the whole database unless the backend can do better
+    fn get(&self, id: &str) -> Result<Option<ZettelMeta>> {
+        Ok(self.get_zk()?.and_then(|mut zk| {
+            let mut meta = zk.zettels.remove(id)?;
+            meta.id = id.to_owned();
+            Some(meta)
+        }))
+    }
+
+    /// file of zettel `id`
+    fn path_to(&self, id: &str) -> Result<Option<PathBuf>> {
+        Ok(self.get(id)?.map(|meta| meta.full_path(self.root_dir())))
+    }
+
+    /// key the database is encrypted with, which files kept beside it like
+    /// the search index are encrypted with too; `None` if it isn't
+    fn key(&self) -> Option<&crypt::Age> {
+        None
+    }
+
+    /// zettel that will be stored under the root directory, named with the
+    /// file name template of `config`; its path is relative to the root
+    fn new_zettel(
+        &self,
+        config: &Config,
+        title: impl AsRef<str>,
+        id: impl AsRef<str>,
+        date: DateTime,
+    ) -> Result<Zettel>
+    where
+        Self: Sized,
+    {
+        let path = config.file_name(title.as_ref(), id.as_ref(), date);
+        let meta = ZettelMeta::new(id.as_ref(), title.as_ref(), &path, date);
+        Ok(Zettel {
+            meta,
+            content: String::new(),
+            extra_frontmatter: HashMap::new(),
+        })
+    }
+
+    /// zettel of `kind`, put in the kind's directory with the kind's
+    /// frontmatter on top of the defaults
+    fn new_zettel_of_kind(
+        &self,
+        config: &Config,
+        kind: &Kind,
+        title: impl AsRef<str>,
+        id: impl AsRef<str>,
+        date: DateTime,
+    ) -> Result<Zettel>
+    where
+        Self: Sized,
+    {
+        let mut zettel = self.new_zettel(config, title.as_ref(), id.as_ref(), date)?;
+        if let Some(dir) = &kind.dir {
+            let contained = fsutil::to_slash(dir).and_then(|dir| fsutil::contained(&dir));
+            let dir = contained.ok_or_else(|| Error::UnsafeKindDir(dir.clone()))?;
+            let path = dir.join(&zettel.meta.path);
+            zettel.meta.path = fsutil::to_slash(&path).ok_or(Error::NotUtf8(path))?;
+        }
+        zettel.extra_frontmatter.extend(kind.frontmatter.clone());
+        Ok(zettel)
+    }
+}
+
+impl<T: Database> Database for &T {
+    fn root_dir(&self) -> &Path {
+        (*self).root_dir()
+    }
+
+    fn is_read_only(&self) -> bool {
+        (*self).is_read_only()
+    }
+
+    fn get_zk(&self) -> Result<Option<Zettelkasten>> {
+        (*self).get_zk()
+    }
+
+    fn commit(&self, zk: &Zettelkasten) -> Result<()> {
+        (*self).commit(zk)
+    }
+
+    fn commit_entry(&self, zk: &Zettelkasten, id: &str) -> Result<()> {
+        (*self).commit_entry(zk, id)
+    }
+
+    fn get(&self, id: &str) -> Result<Option<ZettelMeta>> {
+        (*self).get(id)
+    }
+
+    fn path_to(&self, id: &str) -> Result<Option<PathBuf>> {
+        (*self).path_to(id)
+    }
+
+    fn key(&self) -> Option<&crypt::Age> {
+        (*self).key()
+    }
+}
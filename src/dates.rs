@@ -0,0 +1,523 @@
+use crate::DateTime;
+use chrono::{Datelike, Duration, FixedOffset, NaiveDate, Offset};
+use serde::{Deserialize, Serialize};
+
+/// Days from `start` to `end`, both included
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl DateRange {
+    fn day(date: NaiveDate) -> Self {
+        Self {
+            start: date,
+            end: date,
+        }
+    }
+
+    fn month(year: i32, month: u32) -> Option<Self> {
+        let start = NaiveDate::from_ymd_opt(year, month, 1)?;
+        Some(Self {
+            start,
+            end: add_months(start, 1).pred(),
+        })
+    }
+
+    fn months(year: i32, first: u32, count: i32) -> Option<Self> {
+        let start = NaiveDate::from_ymd_opt(year, first, 1)?;
+        Some(Self {
+            start,
+            end: add_months(start, count).pred(),
+        })
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.start <= date && date <= self.end
+    }
+}
+
+/// parse an absolute or relative date into the days it covers
+///
+/// - `2023-05-14`, `2023-05`, `2023` and `2023-Q2`
+/// - `today`, `yesterday`
+/// - `this week`, `last week` and likewise for `month`, `quarter` and `year`,
+///   also written with a `-` instead of a space; weeks start on Monday
+/// - `-7d`, `-2w`, `-3m` and `-1y` for the day that long before today
+pub fn parse_range(s: &str, today: NaiveDate) -> Option<DateRange> {
+    let s = s.trim().to_lowercase().replace('-', " ");
+    let words: Vec<&str> = s.split_whitespace().collect();
+    match words.as_slice() {
+        ["today"] => return Some(DateRange::day(today)),
+        ["yesterday"] => return Some(DateRange::day(today.pred())),
+        [which @ ("this" | "last"), unit] => {
+            let back = if *which == "last" { 1 } else { 0 };
+            return match *unit {
+                "week" => {
+                    let monday =
+                        today - Duration::days(today.weekday().num_days_from_monday() as i64);
+                    let start = monday - Duration::weeks(back);
+                    Some(DateRange {
+                        start,
+                        end: start + Duration::days(6),
+                    })
+                }
+                "month" => {
+                    let start = add_months(today.with_day(1)?, -back as i32);
+                    DateRange::month(start.year(), start.month())
+                }
+                "quarter" => {
+                    let first = today
+                        .with_day(1)?
+                        .with_month((today.month0() / 3) * 3 + 1)?;
+                    let start = add_months(first, -3 * back as i32);
+                    DateRange::months(start.year(), start.month(), 3)
+                }
+                "year" => DateRange::months(today.year() - back as i32, 1, 12),
+                _ => None,
+            };
+        }
+        _ => (),
+    }
+    if let Some(rest) = s.strip_prefix(' ') {
+        // `-7d` became ` 7d`
+        let (n, unit) = rest.split_at(rest.len().checked_sub(1)?);
+        let n: i64 = n.parse().ok()?;
+        let date = match unit {
+            "d" => today - Duration::days(n),
+            "w" => today - Duration::weeks(n),
+            "m" => add_months(today, -(n as i32)),
+            "y" => add_months(today, -12 * n as i32),
+            _ => return None,
+        };
+        return Some(DateRange::day(date));
+    }
+    match words.as_slice() {
+        [year] => DateRange::months(year.parse().ok()?, 1, 12),
+        [year, quarter] if quarter.starts_with('q') => {
+            let q: u32 = quarter[1..].parse().ok().filter(|q| (1..=4).contains(q))?;
+            DateRange::months(year.parse().ok()?, (q - 1) * 3 + 1, 3)
+        }
+        [year, month] => DateRange::month(year.parse().ok()?, month.parse().ok()?),
+        [year, month, day] => Some(DateRange::day(NaiveDate::from_ymd_opt(
+            year.parse().ok()?,
+            month.parse().ok()?,
+            day.parse().ok()?,
+        )?)),
+        _ => None,
+    }
+}
+
+/// `date` moved by whole months, keeping the day where the month has one
+pub fn add_months(date: NaiveDate, months: i32) -> NaiveDate {
+    let total = date.year() * 12 + date.month0() as i32 + months;
+    let (year, month) = (total.div_euclid(12), total.rem_euclid(12) as u32 + 1);
+    let mut day = date.day();
+    loop {
+        if let Some(d) = NaiveDate::from_ymd_opt(year, month, day) {
+            return d;
+        }
+        day -= 1;
+    }
+}
+
+/// Timezone timestamps are shown and written to frontmatter in; the
+/// database always stores UTC
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Zone {
+    /// the timezone of the machine zk runs on
+    #[default]
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl Zone {
+    /// `dt` as seen in this timezone
+    pub fn show(self, dt: DateTime) -> chrono::DateTime<FixedOffset> {
+        match self {
+            Self::Local => dt.with_timezone(&dt.offset().fix()),
+            Self::Fixed(offset) => dt.with_timezone(&offset),
+        }
+    }
+}
+
+impl std::str::FromStr for Zone {
+    type Err = String;
+
+    /// `local`, `utc` or an offset like `+02:00`
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("invalid timezone {}; use local, utc or +HH:MM", s);
+        match s.to_lowercase().as_str() {
+            "local" => return Ok(Self::Local),
+            "utc" | "z" => return Ok(Self::Fixed(FixedOffset::east(0))),
+            _ => {}
+        }
+        let (sign, rest) = match s.split_at_checked(1) {
+            Some(("+", rest)) => (1, rest),
+            Some(("-", rest)) => (-1, rest),
+            _ => return Err(invalid()),
+        };
+        let (hours, minutes) = rest.split_once(':').ok_or_else(invalid)?;
+        let hours: i32 = hours.parse().map_err(|_| invalid())?;
+        let minutes: i32 = minutes.parse().map_err(|_| invalid())?;
+        if hours > 23 || minutes > 59 {
+            return Err(invalid());
+        }
+        Ok(Self::Fixed(FixedOffset::east(
+            sign * (hours * 3600 + minutes * 60),
+        )))
+    }
+}
+
+impl TryFrom<String> for Zone {
+    type Error = String;
+
+    fn try_from(s: String) -> std::result::Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Zone> for String {
+    fn from(zone: Zone) -> Self {
+        match zone {
+            Zone::Local => "local".to_owned(),
+            Zone::Fixed(offset) if offset.local_minus_utc() == 0 => "utc".to_owned(),
+            Zone::Fixed(offset) => offset.to_string(),
+        }
+    }
+}
+
+/// `s`, an RFC 3339 timestamp with any offset, rewritten in UTC
+pub fn to_utc(s: &str) -> Option<String> {
+    let dt = chrono::DateTime::parse_from_rfc3339(s).ok()?;
+    Some(
+        dt.with_timezone(&chrono::Utc)
+            .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+    )
+}
+
+/// Language of the month and weekday names written by [`format`]
+#[derive(Debug, Default, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+    Es,
+    Fr,
+    It,
+    Nl,
+}
+
+impl Locale {
+    fn months(self) -> [&'static str; 12] {
+        match self {
+            Self::En => [
+                "January",
+                "February",
+                "March",
+                "April",
+                "May",
+                "June",
+                "July",
+                "August",
+                "September",
+                "October",
+                "November",
+                "December",
+            ],
+            Self::De => [
+                "Januar",
+                "Februar",
+                "März",
+                "April",
+                "Mai",
+                "Juni",
+                "Juli",
+                "August",
+                "September",
+                "Oktober",
+                "November",
+                "Dezember",
+            ],
+            Self::Es => [
+                "enero",
+                "febrero",
+                "marzo",
+                "abril",
+                "mayo",
+                "junio",
+                "julio",
+                "agosto",
+                "septiembre",
+                "octubre",
+                "noviembre",
+                "diciembre",
+            ],
+            Self::Fr => [
+                "janvier",
+                "février",
+                "mars",
+                "avril",
+                "mai",
+                "juin",
+                "juillet",
+                "août",
+                "septembre",
+                "octobre",
+                "novembre",
+                "décembre",
+            ],
+            Self::It => [
+                "gennaio",
+                "febbraio",
+                "marzo",
+                "aprile",
+                "maggio",
+                "giugno",
+                "luglio",
+                "agosto",
+                "settembre",
+                "ottobre",
+                "novembre",
+                "dicembre",
+            ],
+            Self::Nl => [
+                "januari",
+                "februari",
+                "maart",
+                "april",
+                "mei",
+                "juni",
+                "juli",
+                "augustus",
+                "september",
+                "oktober",
+                "november",
+                "december",
+            ],
+        }
+    }
+
+    /// from Monday
+    fn weekdays(self) -> [&'static str; 7] {
+        match self {
+            Self::En => [
+                "Monday",
+                "Tuesday",
+                "Wednesday",
+                "Thursday",
+                "Friday",
+                "Saturday",
+                "Sunday",
+            ],
+            Self::De => [
+                "Montag",
+                "Dienstag",
+                "Mittwoch",
+                "Donnerstag",
+                "Freitag",
+                "Samstag",
+                "Sonntag",
+            ],
+            Self::Es => [
+                "lunes",
+                "martes",
+                "miércoles",
+                "jueves",
+                "viernes",
+                "sábado",
+                "domingo",
+            ],
+            Self::Fr => [
+                "lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche",
+            ],
+            Self::It => [
+                "lunedì",
+                "martedì",
+                "mercoledì",
+                "giovedì",
+                "venerdì",
+                "sabato",
+                "domenica",
+            ],
+            Self::Nl => [
+                "maandag",
+                "dinsdag",
+                "woensdag",
+                "donderdag",
+                "vrijdag",
+                "zaterdag",
+                "zondag",
+            ],
+        }
+    }
+}
+
+/// `dt` in the strftime `format`, with month and weekday names in `locale`;
+/// `None` if the format is invalid
+///
+/// `%B` and `%A` are full names, `%b`, `%h` and `%a` their first three
+/// letters.
+pub fn format(dt: chrono::DateTime<FixedOffset>, format: &str, locale: Locale) -> Option<String> {
+    use chrono::format::{Item, StrftimeItems};
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return None;
+    }
+    let month = locale.months()[dt.month0() as usize];
+    let weekday = locale.weekdays()[dt.weekday().num_days_from_monday() as usize];
+    let short = |name: &str| name.chars().take(3).collect::<String>();
+    // names go into the format as literal text for chrono to copy
+    let mut localized = String::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            localized.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('B') => localized.push_str(month),
+            Some('b' | 'h') => localized.push_str(&short(month)),
+            Some('A') => localized.push_str(weekday),
+            Some('a') => localized.push_str(&short(weekday)),
+            Some(c) => {
+                localized.push('%');
+                localized.push(c);
+            }
+            None => localized.push('%'),
+        }
+    }
+    Some(dt.format(&localized).to_string())
+}
+
+/// `template` with `{{date}}`, `{{date:FORMAT}}` and `{{time}}` filled in
+/// with `now` in `zone`
+///
+/// `FORMAT` is a strftime format, see [`format`]; `{{date}}` is `%Y-%m-%d`
+/// and `{{time}}` is `%H:%M`. Other variables and invalid formats are left
+/// as they are.
+pub fn expand(template: &str, now: DateTime, zone: Zone, locale: Locale) -> String {
+    let re = regex::Regex::new(r"\{\{\s*(date|time)(?::([^}]*))?\s*\}\}").unwrap();
+    let now = zone.show(now);
+    re.replace_all(template, |caps: &regex::Captures| {
+        let spec = match (&caps[1], caps.get(2)) {
+            (_, Some(spec)) => spec.as_str(),
+            ("date", None) => "%Y-%m-%d",
+            _ => "%H:%M",
+        };
+        format(now, spec, locale).unwrap_or_else(|| caps[0].to_owned())
+    })
+    .into_owned()
+}
+
+/// serde helpers storing timestamps as RFC 3339 in UTC, for use with
+/// `#[serde(with = "dates::utc")]`; timestamps with other offsets are still
+/// read
+pub mod utc {
+    use crate::DateTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(dt: &DateTime, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(
+            &dt.with_timezone(&chrono::Utc)
+                .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<DateTime, D::Error> {
+        DateTime::deserialize(d)
+    }
+}
+
+/// [`utc`] for optional timestamps
+pub mod utc_option {
+    use crate::DateTime;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(dt: &Option<DateTime>, s: S) -> Result<S::Ok, S::Error> {
+        match dt {
+            Some(dt) => super::utc::serialize(dt, s),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<DateTime>, D::Error> {
+        Option::<DateTime>::deserialize(d)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn relative_dates() {
+        let today = NaiveDate::from_ymd(2023, 5, 17); // a Wednesday
+        let range =
+            |s: &str| parse_range(s, today).map(|r| (r.start.to_string(), r.end.to_string()));
+        let r = |a: &str, b: &str| Some((a.to_owned(), b.to_owned()));
+        assert_eq!(range("2023-05-14"), r("2023-05-14", "2023-05-14"));
+        assert_eq!(range("2023-02"), r("2023-02-01", "2023-02-28"));
+        assert_eq!(range("2023"), r("2023-01-01", "2023-12-31"));
+        assert_eq!(range("2023-Q2"), r("2023-04-01", "2023-06-30"));
+        assert_eq!(range("yesterday"), r("2023-05-16", "2023-05-16"));
+        assert_eq!(range("-7d"), r("2023-05-10", "2023-05-10"));
+        assert_eq!(range("-3m"), r("2023-02-17", "2023-02-17"));
+        assert_eq!(range("last week"), r("2023-05-08", "2023-05-14"));
+        assert_eq!(range("this-week"), r("2023-05-15", "2023-05-21"));
+        assert_eq!(range("last month"), r("2023-04-01", "2023-04-30"));
+        assert_eq!(range("last quarter"), r("2023-01-01", "2023-03-31"));
+        assert_eq!(range("this year"), r("2023-01-01", "2023-12-31"));
+        assert_eq!(range("2023-Q5"), None);
+        assert_eq!(range("someday"), None);
+        assert_eq!(
+            add_months(NaiveDate::from_ymd(2023, 3, 31), -1).to_string(),
+            "2023-02-28"
+        );
+    }
+
+    #[test]
+    fn title_variables() {
+        use chrono::TimeZone;
+        let dt = chrono::Utc
+            .ymd(2015, 5, 14)
+            .and_hms(22, 30, 0)
+            .with_timezone(&chrono::Local);
+        let zone = Zone::Fixed(FixedOffset::east(0));
+        assert_eq!(
+            expand("Standup {{date}} {{ time }}", dt, zone, Locale::En),
+            "Standup 2015-05-14 22:30"
+        );
+        assert_eq!(expand("Week {{date:%V}}", dt, zone, Locale::En), "Week 20");
+        assert_eq!(
+            expand("{{date:%Q}} {{other}}", dt, zone, Locale::En),
+            "{{date:%Q}} {{other}}"
+        );
+        assert_eq!(
+            expand("{{date:%A, %-d. %B %Y}}", dt, zone, Locale::De),
+            "Donnerstag, 14. Mai 2015"
+        );
+        assert_eq!(
+            format(zone.show(dt), "%a %e %b %%B", Locale::Fr).as_deref(),
+            Some("jeu 14 mai %B")
+        );
+    }
+
+    #[test]
+    fn timezones() {
+        use chrono::TimeZone;
+        let utc = chrono::Utc.ymd(2015, 5, 14).and_hms(22, 30, 0);
+        let dt = utc.with_timezone(&chrono::Local);
+        let zone: Zone = "+02:00".parse().unwrap();
+        assert_eq!(zone.show(dt).to_rfc3339(), "2015-05-15T00:30:00+02:00");
+        assert_eq!(String::from(zone), "+02:00");
+        assert_eq!("UTC".parse(), Ok(Zone::Fixed(FixedOffset::east(0))));
+        assert!("+25:00".parse::<Zone>().is_err());
+        assert_eq!(
+            to_utc("2015-05-15T00:30:00+02:00").as_deref(),
+            Some("2015-05-14T22:30:00Z")
+        );
+    }
+}
@@ -0,0 +1,426 @@
+//! Importing a Day One JSON export
+//!
+//! An export is a zip archive, or the directory it unpacks to, holding a
+//! JSON file per journal and the photos of its entries under `photos/`,
+//! named after their md5 sum.
+
+use crate::{
+    config::Kind, database, frontmatter, fsutil, link, outline, zettel, zettelkasten,
+    zettelkasten::Zettelkasten, zip,
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    ZipError(zip::Error),
+    JsonError(String, serde_json::Error),
+    FrontmatterError(frontmatter::Error),
+    DatabaseError(database::Error),
+    ZettelkastenError(zettelkasten::Error),
+    /// an export without a journal in it
+    NoJournal(PathBuf),
+    /// a file of the export that would be written outside the assets
+    /// directory
+    UnsafeName(String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<zip::Error> for Error {
+    fn from(e: zip::Error) -> Self {
+        Self::ZipError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl From<database::Error> for Error {
+    fn from(e: database::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+impl From<zettelkasten::Error> for Error {
+    fn from(e: zettelkasten::Error) -> Self {
+        Self::ZettelkastenError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::ZipError(e) => e.fmt(f),
+            Self::JsonError(name, e) => write!(f, "{}: {}", name, e),
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::DatabaseError(e) => e.fmt(f),
+            Self::ZettelkastenError(e) => e.fmt(f),
+            Self::NoJournal(path) => write!(f, "no journal found in {}", path.display()),
+            Self::UnsafeName(name) => write!(
+                f,
+                "refusing to import {}, which isn't a plain file name under photos/",
+                name
+            ),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// kind of the zettels made from entries, unless `config.kinds` has one
+/// named `journal`
+pub const KIND: &str = "journal";
+
+#[derive(Debug, Deserialize)]
+struct Journal {
+    entries: Vec<Entry>,
+}
+
+/// An entry of a journal, as exported
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    pub uuid: String,
+    pub creation_date: chrono::DateTime<chrono::Utc>,
+    #[serde(default)]
+    pub text: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub starred: bool,
+    pub location: Option<Location>,
+    #[serde(default)]
+    pub photos: Vec<Photo>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    pub place_name: Option<String>,
+    pub locality_name: Option<String>,
+    pub administrative_area: Option<String>,
+    pub country: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+impl Location {
+    /// the place, from most to least specific
+    pub fn name(&self) -> String {
+        let mut parts: Vec<&str> = Vec::new();
+        for part in [
+            &self.place_name,
+            &self.locality_name,
+            &self.administrative_area,
+            &self.country,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !part.is_empty() && !parts.contains(&part.as_str()) {
+                parts.push(part);
+            }
+        }
+        parts.join(", ")
+    }
+
+    fn to_yaml(&self) -> serde_yaml::Value {
+        let mut location = serde_yaml::Mapping::new();
+        let name = self.name();
+        if !name.is_empty() {
+            location.insert("name".into(), name.into());
+        }
+        if let (Some(latitude), Some(longitude)) = (self.latitude, self.longitude) {
+            location.insert("latitude".into(), latitude.into());
+            location.insert("longitude".into(), longitude.into());
+        }
+        location.into()
+    }
+}
+
+/// A photo of an entry, pointed at from its text with
+/// `dayone-moment://<identifier>`
+#[derive(Debug, Deserialize)]
+pub struct Photo {
+    pub identifier: String,
+    pub md5: String,
+}
+
+/// The entries and photos of an export
+#[derive(Debug, Default)]
+pub struct Export {
+    pub entries: Vec<Entry>,
+    /// photo file names by md5 sum, with their contents
+    pub photos: BTreeMap<String, (String, Vec<u8>)>,
+}
+
+impl Export {
+    /// add the file `name` of the export
+    fn add(&mut self, name: &str, data: Vec<u8>) -> Result<()> {
+        let name = name.trim_start_matches("./");
+        if let Some(file) = name.strip_prefix("photos/") {
+            // only a file name, which can't lead out of the assets directory
+            if file.contains('/') || fsutil::contained(file).is_none() {
+                return Err(Error::UnsafeName(name.to_owned()));
+            }
+            let md5 = file.split('.').next().unwrap_or(file);
+            self.photos.insert(md5.to_owned(), (file.to_owned(), data));
+        } else if !name.contains('/') && name.ends_with(".json") {
+            let journal: Journal =
+                serde_json::from_slice(&data).map_err(|e| Error::JsonError(name.to_owned(), e))?;
+            self.entries.extend(journal.entries);
+        }
+        Ok(())
+    }
+
+    /// read the export at `path`, a zip archive or a directory
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut export = Self::default();
+        if path.is_dir() {
+            let mut files = Vec::new();
+            for entry in std::fs::read_dir(path)? {
+                files.push(entry?.path());
+            }
+            let photos = path.join("photos");
+            if photos.is_dir() {
+                for entry in std::fs::read_dir(photos)? {
+                    files.push(entry?.path());
+                }
+            }
+            for file in files.into_iter().filter(|f| f.is_file()) {
+                let name = file.strip_prefix(path).unwrap();
+                let name = name.to_string_lossy().replace('\\', "/");
+                export.add(&name, std::fs::read(&file)?)?;
+            }
+        } else {
+            for entry in zip::entries(&std::fs::read(path)?)? {
+                export.add(&entry.name, entry.data)?;
+            }
+        }
+        if export.entries.is_empty() {
+            return Err(Error::NoJournal(path.to_path_buf()));
+        }
+        export.entries.sort_by_key(|e| e.creation_date);
+        Ok(export)
+    }
+}
+
+/// title and body of a zettel for `entry` created `date`: the date, and the
+/// first line of the text when it is a heading, which is taken out of the
+/// body
+fn title_and_body(entry: &Entry, date: &str) -> (String, String) {
+    let text = entry.text.trim_start();
+    let first = text.lines().next().unwrap_or_default();
+    match outline::headings(first).into_iter().next() {
+        Some(heading) => (
+            format!("{} {}", date, heading.text),
+            text[first.len()..]
+                .trim_start_matches(['\r', '\n'])
+                .to_owned(),
+        ),
+        None => (date.to_owned(), text.to_owned()),
+    }
+}
+
+/// turn the entries of `export` into zettels of the journal kind and its
+/// photos into assets, in one transaction; returns the new ids
+///
+/// zettels are titled with the day of their entry, as seen in the time zone
+/// of the zettelkasten, so they are daily notes for `zk prev` and `zk next`.
+/// Tags, the location and stars are kept in frontmatter.
+pub fn import(
+    db: &impl database::Database,
+    zk: &mut Zettelkasten,
+    export: &Export,
+) -> Result<Vec<zettel::Id>> {
+    let zone = zk.config.timezone.unwrap_or_default();
+    let kind = zk.config.kinds.get(KIND).cloned().unwrap_or(Kind {
+        dir: Some(PathBuf::from(KIND)),
+        ..Default::default()
+    });
+    let root_dir = db.root_dir();
+    let assets_dir = root_dir.join(zk.config.assets_dir());
+    if !export.photos.is_empty() {
+        std::fs::create_dir_all(&assets_dir)?;
+    }
+    let aliases = zk.config.key_aliases.clone();
+    let keeps_state = zk.meta.storage == zettelkasten::Storage::Frontmatter;
+    zk.transaction(|tx| {
+        let mut ids = Vec::new();
+        let mut targets = HashSet::new();
+        for entry in &export.entries {
+            let created = entry.creation_date.with_timezone(&chrono::Local);
+            let day = zone.show(created).format("%Y-%m-%d").to_string();
+            let (title, mut body) = title_and_body(entry, &day);
+            let scheme = tx.zk().config.id_scheme.unwrap_or_default();
+            let id = scheme.generate(created, |id| tx.zettels.contains_key(id));
+            let mut zettel = db.new_zettel_of_kind(&tx.zk().config, &kind, &title, &id, created)?;
+            let path = zettel.meta.full_path(root_dir);
+            if path.exists() || !targets.insert(path.clone()) {
+                return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists).into());
+            }
+            tx.zk().missing_dir(&path)?;
+            for photo in &entry.photos {
+                let (file, data) = match export.photos.get(&photo.md5) {
+                    Some(found) => found,
+                    None => {
+                        tracing::warn!("photo {} of entry {} is missing", photo.md5, entry.uuid);
+                        continue;
+                    }
+                };
+                let asset = match Path::new(file).file_name() {
+                    Some(name) => assets_dir.join(name),
+                    None => return Err(Error::UnsafeName(file.clone())),
+                };
+                if !asset.exists() && targets.insert(asset.clone()) {
+                    tx.write(&asset, data.clone());
+                }
+                body = body.replace(
+                    &format!("dayone-moment://{}", photo.identifier),
+                    &link::relative_target(&path, &asset),
+                );
+            }
+            if !body.is_empty() && !body.ends_with('\n') {
+                body.push('\n');
+            }
+            let (mut fm, _) = frontmatter::parse_yaml(&mut std::io::BufReader::new(
+                tx.zk().render(&zettel)?.as_bytes(),
+            ))?;
+            frontmatter::unalias(&mut fm, &aliases);
+            if !entry.tags.is_empty() {
+                let tags: Vec<serde_yaml::Value> =
+                    entry.tags.iter().map(|t| t.as_str().into()).collect();
+                fm.insert("tags".into(), tags.into());
+            }
+            if let Some(location) = &entry.location {
+                fm.insert("location".into(), location.to_yaml());
+            }
+            if entry.starred {
+                fm.insert("starred".into(), true.into());
+            }
+            zettel.meta.update_from_frontmatter(&fm);
+            zettel.meta.update_from_body(&body);
+            if keeps_state {
+                zettel.meta.write_state(&mut fm, zone);
+            }
+            let fm = frontmatter::aliased(&fm, &aliases);
+            tx.write(path, frontmatter::write_yaml(&fm, &body)?);
+            tx.zettels.insert(id.clone(), zettel.meta);
+            ids.push(id);
+        }
+        Ok(ids)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::memory::Database;
+
+    #[test]
+    fn imports_entries() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_dayone_test")?;
+        let export_dir = tmp_dir.path().join("export");
+        std::fs::create_dir_all(export_dir.join("photos"))?;
+        std::fs::write(
+            export_dir.join("Journal.json"),
+            r##"{"metadata": {"version": "1.0"}, "entries": [
+                {"uuid": "B", "creationDate": "2021-03-05T08:00:00Z",
+                 "text": "No heading ![](dayone-moment://P1)", "starred": true,
+                 "photos": [{"identifier": "P1", "md5": "abc", "type": "jpeg"}]},
+                {"uuid": "A", "creationDate": "2021-03-04T08:00:00Z",
+                 "text": "# A walk\n\nBy the river.", "tags": ["outside"],
+                 "location": {"placeName": "Park", "localityName": "Town",
+                              "latitude": 1.5, "longitude": 2.5}}
+            ]}"##,
+        )?;
+        std::fs::write(export_dir.join("photos/abc.jpeg"), [0xff, 0xd8])?;
+        let export = Export::load(&export_dir)?;
+        assert_eq!(export.entries[0].uuid, "A");
+
+        let root_dir = tmp_dir.path().join("kasten");
+        let db = Database::new(root_dir.clone());
+        let mut zk = Zettelkasten::default();
+        zk.config.timezone = Some("utc".parse().unwrap());
+        let ids = import(&db, &mut zk, &export)?;
+        let walk = &zk.zettels[&ids[0]];
+        assert_eq!(walk.title, "2021-03-04 A walk");
+        assert_eq!(walk.tags, ["outside"]);
+        assert!(walk.full_path(&root_dir).starts_with(root_dir.join(KIND)));
+        let (fm, body) = frontmatter::parse_yaml_path(walk.full_path(&root_dir))?;
+        assert_eq!(body, "By the river.\n");
+        let location = fm.get(&"location".into()).unwrap();
+        assert_eq!(location.get("name"), Some(&"Park, Town".into()));
+        assert_eq!(location.get("latitude"), Some(&1.5.into()));
+
+        let photo = &zk.zettels[&ids[1]];
+        assert_eq!(photo.title, "2021-03-05");
+        let (fm, body) = frontmatter::parse_yaml_path(photo.full_path(&root_dir))?;
+        assert_eq!(fm.get(&"starred".into()), Some(&true.into()));
+        assert_eq!(body, "No heading ![](../assets/abc.jpeg)\n");
+        assert_eq!(
+            std::fs::read(root_dir.join("assets/abc.jpeg"))?,
+            [0xff, 0xd8]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn strict_dirs() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_dayone_test")?;
+        let journal = br#"{"entries": [{"uuid": "A", "creationDate": "2021-03-04T08:00:00Z"}]}"#;
+        let archive = tmp_dir.path().join("export.zip");
+        std::fs::write(&archive, zip::archive(&[("Journal.json", &journal[..])]))?;
+        let export = Export::load(&archive)?;
+        let root_dir = tmp_dir.path().join("kasten");
+        std::fs::create_dir(&root_dir)?;
+        let db = Database::new(root_dir.clone());
+        let mut zk = Zettelkasten::default();
+        zk.config.strict_dirs = Some(true);
+        assert!(import(&db, &mut zk, &export).is_err());
+        assert!(!root_dir.join(KIND).exists());
+        std::fs::create_dir(root_dir.join(KIND))?;
+        assert_eq!(import(&db, &mut zk, &export)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_paths_out_of_photos() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_dayone_test")?;
+        let journal = br#"{"entries": [{"uuid": "A", "creationDate": "2021-03-04T08:00:00Z"}]}"#;
+        for name in [
+            "photos/x/../../../pwned.txt",
+            "photos/../pwned.txt",
+            "photos//etc/pwned.txt",
+            "photos/..\\pwned.txt",
+        ] {
+            let archive = tmp_dir.path().join("export.zip");
+            std::fs::write(
+                &archive,
+                zip::archive(&[("Journal.json", &journal[..]), (name, b"pwned")]),
+            )?;
+            assert!(
+                matches!(Export::load(&archive), Err(Error::UnsafeName(_))),
+                "{}",
+                name
+            );
+        }
+        assert!(!tmp_dir.path().join("pwned.txt").exists());
+        Ok(())
+    }
+}
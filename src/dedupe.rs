@@ -0,0 +1,163 @@
+use crate::{frontmatter, zettel, zettelkasten::Zettelkasten};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    path::Path,
+};
+
+/// number of consecutive words hashed together when comparing bodies
+pub const SHINGLE_SIZE: usize = 5;
+
+/// Zettels whose bodies are the same or nearly the same
+#[derive(Debug, PartialEq)]
+pub struct Group {
+    /// oldest zettel first
+    pub ids: Vec<zettel::Id>,
+    /// lowest similarity between two zettels linked into the group, 1 when
+    /// the bodies are identical up to whitespace
+    pub similarity: f64,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    pub groups: Vec<Group>,
+    /// zettels whose files could not be parsed
+    pub unreadable: Vec<zettel::Id>,
+}
+
+/// hashes of every run of `SHINGLE_SIZE` words, ignoring case
+pub fn shingles(body: &str) -> HashSet<u64> {
+    let words: Vec<String> = body.split_whitespace().map(str::to_lowercase).collect();
+    words
+        .windows(SHINGLE_SIZE.min(words.len()).max(1))
+        .map(|shingle| {
+            let mut hasher = DefaultHasher::new();
+            shingle.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// Jaccard similarity of two sets of shingles
+pub fn similarity(a: &HashSet<u64>, b: &HashSet<u64>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// group zettels whose bodies have a similarity of at least `threshold`
+///
+/// empty bodies are ignored
+pub fn find(zk: &Zettelkasten, root_dir: &Path, threshold: f64) -> Report {
+    let mut report = Report::default();
+    let mut ids: Vec<&zettel::Id> = zk.zettels.keys().collect();
+    ids.sort_by_key(|id| (zk.zettels[*id].created, *id));
+    let mut bodies = Vec::new();
+    for id in ids {
+        match frontmatter::parse_yaml_path(zk.zettels[id].full_path(root_dir)) {
+            Ok((_, body)) if body.split_whitespace().next().is_some() => {
+                let normalized: Vec<&str> = body.split_whitespace().collect();
+                bodies.push((id, normalized.join(" "), shingles(&body)))
+            }
+            Ok(_) => (),
+            Err(_) => report.unreadable.push(id.clone()),
+        }
+    }
+    // union-find over the indices of `bodies`
+    let mut parent: Vec<usize> = (0..bodies.len()).collect();
+    fn root(parent: &mut [usize], mut i: usize) -> usize {
+        while parent[i] != i {
+            parent[i] = parent[parent[i]];
+            i = parent[i];
+        }
+        i
+    }
+    let mut lowest: HashMap<usize, f64> = HashMap::new();
+    for i in 0..bodies.len() {
+        for j in i + 1..bodies.len() {
+            let s = if bodies[i].1 == bodies[j].1 {
+                1.0
+            } else {
+                similarity(&bodies[i].2, &bodies[j].2)
+            };
+            if s < threshold {
+                continue;
+            }
+            let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+            let s = [lowest.get(&a), lowest.get(&b)]
+                .into_iter()
+                .flatten()
+                .fold(s, |s, l| s.min(*l));
+            if a != b {
+                parent[b] = a;
+                lowest.remove(&b);
+            }
+            lowest.insert(a, s);
+        }
+    }
+    let mut groups: Vec<(usize, Vec<zettel::Id>)> = Vec::new();
+    for (i, (id, _, _)) in bodies.iter().enumerate() {
+        let r = root(&mut parent, i);
+        if !lowest.contains_key(&r) {
+            continue;
+        }
+        match groups.iter_mut().find(|(root, _)| *root == r) {
+            Some((_, ids)) => ids.push((*id).clone()),
+            None => groups.push((r, vec![(*id).clone()])),
+        }
+    }
+    report.groups = groups
+        .into_iter()
+        .map(|(r, ids)| Group {
+            ids,
+            similarity: lowest[&r],
+        })
+        .collect();
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn find_duplicates() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_dedupe_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        let text = "the quick brown fox jumps over the lazy dog and runs far away into the woods";
+        for (n, body) in [
+            text.to_owned(),
+            text.replace(' ', "\n  "),
+            format!("{} today", text),
+            "something else entirely, with enough words to not match".to_owned(),
+            String::new(),
+            String::new(),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let dt = chrono::Local.ymd(2015, 5, 14 + n as u32).and_hms(12, 0, 0);
+            let mut zettel = db.new_zettel(
+                &Default::default(),
+                format!("note {}", n),
+                n.to_string(),
+                dt,
+            )?;
+            zettel.content = body;
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        let report = find(&zk, db.root_dir(), 1.0);
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].ids, vec!["0", "1"]);
+        let report = find(&zk, db.root_dir(), 0.8);
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].ids, vec!["0", "1", "2"]);
+        assert!(report.groups[0].similarity < 1.0);
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{summary, zettel, zettelkasten::Zettelkasten, DateTime, ZettelMeta};
+use chrono::NaiveDate;
+
+/// Formats of `zk digest`
+#[derive(Debug, Clone, Copy, PartialEq, Default, clap::ValueEnum)]
+pub enum Format {
+    #[default]
+    Markdown,
+    /// a standalone page, for mail clients
+    Html,
+}
+
+/// What happened in a zettelkasten since a day
+#[derive(Debug, PartialEq)]
+pub struct Digest {
+    pub since: NaiveDate,
+    /// zettels created since then, oldest first
+    pub new: Vec<(zettel::Id, String)>,
+    /// older zettels modified since then, most recent first
+    pub edited: Vec<(zettel::Id, String)>,
+    /// zettels whose review is due, longest overdue first; zettels that were
+    /// never reviewed aren't scheduled yet and so aren't counted
+    pub due: Vec<(zettel::Id, String)>,
+    /// zettels that neither link to nor are linked from another zettel
+    pub orphans: usize,
+}
+
+/// summarize `zk` from the start of day `since` up to `now`
+pub fn digest(zk: &Zettelkasten, since: NaiveDate, now: DateTime) -> Digest {
+    let in_range = |date: DateTime| date.date().naive_local() >= since;
+    let entry = |(id, meta): &(&zettel::Id, &ZettelMeta)| ((*id).clone(), meta.title.clone());
+    let mut new: Vec<_> = zk
+        .zettels
+        .iter()
+        .filter(|(_, meta)| in_range(meta.created))
+        .collect();
+    new.sort_by_key(|(id, meta)| (meta.created, *id));
+    let mut edited: Vec<_> = zk
+        .zettels
+        .iter()
+        .filter(|(_, meta)| !in_range(meta.created) && in_range(meta.modified))
+        .collect();
+    edited.sort_by(|a, b| b.1.modified.cmp(&a.1.modified).then(a.0.cmp(b.0)));
+    let mut due: Vec<_> = zk
+        .zettels
+        .iter()
+        .filter(|(_, meta)| meta.review.as_ref().is_some_and(|s| s.due() <= now))
+        .collect();
+    due.sort_by_key(|(id, meta)| (meta.review.as_ref().map(|s| s.due()), *id));
+    Digest {
+        since,
+        new: new.iter().map(entry).collect(),
+        edited: edited.iter().map(entry).collect(),
+        due: due.iter().map(entry).collect(),
+        orphans: summary::orphans(zk),
+    }
+}
+
+impl Digest {
+    fn sections(&self) -> [(&'static str, &[(zettel::Id, String)]); 3] {
+        [
+            ("New", &self.new),
+            ("Edited", &self.edited),
+            ("Due for review", &self.due),
+        ]
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = format!("# Zettelkasten since {}\n", self.since);
+        for (heading, zettels) in self.sections() {
+            out.push_str(&format!("\n## {} ({})\n\n", heading, zettels.len()));
+            if zettels.is_empty() {
+                out.push_str("None.\n");
+            }
+            for (id, title) in zettels {
+                out.push_str(&format!("- {} ({})\n", title, id));
+            }
+        }
+        out.push_str(&format!(
+            "\n{} zettels are orphans, with no links to or from them.\n",
+            self.orphans
+        ));
+        out
+    }
+
+    pub fn to_html(&self) -> String {
+        let title = format!("Zettelkasten since {}", self.since);
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{0}</title>\n</head>\n<body>\n<h1>{0}</h1>\n",
+            title
+        );
+        for (heading, zettels) in self.sections() {
+            out.push_str(&format!("<h2>{} ({})</h2>\n", heading, zettels.len()));
+            if zettels.is_empty() {
+                out.push_str("<p>None.</p>\n");
+                continue;
+            }
+            out.push_str("<ul>\n");
+            for (id, title) in zettels {
+                out.push_str(&format!(
+                    "<li>{} <code>{}</code></li>\n",
+                    escape(title),
+                    escape(id)
+                ));
+            }
+            out.push_str("</ul>\n");
+        }
+        out.push_str(&format!(
+            "<p>{} zettels are orphans, with no links to or from them.</p>\n</body>\n</html>\n",
+            self.orphans
+        ));
+        out
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Markdown => self.to_markdown(),
+            Format::Html => self.to_html(),
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::prelude::*;
+
+    #[test]
+    fn summarizes_week() {
+        let now = chrono::Local.ymd(2023, 5, 17).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for (id, title, age, edited, links) in [
+            ("old", "Old & untouched", 30, 30, vec![]),
+            ("edit", "Edited", 30, 2, vec!["new"]),
+            ("new", "Brand <new>", 1, 1, vec![]),
+        ] {
+            let created = now - chrono::Duration::days(age);
+            let mut meta = ZettelMeta::new(id, title, "z.md", created);
+            meta.modified = now - chrono::Duration::days(edited);
+            meta.links = links.into_iter().map(str::to_owned).collect();
+            zk.zettels.insert(id.to_owned(), meta);
+        }
+        zk.zettels.get_mut("old").unwrap().review = Some(crate::review::Schedule::grade(
+            None,
+            5,
+            now - chrono::Duration::days(10),
+        ));
+        let digest = digest(&zk, NaiveDate::from_ymd(2023, 5, 10), now);
+        assert_eq!(digest.new, [("new".to_owned(), "Brand <new>".to_owned())]);
+        assert_eq!(digest.edited.len(), 1);
+        assert_eq!(digest.due[0].0, "old");
+        assert_eq!(digest.orphans, 1);
+        let markdown = digest.to_markdown();
+        assert!(markdown
+            .starts_with("# Zettelkasten since 2023-05-10\n\n## New (1)\n\n- Brand <new> (new)\n"));
+        let html = digest.to_html();
+        assert!(html.contains("<li>Brand &lt;new&gt; <code>new</code></li>"));
+        assert!(html.contains("<li>Old &amp; untouched <code>old</code></li>"));
+    }
+}
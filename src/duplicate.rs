@@ -0,0 +1,174 @@
+use crate::{
+    database, frontmatter, fsutil, zettel, zettelkasten, zettelkasten::Zettelkasten, DateTime,
+};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    FrontmatterError(frontmatter::Error),
+    DatabaseError(database::Error),
+    ZettelkastenError(zettelkasten::Error),
+    UnknownZettel(zettel::Id),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl From<database::Error> for Error {
+    fn from(e: database::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+impl From<zettelkasten::Error> for Error {
+    fn from(e: zettelkasten::Error) -> Self {
+        Self::ZettelkastenError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::DatabaseError(e) => e.fmt(f),
+            Self::ZettelkastenError(e) => e.fmt(f),
+            Self::UnknownZettel(id) => write!(f, "no zettel with id {}", id),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// What to carry over to a copy of a zettel
+#[derive(Debug, Default)]
+pub struct Options {
+    /// frontmatter keys to copy; every key but the generated ones when empty
+    pub keep: Vec<String>,
+    /// point the copy at the original with a `source` field
+    pub source: bool,
+}
+
+/// whether frontmatter `key` is filled in afresh for every new zettel, so
+/// it isn't copied unless asked for
+fn is_generated(zk: &Zettelkasten, key: &str) -> bool {
+    matches!(key, "id" | "title" | "created" | "modified")
+        || zk
+            .default_frontmatter
+            .get(key)
+            .is_some_and(|val| val.starts_with('@'))
+}
+
+/// create a zettel titled `title` next to zettel `id`, with a new id, its
+/// body and the frontmatter chosen by `options`. Returns the new id.
+pub fn duplicate(
+    db: &impl database::Database,
+    zk: &mut Zettelkasten,
+    id: &str,
+    title: &str,
+    options: &Options,
+    now: DateTime,
+) -> Result<zettel::Id> {
+    let path = match zk.zettels.get(id) {
+        Some(meta) => meta.full_path(db.root_dir()),
+        None => return Err(Error::UnknownZettel(id.to_owned())),
+    };
+    let (mut fm, body) = frontmatter::parse_yaml_path(&path)?;
+    let aliases = zk.config.key_aliases.clone();
+    frontmatter::unalias(&mut fm, &aliases);
+    let dir = path.parent().unwrap_or(db.root_dir());
+    zk.transaction(|tx| {
+        let scheme = tx.zk().config.id_scheme.unwrap_or_default();
+        let new_id = scheme.generate(now, |id| tx.zettels.contains_key(id));
+        let mut zettel = db.new_zettel(&tx.zk().config, title, &new_id, now)?;
+        let file_name = Path::new(&zettel.meta.path).file_name().unwrap();
+        let new_path = dir.join(file_name);
+        let taken = tx
+            .zettels
+            .values()
+            .any(|meta| meta.full_path(db.root_dir()) == new_path);
+        if new_path.exists() || taken {
+            return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists).into());
+        }
+        // `dir` is that of a zettel, and names from templates are UTF-8
+        zettel.meta.path = fsutil::to_stored(db.root_dir(), &new_path).unwrap();
+        let (mut merged, _) = frontmatter::parse_yaml(&mut std::io::BufReader::new(
+            tx.zk().render(&zettel)?.as_bytes(),
+        ))?;
+        frontmatter::unalias(&mut merged, &aliases);
+        for (key, value) in fm {
+            let copied = match key.as_str() {
+                Some(key) if options.keep.is_empty() => !is_generated(tx.zk(), key),
+                Some(key) => key != "id" && options.keep.iter().any(|k| k == key),
+                None => false,
+            };
+            if copied {
+                merged.insert(key, value);
+            }
+        }
+        if options.source {
+            merged.insert("source".into(), id.into());
+        }
+        zettel.meta.update_from_frontmatter(&merged);
+        zettel.meta.update_from_body(&body);
+        let merged = frontmatter::aliased(&merged, &aliases);
+        tx.write(new_path, frontmatter::write_yaml(&merged, &body)?);
+        tx.zettels.insert(new_id.clone(), zettel.meta);
+        Ok(new_id)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn copies_body_and_frontmatter() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_duplicate_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let zettel = db.new_zettel(&Default::default(), "Meeting", "orig", dt)?;
+        zk.add(db.root_dir(), &zettel)?;
+        let path = zettel.meta.full_path(tmp_dir.path());
+        std::fs::write(
+            &path,
+            "---\nid: orig\ntitle: Meeting\ntags: [a]\nstatus: open\n---\nagenda [[x]]\n",
+        )?;
+        let copy = duplicate(&db, &mut zk, "orig", "Meeting 2", &Options::default(), dt)?;
+        assert_ne!(copy, "orig");
+        let meta = &zk.zettels[&copy];
+        assert_eq!(meta.title, "Meeting 2");
+        assert_eq!(meta.tags, ["a"]);
+        assert_eq!(meta.links, ["x"]);
+        let (fm, body) = frontmatter::parse_yaml_path(meta.full_path(tmp_dir.path()))?;
+        assert_eq!(fm.get(&"id".into()), Some(&copy.as_str().into()));
+        assert_eq!(fm.get(&"status".into()), Some(&"open".into()));
+        assert_eq!(body, "agenda [[x]]\n");
+        let options = Options {
+            keep: vec!["tags".to_owned()],
+            source: true,
+        };
+        let copy = duplicate(&db, &mut zk, "orig", "Meeting 3", &options, dt)?;
+        let path = zk.zettels[&copy].full_path(tmp_dir.path());
+        let (fm, _) = frontmatter::parse_yaml_path(path)?;
+        assert_eq!(fm.get(&"status".into()), None);
+        assert_eq!(fm.get(&"source".into()), Some(&"orig".into()));
+        assert!(duplicate(&db, &mut zk, "orig", "Meeting 3", &options, dt).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{zettel, ZettelMeta};
+use serde::Serialize;
+use std::{collections::HashMap, sync::mpsc};
+
+/// A change to a zettelkasten, sent to receivers from
+/// [`crate::zettelkasten::Zettelkasten::subscribe`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    ZettelAdded {
+        id: zettel::Id,
+    },
+    ZettelMoved {
+        id: zettel::Id,
+        from: String,
+        to: String,
+    },
+    TitleChanged {
+        id: zettel::Id,
+        from: String,
+        to: String,
+    },
+    ZettelRemoved {
+        id: zettel::Id,
+    },
+    /// the zettelkasten was written to its database
+    Committed,
+}
+
+impl Event {
+    /// events turning `before` into `after` for zettel `id`, `None` when it
+    /// doesn't exist on that side
+    pub fn between(id: &str, before: Option<&ZettelMeta>, after: Option<&ZettelMeta>) -> Vec<Self> {
+        let id = id.to_owned();
+        match (before, after) {
+            (None, Some(_)) => vec![Self::ZettelAdded { id }],
+            (Some(_), None) => vec![Self::ZettelRemoved { id }],
+            (Some(before), Some(after)) => {
+                let mut events = Vec::new();
+                if before.path != after.path {
+                    events.push(Self::ZettelMoved {
+                        id: id.clone(),
+                        from: before.path.clone(),
+                        to: after.path.clone(),
+                    });
+                }
+                if before.title != after.title {
+                    events.push(Self::TitleChanged {
+                        id,
+                        from: before.title.clone(),
+                        to: after.title.clone(),
+                    });
+                }
+                events
+            }
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+/// events turning the zettels `before` into `after`, ordered by id
+pub fn changes(
+    before: &HashMap<zettel::Id, ZettelMeta>,
+    after: &HashMap<zettel::Id, ZettelMeta>,
+) -> Vec<Event> {
+    let mut ids: Vec<&zettel::Id> = before.keys().chain(after.keys()).collect();
+    ids.sort();
+    ids.dedup();
+    ids.into_iter()
+        .flat_map(|id| Event::between(id, before.get(id), after.get(id)))
+        .collect()
+}
+
+/// Senders of events to the subscribers of a zettelkasten
+///
+/// they aren't part of its contents: copies start without subscribers and
+/// compare equal whoever listens
+#[derive(Debug, Default)]
+pub struct Subscribers(Vec<mpsc::Sender<Event>>);
+
+impl Subscribers {
+    pub fn subscribe(&mut self) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.0.push(sender);
+        receiver
+    }
+
+    /// send `event` to every subscriber still listening
+    pub fn send(&self, event: &Event) {
+        for sender in &self.0 {
+            // dropping the receiver is how subscribers stop listening
+            let _ = sender.send(event.clone());
+        }
+    }
+}
+
+impl Clone for Subscribers {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl PartialEq for Subscribers {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::prelude::*;
+
+    #[test]
+    fn changes_between_zettels() {
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let before = HashMap::from([
+            ("a".to_owned(), ZettelMeta::new("", "A", "a.md", dt)),
+            ("b".to_owned(), ZettelMeta::new("", "B", "b.md", dt)),
+        ]);
+        let after = HashMap::from([
+            ("a".to_owned(), ZettelMeta::new("", "New A", "dir/a.md", dt)),
+            ("c".to_owned(), ZettelMeta::new("", "C", "c.md", dt)),
+        ]);
+        assert_eq!(
+            changes(&before, &after),
+            vec![
+                Event::ZettelMoved {
+                    id: "a".to_owned(),
+                    from: "a.md".to_owned(),
+                    to: "dir/a.md".to_owned(),
+                },
+                Event::TitleChanged {
+                    id: "a".to_owned(),
+                    from: "A".to_owned(),
+                    to: "New A".to_owned(),
+                },
+                Event::ZettelRemoved { id: "b".to_owned() },
+                Event::ZettelAdded { id: "c".to_owned() },
+            ]
+        );
+        let mut subscribers = Subscribers::default();
+        let receiver = subscribers.subscribe();
+        subscribers.clone().send(&Event::Committed);
+        subscribers.send(&Event::Committed);
+        assert_eq!(receiver.try_iter().count(), 1);
+    }
+}
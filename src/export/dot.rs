@@ -0,0 +1,86 @@
+use crate::{
+    citations,
+    kastens::{self, Kasten},
+};
+use std::io::Write;
+
+/// write a Graphviz digraph with a node per zettel and an edge per wikilink
+///
+/// literature notes are filled boxes, and citations of them are dashed
+/// edges. zettels of other kastens are named `kasten:id`; links into kastens that
+/// aren't given are left out
+pub fn write(kastens: &[Kasten], w: &mut impl Write) -> std::io::Result<()> {
+    writeln!(w, "digraph zettelkasten {{")?;
+    for (k, kasten) in kastens.iter().enumerate() {
+        let mut ids: Vec<_> = kasten.zk.zettels.keys().collect();
+        ids.sort();
+        for id in ids {
+            let meta = &kasten.zk.zettels[id];
+            let style = match meta.cite {
+                Some(_) => ", shape=box, style=filled, fillcolor=lightyellow",
+                None => "",
+            };
+            writeln!(
+                w,
+                "  {} [label={}{}];",
+                quote(&kasten.qualified(id)),
+                quote(&meta.title),
+                style
+            )?;
+            for target in &meta.links {
+                if let Some((to, target)) = kastens::find(kastens, k, target) {
+                    writeln!(
+                        w,
+                        "  {} -> {};",
+                        quote(&kasten.qualified(id)),
+                        quote(&kastens[to].qualified(&target))
+                    )?;
+                }
+            }
+            for cited in citations::cited_notes(&kasten.zk, meta) {
+                writeln!(
+                    w,
+                    "  {} -> {} [style=dashed];",
+                    quote(&kasten.qualified(id)),
+                    quote(&kasten.qualified(&cited))
+                )?;
+            }
+        }
+    }
+    writeln!(w, "}}")
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{zettelkasten::Zettelkasten, ZettelMeta};
+
+    #[test]
+    fn write_graph() -> std::io::Result<()> {
+        let now = chrono::Local::now();
+        let mut zk = Zettelkasten::default();
+        let mut meta = ZettelMeta::new("a", "Say \"hi\"", "a.md", now);
+        meta.links = vec!["b".to_owned(), "missing".to_owned(), "work:x".to_owned()];
+        meta.cites = vec!["knuth1984".to_owned()];
+        zk.zettels.insert("a".to_owned(), meta);
+        let mut lit = ZettelMeta::new("b", "B", "b.md", now);
+        lit.cite = Some("knuth1984".to_owned());
+        zk.zettels.insert("b".to_owned(), lit);
+        let kastens = [Kasten {
+            name: None,
+            root_dir: "/root".into(),
+            zk,
+        }];
+        let mut out = Vec::new();
+        write(&kastens, &mut out)?;
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "digraph zettelkasten {\n  \"a\" [label=\"Say \\\"hi\\\"\"];\n  \"a\" -> \"b\";\n  \"a\" -> \"b\" [style=dashed];\n  \"b\" [label=\"B\", shape=box, style=filled, fillcolor=lightyellow];\n}\n"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use super::Result;
+use crate::{frontmatter, zettelkasten::Zettelkasten, DateTime};
+use chrono::NaiveDate;
+use std::{io::Write, path::Path};
+
+/// An all-day calendar event
+#[derive(Debug, PartialEq)]
+pub struct Event {
+    pub uid: String,
+    pub date: NaiveDate,
+    pub summary: String,
+    pub description: String,
+}
+
+/// find `@due(YYYY-MM-DD)` annotations in a zettel body
+///
+/// returns the line number (starting at 1), the due date and the text of the
+/// line with the annotation and any task list marker removed
+pub fn due_annotations(body: &str) -> Vec<(usize, NaiveDate, String)> {
+    body.lines()
+        .enumerate()
+        .filter_map(|(n, line)| {
+            let (date, text) = due_annotation(line)?;
+            Some((n + 1, date, text))
+        })
+        .collect()
+}
+
+/// due date and remaining text of a line with a `@due(YYYY-MM-DD)`
+/// annotation
+fn due_annotation(line: &str) -> Option<(NaiveDate, String)> {
+    let start = line.find("@due(")?;
+    let end = start + line[start..].find(')')?;
+    let date = NaiveDate::parse_from_str(&line[start + 5..end], "%Y-%m-%d").ok()?;
+    let text = format!("{}{}", &line[..start], &line[end + 1..]);
+    let text = text.trim();
+    let text = ["- [ ]", "- [x]", "* [ ]", "* [x]", "-", "*"]
+        .iter()
+        .find_map(|marker| text.strip_prefix(marker))
+        .unwrap_or(text)
+        .trim();
+    Some((date, text.to_owned()))
+}
+
+/// collect events for daily notes and due annotations of every zettel
+///
+/// a daily note is a zettel whose title is a date formatted as `YYYY-MM-DD`
+pub fn events(zk: &Zettelkasten, root_dir: &Path) -> Result<Vec<Event>> {
+    let mut events = Vec::new();
+    let mut handles: Vec<_> = zk.handles(root_dir).collect();
+    handles.sort_by_key(|zettel| zettel.id);
+    for zettel in handles {
+        let (id, meta) = (zettel.id, zettel.meta);
+        if let Ok(date) = NaiveDate::parse_from_str(&meta.title, "%Y-%m-%d") {
+            events.push(Event {
+                uid: format!("{}@zk", id),
+                date,
+                summary: meta.title.clone(),
+                description: meta.path.clone(),
+            });
+        }
+        // bodies are streamed so large zettels are never held whole
+        let lines = match zettel.body_lines() {
+            Ok((_, lines)) => lines,
+            Err(e) => {
+                tracing::warn!("skipping tasks in {} due to error: {}", meta.path, e);
+                continue;
+            }
+        };
+        // where the body starts in the file, found once a task is
+        let mut first_line = None;
+        for (n, line) in lines.enumerate() {
+            let Some((date, text)) = due_annotation(&line?) else {
+                continue;
+            };
+            let first_line = match first_line {
+                Some(first_line) => first_line,
+                None => *first_line.insert(frontmatter::first_body_line(zettel.path())?),
+            };
+            events.push(Event {
+                uid: format!("{}-{}@zk", id, n + 1),
+                date,
+                summary: text,
+                description: format!("{} ({}:{})", meta.title, meta.path, n + first_line),
+            });
+        }
+    }
+    Ok(events)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// write events as an iCalendar (RFC 5545) document
+pub fn write(events: &[Event], stamp: DateTime, w: &mut impl Write) -> Result<()> {
+    let stamp = stamp
+        .with_timezone(&chrono::Utc)
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string();
+    write!(
+        w,
+        "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//zk//zk//EN\r\n"
+    )?;
+    for event in events {
+        write!(w, "BEGIN:VEVENT\r\n")?;
+        write!(w, "UID:{}\r\n", escape(&event.uid))?;
+        write!(w, "DTSTAMP:{}\r\n", stamp)?;
+        write!(w, "DTSTART;VALUE=DATE:{}\r\n", event.date.format("%Y%m%d"))?;
+        write!(w, "SUMMARY:{}\r\n", escape(&event.summary))?;
+        write!(w, "DESCRIPTION:{}\r\n", escape(&event.description))?;
+        write!(w, "END:VEVENT\r\n")?;
+    }
+    write!(w, "END:VCALENDAR\r\n")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::Kasten;
+    use chrono::prelude::*;
+
+    #[test]
+    fn due_tasks_to_ical() -> Result<()> {
+        let body = "intro\n- [ ] send draft, then relax @due(2022-07-15)\n@due(bad)\n";
+        let annotations = due_annotations(body);
+        assert_eq!(
+            annotations,
+            vec![(
+                2,
+                NaiveDate::from_ymd(2022, 7, 15),
+                "send draft, then relax".to_owned()
+            )]
+        );
+        let events: Vec<Event> = annotations
+            .into_iter()
+            .map(|(line, date, summary)| Event {
+                uid: format!("abc-{}@zk", line),
+                date,
+                summary,
+                description: "a zettel".to_owned(),
+            })
+            .collect();
+        let mut out = Vec::new();
+        let stamp = chrono::Utc
+            .ymd(2022, 7, 1)
+            .and_hms(0, 0, 0)
+            .with_timezone(&Local);
+        write(&events, stamp, &mut out)?;
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("UID:abc-2@zk\r\n"));
+        assert!(out.contains("DTSTAMP:20220701T000000Z\r\n"));
+        assert!(out.contains("DTSTART;VALUE=DATE:20220715\r\n"));
+        assert!(out.contains("SUMMARY:send draft\\, then relax\r\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn tasks_point_at_lines_of_the_file() -> Result<()> {
+        let kasten = Kasten::new();
+        let zk = kasten.add("a", "Plans", "intro\n- [ ] ship it @due(2022-07-15)\n");
+        let path = &zk.zettels["a"].path;
+        let text = std::fs::read_to_string(zk.zettels["a"].full_path(kasten.root_dir()))?;
+        let line = text.lines().position(|l| l.contains("@due")).unwrap() + 1;
+        let events = events(&zk, kasten.root_dir())?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].uid, "a-2@zk");
+        assert_eq!(events[0].description, format!("Plans ({}:{})", path, line));
+        assert!(line > 2);
+        Ok(())
+    }
+}
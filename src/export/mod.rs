@@ -0,0 +1,54 @@
+pub mod dot;
+pub mod ical;
+pub mod sql;
+
+use crate::frontmatter;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    FrontmatterError(frontmatter::Error),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Formats supported by `zk export`
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// iCalendar events for daily notes and `@due(YYYY-MM-DD)` annotations
+    Ical,
+    /// Graphviz graph of the links between zettels and their citations of
+    /// literature notes
+    Dot,
+    /// SQL script creating tables of zettels, tags, links and citations
+    /// with a full text index
+    Sql,
+    /// SQLite database file made by loading the SQL script with `sqlite3`
+    /// or $ZK_SQLITE; needs --out
+    Sqlite,
+    /// JSON tiddlers for TiddlyWiki to import, with links pointed at titles
+    Tiddlywiki,
+}
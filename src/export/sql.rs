@@ -0,0 +1,137 @@
+use super::Result;
+use crate::{frontmatter, zettelkasten::Zettelkasten};
+use std::{io::Write, path::Path};
+
+/// executable that loads the script of [`write`] into a database file, unless
+/// `$ZK_SQLITE` names another
+pub const SQLITE: &str = "sqlite3";
+
+const SCHEMA: &str = "\
+CREATE TABLE zettels (
+  id TEXT PRIMARY KEY,
+  title TEXT NOT NULL,
+  path TEXT NOT NULL,
+  created TEXT NOT NULL,
+  modified TEXT NOT NULL,
+  word_count INTEGER NOT NULL,
+  cite TEXT,
+  follows TEXT,
+  publish INTEGER,
+  body TEXT
+);
+CREATE TABLE tags (zettel_id TEXT NOT NULL REFERENCES zettels(id), tag TEXT NOT NULL);
+CREATE TABLE links (source TEXT NOT NULL REFERENCES zettels(id), target TEXT NOT NULL);
+CREATE TABLE cites (zettel_id TEXT NOT NULL REFERENCES zettels(id), source TEXT NOT NULL);
+CREATE INDEX tags_by_tag ON tags (tag);
+CREATE INDEX links_by_target ON links (target);
+CREATE VIRTUAL TABLE zettels_fts USING fts5 (title, body, content='zettels', content_rowid='rowid');
+";
+
+/// `s` as an SQL string literal
+fn text(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+fn nullable(s: Option<&str>) -> String {
+    s.map_or_else(|| "NULL".to_owned(), text)
+}
+
+/// write an SQLite script creating tables of the zettels, their tags, links
+/// and citations, with a full text index of titles and bodies
+///
+/// timestamps are RFC 3339 in UTC. Bodies that can't be read are left NULL.
+pub fn write(zk: &Zettelkasten, root_dir: &Path, w: &mut impl Write) -> Result<()> {
+    writeln!(w, "BEGIN TRANSACTION;")?;
+    write!(w, "{}", SCHEMA)?;
+    let mut handles: Vec<_> = zk.handles(root_dir).collect();
+    handles.sort_by_key(|zettel| zettel.id);
+    for zettel in handles {
+        let (id, meta) = (zettel.id, zettel.meta);
+        let body = match frontmatter::parse_yaml_path(zettel.path()) {
+            Ok((_, body)) => Some(body),
+            Err(e) => {
+                tracing::warn!("exporting {} without its body: {}", meta.path, e);
+                None
+            }
+        };
+        let time = |dt: crate::DateTime| {
+            dt.with_timezone(&chrono::Utc)
+                .to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)
+        };
+        writeln!(
+            w,
+            "INSERT INTO zettels VALUES ({}, {}, {}, {}, {}, {}, {}, {}, {}, {});",
+            text(id),
+            text(&meta.title),
+            text(&meta.path),
+            text(&time(meta.created)),
+            text(&time(meta.modified)),
+            meta.word_count,
+            nullable(meta.cite.as_deref()),
+            nullable(meta.follows.as_deref()),
+            meta.publish.map_or("NULL", |p| if p { "1" } else { "0" }),
+            nullable(body.as_deref()),
+        )?;
+        for tag in &meta.tags {
+            writeln!(w, "INSERT INTO tags VALUES ({}, {});", text(id), text(tag))?;
+        }
+        for target in &meta.links {
+            writeln!(
+                w,
+                "INSERT INTO links VALUES ({}, {});",
+                text(id),
+                text(target)
+            )?;
+        }
+        for source in &meta.cites {
+            writeln!(
+                w,
+                "INSERT INTO cites VALUES ({}, {});",
+                text(id),
+                text(source)
+            )?;
+        }
+    }
+    writeln!(
+        w,
+        "INSERT INTO zettels_fts (zettels_fts) VALUES ('rebuild');"
+    )?;
+    writeln!(w, "COMMIT;")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn script() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_sql_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        let dt = chrono::Utc
+            .ymd(2015, 5, 14)
+            .and_hms(12, 0, 0)
+            .with_timezone(&Local);
+        let mut zettel = db.new_zettel(&zk.config, "Don't panic", "a", dt)?;
+        zettel.content = "see [[b]]".to_owned();
+        zettel.meta.update_from_body(&zettel.content);
+        zettel.meta.tags = vec!["towel".to_owned()];
+        zk.add(db.root_dir(), &zettel)?;
+        let mut out = Vec::new();
+        write(&zk, tmp_dir.path(), &mut out)?;
+        let out = String::from_utf8(out)?;
+        assert!(out.starts_with("BEGIN TRANSACTION;\nCREATE TABLE zettels ("));
+        assert!(out.contains(&format!(
+            "INSERT INTO zettels VALUES ('a', 'Don''t panic', {}, '2015-05-14T12:00:00Z', \
+             '2015-05-14T12:00:00Z', 2, NULL, NULL, NULL, 'see [[b]]\n');",
+            text(&zk.zettels["a"].path)
+        )));
+        assert!(out.contains("INSERT INTO tags VALUES ('a', 'towel');"));
+        assert!(out.contains("INSERT INTO links VALUES ('a', 'b');"));
+        assert!(out.ends_with("COMMIT;\n"));
+        Ok(())
+    }
+}
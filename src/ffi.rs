@@ -0,0 +1,208 @@
+//! C interface for embedding zk in other programs
+//!
+//! a kasten is opened with `zk_open` and answers calls like the daemon
+//! does: every function returns a JSON-RPC response object with either a
+//! `result` or an `error`, as a string that must be released with
+//! `zk_free_string`; a panic inside zk comes back as an internal error, or
+//! a null pointer, instead of unwinding into the caller
+
+use crate::{daemon::Daemon, database::file::Database};
+use serde_json::{json, Value};
+use std::{
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    path::PathBuf,
+    ptr,
+};
+
+/// An open zettelkasten
+pub struct ZkHandle {
+    daemon: Daemon<Database>,
+}
+
+/// `f()`, or `fallback` when it panics, since unwinding into C is undefined
+/// behavior
+fn guard<T>(fallback: T, f: impl FnOnce() -> T) -> T {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or(fallback)
+}
+
+/// `s` as a string, `None` when it's null or not UTF-8
+///
+/// # Safety
+///
+/// `s` must be null or a valid nul terminated string that outlives `'a`
+unsafe fn to_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    // SAFETY: non-null strings are valid and nul terminated, as promised
+    unsafe { CStr::from_ptr(s) }.to_str().ok()
+}
+
+fn to_c_string(value: Value) -> *mut c_char {
+    // JSON escapes nul characters inside strings, so this can't fail
+    CString::new(value.to_string())
+        .map(CString::into_raw)
+        .unwrap_or(ptr::null_mut())
+}
+
+/// the response of the kasten behind `handle` to calling `method`
+///
+/// # Safety
+///
+/// `handle` must be null or come from `zk_open` and not be closed yet
+unsafe fn call(handle: *mut ZkHandle, method: &str, params: Value) -> *mut c_char {
+    // SAFETY: a non-null handle came from `zk_open`, as promised
+    let Some(handle) = (unsafe { handle.as_mut() }) else {
+        return to_c_string(json!({
+            "jsonrpc": "2.0",
+            "id": 0,
+            "error": {"code": -32602, "message": "invalid params: null handle"},
+        }));
+    };
+    let request = json!({"jsonrpc": "2.0", "id": 0, "method": method, "params": params});
+    let failed = json!({
+        "jsonrpc": "2.0",
+        "id": 0,
+        "error": {"code": -32603, "message": "internal error"},
+    });
+    let response = guard(Some(failed), || {
+        handle.daemon.handle(&request, chrono::Local::now())
+    })
+    .unwrap_or(Value::Null);
+    to_c_string(response)
+}
+
+/// open the zettelkasten in `root_dir`, returning null when it isn't one
+///
+/// # Safety
+///
+/// `root_dir` must be null or a valid nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn zk_open(root_dir: *const c_char) -> *mut ZkHandle {
+    guard(ptr::null_mut(), || {
+        let Some(root_dir) = to_str(root_dir) else {
+            return ptr::null_mut();
+        };
+        let root_dir = PathBuf::from(root_dir);
+        if !root_dir.is_dir() {
+            return ptr::null_mut();
+        }
+        match Database::new(root_dir)
+            .ok()
+            .and_then(|db| Daemon::new(db).ok())
+        {
+            Some(daemon) => Box::into_raw(Box::new(ZkHandle { daemon })),
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+/// close a zettelkasten opened with `zk_open`
+///
+/// # Safety
+///
+/// `handle` must be null or come from `zk_open` and not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn zk_close(handle: *mut ZkHandle) {
+    if !handle.is_null() {
+        guard((), || drop(Box::from_raw(handle)));
+    }
+}
+
+/// zettels matching `query`, in the syntax of `zk list`, or all of them
+/// when it's null
+///
+/// # Safety
+///
+/// `handle` must come from `zk_open`; `query` must be null or a valid nul
+/// terminated string
+#[no_mangle]
+pub unsafe extern "C" fn zk_list(handle: *mut ZkHandle, query: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let params = match to_str(query) {
+            Some(query) => json!({ "query": query }),
+            None => json!({}),
+        };
+        call(handle, "list", params)
+    })
+}
+
+/// create a zettel titled `title`
+///
+/// # Safety
+///
+/// `handle` must come from `zk_open`; `title` must be a valid nul
+/// terminated string
+#[no_mangle]
+pub unsafe extern "C" fn zk_new(handle: *mut ZkHandle, title: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        call(handle, "new", json!({ "title": to_str(title) }))
+    })
+}
+
+/// lines of zettel bodies matching the regular expression `pattern`
+///
+/// # Safety
+///
+/// `handle` must come from `zk_open`; `pattern` must be a valid nul
+/// terminated string
+#[no_mangle]
+pub unsafe extern "C" fn zk_search(handle: *mut ZkHandle, pattern: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        call(handle, "search", json!({ "pattern": to_str(pattern) }))
+    })
+}
+
+/// release a string returned by the other functions
+///
+/// # Safety
+///
+/// `s` must be null or come from one of the functions of this module and
+/// not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn zk_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        guard((), || drop(CString::from_raw(s)));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{database::Database as _, zettelkasten::Zettelkasten};
+
+    #[test]
+    fn embeds_a_kasten() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_ffi_test")?;
+        let root_dir = CString::new(tmp_dir.path().to_str().unwrap())?;
+        unsafe {
+            assert!(zk_open(root_dir.as_ptr()).is_null());
+            Database::new(tmp_dir.path().to_path_buf())?.commit(&Zettelkasten::default())?;
+            let handle = zk_open(root_dir.as_ptr());
+            assert!(!handle.is_null());
+            let response = |s: *mut c_char| -> Value {
+                let value = serde_json::from_str(CStr::from_ptr(s).to_str().unwrap()).unwrap();
+                zk_free_string(s);
+                value
+            };
+            let title = CString::new("A note")?;
+            let created = response(zk_new(handle, title.as_ptr()));
+            assert_eq!(created["result"]["title"], "A note");
+            let listed = response(zk_list(handle, ptr::null()));
+            assert_eq!(listed["result"].as_array().unwrap().len(), 1);
+            let pattern = CString::new("(")?;
+            let failed = response(zk_search(handle, pattern.as_ptr()));
+            assert_eq!(failed["error"]["code"], -32602);
+            zk_close(handle);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn panics_stay_on_this_side() {
+        let failed = guard(ptr::null_mut::<c_char>(), || panic!("inside zk"));
+        assert!(failed.is_null());
+        assert_eq!(guard(0, || 1), 1);
+    }
+}
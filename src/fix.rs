@@ -0,0 +1,445 @@
+use crate::{
+    crypt, frontmatter, fsutil, ignore, import, zettel, zettelkasten, zettelkasten::Zettelkasten,
+    DateTime, ZettelMeta,
+};
+use std::{collections::BTreeMap, path::Path};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    FrontmatterError(frontmatter::Error),
+    ZettelkastenError(zettelkasten::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl From<zettelkasten::Error> for Error {
+    fn from(e: zettelkasten::Error) -> Self {
+        Self::ZettelkastenError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::ZettelkastenError(e) => e.fmt(f),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Which problems [`fix`] remedies
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fixes {
+    /// drop database entries for a file that has another zettel's id
+    pub duplicates: bool,
+    /// point zettels whose files were moved by hand at where they are now
+    pub paths: bool,
+    /// give files with frontmatter but no id one, and add them to the database
+    pub ids: bool,
+    /// rewrite `created` and `date` frontmatter in the form zk writes them
+    pub dates: bool,
+    /// record the hash of bodies changed outside of zk
+    pub hashes: bool,
+}
+
+/// A remedy made by [`fix`]; paths are relative to the root directory
+#[derive(Debug, PartialEq)]
+pub enum Fix {
+    AddId {
+        path: String,
+        id: zettel::Id,
+    },
+    NormalizeDate {
+        path: String,
+        key: String,
+        from: String,
+        to: String,
+    },
+    RepairPath {
+        id: zettel::Id,
+        from: String,
+        to: String,
+    },
+    RemoveDuplicate {
+        id: zettel::Id,
+        path: String,
+        kept: zettel::Id,
+    },
+    RegenerateHash {
+        id: zettel::Id,
+    },
+}
+
+impl std::fmt::Display for Fix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AddId { path, id } => write!(f, "{}: added id {}", path, id),
+            Self::NormalizeDate {
+                path,
+                key,
+                from,
+                to,
+            } => write!(f, "{}: {} {} -> {}", path, key, from, to),
+            Self::RepairPath { id, from, to } => write!(f, "{}: moved {} -> {}", id, from, to),
+            Self::RemoveDuplicate { id, path, kept } => {
+                write!(f, "{}: removed, {} belongs to {}", id, path, kept)
+            }
+            Self::RegenerateHash { id } => write!(f, "{}: recorded new hash", id),
+        }
+    }
+}
+
+/// A file that may be a zettel, with its frontmatter unaliased
+struct File {
+    path: String,
+    fm: serde_yaml::Mapping,
+    body: String,
+    modified: DateTime,
+    changed: bool,
+}
+
+impl File {
+    fn id(&self) -> Option<&str> {
+        self.fm.get(&"id".into()).and_then(|id| id.as_str())
+    }
+}
+
+/// files under `dir` that sync would look at and that parse, ordered by path
+fn files(
+    zk: &Zettelkasten,
+    root_dir: &Path,
+    ignore: &ignore::Ignore,
+    dir: &Path,
+) -> Result<Vec<File>> {
+    let mut entries: Vec<std::fs::DirEntry> =
+        std::fs::read_dir(dir)?.collect::<std::io::Result<_>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    let mut found = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let relative = path.strip_prefix(root_dir).unwrap();
+        if name.starts_with("_zettel") || name.starts_with('.') || ignore.is_ignored(relative) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if path != root_dir.join(zk.config.assets_dir()) {
+                found.extend(files(zk, root_dir, ignore, &path)?);
+            }
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if !file_type.is_file() || metadata.len() == 0 {
+            continue;
+        }
+        let (path, (mut fm, body)) = match (
+            fsutil::to_slash(relative),
+            frontmatter::parse_yaml_path(&path),
+        ) {
+            (Some(path), Ok(parsed)) => (path, parsed),
+            _ => continue,
+        };
+        frontmatter::unalias(&mut fm, &zk.config.key_aliases);
+        found.push(File {
+            path,
+            fm,
+            body,
+            modified: metadata.modified()?.into(),
+            changed: false,
+        });
+    }
+    Ok(found)
+}
+
+/// `created` or `date` frontmatter written the way zk writes it, if it can
+/// be read and isn't already; timestamps with any offset are left as they are
+fn normalized_date(key: &str, value: &str, zone: crate::dates::Zone) -> Option<String> {
+    if key == "created" && chrono::DateTime::parse_from_rfc3339(value).is_ok() {
+        return None;
+    }
+    let date = import::parse_date(value.trim())?;
+    let normal = match key {
+        "created" => zone.show(date).to_rfc3339(),
+        _ => date.format("%Y-%m-%d").to_string(),
+    };
+    Some(normal).filter(|normal| normal != value)
+}
+
+/// remedy the problems of `zk` chosen by `fixes` in one transaction, returning
+/// what was done
+///
+/// fixes are made in the order of the fields of [`Fixes`], so duplicates are
+/// dropped before paths are repaired and hashes are recorded last. Files
+/// sync would skip are left alone.
+pub fn fix(zk: &mut Zettelkasten, root_dir: &Path, fixes: Fixes) -> Result<Vec<Fix>> {
+    let ignore = ignore::Ignore::load(root_dir)?;
+    let mut files = files(zk, root_dir, &ignore, root_dir)?;
+    let zone = zk.config.timezone.unwrap_or_default();
+    let scheme = zk.config.id_scheme.unwrap_or_default();
+    zk.transaction(|tx| {
+        let mut done = Vec::new();
+        let mut ids: Vec<zettel::Id> = tx.zettels.keys().cloned().collect();
+        ids.sort();
+        let path_of = |meta: &ZettelMeta| fsutil::to_slash(&meta.relative_path(root_dir));
+        if fixes.duplicates {
+            let mut by_path: BTreeMap<String, Vec<&zettel::Id>> = BTreeMap::new();
+            for id in &ids {
+                if let Some(path) = path_of(&tx.zettels[id]) {
+                    by_path.entry(path).or_default().push(id);
+                }
+            }
+            for (path, claims) in by_path.into_iter().filter(|(_, ids)| ids.len() > 1) {
+                let owner = files
+                    .iter()
+                    .find(|file| file.path == path)
+                    .and_then(|file| file.id())
+                    .filter(|owner| claims.iter().any(|id| id == owner));
+                let owner = match owner {
+                    Some(owner) => owner.to_owned(),
+                    None => continue,
+                };
+                for id in claims.into_iter().filter(|id| **id != owner) {
+                    tx.zettels.remove(id);
+                    done.push(Fix::RemoveDuplicate {
+                        id: id.clone(),
+                        path: path.clone(),
+                        kept: owner.clone(),
+                    });
+                }
+            }
+        }
+        if fixes.paths {
+            for id in &ids {
+                let meta = match tx.zettels.get_mut(id) {
+                    Some(meta) if !meta.full_path(root_dir).exists() => meta,
+                    _ => continue,
+                };
+                let found = files.iter().find(|file| file.id() == Some(id.as_str()));
+                if let Some(file) = found {
+                    done.push(Fix::RepairPath {
+                        id: id.clone(),
+                        from: path_of(meta).unwrap_or_default(),
+                        to: file.path.clone(),
+                    });
+                    meta.path = file.path.clone();
+                }
+            }
+        }
+        if fixes.ids {
+            for file in files.iter_mut().filter(|file| file.id().is_none()) {
+                let known = tx
+                    .zettels
+                    .iter()
+                    .find(|(_, meta)| path_of(meta).as_ref() == Some(&file.path));
+                let id = match known {
+                    Some((id, _)) => id.clone(),
+                    None => {
+                        let stem = Path::new(&file.path).file_stem().unwrap().to_string_lossy();
+                        let mut meta = ZettelMeta::new("", &stem, &file.path, file.modified);
+                        meta.read_state(&file.fm, file.modified);
+                        meta.update_from_frontmatter(&file.fm);
+                        meta.update_from_body(&file.body);
+                        let id = scheme.generate(meta.created, |id| tx.zettels.contains_key(id));
+                        meta.id = id.clone();
+                        tx.zettels.insert(id.clone(), meta);
+                        id
+                    }
+                };
+                file.fm.insert("id".into(), id.as_str().into());
+                file.changed = true;
+                done.push(Fix::AddId {
+                    path: file.path.clone(),
+                    id,
+                });
+            }
+        }
+        if fixes.dates {
+            for file in files.iter_mut() {
+                for key in ["created", "date"] {
+                    let value = match file.fm.get(&key.into()).and_then(|v| v.as_str()) {
+                        Some(value) => value.to_owned(),
+                        None => continue,
+                    };
+                    if let Some(normal) = normalized_date(key, &value, zone) {
+                        file.fm.insert(key.into(), normal.as_str().into());
+                        file.changed = true;
+                        done.push(Fix::NormalizeDate {
+                            path: file.path.clone(),
+                            key: key.to_owned(),
+                            from: value,
+                            to: normal,
+                        });
+                    }
+                }
+            }
+        }
+        if fixes.hashes {
+            for id in &ids {
+                let meta = match tx.zettels.get_mut(id) {
+                    Some(meta) => meta,
+                    None => continue,
+                };
+                let path = path_of(meta);
+                let body = match files.iter().find(|file| Some(&file.path) == path.as_ref()) {
+                    Some(file) => &file.body,
+                    None => continue,
+                };
+                if meta.hash.as_deref() == Some(crate::verify::hash(body).as_str()) {
+                    continue;
+                }
+                // the links and word count of encrypted bodies are kept
+                match crypt::is_encrypted(body) {
+                    true => meta.update_hash(body),
+                    false => meta.update_from_body(body),
+                }
+                done.push(Fix::RegenerateHash { id: id.clone() });
+            }
+        }
+        let aliases = tx.zk().config.key_aliases.clone();
+        for file in files.iter().filter(|file| file.changed) {
+            let path = root_dir.join(fsutil::from_slash(&file.path));
+            let fm = frontmatter::aliased(&file.fm, &aliases);
+            let contents = frontmatter::write_for(&path, &fm, &file.body)?;
+            tx.write(path, contents);
+        }
+        Ok(done)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        database::Database as _,
+        testutil::{self, Kasten},
+    };
+    use chrono::prelude::*;
+
+    const ALL: Fixes = Fixes {
+        duplicates: true,
+        paths: true,
+        ids: true,
+        dates: true,
+        hashes: true,
+    };
+
+    #[test]
+    fn fixes_each_problem() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = Kasten::new();
+        let root_dir = kasten.root_dir().to_path_buf();
+        let db = &kasten.db;
+        let mut zk = Zettelkasten::default();
+        zk.config.timezone = Some("utc".parse().unwrap());
+        let dt = testutil::date();
+        for id in ["a", "b", "c"] {
+            let mut zettel = db.new_zettel(&zk.config, id, id, dt)?;
+            zettel.meta.path = fsutil::to_slash(&root_dir.join(format!("{}.md", id))).unwrap();
+            zettel.content = format!("body of {}\n", id);
+            zettel.meta.update_from_body(&zettel.content);
+            zk.add(db.root_dir(), &zettel)?;
+            zk.zettels.get_mut(id).unwrap().path = format!("{}.md", id);
+        }
+        // a moved by hand, b edited outside of zk, c claimed twice
+        std::fs::create_dir(root_dir.join("moved"))?;
+        std::fs::rename(root_dir.join("a.md"), root_dir.join("moved/a.md"))?;
+        std::fs::write(
+            root_dir.join("b.md"),
+            "---\nid: b\ntitle: b\ncreated: 2015-05-14 12:00\n---\nnew body\n",
+        )?;
+        let mut stray = zk.zettels["c"].clone();
+        stray.id = "stray".to_owned();
+        zk.zettels.insert("stray".to_owned(), stray);
+        std::fs::write(root_dir.join("new.md"), "---\ntitle: New\n---\nfresh\n")?;
+        std::fs::write(root_dir.join("plain.txt"), "no frontmatter\n")?;
+
+        assert!(fix(&mut zk, &root_dir, Fixes::default())?.is_empty());
+        let done = fix(&mut zk, &root_dir, ALL)?;
+        let new_id = zk
+            .zettels
+            .iter()
+            .find(|(_, meta)| meta.path == "new.md")
+            .map(|(id, _)| id.clone())
+            .unwrap();
+        assert_eq!(
+            done,
+            [
+                Fix::RemoveDuplicate {
+                    id: "stray".to_owned(),
+                    path: "c.md".to_owned(),
+                    kept: "c".to_owned()
+                },
+                Fix::RepairPath {
+                    id: "a".to_owned(),
+                    from: "a.md".to_owned(),
+                    to: "moved/a.md".to_owned()
+                },
+                Fix::AddId {
+                    path: "new.md".to_owned(),
+                    id: new_id.clone()
+                },
+                Fix::NormalizeDate {
+                    path: "b.md".to_owned(),
+                    key: "created".to_owned(),
+                    from: "2015-05-14 12:00".to_owned(),
+                    to: dt.with_timezone(&Utc).to_rfc3339()
+                },
+                Fix::RegenerateHash { id: "b".to_owned() },
+            ]
+        );
+        assert_eq!(zk.zettels[&new_id].title, "New");
+        let (fm, _) = frontmatter::parse_yaml_path(root_dir.join("new.md"))?;
+        assert_eq!(fm.get(&"id".into()), Some(&new_id.as_str().into()));
+        assert!(fix(&mut zk, &root_dir, ALL)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn leaves_what_it_cant_fix() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = Kasten::new();
+        let root_dir = kasten.root_dir();
+        kasten.add("gone", "Gone", "body\n");
+        kasten.add("b", "B", "body\n");
+        let mut zk = kasten.add("c", "C", "body\n");
+        // a file that went missing with no other file claiming its id
+        std::fs::remove_file(zk.zettels["gone"].full_path(root_dir))?;
+        // a file claimed twice whose frontmatter names neither claim
+        let shared = zk.zettels["b"].path.clone();
+        zk.zettels.get_mut("c").unwrap().path = shared.clone();
+        let shared = zk.zettels["b"].full_path(root_dir);
+        std::fs::write(&shared, "---\nid: other\ntitle: B\n---\nbody\n")?;
+        // frontmatter that doesn't parse
+        let broken = root_dir.join("broken.md");
+        std::fs::write(&broken, "---\ntitle: [\n---\nbody\n")?;
+        let before = zk.zettels.clone();
+        assert!(fix(&mut zk, root_dir, ALL)?.is_empty());
+        assert_eq!(zk.zettels["gone"], before["gone"]);
+        assert_eq!(zk.zettels["c"].path, before["c"].path);
+        assert_eq!(
+            std::fs::read_to_string(&broken)?,
+            "---\ntitle: [\n---\nbody\n"
+        );
+        assert!(matches!(
+            fix(&mut zk, &root_dir.join("missing"), ALL),
+            Err(Error::IoError(_))
+        ));
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
@@ -8,6 +9,8 @@ use std::path::Path;
 pub enum Error {
     MissingInitialDelimiter,
     MissingFinalDelimiter,
+    /// an org property drawer without `:END:`
+    UnclosedDrawer,
     IoError(std::io::Error),
     SerializationError(serde_yaml::Error),
 }
@@ -31,6 +34,7 @@ impl std::fmt::Display for Error {
         match self {
             Self::MissingInitialDelimiter => f.write_str("missing initial delimiter ---"),
             Self::MissingFinalDelimiter => f.write_str("missing final delimiter ---"),
+            Self::UnclosedDrawer => f.write_str("property drawer missing :END:"),
             Self::IoError(e) => e.fmt(f),
             Self::SerializationError(e) => e.fmt(f),
         }
@@ -39,15 +43,103 @@ impl std::fmt::Display for Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
-pub fn parse_yaml_path(path: impl AsRef<Path>) -> Result<serde_yaml::Mapping> {
+/// Formats of zettel files, told apart by their extension
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    /// markdown with yaml frontmatter between `---` lines
+    #[default]
+    Markdown,
+    /// org-mode with an org-roam `:PROPERTIES:` drawer holding the id and
+    /// `#+title:` style keywords
+    Org,
+}
+
+impl Format {
+    pub fn of(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext == "org" => Self::Org,
+            _ => Self::Markdown,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Markdown => "md",
+            Self::Org => "org",
+        }
+    }
+
+    /// frontmatter from `reader` along with the lines of the body
+    pub fn parse_lines<R: BufRead>(
+        self,
+        reader: R,
+    ) -> Result<(serde_yaml::Mapping, std::io::Lines<R>)> {
+        match self {
+            Self::Markdown => parse_yaml_lines(reader),
+            Self::Org => parse_org_lines(reader),
+        }
+    }
+
+    /// render a complete zettel file from frontmatter and a body
+    pub fn write(self, frontmatter: &serde_yaml::Mapping, body: &str) -> Result<String> {
+        match self {
+            Self::Markdown => write_yaml(frontmatter, body),
+            Self::Org => write_org(frontmatter, body),
+        }
+    }
+}
+
+/// parse frontmatter at path, returning it along with the body that follows;
+/// `.org` files have theirs in org-roam style, see [`Format::Org`]
+pub fn parse_yaml_path(path: impl AsRef<Path>) -> Result<(serde_yaml::Mapping, String)> {
+    let format = Format::of(path.as_ref());
     let file = File::open(&path)?;
-    let mut buf_reader = BufReader::new(file);
-    parse_yaml(&mut buf_reader)
+    let (frontmatter, lines) = format.parse_lines(BufReader::new(file))?;
+    let mut body = String::new();
+    for line in lines {
+        body.push_str(&line?);
+        body.push('\n');
+    }
+    Ok((frontmatter, body))
+}
+
+/// number of the line the body of the file at `path` starts on, to report
+/// lines of the body as lines of the file; both are streamed
+pub fn first_body_line(path: impl AsRef<Path>) -> Result<usize> {
+    let path = path.as_ref();
+    let (_, body) = Format::of(path).parse_lines(BufReader::new(File::open(path)?))?;
+    let body_lines = body.count();
+    let lines = BufReader::new(File::open(path)?).lines().count();
+    Ok(lines.saturating_sub(body_lines) + 1)
+}
+
+/// render the file at `path` from frontmatter and a body, in the format of
+/// its extension
+pub fn write_for(path: &Path, frontmatter: &serde_yaml::Mapping, body: &str) -> Result<String> {
+    Format::of(path).write(frontmatter, body)
 }
 
-pub fn parse_yaml<T: Read>(buf_reader: &mut BufReader<T>) -> Result<serde_yaml::Mapping> {
-    let mut lines = buf_reader.lines().peekable();
-    if !lines.next().unwrap()?.eq("---") {
+pub fn parse_yaml<T: Read>(buf_reader: &mut BufReader<T>) -> Result<(serde_yaml::Mapping, String)> {
+    let (frontmatter, lines) = parse_yaml_lines(buf_reader)?;
+    let mut body = String::new();
+    for line in lines {
+        body.push_str(&line?);
+        body.push('\n');
+    }
+    Ok((frontmatter, body))
+}
+
+/// parse frontmatter from `reader`, returning it along with the lines of
+/// the body, which are only read as they are consumed
+pub fn parse_yaml_lines<R: BufRead>(reader: R) -> Result<(serde_yaml::Mapping, std::io::Lines<R>)> {
+    let mut lines = reader.lines();
+    let frontmatter = read_frontmatter(&mut lines)?;
+    Ok((serde_yaml::from_str(&frontmatter)?, lines))
+}
+
+fn read_frontmatter<T: BufRead>(lines: &mut std::io::Lines<T>) -> Result<String> {
+    if lines.next().transpose()?.as_deref() != Some("---") {
         return Err(Error::MissingInitialDelimiter);
     }
     let mut frontmatter = String::new();
@@ -58,14 +150,225 @@ pub fn parse_yaml<T: Read>(buf_reader: &mut BufReader<T>) -> Result<serde_yaml::
                 break;
             }
             frontmatter.push_str(&line);
-            frontmatter.push_str("\n");
+            frontmatter.push('\n');
         } else {
             return Err(Error::MissingFinalDelimiter);
         }
     }
-    Ok(serde_yaml::from_str(&frontmatter)?)
+    Ok(frontmatter)
+}
+
+/// render a complete zettel file from parsed frontmatter and a body
+pub fn write_yaml(frontmatter: &serde_yaml::Mapping, body: &str) -> Result<String> {
+    let yaml = serde_yaml::to_string(frontmatter)?;
+    let yaml = yaml.strip_prefix("---\n").unwrap_or(&yaml);
+    Ok(format!("---\n{}\n---\n{}", yaml.trim_end(), body))
+}
+
+/// org keywords written as `#+key: value` lines; other keys go in the
+/// property drawer, and `tags` are written as `#+filetags:`
+const ORG_KEYWORDS: [&str; 10] = [
+    "title",
+    "author",
+    "date",
+    "email",
+    "description",
+    "keywords",
+    "language",
+    "category",
+    "options",
+    "startup",
+];
+
+/// parse the property drawer and keywords at the top of an org file; keys
+/// are lowercased and `#+filetags: :a:b:` becomes `tags`
+///
+/// a file without either has no frontmatter rather than failing to parse
+pub fn parse_org_lines<R: BufRead>(
+    mut reader: R,
+) -> Result<(serde_yaml::Mapping, std::io::Lines<R>)> {
+    let mut fm = serde_yaml::Mapping::new();
+    let mut in_drawer = false;
+    let mut line = String::new();
+    loop {
+        let next = reader.fill_buf()?;
+        // the header ends at the first line that is none of it, which is
+        // left for the body
+        if !in_drawer && !next.starts_with(b"#+") && !next.starts_with(b":PROPERTIES:") {
+            break;
+        }
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(Error::UnclosedDrawer);
+        }
+        let text = line.trim_end();
+        if in_drawer {
+            if text.eq_ignore_ascii_case(":END:") {
+                in_drawer = false;
+            } else if let Some((key, value)) =
+                text.strip_prefix(':').and_then(|t| t.split_once(':'))
+            {
+                fm.insert(key.to_lowercase().into(), value.trim().into());
+            }
+        } else if text == ":PROPERTIES:" {
+            in_drawer = true;
+        } else if let Some((key, value)) = text[2..].split_once(':') {
+            match key.to_lowercase().as_str() {
+                "filetags" => {
+                    let tags: Vec<serde_yaml::Value> = value
+                        .split(':')
+                        .map(str::trim)
+                        .filter(|tag| !tag.is_empty())
+                        .map(Into::into)
+                        .collect();
+                    fm.insert("tags".into(), tags.into());
+                }
+                key => {
+                    fm.insert(key.into(), value.trim().into());
+                }
+            }
+        }
+    }
+    Ok((fm, reader.lines()))
+}
+
+/// value of a property or keyword
+fn org_value(value: &serde_yaml::Value) -> Result<String> {
+    Ok(match value {
+        serde_yaml::Value::Null => String::new(),
+        serde_yaml::Value::Bool(b) => b.to_string(),
+        serde_yaml::Value::Number(n) => n.to_string(),
+        serde_yaml::Value::String(s) => s.clone(),
+        serde_yaml::Value::Sequence(items) => items
+            .iter()
+            .map(org_value)
+            .collect::<Result<Vec<_>>>()?
+            .join(" "),
+        value => serde_yaml::to_string(value)?
+            .trim_start_matches("---")
+            .trim()
+            .replace('\n', " "),
+    })
+}
+
+/// render an org file from frontmatter and a body, with the id and other
+/// properties in a drawer followed by keywords
+pub fn write_org(frontmatter: &serde_yaml::Mapping, body: &str) -> Result<String> {
+    let mut drawer = String::new();
+    let mut keywords = String::new();
+    for (key, value) in frontmatter {
+        let key = match key.as_str() {
+            Some(key) => key,
+            None => continue,
+        };
+        if key == "tags" {
+            let tags = match value {
+                serde_yaml::Value::Sequence(tags) => tags
+                    .iter()
+                    .map(org_value)
+                    .collect::<Result<Vec<_>>>()?
+                    .join(":"),
+                tags => org_value(tags)?,
+            };
+            if !tags.is_empty() {
+                keywords.push_str(&format!("#+filetags: :{}:\n", tags));
+            }
+        } else if ORG_KEYWORDS.contains(&key) {
+            keywords.push_str(&format!("#+{}: {}\n", key, org_value(value)?));
+        } else {
+            drawer.push_str(&format!(":{}: {}\n", key.to_uppercase(), org_value(value)?));
+        }
+    }
+    let drawer = match drawer.is_empty() {
+        true => drawer,
+        false => format!(":PROPERTIES:\n{}:END:\n", drawer),
+    };
+    Ok(format!("{}{}{}", drawer, keywords, body))
+}
+
+/// rename keys of `fm` written under an alias back to zk's own names, where
+/// `aliases` maps zk's keys to the names used in files
+///
+/// an alias is ignored when zk's own key is there too
+pub fn unalias(fm: &mut serde_yaml::Mapping, aliases: &BTreeMap<String, String>) {
+    for (key, alias) in aliases {
+        if fm.contains_key(&key.as_str().into()) {
+            continue;
+        }
+        *fm = std::mem::take(fm)
+            .into_iter()
+            .map(|(k, v)| match k.as_str() {
+                Some(k) if k == alias => (key.as_str().into(), v),
+                _ => (k, v),
+            })
+            .collect();
+    }
+}
+
+/// `fm` with zk's own keys renamed to their aliases, for writing to a file
+pub fn aliased(
+    fm: &serde_yaml::Mapping,
+    aliases: &BTreeMap<String, String>,
+) -> serde_yaml::Mapping {
+    fm.iter()
+        .map(|(k, v)| match k.as_str().and_then(|k| aliases.get(k)) {
+            Some(alias) => (alias.as_str().into(), v.clone()),
+            None => (k.clone(), v.clone()),
+        })
+        .collect()
 }
 
 pub fn write_str(frontmatter: &HashMap<String, String>) -> Result<String> {
     Ok(serde_yaml::to_string(frontmatter)?)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn org_round_trip() -> Result<()> {
+        let text = ":PROPERTIES:\n:ID:       3f2a\n:ROAM_ALIASES: other\n:END:\n#+title: A note\n#+filetags: :one:two:\n\nSee [[id:9c1e][another]].\n";
+        let (fm, lines) = parse_org_lines(text.as_bytes())?;
+        assert_eq!(fm.get(&"id".into()), Some(&"3f2a".into()));
+        assert_eq!(fm.get(&"title".into()), Some(&"A note".into()));
+        assert_eq!(
+            fm.get(&"tags".into()),
+            Some(&serde_yaml::Value::from(vec!["one", "two"]))
+        );
+        let body: Vec<String> = lines.collect::<std::io::Result<_>>()?;
+        assert_eq!(body, ["", "See [[id:9c1e][another]]."]);
+        let written = write_org(&fm, "\nbody\n")?;
+        assert_eq!(
+            written,
+            ":PROPERTIES:\n:ID: 3f2a\n:ROAM_ALIASES: other\n:END:\n#+title: A note\n#+filetags: :one:two:\n\nbody\n"
+        );
+        let (plain, _) = parse_org_lines("* Heading\n".as_bytes())?;
+        assert!(plain.is_empty());
+        assert!(matches!(
+            parse_org_lines(":PROPERTIES:\n:ID: x\n".as_bytes()),
+            Err(Error::UnclosedDrawer)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn body_line_offsets() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_frontmatter_test")?;
+        let md = tmp_dir.path().join("note.md");
+        std::fs::write(&md, "---\ntitle: A\ntags: [x]\n---\nfirst\nsecond\n")?;
+        assert_eq!(first_body_line(&md)?, 5);
+        let org = tmp_dir.path().join("note.org");
+        std::fs::write(&org, ":PROPERTIES:\n:ID: a\n:END:\n#+title: A\nfirst\n")?;
+        assert_eq!(first_body_line(&org)?, 5);
+        let empty = tmp_dir.path().join("empty.md");
+        std::fs::write(&empty, "---\ntitle: A\n---\n")?;
+        assert_eq!(first_body_line(&empty)?, 4);
+        std::fs::write(&empty, "")?;
+        assert!(matches!(
+            parse_yaml_path(&empty),
+            Err(Error::MissingInitialDelimiter)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Identity of a file or directory, the same through every link to it
+#[cfg(unix)]
+pub type FileId = (u64, u64);
+#[cfg(not(unix))]
+pub type FileId = PathBuf;
+
+/// identity of what `path` points to, following symlinks
+#[cfg(unix)]
+pub fn file_id(path: &Path) -> std::io::Result<FileId> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = std::fs::metadata(path)?;
+    Ok((meta.dev(), meta.ino()))
+}
+
+/// identity of what `path` points to, following symlinks; hard links can't
+/// be told apart without inodes
+#[cfg(not(unix))]
+pub fn file_id(path: &Path) -> std::io::Result<FileId> {
+    std::fs::canonicalize(path)
+}
+
+/// `path` with `/` separators, as stored in the database so a kasten reads
+/// the same on every platform; `None` if it isn't UTF-8
+pub fn to_slash(path: &Path) -> Option<String> {
+    let path = path.to_str()?;
+    match std::path::MAIN_SEPARATOR {
+        '/' => Some(path.to_owned()),
+        sep => Some(path.replace(sep, "/")),
+    }
+}
+
+/// `path` as stored in the database: relative to `root_dir` if it is under
+/// it, with `/` separators; `None` if it isn't UTF-8
+pub fn to_stored(root_dir: &Path, path: &Path) -> Option<String> {
+    to_slash(path.strip_prefix(root_dir).unwrap_or(path))
+}
+
+/// platform path of a path stored with `/` separators
+pub fn from_slash(path: &str) -> PathBuf {
+    match std::path::MAIN_SEPARATOR {
+        '/' => PathBuf::from(path),
+        sep => PathBuf::from(path.replace('/', &sep.to_string())),
+    }
+}
+
+/// `path`, a `/` separated path from outside such as an archive entry or
+/// a server's listing, as a path relative to the directory it is meant for;
+/// `None` if it could lead anywhere else, through `..`, a root or drive, or
+/// backslashes
+pub fn contained(path: &str) -> Option<PathBuf> {
+    use std::path::Component;
+    if path.contains('\\') {
+        return None;
+    }
+    let path = from_slash(path);
+    let normal = path.components().all(|c| match c {
+        Component::Normal(_) | Component::CurDir => true,
+        Component::ParentDir | Component::RootDir | Component::Prefix(_) => false,
+    });
+    match normal && path.components().any(|c| matches!(c, Component::Normal(_))) {
+        true => Some(path),
+        false => None,
+    }
+}
+
+/// names Windows reserves for devices, whatever the extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// `name` made usable as a file name on Linux, macOS and Windows alike
+///
+/// characters Windows forbids become `-`, trailing dots and spaces are
+/// dropped and reserved device names get a trailing `_`
+pub fn sanitize_file_name(name: &str) -> String {
+    let mut name: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '-',
+            c if c.is_control() => '-',
+            c => c,
+        })
+        .collect();
+    name.truncate(name.trim_end_matches(['.', ' ']).len());
+    let stem = name.split('.').next().unwrap_or_default();
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        name.insert(stem.len(), '_');
+    }
+    name
+}
+
+/// how long to wait for a [`Lock`] held by someone else
+const LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// age after which a lock is taken to be left behind by a process that
+/// died holding it
+const STALE_LOCK: Duration = Duration::from_secs(60);
+
+/// Lock held across processes by creating a file, removed again when the
+/// lock is dropped
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    /// wait until the lock file at `path` can be created
+    pub fn acquire(path: &Path) -> std::io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let start = Instant::now();
+        loop {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(path)
+            {
+                Ok(_) => {
+                    return Ok(Self {
+                        path: path.to_path_buf(),
+                    })
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+            let stale = std::fs::metadata(path)
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > STALE_LOCK);
+            if stale {
+                tracing::warn!("removing stale lock {}", path.display());
+                let _ = std::fs::remove_file(path);
+            } else if start.elapsed() > LOCK_TIMEOUT {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::WouldBlock,
+                    format!("{} is locked", path.display()),
+                ));
+            } else {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// write `path` through a temporary file renamed over it, so readers see
+/// either the old or the new contents
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })
+}
+
+/// Record of file changes made so far, to put the files back as they were
+/// when a later change fails
+#[derive(Debug, Default)]
+pub struct Journal {
+    undo: Vec<Undo>,
+    /// directories created for new files, removed again on rollback
+    dirs: Vec<PathBuf>,
+}
+
+/// How to take back a change made through a [`Journal`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Undo {
+    /// previous contents of a file, `None` if it didn't exist
+    Restore(PathBuf, Option<Vec<u8>>),
+    Rename {
+        from: PathBuf,
+        to: PathBuf,
+    },
+}
+
+impl Journal {
+    /// write `contents` to `path`, creating the directories leading to it
+    pub fn write(&mut self, path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+        let previous = if path.exists() {
+            Some(std::fs::read(path)?)
+        } else {
+            None
+        };
+        if let Some(dir) = path.parent() {
+            self.create_dir_all(dir)?;
+        }
+        std::fs::write(path, contents)?;
+        self.undo.push(Undo::Restore(path.to_path_buf(), previous));
+        Ok(())
+    }
+
+    /// move `from` to `to`, creating the directories leading to it
+    pub fn rename(&mut self, from: &Path, to: &Path) -> std::io::Result<()> {
+        if let Some(dir) = to.parent() {
+            self.create_dir_all(dir)?;
+        }
+        std::fs::rename(from, to)?;
+        self.undo.push(Undo::Rename {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, path: &Path) -> std::io::Result<()> {
+        let previous = std::fs::read(path)?;
+        std::fs::remove_file(path)?;
+        self.undo
+            .push(Undo::Restore(path.to_path_buf(), Some(previous)));
+        Ok(())
+    }
+
+    fn create_dir_all(&mut self, dir: &Path) -> std::io::Result<()> {
+        let missing: Vec<&Path> = dir
+            .ancestors()
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .collect();
+        std::fs::create_dir_all(dir)?;
+        self.dirs
+            .extend(missing.into_iter().rev().map(Path::to_path_buf));
+        Ok(())
+    }
+
+    /// the changes made, oldest first, to be taken back later
+    pub fn into_undo(self) -> Vec<Undo> {
+        self.undo
+    }
+
+    /// undo every change, latest first
+    pub fn rollback(self) {
+        for undo in self.undo.into_iter().rev() {
+            // best effort: the error that caused the rollback is more useful
+            // than one from restoring
+            let _ = match undo {
+                Undo::Restore(path, Some(previous)) => std::fs::write(path, previous),
+                Undo::Restore(path, None) => std::fs::remove_file(path),
+                Undo::Rename { from, to } => std::fs::rename(to, from),
+            };
+        }
+        for dir in self.dirs.into_iter().rev() {
+            let _ = std::fs::remove_dir(dir);
+        }
+    }
+}
+
+/// write every file, restoring the previous contents of files already
+/// written if one of the writes fails
+///
+/// files that did not exist before are removed again on failure
+pub fn write_all_or_restore(files: &[(PathBuf, String)]) -> std::io::Result<()> {
+    let mut journal = Journal::default();
+    for (path, contents) in files {
+        if let Err(e) = journal.write(path, contents) {
+            journal.rollback();
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn restores_on_failure() -> std::io::Result<()> {
+        let tmp_dir = tempdir::TempDir::new("zk_fsutil_test")?;
+        let existing = tmp_dir.path().join("existing.md");
+        let created = tmp_dir.path().join("created.md");
+        std::fs::write(&existing, "before")?;
+        let result = write_all_or_restore(&[
+            (existing.clone(), "after".to_owned()),
+            (created.clone(), "new".to_owned()),
+            (tmp_dir.path().join("new/dir.md"), "new".to_owned()),
+            // a file can't be made a directory
+            (existing.join("dir.md"), "fails".to_owned()),
+        ]);
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(&existing)?, "before");
+        assert!(!created.exists());
+        assert!(!tmp_dir.path().join("new").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn contained_paths() {
+        assert_eq!(contained("a/./b.md"), Some(PathBuf::from("a/./b.md")));
+        for path in ["../a", "a/../../b", "/etc/passwd", "a\\..\\b", "", "."] {
+            assert_eq!(contained(path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn portable_names() {
+        assert_eq!(sanitize_file_name("a: b/c?"), "a- b-c-");
+        assert_eq!(sanitize_file_name("notes. "), "notes");
+        assert_eq!(sanitize_file_name("con"), "con_");
+        assert_eq!(sanitize_file_name("Aux.md"), "Aux_.md");
+        assert_eq!(sanitize_file_name("console.md"), "console.md");
+        let path = Path::new("dir").join("a.md");
+        assert_eq!(to_slash(&path).as_deref(), Some("dir/a.md"));
+        assert_eq!(from_slash("dir/a.md"), path);
+    }
+}
@@ -0,0 +1,60 @@
+use regex::Regex;
+
+/// line numbers, starting at 1, of lines matching `re`
+pub fn matching_lines(re: &Regex, text: &str) -> Vec<usize> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| re.is_match(line))
+        .map(|(n, _)| n + 1)
+        .collect()
+}
+
+/// matching lines as `n:line` with up to `context` surrounding lines as
+/// `n-line`, separating groups that aren't adjacent with `--`
+///
+/// line numbers are offset by `first_line - 1`, so they can refer to the
+/// whole file when `text` is only its body
+pub fn format_matches(text: &str, matches: &[usize], context: usize, first_line: usize) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut out = String::new();
+    let mut last_printed = 0;
+    for &n in matches {
+        let start = n.saturating_sub(context).max(last_printed + 1);
+        let end = (n + context).min(lines.len());
+        if last_printed > 0 && start > last_printed + 1 {
+            out.push_str("--\n");
+        }
+        for line in start..=end {
+            let sep = if matches.contains(&line) { ':' } else { '-' };
+            out.push_str(&format!(
+                "{}{}{}\n",
+                line + first_line - 1,
+                sep,
+                lines[line - 1]
+            ));
+        }
+        last_printed = last_printed.max(end);
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn grep_with_context() {
+        let text = "a\nfoo\nb\nc\nd\ne\nfoo\nfoo\nf\n";
+        let re = Regex::new("fo+").unwrap();
+        let matches = matching_lines(&re, text);
+        assert_eq!(matches, vec![2, 7, 8]);
+        assert_eq!(
+            format_matches(text, &matches, 1, 4),
+            "4-a\n5:foo\n6-b\n--\n9-e\n10:foo\n11:foo\n12-f\n"
+        );
+        assert_eq!(
+            format_matches(text, &matches, 0, 1),
+            "2:foo\n--\n7:foo\n8:foo\n"
+        );
+    }
+}
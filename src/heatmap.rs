@@ -0,0 +1,243 @@
+use crate::{dates, zettelkasten::Zettelkasten};
+use chrono::{Datelike, Duration, NaiveDate};
+use std::collections::BTreeMap;
+
+/// cells of [`Heatmap::to_text`] from no activity to the most
+const SHADES: [char; 5] = ['·', '░', '▒', '▓', '█'];
+
+/// fills of [`Heatmap::to_svg`], GitHub's greens
+const COLORS: [&str; 5] = ["#ebedf0", "#9be9a8", "#40c463", "#30a14e", "#216e39"];
+
+/// side of a day in [`Heatmap::to_svg`], and the gap between days
+const CELL: usize = 11;
+const GAP: usize = 2;
+
+/// Formats of `zk stats --heatmap`
+#[derive(Debug, Clone, Copy, PartialEq, Default, clap::ValueEnum)]
+pub enum Format {
+    /// a calendar of shaded cells
+    #[default]
+    Text,
+    Json,
+    Svg,
+}
+
+/// What counts as activity on a day
+#[derive(Debug, Clone, Copy, PartialEq, Default, clap::ValueEnum)]
+pub enum Activity {
+    Created,
+    Modified,
+    /// zettels created or modified, each counted once a day
+    #[default]
+    Both,
+}
+
+/// Zettels active per day over whole weeks, Sunday to Saturday
+#[derive(Debug, PartialEq)]
+pub struct Heatmap {
+    /// a Sunday
+    pub start: NaiveDate,
+    /// the last day shown, the weeks after it left blank
+    pub end: NaiveDate,
+    /// days with any activity
+    pub counts: BTreeMap<NaiveDate, usize>,
+}
+
+/// activity in `zk` over `weeks` weeks up to `end`, with days as seen in
+/// `zone`
+pub fn heatmap(
+    zk: &Zettelkasten,
+    end: NaiveDate,
+    weeks: usize,
+    activity: Activity,
+    zone: dates::Zone,
+) -> Heatmap {
+    let sunday = end - Duration::days(end.weekday().num_days_from_sunday() as i64);
+    let start = sunday - Duration::weeks(weeks.max(1) as i64 - 1);
+    let mut counts = BTreeMap::new();
+    for meta in zk.zettels.values() {
+        let created = zone.show(meta.created).date().naive_local();
+        let modified = zone.show(meta.modified).date().naive_local();
+        let days = match activity {
+            Activity::Created => vec![created],
+            Activity::Modified => vec![modified],
+            Activity::Both if created == modified => vec![created],
+            Activity::Both => vec![created, modified],
+        };
+        for day in days.into_iter().filter(|d| (start..=end).contains(d)) {
+            *counts.entry(day).or_insert(0) += 1;
+        }
+    }
+    Heatmap { start, end, counts }
+}
+
+impl Heatmap {
+    fn weeks(&self) -> usize {
+        ((self.end - self.start).num_days() / 7 + 1) as usize
+    }
+
+    fn day(&self, week: usize, weekday: usize) -> NaiveDate {
+        self.start + Duration::days((week * 7 + weekday) as i64)
+    }
+
+    /// 0 for no activity, up to 4 for the busiest days
+    fn level(&self, count: usize) -> usize {
+        let max = self.counts.values().copied().max().unwrap_or(0);
+        match count {
+            0 => 0,
+            count => (4 * count).div_ceil(max).clamp(1, 4),
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.values().sum()
+    }
+
+    /// a row per weekday and a column per week, under the months' names
+    pub fn to_text(&self) -> String {
+        let mut months = String::new();
+        let mut last = None;
+        for week in 0..self.weeks() {
+            let month = self.day(week, 0).format("%b").to_string();
+            if last.as_ref() == Some(&month) {
+                continue;
+            }
+            // a name is left out where the one before it would overlap it
+            if months.chars().count() <= 4 + week {
+                months = format!("{:<width$}{}", months, month, width = 4 + week);
+            }
+            last = Some(month);
+        }
+        let mut out = format!("{}\n", months.trim_end());
+        for weekday in 0..7 {
+            let label = match weekday {
+                1 => "Mon",
+                3 => "Wed",
+                5 => "Fri",
+                _ => "",
+            };
+            let mut row = format!("{:<4}", label);
+            for week in 0..self.weeks() {
+                let day = self.day(week, weekday);
+                if day > self.end {
+                    break;
+                }
+                let count = self.counts.get(&day).copied().unwrap_or(0);
+                row.push(SHADES[self.level(count)]);
+            }
+            out.push_str(row.trim_end());
+            out.push('\n');
+        }
+        out.push_str(&format!(
+            "{} from {} to {}   less {} more\n",
+            self.total(),
+            self.start,
+            self.end,
+            SHADES.iter().collect::<String>()
+        ));
+        out
+    }
+
+    /// every day from the start to the end with its count
+    pub fn to_json(&self) -> String {
+        let days: Vec<serde_json::Value> = (0..=(self.end - self.start).num_days())
+            .map(|n| self.start + Duration::days(n))
+            .map(|day| {
+                serde_json::json!({
+                    "date": day.to_string(),
+                    "count": self.counts.get(&day).copied().unwrap_or(0),
+                })
+            })
+            .collect();
+        serde_json::to_string_pretty(&serde_json::json!({
+            "start": self.start.to_string(),
+            "end": self.end.to_string(),
+            "total": self.total(),
+            "days": days,
+        }))
+        .expect("heatmaps serialize")
+    }
+
+    /// a standalone image with a square per day, titled with its count
+    pub fn to_svg(&self) -> String {
+        let step = CELL + GAP;
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+            self.weeks() * step,
+            7 * step
+        );
+        for week in 0..self.weeks() {
+            for weekday in 0..7 {
+                let day = self.day(week, weekday);
+                if day > self.end {
+                    break;
+                }
+                let count = self.counts.get(&day).copied().unwrap_or(0);
+                out.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"2\" fill=\"{}\"><title>{}: {}</title></rect>\n",
+                    week * step,
+                    weekday * step,
+                    CELL,
+                    CELL,
+                    COLORS[self.level(count)],
+                    day,
+                    count
+                ));
+            }
+        }
+        out.push_str("</svg>\n");
+        out
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Text => self.to_text(),
+            Format::Json => self.to_json() + "\n",
+            Format::Svg => self.to_svg(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ZettelMeta;
+    use chrono::prelude::*;
+
+    #[test]
+    fn counts_per_day() {
+        let utc: dates::Zone = "utc".parse().unwrap();
+        let day = |d: u32| {
+            chrono::Utc
+                .ymd(2023, 5, d)
+                .and_hms(12, 0, 0)
+                .with_timezone(&Local)
+        };
+        let mut zk = Zettelkasten::default();
+        for (id, created, modified) in [("a", 1, 17), ("b", 17, 17), ("c", 3, 3), ("d", 3, 3)] {
+            let mut meta = ZettelMeta::new(id, id, "z.md", day(created));
+            meta.modified = day(modified);
+            zk.zettels.insert(id.to_owned(), meta);
+        }
+        // Wednesday the 17th
+        let end = NaiveDate::from_ymd(2023, 5, 17);
+        let map = heatmap(&zk, end, 2, Activity::Both, utc);
+        assert_eq!(map.start, NaiveDate::from_ymd(2023, 5, 7));
+        assert_eq!(map.counts.get(&NaiveDate::from_ymd(2023, 5, 17)), Some(&2));
+        // the 1st and 3rd are before the first week shown
+        assert_eq!(map.total(), 2);
+        let map = heatmap(&zk, end, 3, Activity::Created, utc);
+        assert_eq!(map.start, NaiveDate::from_ymd(2023, 4, 30));
+        assert_eq!(map.counts.values().copied().collect::<Vec<_>>(), [1, 2, 1]);
+        let text = map.to_text();
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(rows[0], "    Apr");
+        // Mondays: the 1st, 8th and 15th
+        assert_eq!(rows[2], "Mon ▒··");
+        // Wednesdays: the 3rd is the busiest day, the 17th the last shown
+        assert_eq!(rows[4], "Wed █·▒");
+        assert_eq!(rows[5], "    ··");
+        assert!(map.to_svg().contains("<title>2023-05-03: 2</title>"));
+        assert!(map.to_json().contains("\"total\": 4"));
+    }
+}
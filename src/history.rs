@@ -0,0 +1,116 @@
+use crate::{zettel, ZettelMeta};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs::File, path::Path};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    SerializationError(serde_yaml::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(e: serde_yaml::Error) -> Self {
+        Self::SerializationError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::SerializationError(e) => e.fmt(f),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// number of zettels remembered
+pub const MAX_ENTRIES: usize = 50;
+
+/// Zettels recently created, shown or changed by zk, most recent first
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct History {
+    pub ids: Vec<zettel::Id>,
+}
+
+impl History {
+    /// path of the state file under the root directory
+    pub fn path(root_dir: &Path) -> std::path::PathBuf {
+        root_dir.join(".zk").join("history.yaml")
+    }
+
+    pub fn load(root_dir: &Path) -> Result<Self> {
+        let path = Self::path(root_dir);
+        if path.is_file() {
+            Ok(serde_yaml::from_reader(File::open(path)?)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self, root_dir: &Path) -> Result<()> {
+        let path = Self::path(root_dir);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        serde_yaml::to_writer(File::create(path)?, self)?;
+        Ok(())
+    }
+
+    /// move `id` to the front
+    pub fn touch(&mut self, id: &str) {
+        self.ids.retain(|i| i != id);
+        self.ids.insert(0, id.to_owned());
+        self.ids.truncate(MAX_ENTRIES);
+    }
+
+    /// forget zettels that no longer exist
+    pub fn retain_known(&mut self, zettels: &HashMap<zettel::Id, ZettelMeta>) {
+        self.ids.retain(|id| zettels.contains_key(id));
+    }
+}
+
+/// add zettels to the history in the root directory, the last one ending up
+/// most recent
+///
+/// failing to record history shouldn't fail the command that touched the
+/// zettels, so errors are only reported
+pub fn record<'a>(root_dir: &Path, ids: impl IntoIterator<Item = &'a str>) {
+    let result = History::load(root_dir).and_then(|mut history| {
+        for id in ids {
+            history.touch(id);
+        }
+        history.save(root_dir)
+    });
+    if let Err(e) = result {
+        tracing::warn!("couldn't update history: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn most_recent_first() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_history_test")?;
+        record(tmp_dir.path(), ["a", "b"]);
+        record(tmp_dir.path(), ["c", "a"]);
+        assert_eq!(History::load(tmp_dir.path())?.ids, vec!["a", "c", "b"]);
+        record(tmp_dir.path(), (0..MAX_ENTRIES).map(|_| "d"));
+        let mut history = History::load(tmp_dir.path())?;
+        assert_eq!(history.ids.len(), 4);
+        history.retain_known(&Default::default());
+        assert!(history.ids.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use crate::ZettelMeta;
+use serde::{Deserialize, Serialize};
+use std::{io::Write, path::Path, process::Stdio};
+
+/// Shell commands run when the zettelkasten changes
+///
+/// each command runs from the root directory with `ZK_EVENT`, `ZK_ROOT_DIR`
+/// and `ZK_IDS` set, plus `ZK_ID`, `ZK_TITLE` and `ZK_PATH` when a single
+/// zettel is affected; the metadata of the affected zettels is written to
+/// its stdin as JSON
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Hooks {
+    /// after zettels are created
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_new: Vec<String>,
+    /// after `sync`, with the zettels whose metadata changed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_sync: Vec<String>,
+    /// after the database is written
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_commit: Vec<String>,
+    /// after zettels are deleted, merged into others or archived
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_delete: Vec<String>,
+}
+
+impl Hooks {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    New,
+    Sync,
+    Commit,
+    Delete,
+}
+
+impl Event {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::New => "new",
+            Self::Sync => "sync",
+            Self::Commit => "commit",
+            Self::Delete => "delete",
+        }
+    }
+
+    fn commands(self, hooks: &Hooks) -> &[String] {
+        match self {
+            Self::New => &hooks.on_new,
+            Self::Sync => &hooks.on_sync,
+            Self::Commit => &hooks.on_commit,
+            Self::Delete => &hooks.on_delete,
+        }
+    }
+}
+
+/// JSON written to the stdin of hooks
+fn payload(root_dir: &Path, event: Event, zettels: &[(&str, &ZettelMeta)]) -> serde_json::Value {
+    let zettels: Vec<serde_json::Value> = zettels
+        .iter()
+        .map(|(id, meta)| {
+            let mut value = serde_json::to_value(meta).unwrap_or_default();
+            // the id is only stored as a key of the database
+            if let Some(map) = value.as_object_mut() {
+                map.insert("id".to_owned(), (*id).into());
+            }
+            value
+        })
+        .collect();
+    serde_json::json!({
+        "event": event.name(),
+        "root_dir": root_dir.to_string_lossy(),
+        "zettels": zettels,
+    })
+}
+
+fn shell(command: &str) -> std::process::Command {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let mut cmd = std::process::Command::new(shell);
+    cmd.arg(flag).arg(command);
+    cmd
+}
+
+fn run_one(
+    command: &str,
+    root_dir: &Path,
+    env: &[(&str, String)],
+    input: &[u8],
+) -> std::io::Result<()> {
+    let mut child = shell(command)
+        .current_dir(root_dir)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .stdin(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(input) {
+            // hooks don't have to read their input
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+            result => result?,
+        }
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!("exited with {}", status)));
+    }
+    Ok(())
+}
+
+/// run the hooks for `event` about `zettels`, given by id
+///
+/// a failing hook shouldn't fail the command whose changes are already
+/// written, so errors are only reported
+pub fn run(root_dir: &Path, hooks: &Hooks, event: Event, zettels: &[(&str, &ZettelMeta)]) {
+    let commands = event.commands(hooks);
+    if commands.is_empty() {
+        return;
+    }
+    let mut env = vec![
+        ("ZK_EVENT", event.name().to_owned()),
+        ("ZK_ROOT_DIR", root_dir.to_string_lossy().into_owned()),
+        (
+            "ZK_IDS",
+            zettels
+                .iter()
+                .map(|(id, _)| *id)
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+    ];
+    if let [(id, meta)] = zettels {
+        env.push(("ZK_ID", id.to_string()));
+        env.push(("ZK_TITLE", meta.title.clone()));
+        env.push((
+            "ZK_PATH",
+            meta.full_path(root_dir).to_string_lossy().into_owned(),
+        ));
+    }
+    let input = payload(root_dir, event, zettels).to_string();
+    for command in commands {
+        if let Err(e) = run_one(command, root_dir, &env, input.as_bytes()) {
+            tracing::warn!("{} hook `{}` failed: {}", event.name(), command, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::prelude::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn passes_metadata() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_hooks_test")?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let meta = ZettelMeta::new("", "A note", "a.md", dt);
+        let hooks = Hooks {
+            on_new: vec![
+                "echo \"$ZK_EVENT $ZK_ID $ZK_TITLE\" > env.txt".to_owned(),
+                "cat > stdin.json".to_owned(),
+                "exit 1".to_owned(),
+            ],
+            ..Default::default()
+        };
+        run(tmp_dir.path(), &hooks, Event::New, &[("abc", &meta)]);
+        let env = std::fs::read_to_string(tmp_dir.path().join("env.txt"))?;
+        assert_eq!(env, "new abc A note\n");
+        let stdin = std::fs::read_to_string(tmp_dir.path().join("stdin.json"))?;
+        let stdin: serde_json::Value = serde_json::from_str(&stdin)?;
+        assert_eq!(stdin["zettels"][0]["id"], "abc");
+        assert_eq!(stdin["zettels"][0]["title"], "A note");
+        Ok(())
+    }
+}
@@ -0,0 +1,424 @@
+use crate::{import, outline};
+use regex::Regex;
+use std::path::{Path, PathBuf};
+
+/// `s` with character references like `&amp;` and `&#39;` replaced
+pub fn unescape(s: &str) -> String {
+    let re = Regex::new(r"&(#[0-9]+|#[xX][0-9a-fA-F]+|[a-zA-Z]+);").unwrap();
+    re.replace_all(s, |caps: &regex::Captures| {
+        let name = &caps[1];
+        let c = match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => name
+                    .strip_prefix('#')
+                    .and_then(|n| n.parse().ok())
+                    .and_then(char::from_u32),
+            },
+        };
+        c.map_or_else(|| caps[0].to_owned(), String::from)
+    })
+    .into_owned()
+}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Open(String, Vec<(String, String)>),
+    Close(String),
+    Text(&'a str),
+}
+
+/// tags and text of `html`, leaving out comments and declarations
+fn tokens(html: &str) -> Vec<Token<'_>> {
+    let attr =
+        Regex::new(r#"([a-zA-Z_:][-a-zA-Z0-9_:.]*)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s>]+))"#)
+            .unwrap();
+    let mut tokens = Vec::new();
+    let mut rest = html;
+    while let Some(at) = rest.find('<') {
+        if at > 0 {
+            tokens.push(Token::Text(&rest[..at]));
+        }
+        rest = &rest[at..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let name_len = tag
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(tag.len());
+        let name = tag[..name_len].to_ascii_lowercase();
+        if name.is_empty() {
+            tokens.push(Token::Text("<"));
+            continue;
+        }
+        if closing {
+            tokens.push(Token::Close(name));
+        } else {
+            let attrs = attr
+                .captures_iter(&tag[name_len..])
+                .map(|caps| {
+                    let value = caps.get(2).or(caps.get(3)).or(caps.get(4)).unwrap();
+                    (caps[1].to_ascii_lowercase(), unescape(value.as_str()))
+                })
+                .collect();
+            tokens.push(Token::Open(name, attrs));
+        }
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    tokens
+}
+
+/// Markdown being written from HTML
+#[derive(Default)]
+struct Writer {
+    out: String,
+    /// line breaks owed before the next text
+    breaks: usize,
+    quotes: usize,
+    /// open lists, with the number of the next item of ordered ones
+    lists: Vec<Option<usize>>,
+    /// marker of a list item that has no text yet
+    marker: Option<String>,
+    links: Vec<Option<String>>,
+    pre: bool,
+}
+
+impl Writer {
+    fn need(&mut self, breaks: usize) {
+        if !self.out.is_empty() {
+            self.breaks = self.breaks.max(breaks);
+        }
+    }
+
+    fn at_line_start(&self) -> bool {
+        self.out.is_empty() || self.out.ends_with('\n')
+    }
+
+    /// write owed line breaks, and the prefix of a new line
+    fn flush(&mut self) {
+        if self.breaks > 0 {
+            let trimmed = self.out.trim_end_matches(' ').len();
+            self.out.truncate(trimmed);
+            for _ in 0..self.breaks {
+                self.out.push('\n');
+            }
+            self.breaks = 0;
+        }
+        if self.at_line_start() {
+            self.out.push_str(&"> ".repeat(self.quotes));
+            match self.marker.take() {
+                Some(marker) => self.out.push_str(&marker),
+                None => self.out.push_str(&"  ".repeat(self.lists.len())),
+            }
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        let text = unescape(text);
+        if self.pre {
+            self.out.push_str(&text);
+            return;
+        }
+        let words: Vec<&str> = text.split_whitespace().collect();
+        if text.starts_with(char::is_whitespace)
+            && self.breaks == 0
+            && !self.at_line_start()
+            && !self.out.ends_with(' ')
+        {
+            self.out.push(' ');
+        }
+        if words.is_empty() {
+            return;
+        }
+        self.flush();
+        self.out.push_str(&words.join(" "));
+        if text.ends_with(char::is_whitespace) {
+            self.out.push(' ');
+        }
+    }
+
+    /// write the marker opening inline formatting
+    fn open(&mut self, marker: &str) {
+        self.flush();
+        self.out.push_str(marker);
+    }
+
+    /// write the marker closing inline formatting, before any trailing space
+    fn close(&mut self, marker: &str) {
+        let space = self.out.ends_with(' ');
+        if space {
+            self.out.pop();
+        }
+        self.out.push_str(marker);
+        if space {
+            self.out.push(' ');
+        }
+    }
+}
+
+/// markdown for the body of `html`, with headings, paragraphs, lists, quotes,
+/// code, links, images and emphasis kept
+///
+/// anything else is reduced to its text, and the head, scripts and styles are
+/// left out
+pub fn to_markdown(html: &str) -> String {
+    let mut w = Writer::default();
+    let mut skip: Option<String> = None;
+    for token in tokens(html) {
+        if let Some(until) = &skip {
+            if token == Token::Close(until.clone()) {
+                skip = None;
+            }
+            continue;
+        }
+        let attr = |attrs: &[(String, String)], name: &str| {
+            attrs
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+        };
+        match token {
+            Token::Text(text) => w.text(text),
+            Token::Open(name, attrs) => match name.as_str() {
+                "head" | "script" | "style" | "title" => skip = Some(name),
+                "p" | "table" => w.need(2),
+                "div" | "tr" => w.need(1),
+                "br" => {
+                    w.breaks += 1;
+                    if w.out.is_empty() {
+                        w.out.push('\n');
+                    }
+                }
+                "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                    w.need(2);
+                    let level = name[1..].parse().unwrap_or(1);
+                    w.open(&format!("{} ", "#".repeat(level)));
+                }
+                "blockquote" => {
+                    w.need(2);
+                    w.quotes += 1;
+                }
+                "ul" | "ol" => {
+                    w.need(if w.lists.is_empty() { 2 } else { 1 });
+                    w.lists.push((name == "ol").then_some(1));
+                }
+                "li" => {
+                    w.need(1);
+                    let indent = "  ".repeat(w.lists.len().saturating_sub(1));
+                    let bullet = match w.lists.last_mut() {
+                        Some(Some(n)) => {
+                            *n += 1;
+                            format!("{}. ", *n - 1)
+                        }
+                        _ => "- ".to_owned(),
+                    };
+                    w.marker = Some(indent + &bullet);
+                }
+                "pre" => {
+                    w.need(2);
+                    w.open("```\n");
+                    w.pre = true;
+                }
+                "code" if !w.pre => w.open("`"),
+                "strong" | "b" => w.open("**"),
+                "em" | "i" => w.open("*"),
+                "del" | "s" | "strike" => w.open("~~"),
+                "a" => {
+                    let href = attr(&attrs, "href");
+                    if href.is_some() {
+                        w.open("[");
+                    }
+                    w.links.push(href);
+                }
+                "img" => {
+                    if let Some(src) = attr(&attrs, "src") {
+                        let alt = attr(&attrs, "alt").unwrap_or_default();
+                        w.open(&format!("![{}]({})", alt, src));
+                    }
+                }
+                "hr" => {
+                    w.need(2);
+                    w.open("---");
+                    w.need(2);
+                }
+                "td" | "th" if !w.at_line_start() && w.breaks == 0 => w.out.push_str(" | "),
+                _ => {}
+            },
+            Token::Close(name) => match name.as_str() {
+                "p" | "table" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => w.need(2),
+                "div" | "tr" | "li" => w.need(1),
+                "blockquote" => {
+                    w.quotes = w.quotes.saturating_sub(1);
+                    w.need(2);
+                }
+                "ul" | "ol" => {
+                    w.lists.pop();
+                    w.need(if w.lists.is_empty() { 2 } else { 1 });
+                }
+                "pre" if w.pre => {
+                    w.pre = false;
+                    if !w.out.ends_with('\n') {
+                        w.out.push('\n');
+                    }
+                    w.out.push_str("```");
+                    w.need(2);
+                }
+                "code" if !w.pre => w.close("`"),
+                "strong" | "b" => w.close("**"),
+                "em" | "i" => w.close("*"),
+                "del" | "s" | "strike" => w.close("~~"),
+                "a" => {
+                    if let Some(Some(href)) = w.links.pop() {
+                        w.close(&format!("]({})", href));
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+    let mut out = w.out.trim().to_owned();
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+/// the text of the `<title>` of `html`, if it has one
+pub fn title(html: &str) -> Option<String> {
+    let re = Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap();
+    let title = unescape(re.captures(html)?[1].trim());
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!title.is_empty()).then_some(title)
+}
+
+/// the time written in a `<meta name="created">` or `<meta name="date">` of
+/// `html`
+fn created(html: &str) -> Option<String> {
+    tokens(html).into_iter().find_map(|token| match token {
+        Token::Open(name, attrs) if name == "meta" => {
+            let get = |key: &str| attrs.iter().find(|(k, _)| k == key).map(|(_, v)| v);
+            let name = get("name")?.to_ascii_lowercase();
+            ["created", "date", "dcterms.created"]
+                .contains(&name.as_str())
+                .then(|| get("content").cloned())?
+        }
+        _ => None,
+    })
+}
+
+/// HTML files under `dir`, skipping hidden files and directories
+pub fn sources(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    entries.sort();
+    for entry in entries {
+        let hidden = entry
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if entry.is_dir() {
+            found.extend(sources(&entry)?);
+        } else if entry
+            .extension()
+            .is_some_and(|ext| ext == "html" || ext == "htm")
+        {
+            found.push(entry);
+        }
+    }
+    Ok(found)
+}
+
+/// a row for [`import::import`] from the HTML file at `path`
+///
+/// the title is that of the document, its first heading or the file name,
+/// and a first heading repeating the title is left out of the body. The
+/// creation time is taken from a `created` or `date` meta tag, or else from
+/// the file.
+pub fn row(path: &Path) -> std::io::Result<import::Row> {
+    let html = std::fs::read_to_string(path)?;
+    let mut body = to_markdown(&html);
+    let heading = outline::headings(&body).into_iter().next();
+    let title = title(&html)
+        .or_else(|| heading.as_ref().map(|h| h.text.clone()))
+        .unwrap_or_else(|| {
+            path.file_stem()
+                .map(|stem| stem.to_string_lossy().replace(['-', '_'], " "))
+                .unwrap_or_default()
+        });
+    if let Some(first) = body.lines().next() {
+        if heading.is_some_and(|h| h.line == 1 && h.text == title) {
+            body = body[first.len()..].trim_start().to_owned();
+        }
+    }
+    let created = created(&html).or_else(|| {
+        let metadata = std::fs::metadata(path).ok()?;
+        let time = metadata.created().or_else(|_| metadata.modified()).ok()?;
+        Some(chrono::DateTime::<chrono::Local>::from(time).to_rfc3339())
+    });
+    let mut row = import::Row::new();
+    row.insert("title".to_owned(), title);
+    row.insert("body".to_owned(), body);
+    if let Some(created) = created {
+        row.insert("created".to_owned(), created);
+    }
+    Ok(row)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn converts_to_markdown() {
+        let html = "<html><head><title>Groceries &amp; more</title>\
+                    <style>p { color: red }</style></head><body>\
+                    <h1>Groceries &amp; more</h1>\
+                    <div>Buy <b>milk </b>and <i>eggs</i>.</div><div><br></div>\
+                    <div>See <a href=\"https://example.com\">the list</a></div>\
+                    <ul><li>one</li><li>two<ol><li>a</li><li>b</li></ol></li></ul>\
+                    <blockquote><p>quoted</p></blockquote>\
+                    <pre><code>let x = 1 &lt; 2;\n</code></pre>\
+                    <p>An <img src=\"a.png\" alt=\"image\"><!-- gone --></p>\
+                    </body></html>";
+        assert_eq!(title(html).as_deref(), Some("Groceries & more"));
+        assert_eq!(
+            to_markdown(html),
+            "# Groceries & more\n\n\
+             Buy **milk** and *eggs*.\n\n\
+             See [the list](https://example.com)\n\n\
+             - one\n\
+             - two\n  \
+               1. a\n  \
+               2. b\n\n\
+             > quoted\n\n\
+             ```\nlet x = 1 < 2;\n```\n\n\
+             An ![image](a.png)\n"
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use crate::query;
+use std::path::Path;
+
+/// name of the file listing paths zk should leave alone
+pub const FILE_NAME: &str = ".zkignore";
+
+/// Glob patterns from `.zkignore`, one per line
+///
+/// Patterns without a `/` match a file or directory name anywhere, others
+/// match paths relative to the root directory. Everything inside an ignored
+/// directory is ignored as well. Lines starting with `#` are comments.
+#[derive(Debug, Default)]
+pub struct Ignore {
+    patterns: Vec<String>,
+}
+
+impl Ignore {
+    /// patterns from `.zkignore` in the root directory, if there is one
+    pub fn load(root_dir: &Path) -> std::io::Result<Self> {
+        match std::fs::read_to_string(root_dir.join(FILE_NAME)) {
+            Ok(text) => Ok(Self::parse(&text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn parse(text: &str) -> Self {
+        let patterns = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                line.trim_start_matches('/')
+                    .trim_end_matches('/')
+                    .to_owned()
+            })
+            .collect();
+        Self { patterns }
+    }
+
+    /// whether a path relative to the root directory is ignored
+    pub fn is_ignored(&self, path: &Path) -> bool {
+        let components: Vec<String> = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        self.patterns.iter().any(|pattern| {
+            if pattern.contains('/') {
+                (1..=components.len())
+                    .any(|n| query::glob_match(pattern, &components[..n].join("/")))
+            } else {
+                components.iter().any(|c| query::glob_match(pattern, c))
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ignore_patterns() {
+        let ignore = Ignore::parse("# comment\n*.tmp\ndrafts/\n/archive/20*\n");
+        assert!(ignore.is_ignored(Path::new("notes/a.tmp")));
+        assert!(ignore.is_ignored(Path::new("x/drafts/a.md")));
+        assert!(ignore.is_ignored(Path::new("archive/2015/a.md")));
+        assert!(!ignore.is_ignored(Path::new("notes/archive/2015/a.md")));
+        assert!(!ignore.is_ignored(Path::new("notes/a.md")));
+    }
+}
@@ -0,0 +1,358 @@
+use crate::{database, frontmatter, zettel, zettelkasten, zettelkasten::Zettelkasten, DateTime};
+use std::collections::{BTreeMap, HashSet};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    JsonError(serde_json::Error),
+    FrontmatterError(frontmatter::Error),
+    DatabaseError(database::Error),
+    ZettelkastenError(zettelkasten::Error),
+    /// a `--map` entry that isn't `key=Column`
+    BadMapping(String),
+    /// a quote left open at the end of a CSV file
+    UnclosedQuote,
+    /// JSON that isn't an array of objects
+    NotRows,
+    /// a row, counted from 1, without a title
+    MissingTitle(usize),
+    /// a row, counted from 1, with a date that can't be read
+    BadDate(usize, String),
+    /// a row, counted from 1, with the id of another zettel
+    DuplicateId(usize, String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::JsonError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl From<database::Error> for Error {
+    fn from(e: database::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+impl From<zettelkasten::Error> for Error {
+    fn from(e: zettelkasten::Error) -> Self {
+        Self::ZettelkastenError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::JsonError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::DatabaseError(e) => e.fmt(f),
+            Self::ZettelkastenError(e) => e.fmt(f),
+            Self::BadMapping(m) => write!(f, "{} is not of the form key=Column", m),
+            Self::UnclosedQuote => write!(f, "quoted field is never closed"),
+            Self::NotRows => write!(f, "expected an array of objects"),
+            Self::MissingTitle(row) => write!(f, "row {} has no title", row),
+            Self::BadDate(row, date) => write!(f, "row {} has an unknown date {}", row, date),
+            Self::DuplicateId(row, id) => {
+                write!(f, "row {} has the id {} of another zettel", row, id)
+            }
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Format of a file of rows to import
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// a header line of column names, then one line per row
+    Csv,
+    /// an array of objects
+    Json,
+}
+
+/// values of a row by column name
+pub type Row = BTreeMap<String, String>;
+
+/// rows of `text`, parsed as `format`
+pub fn rows(text: &str, format: Format) -> Result<Vec<Row>> {
+    match format {
+        Format::Csv => csv(text),
+        Format::Json => json(text),
+    }
+}
+
+/// records of CSV `text`: fields separated by commas, quoted with `"` when
+/// they hold commas, quotes or line breaks, and quotes doubled inside quotes
+fn records(text: &str) -> Result<Vec<Vec<String>>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => record.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(Error::UnclosedQuote);
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records.retain(|r| r.iter().any(|f| !f.is_empty()));
+    Ok(records)
+}
+
+fn csv(text: &str) -> Result<Vec<Row>> {
+    let mut records = records(text)?.into_iter();
+    let header = match records.next() {
+        Some(header) => header,
+        None => return Ok(Vec::new()),
+    };
+    Ok(records
+        .map(|record| header.iter().cloned().zip(record).collect())
+        .collect())
+}
+
+fn json(text: &str) -> Result<Vec<Row>> {
+    let value: serde_json::Value = serde_json::from_str(text)?;
+    let objects = value.as_array().ok_or(Error::NotRows)?;
+    objects
+        .iter()
+        .map(|object| {
+            let object = object.as_object().ok_or(Error::NotRows)?;
+            Ok(object
+                .iter()
+                .filter_map(|(column, value)| Some((column.clone(), plain(value)?)))
+                .collect())
+        })
+        .collect()
+}
+
+/// a JSON value as text, with lists joined by commas
+fn plain(value: &serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Array(items) => {
+            Some(items.iter().filter_map(plain).collect::<Vec<_>>().join(","))
+        }
+        other => Some(other.to_string()),
+    }
+}
+
+/// frontmatter keys by the column they are taken from, from `key=Column`
+/// entries
+///
+/// `title`, `created`, `body` and `tags` are taken from the columns of the
+/// same name, in any case, unless mapped otherwise; other columns, including
+/// one of ids to keep, are only imported when mapped
+pub fn mapping(entries: &[String]) -> Result<Vec<(String, String)>> {
+    entries
+        .iter()
+        .map(|entry| match entry.split_once('=') {
+            Some((key, column)) if !key.trim().is_empty() && !column.trim().is_empty() => {
+                Ok((key.trim().to_owned(), column.trim().to_owned()))
+            }
+            _ => Err(Error::BadMapping(entry.clone())),
+        })
+        .collect()
+}
+
+/// the value of `key` in `row` under `map`
+fn field<'a>(row: &'a Row, map: &[(String, String)], key: &str) -> Option<&'a str> {
+    let value = match map.iter().find(|(k, _)| k == key) {
+        Some((_, column)) => row.get(column),
+        None if ["title", "created", "body", "tags"].contains(&key) => row
+            .iter()
+            .find(|(column, _)| column.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value),
+        None => None,
+    };
+    value.map(|v| v.trim()).filter(|v| !v.is_empty())
+}
+
+/// a date as exported by spreadsheets and databases, in local time unless it
+/// has an offset
+pub fn parse_date(s: &str) -> Option<DateTime> {
+    use chrono::TimeZone;
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Some(dt.with_timezone(&chrono::Local));
+    }
+    let naive = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| chrono::NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| {
+            chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .map(|d| d.and_hms(0, 0, 0))
+        })?;
+    chrono::Local.from_local_datetime(&naive).earliest()
+}
+
+/// turn each of `rows` into a zettel, in one transaction, with fields taken
+/// as `map` says; returns the new ids
+///
+/// rows without a date are created `now`. Tags are separated by commas or
+/// semicolons.
+pub fn import(
+    db: &impl database::Database,
+    zk: &mut Zettelkasten,
+    rows: &[Row],
+    map: &[(String, String)],
+    now: DateTime,
+) -> Result<Vec<zettel::Id>> {
+    let aliases = zk.config.key_aliases.clone();
+    let zone = zk.config.timezone.unwrap_or_default();
+    let keeps_state = zk.meta.storage == zettelkasten::Storage::Frontmatter;
+    zk.transaction(|tx| {
+        let mut ids = Vec::new();
+        let mut targets = HashSet::new();
+        for (n, row) in rows.iter().enumerate() {
+            let title = field(row, map, "title").ok_or(Error::MissingTitle(n + 1))?;
+            let created = match field(row, map, "created") {
+                Some(date) => parse_date(date).ok_or_else(|| Error::BadDate(n + 1, date.into()))?,
+                None => now,
+            };
+            let body = field(row, map, "body").unwrap_or_default();
+            let scheme = tx.zk().config.id_scheme.unwrap_or_default();
+            let id = match field(row, map, "id") {
+                Some(id) if tx.zettels.contains_key(id) => {
+                    return Err(Error::DuplicateId(n + 1, id.to_owned()))
+                }
+                Some(id) => id.to_owned(),
+                None => scheme.generate(created, |id| tx.zettels.contains_key(id)),
+            };
+            let mut zettel = db.new_zettel(&tx.zk().config, title, &id, created)?;
+            let path = zettel.meta.full_path(db.root_dir());
+            if path.exists() || !targets.insert(path.clone()) {
+                return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists).into());
+            }
+            let (mut fm, _) = frontmatter::parse_yaml(&mut std::io::BufReader::new(
+                tx.zk().render(&zettel)?.as_bytes(),
+            ))?;
+            frontmatter::unalias(&mut fm, &aliases);
+            if let Some(tags) = field(row, map, "tags") {
+                let tags: Vec<serde_yaml::Value> = tags
+                    .split([',', ';'])
+                    .map(str::trim)
+                    .filter(|t| !t.is_empty())
+                    .map(Into::into)
+                    .collect();
+                fm.insert("tags".into(), tags.into());
+            }
+            for (key, _) in map {
+                if ["id", "title", "created", "body", "tags"].contains(&key.as_str()) {
+                    continue;
+                }
+                if let Some(value) = field(row, map, key) {
+                    fm.insert(key.as_str().into(), value.into());
+                }
+            }
+            zettel.meta.update_from_frontmatter(&fm);
+            zettel.meta.update_from_body(body);
+            if keeps_state {
+                zettel.meta.write_state(&mut fm, zone);
+            }
+            let mut body = body.to_owned();
+            if !body.is_empty() && !body.ends_with('\n') {
+                body.push('\n');
+            }
+            let fm = frontmatter::aliased(&fm, &aliases);
+            tx.write(path, frontmatter::write_yaml(&fm, &body)?);
+            tx.zettels.insert(id.clone(), zettel.meta);
+            ids.push(id);
+        }
+        Ok(ids)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::memory::Database;
+    use chrono::prelude::*;
+
+    #[test]
+    fn imports_rows() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let csv = "\u{feff}Title,Date,Text,Tags,Source\r\n\
+                   Plain,2015-05-14,body,,ignored\r\n\
+                   \"Quoted, \"\"title\"\"\",,\"two\nlines [[x]]\",\"a; b\",web\r\n";
+        let rows = rows(csv, Format::Csv)?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1]["Title"], "Quoted, \"title\"");
+        assert_eq!(rows[1]["Text"], "two\nlines [[x]]");
+        assert!(matches!(
+            super::rows("a\n\"open", Format::Csv),
+            Err(Error::UnclosedQuote)
+        ));
+        let json = r#"[{"name": "From JSON", "tags": ["c", "d"], "when": null}]"#;
+        let json_rows = super::rows(json, Format::Json)?;
+        assert_eq!(json_rows[0]["tags"], "c,d");
+        assert!(!json_rows[0].contains_key("when"));
+
+        let map = mapping(&[
+            "created=Date".to_owned(),
+            "body=Text".to_owned(),
+            "source=Source".to_owned(),
+        ])?;
+        assert!(mapping(&["title".to_owned()]).is_err());
+        let tmp_dir = tempdir::TempDir::new("zk_import_test")?;
+        let root_dir = tmp_dir.path().to_path_buf();
+        let db = Database::new(root_dir.clone());
+        let now = chrono::Local.ymd(2023, 5, 17).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        let ids = import(&db, &mut zk, &rows, &map, now)?;
+        let plain = &zk.zettels[&ids[0]];
+        assert_eq!(plain.title, "Plain");
+        assert_eq!(
+            plain.created,
+            chrono::Local.ymd(2015, 5, 14).and_hms(0, 0, 0)
+        );
+        let quoted = &zk.zettels[&ids[1]];
+        assert_eq!(quoted.created, now);
+        assert_eq!(quoted.tags, ["a", "b"]);
+        assert_eq!(quoted.links, ["x"]);
+        let (fm, body) = frontmatter::parse_yaml_path(quoted.full_path(&root_dir))?;
+        assert_eq!(fm.get(&"source".into()), Some(&"web".into()));
+        assert_eq!(body, "two\nlines [[x]]\n");
+
+        let json_map = mapping(&["title=name".to_owned()])?;
+        let ids = import(&db, &mut zk, &json_rows, &json_map, now)?;
+        assert_eq!(zk.zettels[&ids[0]].tags, ["c", "d"]);
+        assert!(matches!(
+            import(&db, &mut zk, &json_rows, &[], now),
+            Err(Error::MissingTitle(1))
+        ));
+        Ok(())
+    }
+}
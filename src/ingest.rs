@@ -0,0 +1,242 @@
+use crate::{
+    database, frontmatter, link, outline, zettel, zettelkasten, zettelkasten::Zettelkasten,
+    DateTime,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    FrontmatterError(frontmatter::Error),
+    DatabaseError(database::Error),
+    ZettelkastenError(zettelkasten::Error),
+    /// a directory given without `recursive`
+    IsDirectory(PathBuf),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl From<database::Error> for Error {
+    fn from(e: database::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+impl From<zettelkasten::Error> for Error {
+    fn from(e: zettelkasten::Error) -> Self {
+        Self::ZettelkastenError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::DatabaseError(e) => e.fmt(f),
+            Self::ZettelkastenError(e) => e.fmt(f),
+            Self::IsDirectory(path) => write!(
+                f,
+                "{} is a directory; use --recursive to import the files in it",
+                path.display()
+            ),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// markdown files to import from `path`, which is a file or, when
+/// `recursive`, a directory searched for `.md` files
+///
+/// hidden files and directories are skipped
+pub fn sources(path: &Path, recursive: bool) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    if !recursive {
+        return Err(Error::IsDirectory(path.to_path_buf()));
+    }
+    let mut found = Vec::new();
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<_>>()?;
+    entries.sort();
+    for entry in entries {
+        let hidden = entry
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if entry.is_dir() {
+            found.extend(sources(&entry, true)?);
+        } else if entry.extension().is_some_and(|ext| ext == "md") {
+            found.push(entry);
+        }
+    }
+    Ok(found)
+}
+
+/// frontmatter and body of a file that may not have frontmatter
+fn read(path: &Path) -> Result<(serde_yaml::Mapping, String)> {
+    let text = std::fs::read_to_string(path)?;
+    if !text.starts_with("---\n") && !text.starts_with("---\r\n") {
+        return Ok((serde_yaml::Mapping::new(), text));
+    }
+    Ok(frontmatter::parse_yaml(&mut std::io::BufReader::new(
+        text.as_bytes(),
+    ))?)
+}
+
+/// title for a file being imported: the title in its frontmatter, its first
+/// heading or its file name, in that order
+pub fn title(path: &Path, fm: &serde_yaml::Mapping, body: &str) -> String {
+    if let Some(title) = fm.get(&"title".into()).and_then(|t| t.as_str()) {
+        return title.to_owned();
+    }
+    if let Some(heading) = outline::headings(body).into_iter().next() {
+        return heading.text;
+    }
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().replace(['-', '_'], " "))
+        .unwrap_or_default()
+}
+
+/// turn each file of `sources` into a zettel, in one transaction
+///
+/// the files get the default frontmatter of new zettels under whatever
+/// frontmatter they already have, with a new id, and are moved into the
+/// root directory, or copied when `copy` is set. Relative markdown links
+/// are rewritten to resolve from the new files. Returns the new ids.
+pub fn ingest(
+    db: &impl database::Database,
+    zk: &mut Zettelkasten,
+    sources: &[PathBuf],
+    copy: bool,
+    now: DateTime,
+) -> Result<Vec<zettel::Id>> {
+    let aliases = zk.config.key_aliases.clone();
+    zk.transaction(|tx| {
+        let mut ids = Vec::new();
+        let mut targets = HashSet::new();
+        let mut moved = HashMap::new();
+        let mut ingested = Vec::new();
+        for source in sources {
+            let (mut fm, body) = read(source)?;
+            frontmatter::unalias(&mut fm, &aliases);
+            let title = title(source, &fm, &body);
+            let scheme = tx.zk().config.id_scheme.unwrap_or_default();
+            let id = scheme.generate(now, |id| tx.zettels.contains_key(id));
+            let mut zettel = db.new_zettel(&tx.zk().config, &title, &id, now)?;
+            let path = zettel.meta.full_path(db.root_dir());
+            if path.exists() || !targets.insert(path.clone()) {
+                return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists).into());
+            }
+            let (mut merged, _) = frontmatter::parse_yaml(&mut std::io::BufReader::new(
+                tx.zk().render(&zettel)?.as_bytes(),
+            ))?;
+            frontmatter::unalias(&mut merged, &aliases);
+            for (key, value) in fm {
+                if key.as_str() != Some("id") {
+                    merged.insert(key, value);
+                }
+            }
+            zettel.meta.update_from_frontmatter(&merged);
+            zettel.meta.update_from_body(&body);
+            moved.insert(
+                link::normalize(&std::path::absolute(source)?),
+                link::normalize(&std::path::absolute(&path)?),
+            );
+            ingested.push((source, path, merged, body, zettel.meta));
+            ids.push(id);
+        }
+        for (source, path, merged, body, meta) in ingested {
+            let (body, _) = link::rebase(
+                &body,
+                &std::path::absolute(source)?,
+                &std::path::absolute(&path)?,
+                &moved,
+            );
+            if !copy {
+                tx.rename(source, &path);
+            }
+            let merged = frontmatter::aliased(&merged, &aliases);
+            tx.write(path, frontmatter::write_yaml(&merged, &body)?);
+            tx.zettels.insert(meta.id.clone(), meta);
+        }
+        Ok(ids)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::memory::Database;
+    use chrono::prelude::*;
+
+    #[test]
+    fn imports_files() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_ingest_test")?;
+        let root_dir = tmp_dir.path().join("kasten");
+        let notes = tmp_dir.path().join("notes");
+        std::fs::create_dir_all(notes.join("sub"))?;
+        std::fs::create_dir_all(&root_dir)?;
+        std::fs::write(
+            notes.join("sub/headed.md"),
+            "intro\n\n## The Heading\ntext\n",
+        )?;
+        std::fs::write(
+            notes.join("sub/tagged.md"),
+            "---\ntitle: Kept\ntags: [a, b]\nid: old\n---\nbody [[x]]\n",
+        )?;
+        std::fs::write(
+            notes.join("plain-note.md"),
+            "no heading here, see [headed](sub/headed.md) and ![](img.png)\n",
+        )?;
+        std::fs::write(notes.join("sub/skipped.txt"), "")?;
+        assert!(matches!(sources(&notes, false), Err(Error::IsDirectory(_))));
+        let found = sources(&notes, true)?;
+        assert_eq!(found.len(), 3);
+        let db = Database::new(root_dir.clone());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        let ids = ingest(&db, &mut zk, &found, false, dt)?;
+        let titles: Vec<&str> = ids.iter().map(|id| zk.zettels[id].title.as_str()).collect();
+        assert_eq!(titles, ["plain note", "The Heading", "Kept"]);
+        assert!(!notes.join("sub/tagged.md").exists());
+        let kept = &zk.zettels[&ids[2]];
+        assert_eq!(kept.tags, ["a", "b"]);
+        assert_eq!(kept.links, ["x"]);
+        let (fm, body) = frontmatter::parse_yaml_path(kept.full_path(&root_dir))?;
+        assert_eq!(fm.get(&"id".into()), Some(&ids[2].as_str().into()));
+        assert_eq!(body, "body [[x]]\n");
+        let plain = &zk.zettels[&ids[0]];
+        let headed = Path::new(&zk.zettels[&ids[1]].path).file_name().unwrap();
+        let headed = headed.to_str().unwrap().replace(' ', "%20");
+        let (_, body) = frontmatter::parse_yaml_path(plain.full_path(&root_dir))?;
+        assert_eq!(
+            body,
+            format!(
+                "no heading here, see [headed]({}) and ![](../notes/img.png)\n",
+                headed
+            )
+        );
+        Ok(())
+    }
+}
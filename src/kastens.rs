@@ -0,0 +1,160 @@
+use crate::{database, database::Database as _, link, zettel, zettelkasten::Zettelkasten};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    DatabaseError(database::Error),
+    /// a registered name whose path has no database
+    NotAKasten(String, PathBuf),
+}
+
+impl From<database::Error> for Error {
+    fn from(e: database::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DatabaseError(e) => e.fmt(f),
+            Self::NotAKasten(name, path) => write!(
+                f,
+                "kasten {} at {} has no database; run `zk init` there",
+                name,
+                path.display()
+            ),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A zettelkasten taking part in cross-kasten links
+#[derive(Debug)]
+pub struct Kasten {
+    /// name under which the current kasten registered this one, `None` for
+    /// the current kasten itself
+    pub name: Option<String>,
+    /// canonical root directory
+    pub root_dir: PathBuf,
+    pub zk: Zettelkasten,
+}
+
+impl Kasten {
+    /// how the current kasten refers to zettel `id` of this kasten
+    pub fn qualified(&self, id: &str) -> String {
+        match &self.name {
+            Some(name) => format!("{}:{}", name, id),
+            None => id.to_owned(),
+        }
+    }
+
+    /// root directory and id of the zettel a wikilink target written in this
+    /// kasten points to, following this kasten's own registrations
+    pub fn resolve(&self, target: &str) -> Option<(PathBuf, zettel::Id)> {
+        match link::split_kasten(target) {
+            (None, id) => Some((self.root_dir.clone(), id.to_owned())),
+            (Some(name), id) => {
+                let path = self.zk.config.kastens.get(name)?;
+                let root = self.root_dir.join(path).canonicalize().ok()?;
+                Some((root, id.to_owned()))
+            }
+        }
+    }
+}
+
+/// the current kasten followed by every kasten it registered
+pub fn load(root_dir: &Path, zk: Zettelkasten) -> Result<Vec<Kasten>> {
+    let mut kastens = Vec::new();
+    for (name, path) in &zk.config.kastens {
+        let path = root_dir.join(path);
+        let not_a_kasten = || Error::NotAKasten(name.clone(), path.clone());
+        let db = database::file::Database::new(path.canonicalize().map_err(|_| not_a_kasten())?)?;
+        let remote = db.get_zk()?.ok_or_else(not_a_kasten)?;
+        kastens.push(Kasten {
+            name: Some(name.clone()),
+            root_dir: db.root_dir().to_path_buf(),
+            zk: remote,
+        });
+    }
+    kastens.insert(
+        0,
+        Kasten {
+            name: None,
+            root_dir: root_dir.to_path_buf(),
+            zk,
+        },
+    );
+    Ok(kastens)
+}
+
+/// index into `kastens` and id of the zettel that `target`, written in
+/// `kastens[from]`, links to if it is in one of the kastens
+pub fn find(kastens: &[Kasten], from: usize, target: &str) -> Option<(usize, zettel::Id)> {
+    let (root, id) = kastens[from].resolve(target)?;
+    let to = kastens.iter().position(|k| k.root_dir == root)?;
+    kastens[to].zk.zettels.contains_key(&id).then_some((to, id))
+}
+
+/// zettels in any of the kastens linking to zettel `id` of `kastens[to]`,
+/// as indices into `kastens` and ids
+pub fn backlinks(kastens: &[Kasten], to: usize, id: &str) -> Vec<(usize, zettel::Id)> {
+    let mut found = Vec::new();
+    for (from, kasten) in kastens.iter().enumerate() {
+        let mut ids: Vec<&zettel::Id> = kasten.zk.zettels.keys().collect();
+        ids.sort();
+        for source in ids {
+            let links = &kasten.zk.zettels[source].links;
+            if links
+                .iter()
+                .any(|l| find(kastens, from, l).is_some_and(|(k, i)| k == to && i == id))
+            {
+                found.push((from, source.clone()));
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{database::file::Database, ZettelMeta};
+    use chrono::prelude::*;
+
+    #[test]
+    fn links_across_kastens() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_kastens_test")?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut roots = Vec::new();
+        for (name, other) in [("personal", "work"), ("work", "personal")] {
+            let root = tmp_dir.path().join(name);
+            std::fs::create_dir(&root)?;
+            let db = Database::new(root.clone())?;
+            let mut zk = Zettelkasten::default();
+            zk.config
+                .kastens
+                .insert(other.to_owned(), format!("../{}", other).into());
+            let mut meta = ZettelMeta::new("a", "A", "a.md", dt);
+            meta.links = vec![format!("{}:b", other), "a".to_owned()];
+            zk.zettels.insert("a".to_owned(), meta);
+            zk.zettels
+                .insert("b".to_owned(), ZettelMeta::new("b", "B", "b.md", dt));
+            db.commit(&zk)?;
+            roots.push(db);
+        }
+        let zk = roots[0].get_zk()?.unwrap();
+        let kastens = load(roots[0].root_dir(), zk)?;
+        assert_eq!(kastens.len(), 2);
+        assert_eq!(kastens[1].qualified("b"), "work:b");
+        assert_eq!(find(&kastens, 0, "work:b"), Some((1, "b".to_owned())));
+        assert_eq!(find(&kastens, 0, "work:nope"), None);
+        assert_eq!(find(&kastens, 1, "personal:b"), Some((0, "b".to_owned())));
+        assert_eq!(backlinks(&kastens, 0, "b"), vec![(1, "a".to_owned())]);
+        assert_eq!(backlinks(&kastens, 0, "a"), vec![(0, "a".to_owned())]);
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+#![allow(clippy::enum_variant_names)]
+//! The zettelkasten engine behind the `zk` command, also built as a C
+//! library; see [`ffi`]
+
+pub mod assets;
+pub mod autolink;
+pub mod bibtex;
+pub mod board;
+pub mod capture;
+pub mod citations;
+pub mod compile;
+pub mod config;
+pub mod crypt;
+pub mod daemon;
+pub mod database;
+pub mod dates;
+pub mod dayone;
+pub mod dedupe;
+pub mod digest;
+pub mod duplicate;
+pub mod events;
+pub mod export;
+pub mod ffi;
+pub mod fix;
+pub mod frontmatter;
+pub mod fsutil;
+pub mod grep;
+pub mod heatmap;
+pub mod history;
+pub mod hooks;
+pub mod html;
+pub mod ignore;
+pub mod import;
+pub mod ingest;
+pub mod kastens;
+pub mod link;
+pub mod linkcheck;
+pub mod lsp;
+pub mod merge;
+pub mod metaedit;
+pub mod metagrep;
+pub mod navigate;
+pub mod opener;
+pub mod outline;
+pub mod pick;
+pub mod publish;
+pub mod query;
+pub mod reading;
+pub mod reconcile;
+pub mod renameid;
+pub mod render;
+pub mod reorganize;
+pub mod resolve;
+pub mod review;
+pub mod search;
+pub mod section;
+pub mod sequence;
+pub mod serve;
+pub mod share;
+pub mod snapshot;
+pub mod split;
+pub mod storage;
+pub mod summary;
+pub mod tags;
+#[cfg(test)]
+mod testutil;
+pub mod tiddlywiki;
+pub mod transclude;
+pub mod undo;
+pub mod verify;
+pub mod zettel;
+pub mod zettelkasten;
+pub mod zip;
+
+pub use zettel::ZettelMeta;
+
+pub type DateTime = chrono::DateTime<chrono::Local>;
@@ -0,0 +1,455 @@
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+/// A markdown link or image found in a zettel body
+#[derive(Debug, PartialEq, Clone)]
+pub struct Link {
+    pub target: String,
+    /// line number starting at 1
+    pub line: usize,
+    pub image: bool,
+}
+
+impl Link {
+    /// whether the target is a path on the local filesystem rather than a url
+    /// or an anchor within the same document
+    pub fn is_local(&self) -> bool {
+        !self.target.is_empty() && !self.target.starts_with('#') && !self.has_scheme()
+    }
+
+    /// whether the target starts with a url scheme such as `https:`
+    ///
+    /// single letters are treated as windows drive letters instead
+    pub fn has_scheme(&self) -> bool {
+        match self.target.split_once(':') {
+            Some((scheme, _)) => {
+                scheme.len() > 1
+                    && scheme
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+            }
+            None => false,
+        }
+    }
+
+    /// the target without fragment or query
+    pub fn local_path(&self) -> &str {
+        let end = self.target.find(['#', '?']).unwrap_or(self.target.len());
+        &self.target[..end]
+    }
+
+    /// resolve a local target relative to the directory of the linking file
+    pub fn resolve(&self, from_file: &Path) -> PathBuf {
+        let path = self.local_path().replace("%20", " ");
+        let dir = from_file.parent().unwrap_or_else(|| Path::new(""));
+        normalize(&dir.join(path))
+    }
+}
+
+/// A `[[target]]` or `[[target|label]]` link, embedded when written `![[target]]`
+///
+/// `[[target#Heading]]` points at a section of the target and
+/// `[[target#^block]]` at a line ending in `^block`. org-roam's `[[id:target][label]]` links in org files count as well
+#[derive(Debug, PartialEq, Clone)]
+pub struct WikiLink {
+    pub target: String,
+    pub label: Option<String>,
+    /// heading or `^block` after `#` in the target
+    pub anchor: Option<String>,
+    /// written org-roam style
+    pub org: bool,
+    /// line number starting at 1
+    pub line: usize,
+    pub embed: bool,
+    /// byte range of the whole link, including `!` for embeds, within its line
+    pub span: std::ops::Range<usize>,
+}
+
+/// find wikilinks in a zettel body
+pub fn wikilinks(body: &str) -> Vec<WikiLink> {
+    let mut links = Vec::new();
+    for (n, line) in body.lines().enumerate() {
+        let mut offset = 0;
+        while let Some(open) = line[offset..].find("[[") {
+            let open = offset + open;
+            let close = match line[open..].find("]]") {
+                Some(close) => open + close,
+                None => break,
+            };
+            let inner = &line[open + 2..close];
+            let org = inner.starts_with("id:");
+            let (target, label) = match (org, inner.split_once("]["), inner.split_once('|')) {
+                (true, Some((target, label)), _) => (&target[3..], Some(label.trim().to_owned())),
+                (true, None, _) => (&inner[3..], None),
+                (false, _, Some((target, label))) => (target, Some(label.trim().to_owned())),
+                (false, _, None) => (inner, None),
+            };
+            let (target, anchor) = match target.split_once('#').filter(|_| !org) {
+                Some((target, anchor)) => (target, Some(anchor.trim().to_owned())),
+                None => (target, None),
+            };
+            let embed = line[..open].ends_with('!');
+            let start = if embed { open - 1 } else { open };
+            if !target.trim().is_empty() {
+                links.push(WikiLink {
+                    target: target.trim().to_owned(),
+                    label,
+                    anchor: anchor.filter(|anchor| !anchor.is_empty()),
+                    org,
+                    line: n + 1,
+                    embed,
+                    span: start..close + 2,
+                });
+            }
+            offset = close + 2;
+        }
+    }
+    links
+}
+
+/// point wikilinks and embeds targeting `from` at `to`, keeping labels
+pub fn rewrite_wikilinks(body: &str, from: &str, to: &str) -> String {
+    let links = wikilinks(body);
+    let mut out = String::new();
+    for (n, line) in body.split_inclusive('\n').enumerate() {
+        let mut last = 0;
+        for link in links.iter().filter(|l| l.line == n + 1 && l.target == from) {
+            out.push_str(&line[last..link.span.start]);
+            out.push_str(if link.embed { "![[" } else { "[[" });
+            if link.org {
+                out.push_str("id:");
+            }
+            out.push_str(to);
+            if let Some(anchor) = &link.anchor {
+                out.push('#');
+                out.push_str(anchor);
+            }
+            match (&link.label, link.org) {
+                (Some(label), true) => out.push_str(&format!("][{}", label)),
+                (Some(label), false) => out.push_str(&format!("|{}", label)),
+                (None, _) => {}
+            }
+            out.push_str("]]");
+            last = link.span.end;
+        }
+        out.push_str(&line[last..]);
+    }
+    out
+}
+
+/// anchor of a heading in urls: lowercase, with spaces turned into `-` and
+/// punctuation dropped
+pub fn slug(heading: &str) -> String {
+    heading
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            c if c.is_whitespace() => Some('-'),
+            _ => None,
+        })
+        .collect()
+}
+
+/// split a wikilink target like `work:id` into the name of the kasten it
+/// points into and the id
+pub fn split_kasten(target: &str) -> (Option<&str>, &str) {
+    match target.split_once(':') {
+        Some((kasten, id)) => (Some(kasten), id),
+        None => (None, target),
+    }
+}
+
+/// lexically remove `.` and `..` components
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => (),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+/// url-style path from the file `from` to the file `to`, both relative to the
+/// same directory, with spaces written `%20`
+pub fn relative_target(from: &Path, to: &Path) -> String {
+    let from_dir = normalize(from.parent().unwrap_or_else(|| Path::new("")));
+    let from_dir: Vec<Component> = from_dir.components().collect();
+    let to = normalize(to);
+    let to: Vec<Component> = to.components().collect();
+    let common = from_dir.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    let mut parts: Vec<String> = vec!["..".to_owned(); from_dir.len() - common];
+    parts.extend(
+        to[common..]
+            .iter()
+            .map(|c| c.as_os_str().to_string_lossy().replace(' ', "%20")),
+    );
+    parts.join("/")
+}
+
+/// `line` with the link targets `retargets` replaced, left to right
+fn retarget_line(line: &str, retargets: &[(String, String)]) -> String {
+    let mut out = String::new();
+    let mut rest = line;
+    for (old, new) in retargets {
+        let found = rest.match_indices(old.as_str()).find(|(at, _)| {
+            let before = rest[..*at].trim_end();
+            before.ends_with("](") || before.ends_with("](<") || before.ends_with("]:")
+        });
+        if let Some((at, _)) = found {
+            out.push_str(&rest[..at]);
+            out.push_str(new);
+            rest = &rest[at + old.len()..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `text` of a file moved from `from` to `to` with its relative markdown
+/// links and images rewritten to resolve as they did, or to where `moved`
+/// says their targets went; returns the old and new targets as well
+///
+/// all paths are taken relative to the same directory. Links of a file that
+/// stays put are only touched when their targets moved.
+pub fn rebase(
+    text: &str,
+    from: &Path,
+    to: &Path,
+    moved: &HashMap<PathBuf, PathBuf>,
+) -> (String, Vec<(String, String)>) {
+    let (from, to) = (normalize(from), normalize(to));
+    let mut retargets: HashMap<usize, Vec<(String, String)>> = HashMap::new();
+    let mut changed = Vec::new();
+    for link in markdown_links(text) {
+        if !link.is_local() || link.local_path().starts_with('/') {
+            continue;
+        }
+        let target = link.resolve(&from);
+        let new_target = moved.get(&target).unwrap_or(&target);
+        if to == from && *new_target == target {
+            continue;
+        }
+        let mut new = relative_target(&to, new_target);
+        new.push_str(&link.target[link.local_path().len()..]);
+        if new != link.target {
+            changed.push((link.target.clone(), new.clone()));
+            retargets
+                .entry(link.line)
+                .or_default()
+                .push((link.target, new));
+        }
+    }
+    if retargets.is_empty() {
+        return (text.to_owned(), changed);
+    }
+    let text = text
+        .split_inclusive('\n')
+        .enumerate()
+        .map(|(n, line)| match retargets.get(&(n + 1)) {
+            Some(retargets) => retarget_line(line, retargets),
+            None => line.to_owned(),
+        })
+        .collect();
+    (text, changed)
+}
+
+/// find inline links `[text](target)`, images `![alt](target)` and
+/// reference definitions `[label]: target` in markdown
+pub fn markdown_links(body: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    for (n, line) in body.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('[') {
+            if let Some((_, target)) = trimmed.split_once("]:") {
+                if let Some(target) = target.split_whitespace().next() {
+                    links.push(Link {
+                        target: strip_angle_brackets(target).to_owned(),
+                        line: n + 1,
+                        image: false,
+                    });
+                }
+                continue;
+            }
+        }
+        let mut rest = line;
+        while let Some(close) = rest.find("](") {
+            let image = rest[..close]
+                .rfind('[')
+                .is_some_and(|open| rest[..open].ends_with('!'));
+            let after = &rest[close + 2..];
+            let end = match after.find(')') {
+                Some(end) => end,
+                None => break,
+            };
+            let target = after[..end].split_whitespace().next().unwrap_or("");
+            links.push(Link {
+                target: strip_angle_brackets(target).to_owned(),
+                line: n + 1,
+                image,
+            });
+            rest = &after[end + 1..];
+        }
+    }
+    links
+}
+
+/// find http(s) urls anywhere in the text, returning line numbers starting at 1
+pub fn urls(body: &str) -> Vec<(usize, String)> {
+    let mut urls = Vec::new();
+    for (n, line) in body.lines().enumerate() {
+        let mut rest = line;
+        while let Some(start) = rest.find("http") {
+            let candidate = &rest[start..];
+            if !(candidate.starts_with("http://") || candidate.starts_with("https://")) {
+                rest = &candidate[4..];
+                continue;
+            }
+            // parentheses are allowed in urls as long as they are balanced
+            let mut depth = 0;
+            let end = candidate
+                .find(|c: char| match c {
+                    '(' => {
+                        depth += 1;
+                        false
+                    }
+                    ')' if depth > 0 => {
+                        depth -= 1;
+                        false
+                    }
+                    c => c == ')' || c.is_whitespace() || "<>[]\"'`".contains(c),
+                })
+                .unwrap_or(candidate.len());
+            let url = candidate[..end].trim_end_matches(['.', ',', ';', ':', '!', '?']);
+            urls.push((n + 1, url.to_owned()));
+            rest = &candidate[end..];
+        }
+    }
+    urls
+}
+
+fn strip_angle_brackets(target: &str) -> &str {
+    target
+        .strip_prefix('<')
+        .and_then(|t| t.strip_suffix('>'))
+        .unwrap_or(target)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn relative_targets() {
+        let target = |from: &str, to: &str| relative_target(Path::new(from), Path::new(to));
+        assert_eq!(target("a.md", "b c.md"), "b%20c.md");
+        assert_eq!(target("notes/x/a.md", "notes/b.md"), "../b.md");
+        assert_eq!(target("notes/a.md", "other/./d/b.md"), "../other/d/b.md");
+    }
+
+    #[test]
+    fn rebase_moved_links() {
+        let moved = HashMap::from([(PathBuf::from("notes/b.md"), PathBuf::from("2022/b.md"))]);
+        let text = "![fig](assets/a%20b.png) [b](b.md#part) [web](https://x.org) [top](#top)\n\
+                    [ref]: ../c.md\n";
+        let (text, changed) = rebase(
+            text,
+            Path::new("notes/a.md"),
+            Path::new("2021/03/a.md"),
+            &moved,
+        );
+        assert_eq!(
+            text,
+            "![fig](../../notes/assets/a%20b.png) [b](../../2022/b.md#part) [web](https://x.org) \
+             [top](#top)\n[ref]: ../../c.md\n"
+        );
+        assert_eq!(changed.len(), 3);
+        let (text, changed) = rebase(
+            "[b](b.md) [c](c.md)",
+            Path::new("notes/a.md"),
+            Path::new("notes/a.md"),
+            &moved,
+        );
+        assert_eq!(text, "[b](../2022/b.md) [c](c.md)");
+        assert_eq!(changed.len(), 1);
+    }
+
+    #[test]
+    fn find_links() {
+        let body = "see [this](other.md#part) and ![img](../assets/a%20b.png \"title\")\n\
+                    [ref]: https://example.com\n\
+                    [anchor](#top)\n";
+        let links = markdown_links(body);
+        let targets: Vec<_> = links.iter().map(|l| l.target.as_str()).collect();
+        assert_eq!(
+            targets,
+            vec![
+                "other.md#part",
+                "../assets/a%20b.png",
+                "https://example.com",
+                "#top"
+            ]
+        );
+        assert!(!links[0].image && links[1].image);
+        assert_eq!(links[2].line, 2);
+        let anchored = wikilinks("[[abc#A Heading|x]] [[abc#^block]] [[#]]");
+        assert_eq!(anchored.len(), 2);
+        assert_eq!(anchored[0].target, "abc");
+        assert_eq!(anchored[0].anchor.as_deref(), Some("A Heading"));
+        assert_eq!(anchored[1].anchor.as_deref(), Some("^block"));
+        assert_eq!(slug(" Why? It's 2 words "), "why-its-2-words");
+        let local: Vec<_> = links.iter().filter(|l| l.is_local()).collect();
+        assert_eq!(local.len(), 2);
+        assert_eq!(
+            links[1].resolve(Path::new("/kasten/notes/zettel.md")),
+            PathBuf::from("/kasten/assets/a b.png")
+        );
+        assert_eq!(links[0].local_path(), "other.md");
+    }
+
+    #[test]
+    fn find_wikilinks() {
+        let body = "intro [[abc]] and [[def | the def]]\n![[ghi]] [[]]\n";
+        let links = wikilinks(body);
+        assert_eq!(links.len(), 3);
+        assert_eq!(links[0].target, "abc");
+        assert_eq!(links[1].label.as_deref(), Some("the def"));
+        assert!(links[2].embed && !links[0].embed);
+        assert_eq!(links[2].line, 2);
+        assert_eq!(
+            &body.lines().nth(1).unwrap()[links[2].span.clone()],
+            "![[ghi]]"
+        );
+    }
+
+    #[test]
+    fn rewrite_links() {
+        let body = "see [[old]] and [[old#Part|label]]\n![[old]] [[older]] [[id:old][org]]";
+        assert_eq!(
+            rewrite_wikilinks(body, "old", "new"),
+            "see [[new]] and [[new#Part|label]]\n![[new]] [[older]] [[id:new][org]]"
+        );
+    }
+
+    #[test]
+    fn find_urls() {
+        let body = "see [site](https://example.com/a_(b)) or <http://x.org/p?q=1>.\n\
+                    plain https://foo.bar/baz, done; httpx://nope\n";
+        assert_eq!(
+            urls(body),
+            vec![
+                (1, "https://example.com/a_(b)".to_owned()),
+                (1, "http://x.org/p?q=1".to_owned()),
+                (2, "https://foo.bar/baz".to_owned()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use crate::DateTime;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    path::Path,
+    sync::{mpsc, Arc, Mutex},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    SerializationError(serde_yaml::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<serde_yaml::Error> for Error {
+    fn from(e: serde_yaml::Error) -> Self {
+        Self::SerializationError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::SerializationError(e) => e.fmt(f),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Outcome of checking a url
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum Status {
+    Alive(u16),
+    Dead(u16),
+    /// the request failed before getting a response
+    Unreachable(String),
+}
+
+impl Status {
+    pub fn is_alive(&self) -> bool {
+        matches!(self, Self::Alive(_))
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Alive(code) | Self::Dead(code) => write!(f, "HTTP {}", code),
+            Self::Unreachable(e) => f.write_str(e),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub checked: DateTime,
+    pub status: Status,
+}
+
+/// Results of previous checks so urls are not requested on every run
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cache {
+    pub urls: HashMap<String, CacheEntry>,
+}
+
+impl Cache {
+    pub fn load(path: &Path) -> Result<Self> {
+        if path.is_file() {
+            Ok(serde_yaml::from_reader(File::open(path)?)?)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        serde_yaml::to_writer(File::create(path)?, self)?;
+        Ok(())
+    }
+}
+
+/// Checks urls concurrently, skipping those checked within `max_age`
+#[derive(Debug)]
+pub struct Checker {
+    /// number of requests in flight at once
+    pub jobs: usize,
+    pub max_age: chrono::Duration,
+    pub timeout: std::time::Duration,
+}
+
+impl Checker {
+    pub fn check(
+        &self,
+        urls: &[String],
+        cache: &mut Cache,
+        now: DateTime,
+    ) -> HashMap<String, Status> {
+        let mut results = HashMap::new();
+        let mut queue = Vec::new();
+        for url in urls {
+            match cache.urls.get(url) {
+                Some(entry) if now - entry.checked < self.max_age => {
+                    results.insert(url.clone(), entry.status.clone());
+                }
+                _ if !queue.contains(url) => queue.push(url.clone()),
+                _ => (),
+            }
+        }
+        let workers = self.jobs.max(1).min(queue.len());
+        let queue = Arc::new(Mutex::new(queue));
+        let (tx, rx) = mpsc::channel();
+        let agent = ureq::AgentBuilder::new().timeout(self.timeout).build();
+        for _ in 0..workers {
+            let queue = Arc::clone(&queue);
+            let tx = tx.clone();
+            let agent = agent.clone();
+            std::thread::spawn(move || loop {
+                let url = match queue.lock().unwrap().pop() {
+                    Some(url) => url,
+                    None => break,
+                };
+                let status = request(&agent, &url);
+                if tx.send((url, status)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+        for (url, status) in rx {
+            cache.urls.insert(
+                url.clone(),
+                CacheEntry {
+                    checked: now,
+                    status: status.clone(),
+                },
+            );
+            results.insert(url, status);
+        }
+        results
+    }
+}
+
+/// HEAD the url, falling back to GET for servers that don't allow HEAD
+fn request(agent: &ureq::Agent, url: &str) -> Status {
+    let response = match agent.head(url).call() {
+        Err(ureq::Error::Status(405, _)) | Err(ureq::Error::Status(501, _)) => {
+            agent.get(url).call()
+        }
+        response => response,
+    };
+    match response {
+        Ok(response) => Status::Alive(response.status()),
+        Err(ureq::Error::Status(code, _)) => Status::Dead(code),
+        Err(ureq::Error::Transport(e)) => Status::Unreachable(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+
+    /// serve `n` requests, answering 404 for paths starting with /dead
+    fn serve(n: usize) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().take(n) {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                BufReader::new(&stream)
+                    .read_line(&mut request_line)
+                    .unwrap();
+                let status = if request_line.contains(" /dead") {
+                    "404 Not Found"
+                } else {
+                    "200 OK"
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[test]
+    fn check_and_cache() {
+        let base = serve(2);
+        let now = chrono::Local::now();
+        let alive = format!("{}/alive", base);
+        let dead = format!("{}/dead", base);
+        let cached = "http://cached.invalid/".to_owned();
+        let mut cache = Cache::default();
+        cache.urls.insert(
+            cached.clone(),
+            CacheEntry {
+                checked: now - chrono::Duration::hours(1),
+                status: Status::Dead(410),
+            },
+        );
+        let checker = Checker {
+            jobs: 2,
+            max_age: chrono::Duration::hours(24),
+            timeout: std::time::Duration::from_secs(5),
+        };
+        let urls = vec![alive.clone(), dead.clone(), cached.clone(), alive.clone()];
+        let results = checker.check(&urls, &mut cache, now);
+        assert_eq!(results[&alive], Status::Alive(200));
+        assert_eq!(results[&dead], Status::Dead(404));
+        assert_eq!(results[&cached], Status::Dead(410));
+        assert_eq!(cache.urls[&dead].checked, now);
+        assert_eq!(cache.urls.len(), 3);
+    }
+}
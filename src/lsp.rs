@@ -0,0 +1,465 @@
+use crate::{
+    database, database::Database, frontmatter, link, zettel, zettelkasten::Zettelkasten, ZettelMeta,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    DatabaseError(database::Error),
+    /// no database in the root directory
+    NotInitialized,
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::DatabaseError(e) => e.fmt(f),
+            Self::NotInitialized => write!(f, "database does not exist; use `init` first"),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<database::Error> for Error {
+    fn from(e: database::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// lines of a zettel's body shown when hovering over a link to it
+const HOVER_LINES: usize = 10;
+
+/// Language server for editing the zettels of a zettelkasten
+pub struct Server<D: Database> {
+    db: D,
+    zk: Zettelkasten,
+    /// text of the documents open in the editor, by uri
+    documents: HashMap<String, String>,
+}
+
+impl<D: Database> Server<D> {
+    pub fn new(db: D) -> Result<Self> {
+        let zk = db.get_zk()?.ok_or(Error::NotInitialized)?;
+        Ok(Self {
+            db,
+            zk,
+            documents: HashMap::new(),
+        })
+    }
+
+    /// read LSP messages from `input` and write responses and notifications
+    /// to `output` until the client sends `exit`
+    pub fn run(mut self, mut input: impl BufRead, mut output: impl Write) -> Result<()> {
+        while let Some(message) = read_message(&mut input)? {
+            if message.get("method") == Some(&"exit".into()) {
+                break;
+            }
+            for reply in self.handle(&message) {
+                let body = reply.to_string();
+                write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// responses and notifications to send for a message from the client
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message
+            .get("method")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_owned();
+        let mut replies = Vec::new();
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "completionProvider": {"triggerCharacters": ["["]},
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "renameProvider": true,
+                },
+                "serverInfo": {"name": "zk", "version": env!("CARGO_PKG_VERSION")},
+            }),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), text.to_owned());
+                replies.push(self.diagnostics(&uri));
+                return replies;
+            }
+            "textDocument/didChange" => {
+                // full sync, so the last change holds the whole text
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    self.documents.insert(uri.clone(), text.to_owned());
+                }
+                replies.push(self.diagnostics(&uri));
+                return replies;
+            }
+            "textDocument/didSave" => {
+                // pick up zettels created or synced since the server started
+                if let Ok(Some(zk)) = self.db.get_zk() {
+                    self.zk = zk;
+                }
+                replies.push(self.diagnostics(&uri));
+                return replies;
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![publish(&uri, Vec::new())];
+            }
+            "textDocument/completion" => self.completion(&uri, &params["position"]),
+            "textDocument/definition" => self.link_target(&uri, &params["position"]).map_or(
+                Value::Null,
+                |(_, meta)| json!({"uri": self.uri(meta), "range": range(0, 0, 0)}),
+            ),
+            "textDocument/hover" => self.hover(&uri, &params["position"]),
+            "textDocument/rename" => {
+                let new_name = params["newName"].as_str().unwrap_or_default();
+                self.rename(&uri, &params["position"], new_name)
+            }
+            "shutdown" => Value::Null,
+            _ => {
+                if message.get("id").is_some() {
+                    replies.push(json!({
+                        "jsonrpc": "2.0",
+                        "id": message["id"],
+                        "error": {"code": -32601, "message": format!("unknown method {}", method)},
+                    }));
+                }
+                return replies;
+            }
+        };
+        if let Some(id) = message.get("id") {
+            replies.push(json!({"jsonrpc": "2.0", "id": id, "result": result}));
+        }
+        replies
+    }
+
+    fn uri(&self, meta: &ZettelMeta) -> String {
+        path_to_uri(&meta.full_path(self.db.root_dir()))
+    }
+
+    /// text of an open document, or else of the file
+    fn text(&self, uri: &str) -> Option<String> {
+        match self.documents.get(uri) {
+            Some(text) => Some(text.clone()),
+            None => std::fs::read_to_string(uri_to_path(uri)?).ok(),
+        }
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let text = self.text(uri).unwrap_or_default();
+        let lines: Vec<&str> = text.lines().collect();
+        let diagnostics = link::wikilinks(&text)
+            .into_iter()
+            // links into other kastens are checked by `zk links check`
+            .filter(|l| link::split_kasten(&l.target).0.is_none())
+            .filter(|l| !self.zk.zettels.contains_key(&l.target))
+            .map(|l| {
+                let line = lines[l.line - 1];
+                json!({
+                    "range": range(
+                        l.line - 1,
+                        utf16_column(line, l.span.start),
+                        utf16_column(line, l.span.end),
+                    ),
+                    "severity": 2,
+                    "source": "zk",
+                    "message": format!("no zettel with id {}", l.target),
+                })
+            })
+            .collect();
+        publish(uri, diagnostics)
+    }
+
+    /// zettel linked to at `position`
+    fn link_target(&self, uri: &str, position: &Value) -> Option<(&zettel::Id, &ZettelMeta)> {
+        let text = self.text(uri)?;
+        let (line, column) = position_in(&text, position)?;
+        let link = link::wikilinks(&text)
+            .into_iter()
+            .find(|l| l.line == line + 1 && l.span.contains(&column))?;
+        self.zk.zettels.get_key_value(&link.target)
+    }
+
+    fn completion(&self, uri: &str, position: &Value) -> Value {
+        let text = self.text(uri).unwrap_or_default();
+        let in_link = position_in(&text, position).is_some_and(|(line, column)| {
+            let before = &text.lines().nth(line).unwrap_or_default()[..column];
+            before
+                .rfind("[[")
+                .is_some_and(|open| !before[open..].contains("]]"))
+        });
+        if !in_link {
+            return Value::Array(Vec::new());
+        }
+        let mut zettels: Vec<(&zettel::Id, &ZettelMeta)> = self.zk.zettels.iter().collect();
+        zettels.sort_by(|a, b| a.1.title.cmp(&b.1.title));
+        zettels
+            .into_iter()
+            .map(|(id, meta)| {
+                json!({
+                    "label": meta.title,
+                    "kind": 18,
+                    "detail": id,
+                    "filterText": format!("{} {}", meta.title, id),
+                    "insertText": id,
+                })
+            })
+            .collect()
+    }
+
+    fn hover(&self, uri: &str, position: &Value) -> Value {
+        let (_, meta) = match self.link_target(uri, position) {
+            Some(target) => target,
+            None => return Value::Null,
+        };
+        let body = self
+            .text(&self.uri(meta))
+            .and_then(|text| {
+                frontmatter::parse_yaml(&mut std::io::BufReader::new(text.as_bytes())).ok()
+            })
+            .map(|(_, body)| {
+                body.trim()
+                    .lines()
+                    .take(HOVER_LINES)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default();
+        json!({
+            "contents": {"kind": "markdown", "value": format!("**{}**\n\n{}", meta.title, body)},
+        })
+    }
+
+    /// retitle the zettel linked to at `position`, along with the labels of
+    /// links to it that repeat its old title
+    fn rename(&self, uri: &str, position: &Value, new_title: &str) -> Value {
+        let (id, meta) = match self.link_target(uri, position) {
+            Some(target) => target,
+            None => return Value::Null,
+        };
+        let mut changes: HashMap<String, Vec<Value>> = HashMap::new();
+        let target_uri = self.uri(meta);
+        if let Some(text) = self.text(&target_uri) {
+            // only the frontmatter, which comes first, holds the title
+            if let Some(n) = text.lines().position(|l| l.starts_with("title:")) {
+                let title = serde_yaml::to_string(new_title).unwrap_or_default();
+                let title = title.trim_start_matches("---").trim();
+                let line = text.lines().nth(n).unwrap_or_default();
+                changes.entry(target_uri).or_default().push(json!({
+                    "range": range(n, 0, utf16_column(line, line.len())),
+                    "newText": format!("title: {}", title),
+                }));
+            }
+        }
+        // open documents may not be in the database yet
+        let mut uris: Vec<String> = self.zk.zettels.values().map(|m| self.uri(m)).collect();
+        uris.extend(self.documents.keys().cloned());
+        uris.sort();
+        uris.dedup();
+        for other_uri in uris {
+            let text = match self.text(&other_uri) {
+                Some(text) => text,
+                None => continue,
+            };
+            let lines: Vec<&str> = text.lines().collect();
+            for l in link::wikilinks(&text) {
+                if l.target != *id || l.label.as_deref() != Some(&meta.title) {
+                    continue;
+                }
+                let line = lines[l.line - 1];
+                let open = if l.embed { "![[" } else { "[[" };
+                changes.entry(other_uri.clone()).or_default().push(json!({
+                    "range": range(
+                        l.line - 1,
+                        utf16_column(line, l.span.start),
+                        utf16_column(line, l.span.end),
+                    ),
+                    "newText": format!("{}{}|{}]]", open, id, new_title),
+                }));
+            }
+        }
+        json!({ "changes": changes })
+    }
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": {"uri": uri, "diagnostics": diagnostics},
+    })
+}
+
+fn range(line: usize, start: usize, end: usize) -> Value {
+    json!({
+        "start": {"line": line, "character": start},
+        "end": {"line": line, "character": end},
+    })
+}
+
+/// LSP counts characters in UTF-16 code units
+fn utf16_column(line: &str, byte: usize) -> usize {
+    line[..byte].encode_utf16().count()
+}
+
+/// line and byte offset within it of an LSP position
+fn position_in(text: &str, position: &Value) -> Option<(usize, usize)> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    let content = text.lines().nth(line).unwrap_or_default();
+    let mut units = 0;
+    for (byte, c) in content.char_indices() {
+        if units >= character {
+            return Some((line, byte));
+        }
+        units += c.len_utf16();
+    }
+    Some((line, content.len()))
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        match (b, tail) {
+            (b'%', [h, l, tail @ ..]) => {
+                let hex = std::str::from_utf8(&[*h, *l]).ok()?.to_owned();
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+                rest = tail;
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+fn path_to_uri(path: &Path) -> String {
+    let mut uri = "file://".to_owned();
+    for b in path.to_string_lossy().bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+/// next message from the client, `None` once input ends
+fn read_message(input: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let invalid =
+        |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_owned());
+    let length = length.ok_or_else(|| invalid("missing Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(
+        serde_json::from_slice(&body).map_err(|e| invalid(&e.to_string()))?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn links_between_zettels() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_lsp_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        let mut target = db.new_zettel(&zk.config, "Target note", "t", dt)?;
+        target.content = "what the target says\n".to_owned();
+        zk.add(db.root_dir(), &target)?;
+        db.commit(&zk)?;
+        let mut server = Server::new(&db)?;
+        let uri = path_to_uri(&tmp_dir.path().join("source note.md"));
+        let text = "see [[t|Target note]] and [[gone]]\n[[";
+        let open = json!({
+            "method": "textDocument/didOpen",
+            "params": {"textDocument": {"uri": uri, "text": text}},
+        });
+        let diagnostics = &server.handle(&open)[0]["params"]["diagnostics"];
+        assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+        assert_eq!(diagnostics[0]["range"]["start"]["character"], 26);
+        let request = |method: &str, line: usize, character: usize| {
+            json!({
+                "id": 1,
+                "method": method,
+                "params": {
+                    "textDocument": {"uri": uri},
+                    "position": {"line": line, "character": character},
+                    "newName": "Better title",
+                },
+            })
+        };
+        let definition = server.handle(&request("textDocument/definition", 0, 8));
+        assert_eq!(
+            definition[0]["result"]["uri"],
+            server.uri(&server.zk.zettels["t"])
+        );
+        let hover = server.handle(&request("textDocument/hover", 0, 8));
+        let hover = hover[0]["result"]["contents"]["value"].as_str().unwrap();
+        assert!(hover.contains("what the target says"));
+        let completion = server.handle(&request("textDocument/completion", 1, 2));
+        assert_eq!(completion[0]["result"][0]["insertText"], "t");
+        let rename = server.handle(&request("textDocument/rename", 0, 8));
+        let edits = &rename[0]["result"]["changes"];
+        assert_eq!(edits.as_object().unwrap().len(), 2);
+        assert_eq!(edits[&uri][0]["newText"], "[[t|Better title]]");
+        let target_uri = server.uri(&server.zk.zettels["t"]);
+        assert_eq!(edits[&target_uri][0]["newText"], "title: Better title");
+        assert_eq!(
+            uri_to_path(&uri),
+            Some(tmp_dir.path().join("source note.md"))
+        );
+        Ok(())
+    }
+}
@@ -1,46 +1,1229 @@
-mod database;
-mod frontmatter;
-mod zettel;
-mod zettelkasten;
+#![allow(clippy::enum_variant_names)]
 
-pub(crate) use zettel::ZettelMeta;
+mod prompt;
+
+use database::Database;
 use zettelkasten::Zettelkasten;
+use zk::{
+    assets, autolink, bibtex, board, capture, citations, compile, config, crypt, daemon, database,
+    dates, dayone, dedupe, digest, duplicate, events, export, fix, frontmatter, fsutil, grep,
+    heatmap, history, hooks, html, ignore, import, ingest, kastens, link, linkcheck, lsp, merge,
+    metaedit, metagrep, navigate, opener, outline, pick, publish, query, reading, reconcile,
+    renameid, render, reorganize, resolve, review, search, section, sequence, serve, share,
+    snapshot, split, storage, summary, tags, tiddlywiki, transclude, undo, verify, zettel,
+    zettelkasten, DateTime, ZettelMeta,
+};
 
-use std::path::PathBuf;
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
 
 use clap::{Parser, Subcommand};
 
-type DateTime = chrono::DateTime<chrono::Local>;
-
 #[derive(Debug, Parser)]
 struct Args {
     #[clap(default_value = ".", long)]
     root_dir: PathBuf,
+    /// Never write to the zettelkasten; implied when the database file isn't
+    /// writable
+    #[clap(long)]
+    read_only: bool,
+    /// Never prompt, confirming whatever zk would ask about
+    #[clap(long, short, global = true)]
+    yes: bool,
+    /// Never prompt, declining whatever zk would ask about; implied when
+    /// stdin isn't a terminal
+    #[clap(long, global = true, conflicts_with = "yes")]
+    non_interactive: bool,
+    /// Log more about what zk does to stderr; repeat for more detail
+    #[clap(long, short, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+    /// Only log errors; ZK_LOG overrides both this and --verbose with a
+    /// filter like `debug`. There is no short flag since `-q` is --query.
+    #[clap(long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// How to report a failure; `json` prints `{"error": {"kind", "code",
+    /// "message"}}` to stdout. The exit status tells failures apart either
+    /// way: 3 for not found, 4 for a conflict, 5 for a parse error, 6 for a
+    /// lock timeout and 1 otherwise
+    #[clap(long, value_enum, global = true, default_value = "text")]
+    output: Output,
     #[clap(subcommand)]
     cmd: Command,
 }
 
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+enum Output {
+    Text,
+    Json,
+}
+
+impl Args {
+    /// global flags given a value other than their default
+    fn flags(&self) -> Vec<(String, serde_yaml::Value)> {
+        let mut flags = Vec::new();
+        if self.root_dir != Path::new(".") {
+            let root_dir = self.root_dir.display().to_string();
+            flags.push(("root-dir".to_owned(), root_dir.into()));
+        }
+        for (flag, set) in [
+            ("read-only", self.read_only),
+            ("yes", self.yes),
+            ("non-interactive", self.non_interactive),
+            ("quiet", self.quiet),
+        ] {
+            if set {
+                flags.push((flag.to_owned(), true.into()));
+            }
+        }
+        if self.verbose > 0 {
+            flags.push(("verbose".to_owned(), u64::from(self.verbose).into()));
+        }
+        flags
+    }
+}
+
+/// environment variables zk reads settings from; secrets are left out
+const ENV_SETTINGS: [&str; 8] = [
+    "VISUAL",
+    "EDITOR",
+    "ZK_OPENER",
+    database::file::KEY_FILE_VAR,
+    "ZK_LOG",
+    "ZK_SQLITE",
+    "ZK_REMOTE_USER",
+    "NO_COLOR",
+];
+
 #[derive(Debug, Subcommand)]
 enum Command {
     /// Initialize a new database
-    Init,
+    Init(InitArgs),
     /// Create a new zettel
     New(NewArgs),
     /// Sync changes to zettels with the database
-    Sync,
+    Sync(SyncArgs),
+    /// Print the zettels changed since the database was last committed:
+    /// retitled, moved, new, deleted and untracked ones
+    Diff,
+    /// Check zettel bodies against the hashes recorded on the last sync, to
+    /// find files damaged or changed behind zk's back; checking all of them
+    /// also reports zettels sharing a title
+    Verify {
+        /// Zettels to check, all of them if none are given
+        ids: Vec<zettel::Id>,
+    },
+    /// Repair what goes wrong when files are changed behind zk's back,
+    /// printing each fix made
+    Fix(FixArgs),
+    /// Take back the last command that changed the zettelkasten
+    Undo {
+        /// Print the commands that can be taken back, latest first, instead
+        #[clap(long)]
+        list: bool,
+        /// Put files back even if they changed since
+        #[clap(long, conflicts_with = "list")]
+        force: bool,
+    },
+    /// List zettels in the database
+    List(ListArgs),
+    /// Print the number of zettels matching a query, for prompts and status
+    /// bars
+    Count {
+        /// e.g. `tag:a AND NOT path:archive/**`; all zettels if not given
+        query: Option<String>,
+    },
+    /// Print a one-line overview of the zettelkasten from the database
+    Summary,
+    /// Print statistics about the zettelkasten
+    Stats(StatsArgs),
+    /// Review zettels that are due for spaced repetition
+    Review(ReviewArgs),
+    /// Summarize new, edited and due zettels since a date, to mail from cron
+    Digest(DigestArgs),
+    /// Print zettels grouped into columns by a frontmatter key or tag prefix,
+    /// as a task board
+    Board(BoardArgs),
+    /// Export the zettelkasten to another format
+    Export(ExportArgs),
+    /// Create a zettel for each row of a CSV or JSON file, as exported from
+    /// a spreadsheet or another note taking system
+    Import(ImportArgs),
+    /// Stitch zettels into one markdown document, as the draft of an article
+    Compile(CompileArgs),
+    /// Bundle a zettel with the zettels and files it links to into a
+    /// directory, with links rewritten to relative paths
+    Share(ShareArgs),
+    /// Audit which zettels are marked for publishing with `publish: true`
+    Publish(PublishArgs),
+    /// List zettels citing a source with `cites:`, given its citation key or
+    /// the id of its literature note
+    CitedBy { source: String },
+    /// Work with literature notes for references in the bibliography
+    Cite(CiteArgs),
+    /// Keep a reading list of urls and references as zettels tagged
+    /// `reading`
+    Reading(ReadingArgs),
+    /// Manage files in the assets directory
+    Assets(AssetsArgs),
+    /// Inspect links between zettels and to the outside world
+    Links(LinksArgs),
+    /// Print a zettel
+    Show(ShowArgs),
+    /// Print the headings of a zettel
+    Outline { id: zettel::Id },
+    /// Generate a table of contents of the zettels under a directory
+    Toc(TocArgs),
+    /// Generate a structure note linking all zettels with a tag
+    Index(IndexArgs),
+    /// Append one zettel to another and point links at the survivor
+    Merge(MergeArgs),
+    /// Give a zettel a new id and point links, `follows` and `cites` of
+    /// other zettels at it
+    RenameId(RenameIdArgs),
+    /// Move the sections of a zettel into zettels of their own
+    Split(SplitArgs),
+    /// Copy a zettel's body and frontmatter into a new zettel, to use it as
+    /// a template
+    Duplicate(DuplicateArgs),
+    /// Find zettels with the same or nearly the same body
+    Dedupe(DedupeArgs),
+    /// Edit the frontmatter of many zettels at once
+    Meta(MetaArgs),
+    /// Link mentions of the keywords in `config.autolink` to their zettels
+    Autolink(AutolinkArgs),
+    /// Move every zettel into a new directory scheme, fixing the relative
+    /// links the moves would break
+    Reorganize(ReorganizeArgs),
+    /// Rename, merge and suggest tags across all zettels
+    #[clap(alias = "tags")]
+    Tag(TagArgs),
+    /// Search the bodies of zettels with a regular expression
+    Grep(GrepArgs),
+    /// Print the files whose frontmatter satisfies every predicate, such as
+    /// `status == draft`, reading files rather than the database
+    GrepFrontmatter(GrepFrontmatterArgs),
+    /// Find the zettels containing words, best matches first, using the
+    /// index kept up to date by `sync`
+    Search(SearchArgs),
+    /// Rebuild the search index from scratch
+    Reindex,
+    /// Open the zettels most recently created, shown or changed by zk
+    Last(LastArgs),
+    /// Print the sequence a zettel belongs to
+    Seq(SeqArgs),
+    /// List zettels linking to a zettel
+    Backlinks(BacklinksArgs),
+    /// Go to the zettel before one in its sequence, or the daily note before
+    /// it
+    Prev(StepArgs),
+    /// Go to the zettel after one in its sequence, or the daily note after
+    /// it
+    Next(StepArgs),
+    /// Open a zettel with the application the system associates with it,
+    /// or $ZK_OPENER
+    Open { id: zettel::Id },
+    /// List zettels with matching titles for Alfred, Raycast or rofi
+    Pick(PickArgs),
+    /// Open the zettel a `zk://id` url points at, as the system's handler
+    /// of such urls
+    OpenUrl {
+        #[clap(required_unless_present = "register")]
+        url: Option<String>,
+        /// Register `zk open-url` on this zettelkasten as the handler of
+        /// `zk://` urls, with a freedesktop entry
+        #[clap(long, conflicts_with = "url")]
+        register: bool,
+    },
+    /// Print the settings of the zettelkasten
+    Config(ConfigArgs),
+    /// Register other zettelkastens to link into with `[[name:id]]`
+    Kasten(KastenArgs),
+    /// Mirror the zettelkasten to and from a WebDAV server
+    Remote(RemoteArgs),
+    /// Archive the whole zettelkasten under .zk/snapshots and go back to
+    /// earlier archives
+    Snapshot(SnapshotArgs),
+    /// Mark a zettel as modified now
+    Touch(TouchArgs),
+    /// Encrypt the body of a zettel to the age recipients in the config,
+    /// leaving its frontmatter readable
+    Encrypt { id: zettel::Id },
+    /// Decrypt the body of an encrypted zettel for good
+    Decrypt { id: zettel::Id },
+    /// Create a zettel from text read from stdin or the clipboard
+    Capture(CaptureArgs),
+    /// Upgrade the database to the current version or convert it to another
+    /// format
+    MigrateDb {
+        #[clap(long, value_enum)]
+        to: Option<database::file::DatabaseKind>,
+        /// Encrypt the database, its sidecars and the search index with the
+        /// age identity in $ZK_KEY_FILE
+        #[clap(long)]
+        encrypt: bool,
+        /// Store the database in the clear again
+        #[clap(long, conflicts_with = "encrypt")]
+        decrypt: bool,
+        /// Only print what would change
+        #[clap(long)]
+        check: bool,
+    },
+    /// Serve JSON-RPC requests on a unix socket, keeping the database in
+    /// memory between them
+    Daemon(DaemonArgs),
+    /// Serve HTTP, creating a zettel for each authenticated `POST /capture`
+    /// from a bookmarklet or phone shortcut
+    Serve(ServeArgs),
+    /// Answer the daemon's JSON-RPC requests as a child process of an editor
+    /// plugin
+    Api(ApiArgs),
+    /// Serve the Language Server Protocol on stdin and stdout, for link
+    /// completion, navigation and diagnostics in editors
+    Lsp,
+    /// Any other command runs the `zk-<name>` executable found on PATH
+    #[clap(external_subcommand)]
+    External(Vec<OsString>),
+}
+
+impl Command {
+    /// the zettel a command about one zettel is about, given as anything
+    /// [`resolve`] understands
+    fn id_mut(&mut self) -> Option<&mut zettel::Id> {
+        match self {
+            Self::Show(ShowArgs { id, .. })
+            | Self::Outline { id }
+            | Self::Seq(SeqArgs { id, .. })
+            | Self::Backlinks(BacklinksArgs { id, .. })
+            | Self::Prev(StepArgs { id: Some(id), .. })
+            | Self::Next(StepArgs { id: Some(id), .. })
+            | Self::Open { id }
+            | Self::Share(ShareArgs { id, .. })
+            | Self::Touch(TouchArgs { id, .. })
+            | Self::Encrypt { id }
+            | Self::Decrypt { id }
+            | Self::Meta(MetaArgs {
+                cmd: MetaCommand::Edit { id },
+            }) => Some(id),
+            _ => None,
+        }
+    }
+
+    /// whether the command can run without writing to the zettelkasten
+    fn is_read_only(&self) -> bool {
+        match self {
+            Self::List(_)
+            | Self::Count { .. }
+            | Self::Summary
+            | Self::Stats(_)
+            | Self::Export(_)
+            | Self::Digest(_)
+            | Self::Board(_)
+            | Self::Publish(_)
+            | Self::Share(_)
+            | Self::Compile(_)
+            | Self::CitedBy { .. }
+            | Self::Cite(_)
+            | Self::Reading(ReadingArgs {
+                cmd: ReadingCommand::List { .. },
+            })
+            | Self::Links(_)
+            | Self::Show(_)
+            | Self::Outline { .. }
+            | Self::Diff
+            | Self::Verify { .. }
+            | Self::Undo { list: true, .. }
+            | Self::Grep(_)
+            | Self::GrepFrontmatter(_)
+            | Self::Search(_)
+            | Self::Last(_)
+            | Self::Seq(_)
+            | Self::Backlinks(_)
+            | Self::Prev(_)
+            | Self::Next(_)
+            | Self::Open { .. }
+            | Self::Pick(PickArgs {
+                create_if_missing: false,
+                ..
+            })
+            | Self::OpenUrl { .. }
+            | Self::Lsp => true,
+            // plugins are told whether they may write
+            Self::External(_) => true,
+            Self::Toc(args) => args.write.is_none(),
+            Self::Index(args) => args.write.is_none(),
+            Self::Sync(args) => args.dry_run,
+            Self::Dedupe(args) => args.list,
+            Self::Autolink(args) => !args.apply,
+            Self::Meta(args) => match &args.cmd {
+                MetaCommand::Edit { .. } => false,
+                MetaCommand::Set { args, .. }
+                | MetaCommand::Unset { args, .. }
+                | MetaCommand::RenameKey { args, .. } => args.dry_run,
+            },
+            Self::Tag(args) => match &args.cmd {
+                TagCommand::Rename { dry_run, .. } | TagCommand::Merge { dry_run, .. } => *dry_run,
+                TagCommand::Suggest { .. } | TagCommand::Related { .. } => true,
+            },
+            Self::Config(_) => true,
+            Self::Kasten(args) => matches!(args.cmd, KastenCommand::List),
+            Self::Snapshot(args) => matches!(
+                args.cmd,
+                SnapshotCommand::List | SnapshotCommand::Diff { .. }
+            ),
+            Self::MigrateDb { check, .. } => *check,
+            _ => self.is_dry_run(),
+        }
+    }
+
+    /// whether the command was asked only to print the changes it would make
+    fn is_dry_run(&self) -> bool {
+        match self {
+            Self::New(NewArgs { dry_run, .. })
+            | Self::Import(ImportArgs { dry_run, .. })
+            | Self::Merge(MergeArgs { dry_run, .. })
+            | Self::RenameId(RenameIdArgs { dry_run, .. })
+            | Self::Split(SplitArgs { dry_run, .. })
+            | Self::Duplicate(DuplicateArgs { dry_run, .. })
+            | Self::Fix(FixArgs { dry_run, .. })
+            | Self::Reorganize(ReorganizeArgs { dry_run, .. }) => *dry_run,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ApiArgs {
+    /// Read newline separated requests from stdin and write responses to
+    /// stdout, the only transport so far
+    #[clap(long, required = true)]
+    pub stdio: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DaemonArgs {
+    /// Socket to listen on; defaults to .zk/daemon.sock under the root
+    /// directory
+    #[clap(long)]
+    pub socket: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ServeArgs {
+    /// Address to listen on
+    #[clap(long, default_value = "127.0.0.1:7700")]
+    pub listen: String,
+    /// Kind of the captured zettels; "inbox" if one is configured
+    #[clap(long)]
+    pub kind: Option<String>,
 }
 
 #[derive(Debug, clap::Args)]
+pub struct InitArgs {
+    /// Format of the database file; asked for if not given
+    #[clap(long, value_enum)]
+    pub format: Option<database::file::DatabaseKind>,
+    /// Where the metadata of each zettel is kept; asked for if not given
+    #[clap(long, value_enum)]
+    pub storage: Option<zettelkasten::Storage>,
+    /// Don't ask anything, using defaults for whatever wasn't given
+    #[clap(long)]
+    pub defaults: bool,
+    /// Register the notes already in the directory, giving them ids and
+    /// frontmatter as needed, and summarize what was registered, skipped and
+    /// needs attention
+    #[clap(long)]
+    pub adopt: bool,
+}
+
+#[derive(Debug, Default, clap::Args)]
 pub struct NewArgs {
-    pub title: String,
+    /// Defaults to the reference's title when using --cite; `{{date}}`,
+    /// `{{date:FORMAT}}` with a strftime format and `{{time}}` are filled in
+    #[clap(required_unless_present_any = &["cite", "from-file", "title-template"])]
+    pub title: Option<String>,
+    /// Take the title from this entry of `title_templates` in the config
+    #[clap(long, conflicts_with_all = &["title", "cite"])]
+    pub title_template: Option<String>,
+    /// Create a literature note for this citation key from the bibliography
+    #[clap(long)]
+    pub cite: Option<String>,
+    /// Turn an existing markdown file into a zettel, titled after its
+    /// frontmatter, first heading or file name, and move it into the
+    /// zettelkasten
+    #[clap(long, conflicts_with_all = &["title", "cite", "follows", "title-template"])]
+    pub from_file: Option<PathBuf>,
+    /// Import every markdown file in the directory given to --from-file and
+    /// its subdirectories
+    #[clap(long, short, requires = "from-file")]
+    pub recursive: bool,
+    /// Leave the files given to --from-file where they are and import copies
+    #[clap(long, requires = "from-file")]
+    pub copy: bool,
+    /// Continue the sequence of this zettel
+    #[clap(long)]
+    pub follows: Option<zettel::Id>,
+    /// Create a note of this kind from `kinds` in the config, in its
+    /// directory and with its frontmatter
+    #[clap(long, conflicts_with = "from-file")]
+    pub kind: Option<String>,
+    /// Print the changes to files and the database without making them
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default, clap::Args)]
+pub struct SyncArgs {
+    /// How to settle fields that differ between frontmatter and database,
+    /// instead of the configured policy
+    #[clap(long, value_enum)]
+    pub policy: Option<reconcile::Policy>,
+    /// Give files with frontmatter but no id an id and add them to the
+    /// database
+    #[clap(long)]
+    pub assign_ids: bool,
+    /// Give copies of a zettel that share its id a new id, instead of
+    /// skipping them
+    #[clap(long)]
+    pub reassign: bool,
+    /// Only print what would change, like `zk diff`
+    #[clap(long)]
+    pub dry_run: bool,
+    /// also give markdown files without frontmatter a title and an id, and
+    /// summarize what was registered; for `init --adopt`
+    #[clap(skip)]
+    pub adopt: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ListArgs {
+    #[clap(flatten)]
+    pub filter: query::Filter,
+    /// Only list zettels with at least this many words
+    #[clap(long)]
+    pub min_words: Option<usize>,
+    /// Only list zettels with at most this many words
+    #[clap(long)]
+    pub max_words: Option<usize>,
+    /// Show the day each zettel was created, in `config.date_format`
+    #[clap(long)]
+    pub dates: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ReviewArgs {
+    /// Stop after reviewing this many zettels
+    #[clap(long)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DigestArgs {
+    /// Start of the period: 7d, 2w, 1m, last week or a date like 2023-05-14
+    #[clap(long, default_value = "7d", allow_hyphen_values = true)]
+    pub since: String,
+    #[clap(long, value_enum, default_value = "markdown")]
+    pub format: digest::Format,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct StatsArgs {
+    /// Draw a calendar of the zettels created and modified each day
+    #[clap(long)]
+    pub heatmap: bool,
+    /// Number of weeks the calendar covers, up to today
+    #[clap(long, default_value_t = 52, requires = "heatmap")]
+    pub weeks: usize,
+    #[clap(long, value_enum, default_value = "both", requires = "heatmap")]
+    pub activity: heatmap::Activity,
+    #[clap(long, value_enum, default_value = "text", requires = "heatmap")]
+    pub format: heatmap::Format,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct BoardArgs {
+    #[clap(flatten)]
+    pub filter: query::Filter,
+    /// Frontmatter key whose value names the column, or `tag:<prefix>` to
+    /// use what follows the prefix in tags
+    #[clap(long, default_value = "status")]
+    pub by: board::By,
+    /// Columns to show first and in this order, even when empty
+    #[clap(long, use_value_delimiter = true, default_value = "todo,doing,done")]
+    pub columns: Vec<String>,
+    #[clap(long, value_enum, default_value = "text")]
+    pub format: board::Format,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ExportArgs {
+    #[clap(flatten)]
+    pub filter: query::Filter,
+    #[clap(long, value_enum)]
+    pub format: export::Format,
+    /// Write to this file instead of stdout
+    #[clap(long)]
+    pub out: Option<PathBuf>,
+    /// Include the zettels of registered kastens in graphs
+    #[clap(long)]
+    pub all_kastens: bool,
+    /// Only zettels marked `publish: true` or `visibility: public`
+    #[clap(long)]
+    pub published: bool,
+}
+
+#[derive(Debug, clap::Args)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct ImportArgs {
+    #[clap(subcommand)]
+    pub cmd: Option<ImportCommand>,
+    /// CSV or JSON file of rows
+    #[clap(required = true)]
+    pub path: Option<PathBuf>,
+    #[clap(long, value_enum, required = true)]
+    pub format: Option<import::Format>,
+    /// Columns to take fields from, as key=Column; title, created, body and
+    /// tags default to the columns of the same name
+    #[clap(long, use_value_delimiter = true)]
+    pub map: Vec<String>,
+    /// Print the changes to files and the database without making them
+    #[clap(long, global = true)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ImportCommand {
+    /// Import the entries of a Day One JSON export as journal zettels, with
+    /// their photos as assets
+    Dayone {
+        /// the export's zip archive or the directory it unpacks to
+        path: PathBuf,
+    },
+    /// Convert a folder of HTML notes, like those exported from Apple Notes,
+    /// into markdown zettels
+    Html { dir: PathBuf },
+    /// Import the tiddlers of a TiddlyWiki JSON export, pointing links
+    /// between them at the new zettels
+    Tiddlywiki { path: PathBuf },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CiteArgs {
+    #[clap(subcommand)]
+    pub cmd: CiteCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CiteCommand {
+    /// List references in the bibliography and their literature notes
+    List {
+        /// Only list references without a literature note
+        #[clap(long)]
+        missing: bool,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ReadingArgs {
+    #[clap(subcommand)]
+    pub cmd: ReadingCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ReadingCommand {
+    /// Add a url or the citation key of a reference to the reading list
+    Add {
+        source: String,
+        /// Title of the item; the reference's title or the url if not given
+        #[clap(long)]
+        title: Option<String>,
+    },
+    /// List the items on the reading list, oldest first
+    List {
+        #[clap(long, value_enum)]
+        status: Option<reading::Status>,
+    },
+    /// Change the status of an item; a finished item becomes a literature
+    /// note citing its reference, with a template to take notes in
+    Set {
+        id: zettel::Id,
+        #[clap(value_enum)]
+        status: reading::Status,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CompileArgs {
+    /// Zettels to compile; those matching the filter if none are given
+    pub ids: Vec<zettel::Id>,
+    #[clap(flatten)]
+    pub filter: query::Filter,
+    /// Order of the zettels; as listed when ids are given, otherwise by
+    /// creation
+    #[clap(long, value_enum)]
+    pub order: Option<compile::Order>,
+    /// Title of the document; the first zettel's title if not given
+    #[clap(long)]
+    pub title: Option<String>,
+    /// Write to this file instead of stdout
+    #[clap(long)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ShareArgs {
+    pub id: zettel::Id,
+    /// Directory to write the bundle to; must be empty if it exists
+    #[clap(long)]
+    pub out: PathBuf,
+    /// How many links to follow from the zettel; embedded zettels are
+    /// always included
+    #[clap(long, default_value_t = 1)]
+    pub depth: usize,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct PublishArgs {
+    #[clap(subcommand)]
+    pub cmd: PublishCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PublishCommand {
+    /// List the public zettels and the private ones they link to or embed
+    List,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct AssetsArgs {
+    #[clap(subcommand)]
+    pub cmd: AssetsCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum AssetsCommand {
+    /// Report assets no zettel links to and links to missing files
+    Gc {
+        /// Delete unreferenced assets
+        #[clap(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct LinksArgs {
+    #[clap(subcommand)]
+    pub cmd: LinksCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum LinksCommand {
+    /// Report links to missing files and, optionally, dead urls
+    Check(LinksCheckArgs),
+}
+
+#[derive(Debug, clap::Args)]
+pub struct LinksCheckArgs {
+    /// Also request every http(s) url found in zettels
+    #[clap(long)]
+    pub external: bool,
+    /// Number of concurrent requests
+    #[clap(long, default_value_t = 8)]
+    pub jobs: usize,
+    /// Reuse results of checks younger than this many hours
+    #[clap(long, default_value_t = 24)]
+    pub max_age: i64,
+    /// Seconds to wait for a response
+    #[clap(long, default_value_t = 10)]
+    pub timeout: u64,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ShowArgs {
+    pub id: zettel::Id,
+    /// Format the body for the terminal, inlining embedded zettels (`![[id]]`)
+    /// and showing titles for links to zettels
+    #[clap(long)]
+    pub render: bool,
+    /// How deep to follow nested embeds when rendering
+    #[clap(long, default_value_t = transclude::DEFAULT_MAX_DEPTH)]
+    pub depth: usize,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct TocArgs {
+    /// Directory relative to the root directory
+    #[clap(default_value = ".")]
+    pub subdir: PathBuf,
+    /// Write the table of contents into this index note, relative to the
+    /// root directory, instead of printing it; the note is created if needed
+    #[clap(long)]
+    pub write: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct IndexArgs {
+    #[clap(long)]
+    pub tag: String,
+    /// Write the index into this structure note, relative to the root
+    /// directory, instead of printing it; the note is created if needed and
+    /// only its managed section is replaced on later runs
+    #[clap(long)]
+    pub write: Option<PathBuf>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RenameIdArgs {
+    /// Id the zettel has now
+    pub old: zettel::Id,
+    /// Id to give it
+    pub new: zettel::Id,
+    /// Print the changes to files and the database without making them
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct MergeArgs {
+    /// Zettel that is kept
+    pub survivor: zettel::Id,
+    /// Zettel whose body is appended to the survivor before it is removed
+    pub absorbed: zettel::Id,
+    /// Whose frontmatter values win when both zettels set a key
+    #[clap(long, value_enum, default_value = "survivor")]
+    pub frontmatter: merge::Winner,
+    /// Move the absorbed file into `.zk/archive` instead of deleting it;
+    /// refused if a file there has its name already
+    #[clap(long)]
+    pub archive: bool,
+    /// Print the changes to files and the database without making them
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SplitArgs {
+    pub id: zettel::Id,
+    /// Split at headings of this level, 2 for `##`
+    #[clap(long, default_value_t = 2)]
+    pub level: usize,
+    /// Print the changes to files and the database without making them
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DuplicateArgs {
+    pub id: zettel::Id,
+    /// Title of the copy; defaults to the original's with " (copy)" added
+    pub title: Option<String>,
+    /// Only copy these frontmatter keys instead of all but the generated
+    /// ones like id and title
+    #[clap(long, value_name = "KEY")]
+    pub keep: Vec<String>,
+    /// Add `source: <id>` pointing at the original
+    #[clap(long)]
+    pub source: bool,
+    /// Print the changes to files and the database without making them
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct DedupeArgs {
+    /// Share of word sequences two bodies must have in common, from 0 to 1
+    #[clap(long, default_value_t = 0.8)]
+    pub threshold: f64,
+    /// Only print the duplicates instead of asking what to do with them
+    #[clap(long)]
+    pub list: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct MetaArgs {
+    #[clap(subcommand)]
+    pub cmd: MetaCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MetaCommand {
+    /// Edit the metadata of a zettel as YAML in $VISUAL or $EDITOR
+    Edit { id: zettel::Id },
+    /// Set frontmatter keys; values are read as YAML, e.g. `tags=[a, b]`
+    Set {
+        #[clap(required = true)]
+        assignments: Vec<String>,
+        #[clap(flatten)]
+        args: MetaEditArgs,
+    },
+    /// Remove frontmatter keys
+    Unset {
+        #[clap(required = true)]
+        keys: Vec<String>,
+        #[clap(flatten)]
+        args: MetaEditArgs,
+    },
+    /// Rename a frontmatter key, unless the new key is already set
+    RenameKey {
+        from: String,
+        to: String,
+        #[clap(flatten)]
+        args: MetaEditArgs,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct MetaEditArgs {
+    #[clap(flatten)]
+    pub filter: query::Filter,
+    /// Print the changes without writing them
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct AutolinkArgs {
+    #[clap(flatten)]
+    pub filter: query::Filter,
+    /// Write the links, asking about each zettel; otherwise only print them
+    #[clap(long)]
+    pub apply: bool,
+}
+
+#[derive(Debug, clap::Args)]
+#[clap(group(clap::ArgGroup::new("fixes").required(true).multiple(true)))]
+pub struct FixArgs {
+    /// Drop database entries for a file that has another zettel's id
+    #[clap(long, group = "fixes")]
+    pub duplicates: bool,
+    /// Point zettels whose files were moved by hand at where they are now
+    #[clap(long, group = "fixes")]
+    pub paths: bool,
+    /// Give files with frontmatter but no id one, and add them to the
+    /// database
+    #[clap(long, group = "fixes")]
+    pub ids: bool,
+    /// Rewrite `created` and `date` frontmatter the way zk writes it
+    #[clap(long, group = "fixes")]
+    pub dates: bool,
+    /// Record the hashes of bodies changed outside of zk
+    #[clap(long, group = "fixes")]
+    pub hashes: bool,
+    /// Make every fix
+    #[clap(long, group = "fixes")]
+    pub all: bool,
+    /// Print the changes to files and the database without making them
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ReorganizeArgs {
+    #[clap(long, value_enum)]
+    pub by: reorganize::By,
+    /// Print the moves and link changes without making them
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct TagArgs {
+    #[clap(subcommand)]
+    pub cmd: TagCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TagCommand {
+    /// Rename a tag
+    Rename {
+        old: String,
+        new: String,
+        /// Print the changes without writing them
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Replace several tags with one
+    Merge {
+        #[clap(required = true)]
+        tags: Vec<String>,
+        #[clap(long)]
+        into: String,
+        /// Print the changes without writing them
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Propose tags for a zettel from the words of its body and the tags
+    /// of zettels linked with it or sharing its tags
+    Suggest {
+        id: zettel::Id,
+        #[clap(long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// List the tags most often found together with a tag
+    Related { tag: String },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct GrepArgs {
+    pub pattern: String,
+    /// Lines of context to print around each match
+    #[clap(long, short = 'C', default_value_t = 0)]
+    pub context: usize,
+    #[clap(long, short = 'i')]
+    pub ignore_case: bool,
+    /// Only print the paths of zettels with a match
+    #[clap(long, short = 'l')]
+    pub files_with_matches: bool,
+    #[clap(flatten)]
+    pub filter: query::Filter,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct GrepFrontmatterArgs {
+    /// `key == value`, `key != value`, `key ~= regex`, `<`, `<=`, `>`,
+    /// `>=`, or `key` or `!key` for whether the key is there
+    #[clap(required = true)]
+    pub predicates: Vec<String>,
+    /// Also print the values of the keys the predicates test
+    #[clap(long)]
+    pub values: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SearchArgs {
+    #[clap(required = true)]
+    pub words: Vec<String>,
+    /// Only print this many zettels
+    #[clap(long, short = 'n')]
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct LastArgs {
+    /// Number of zettels to open
+    #[clap(default_value_t = 1)]
+    pub n: usize,
+    /// Print the zettels instead of opening them in $VISUAL or $EDITOR
+    #[clap(long)]
+    pub list: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SeqArgs {
+    pub id: zettel::Id,
+    /// Only print the zettel after this one
+    #[clap(long, conflicts_with = "prev")]
+    pub next: bool,
+    /// Only print the zettel before this one
+    #[clap(long)]
+    pub prev: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct StepArgs {
+    /// Zettel to start from; the one most recently created, shown or
+    /// changed by zk if not given
+    pub id: Option<zettel::Id>,
+    /// Open the zettel in $VISUAL or $EDITOR instead of printing it
+    #[clap(long)]
+    pub open: bool,
+    /// Go between daily notes, those with a `YYYY-MM-DD` date in their
+    /// title, even within a sequence
+    #[clap(long)]
+    pub journal: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct BacklinksArgs {
+    pub id: zettel::Id,
+    /// Also search the zettels of registered kastens
+    #[clap(long)]
+    pub all_kastens: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct PickArgs {
+    /// Words the titles must contain, all zettels if none are given
+    pub query: Vec<String>,
+    #[clap(long, value_enum)]
+    pub format: pick::Format,
+    /// What the launcher gets back when a zettel is chosen
+    #[clap(long, value_enum, default_value = "id")]
+    pub arg: pick::Arg,
+    /// Create a zettel titled with the query when none has that title, for
+    /// "search or create" workflows
+    #[clap(long)]
+    pub create_if_missing: bool,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ConfigArgs {
+    #[clap(subcommand)]
+    pub cmd: ConfigCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print the config stored with the zettelkasten
+    Show {
+        /// Print every setting in effect instead, with zk's defaults, the
+        /// environment and flags applied, and where each one comes from
+        #[clap(long)]
+        resolved: bool,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct KastenArgs {
+    #[clap(subcommand)]
+    pub cmd: KastenCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KastenCommand {
+    /// Register a zettelkasten under a name
+    Add { name: String, path: PathBuf },
+    /// List registered zettelkastens
+    List,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RemoteArgs {
+    #[clap(subcommand)]
+    pub cmd: RemoteCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RemoteCommand {
+    /// Copy files changed on either side since the last sync to the other;
+    /// credentials are read from $ZK_REMOTE_USER and $ZK_REMOTE_PASSWORD
+    Sync {
+        /// Url of the WebDAV collection, remembered for later syncs
+        url: Option<String>,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SnapshotArgs {
+    #[clap(subcommand)]
+    pub cmd: SnapshotCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SnapshotCommand {
+    /// Archive every file, the database and the index
+    Create { label: Option<String> },
+    /// List snapshots, oldest first
+    List,
+    /// Print the files that differ between a snapshot and another one, or
+    /// the current files
+    Diff { from: String, to: Option<String> },
+    /// Put the files back the way they are in a snapshot, taking a snapshot
+    /// of the current files first
+    Restore { name: String },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CaptureArgs {
+    /// Read the system clipboard instead of stdin
+    #[clap(long)]
+    pub clipboard: bool,
+    /// Defaults to the first line of the text
+    #[clap(long, short)]
+    pub title: Option<String>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct TouchArgs {
+    pub id: zettel::Id,
+    /// Also move the creation date, e.g. `2015-05-14` or `yesterday`
+    #[clap(long)]
+    pub created: Option<String>,
 }
 
 #[derive(Debug)]
 pub enum Error {
-    YamlDatabaseError(database::yaml::Error),
+    DaemonError(daemon::Error),
+    ServeError(serve::Error),
+    ShareError(share::Error),
+    CompileError(compile::Error),
+    LspError(lsp::Error),
+    DatabaseError(database::Error),
+    DuplicateError(duplicate::Error),
+    FixError(fix::Error),
+    UndoError(undo::Error),
+    FrontmatterError(frontmatter::Error),
+    HistoryError(history::Error),
+    KastensError(kastens::Error),
+    TranscludeError(transclude::Error),
+    LinkCheckError(linkcheck::Error),
+    MergeError(merge::Error),
+    RenameIdError(renameid::Error),
+    MetaEditError(metaedit::Error),
+    MetaGrepError(metagrep::Error),
+    QueryError(query::Error),
+    RegexError(regex::Error),
+    IngestError(ingest::Error),
+    ImportError(import::Error),
+    DayoneError(dayone::Error),
+    TiddlywikiError(tiddlywiki::Error),
+    SearchError(search::Error),
+    SnapshotError(snapshot::Error),
+    SplitError(split::Error),
+    StorageError(storage::Error),
+    AssetsError(assets::Error),
+    BibtexError(bibtex::Error),
+    CryptError(crypt::Error),
+    ExportError(export::Error),
     ZettelError(zettel::Error),
     ZettelkastenError(zettelkasten::Error),
     IoError(std::io::Error),
+    /// no `zk-<name>` executable for an unknown subcommand
+    UnknownCommand(String),
+    /// the root directory has no database yet
+    NoDatabase,
+    NoZettel(zettel::Id),
+    /// several zettels match what was typed for one; the ids and titles of
+    /// the candidates
+    Ambiguous(String, Vec<(zettel::Id, String)>),
+    /// the title policy refuses a title other zettels have
+    TitleTaken(String, Vec<zettel::Id>),
+    /// `serve` without `ZK_CAPTURE_TOKEN`
+    NoCaptureToken,
+    /// no `sqlite3` program to build an export with
+    NoSqlite(String),
+    /// `--kind` names no kind in the config
+    UnknownKind(String),
+    /// `--title-template` names no template in the config
+    UnknownTemplate(String),
+    /// no `config.bibliography` to look citation keys up in
+    NoBibliography,
+    /// a citation key that isn't in the bibliography
+    UnknownReference(String),
+    /// no `config.age` keys to encrypt or decrypt with
+    NoAgeKeys,
+    /// neither `$VISUAL` nor `$EDITOR` is set
+    NoEditor,
+    /// the editor exited unsuccessfully
+    EditorFailed,
+    /// `sqlite3` didn't load an export
+    SqliteFailed(String),
+    /// captured text without a title line and no `--title`
+    NoTitle,
+    /// a url that isn't `zk://...`
+    NotZkUrl(String),
+    /// the system opener couldn't be run or failed; what happened
+    OpenerFailed(String),
+    /// neither `$HOME` nor `$XDG_DATA_HOME` to put the url handler under
+    NoDataDir,
+    /// a kasten name with characters links use
+    InvalidKastenName(String),
+    /// a directory to add as a kasten that can't be read
+    Unreadable(PathBuf, std::io::Error),
+    /// a directory to add as a kasten without a database
+    NoDatabaseIn(PathBuf),
 }
 
 impl From<std::io::Error> for Error {
@@ -49,155 +1232,3805 @@ impl From<std::io::Error> for Error {
     }
 }
 
-impl From<database::yaml::Error> for Error {
-    fn from(e: database::yaml::Error) -> Self {
-        Self::YamlDatabaseError(e)
+impl From<daemon::Error> for Error {
+    fn from(e: daemon::Error) -> Self {
+        Self::DaemonError(e)
+    }
+}
+
+impl From<serve::Error> for Error {
+    fn from(e: serve::Error) -> Self {
+        Self::ServeError(e)
+    }
+}
+
+impl From<share::Error> for Error {
+    fn from(e: share::Error) -> Self {
+        Self::ShareError(e)
+    }
+}
+
+impl From<compile::Error> for Error {
+    fn from(e: compile::Error) -> Self {
+        Self::CompileError(e)
+    }
+}
+
+impl From<lsp::Error> for Error {
+    fn from(e: lsp::Error) -> Self {
+        Self::LspError(e)
+    }
+}
+
+impl From<database::Error> for Error {
+    fn from(e: database::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+impl From<duplicate::Error> for Error {
+    fn from(e: duplicate::Error) -> Self {
+        Self::DuplicateError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl From<assets::Error> for Error {
+    fn from(e: assets::Error) -> Self {
+        Self::AssetsError(e)
+    }
+}
+
+impl From<linkcheck::Error> for Error {
+    fn from(e: linkcheck::Error) -> Self {
+        Self::LinkCheckError(e)
+    }
+}
+
+impl From<history::Error> for Error {
+    fn from(e: history::Error) -> Self {
+        Self::HistoryError(e)
+    }
+}
+
+impl From<kastens::Error> for Error {
+    fn from(e: kastens::Error) -> Self {
+        Self::KastensError(e)
+    }
+}
+
+impl From<merge::Error> for Error {
+    fn from(e: merge::Error) -> Self {
+        Self::MergeError(e)
+    }
+}
+
+impl From<renameid::Error> for Error {
+    fn from(e: renameid::Error) -> Self {
+        Self::RenameIdError(e)
+    }
+}
+
+impl From<metagrep::Error> for Error {
+    fn from(e: metagrep::Error) -> Self {
+        Self::MetaGrepError(e)
+    }
+}
+
+impl From<metaedit::Error> for Error {
+    fn from(e: metaedit::Error) -> Self {
+        Self::MetaEditError(e)
+    }
+}
+
+impl From<query::Error> for Error {
+    fn from(e: query::Error) -> Self {
+        Self::QueryError(e)
+    }
+}
+
+impl From<fix::Error> for Error {
+    fn from(e: fix::Error) -> Self {
+        Self::FixError(e)
+    }
+}
+
+impl From<undo::Error> for Error {
+    fn from(e: undo::Error) -> Self {
+        Self::UndoError(e)
+    }
+}
+
+impl From<ingest::Error> for Error {
+    fn from(e: ingest::Error) -> Self {
+        Self::IngestError(e)
+    }
+}
+
+impl From<import::Error> for Error {
+    fn from(e: import::Error) -> Self {
+        Self::ImportError(e)
+    }
+}
+
+impl From<dayone::Error> for Error {
+    fn from(e: dayone::Error) -> Self {
+        Self::DayoneError(e)
+    }
+}
+
+impl From<tiddlywiki::Error> for Error {
+    fn from(e: tiddlywiki::Error) -> Self {
+        Self::TiddlywikiError(e)
+    }
+}
+
+impl From<search::Error> for Error {
+    fn from(e: search::Error) -> Self {
+        Self::SearchError(e)
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Self {
+        Self::RegexError(e)
+    }
+}
+
+impl From<split::Error> for Error {
+    fn from(e: split::Error) -> Self {
+        Self::SplitError(e)
+    }
+}
+
+impl From<snapshot::Error> for Error {
+    fn from(e: snapshot::Error) -> Self {
+        Self::SnapshotError(e)
+    }
+}
+
+impl From<storage::Error> for Error {
+    fn from(e: storage::Error) -> Self {
+        Self::StorageError(e)
+    }
+}
+
+impl From<transclude::Error> for Error {
+    fn from(e: transclude::Error) -> Self {
+        Self::TranscludeError(e)
+    }
+}
+
+impl From<bibtex::Error> for Error {
+    fn from(e: bibtex::Error) -> Self {
+        Self::BibtexError(e)
+    }
+}
+
+impl From<crypt::Error> for Error {
+    fn from(e: crypt::Error) -> Self {
+        Self::CryptError(e)
+    }
+}
+
+impl From<export::Error> for Error {
+    fn from(e: export::Error) -> Self {
+        Self::ExportError(e)
+    }
+}
+
+impl From<zettel::Error> for Error {
+    fn from(e: zettel::Error) -> Self {
+        Self::ZettelError(e)
+    }
+}
+
+impl From<zettelkasten::Error> for Error {
+    fn from(e: zettelkasten::Error) -> Self {
+        Self::ZettelkastenError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::DaemonError(e) => e.fmt(f),
+            Self::ServeError(e) => e.fmt(f),
+            Self::ShareError(e) => e.fmt(f),
+            Self::CompileError(e) => e.fmt(f),
+            Self::LspError(e) => e.fmt(f),
+            Self::UnknownCommand(name) => write!(
+                f,
+                "no such command `{}`; no zk-{} executable found on PATH",
+                name, name
+            ),
+            Self::NoDatabase => f.write_str("database does not exist; use `init` first"),
+            Self::NoZettel(id) => write!(f, "no zettel with id {}", id),
+            Self::Ambiguous(spec, candidates) => {
+                write!(f, "{} zettels match {}:", candidates.len(), spec)?;
+                for (id, title) in candidates {
+                    write!(f, "\n{}  {}", id, title)?;
+                }
+                Ok(())
+            }
+            Self::TitleTaken(title, others) => {
+                write!(f, "{} already titled {:?}", others.join(", "), title)
+            }
+            Self::NoCaptureToken => {
+                f.write_str("set $ZK_CAPTURE_TOKEN to the token captures must carry")
+            }
+            Self::NoSqlite(sqlite) => write!(
+                f,
+                "no {} found; install it or use --format sql and load the script yourself",
+                sqlite
+            ),
+            Self::SqliteFailed(sqlite) => write!(f, "{} failed to load the export", sqlite),
+            Self::UnknownKind(kind) => write!(f, "no kind named {}", kind),
+            Self::UnknownTemplate(name) => write!(f, "no title template named {}", name),
+            Self::NoBibliography => {
+                f.write_str("no bibliography configured; set `config.bibliography` in _zettel.yaml")
+            }
+            Self::UnknownReference(key) => {
+                write!(f, "no reference with key {} in the bibliography", key)
+            }
+            Self::NoAgeKeys => f.write_str("no age keys in the config"),
+            Self::NoEditor => f.write_str("set $VISUAL or $EDITOR to edit metadata"),
+            Self::EditorFailed => f.write_str("editor failed, metadata left unchanged"),
+            Self::NoTitle => {
+                f.write_str("couldn't find a title in the text; give one with --title")
+            }
+            Self::NotZkUrl(url) => write!(f, "not a zk url: {}", url),
+            Self::OpenerFailed(e) => {
+                write!(f, "{}; set $ZK_OPENER to the command opening files", e)
+            }
+            Self::NoDataDir => f.write_str("set $HOME or $XDG_DATA_HOME to register the handler"),
+            Self::InvalidKastenName(name) => write!(
+                f,
+                "invalid kasten name {:?}: names can't contain `:`, `|`, `]` or spaces",
+                name
+            ),
+            Self::Unreadable(path, e) => write!(f, "can't read {}: {}", path.display(), e),
+            Self::NoDatabaseIn(path) => {
+                write!(
+                    f,
+                    "no database in {}; use `init` there first",
+                    path.display()
+                )
+            }
+            Self::DatabaseError(e) => e.fmt(f),
+            Self::DuplicateError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::HistoryError(e) => e.fmt(f),
+            Self::KastensError(e) => e.fmt(f),
+            Self::AssetsError(e) => e.fmt(f),
+            Self::LinkCheckError(e) => e.fmt(f),
+            Self::MergeError(e) => e.fmt(f),
+            Self::RenameIdError(e) => e.fmt(f),
+            Self::FixError(e) => e.fmt(f),
+            Self::UndoError(e) => e.fmt(f),
+            Self::MetaEditError(e) => e.fmt(f),
+            Self::MetaGrepError(e) => e.fmt(f),
+            Self::QueryError(e) => e.fmt(f),
+            Self::RegexError(e) => e.fmt(f),
+            Self::IngestError(e) => e.fmt(f),
+            Self::ImportError(e) => e.fmt(f),
+            Self::DayoneError(e) => e.fmt(f),
+            Self::TiddlywikiError(e) => e.fmt(f),
+            Self::SearchError(e) => e.fmt(f),
+            Self::SnapshotError(e) => e.fmt(f),
+            Self::SplitError(e) => e.fmt(f),
+            Self::StorageError(e) => e.fmt(f),
+            Self::TranscludeError(e) => e.fmt(f),
+            Self::BibtexError(e) => e.fmt(f),
+            Self::CryptError(e) => e.fmt(f),
+            Self::ExportError(e) => e.fmt(f),
+            Self::ZettelError(e) => e.fmt(f),
+            Self::ZettelkastenError(e) => e.fmt(f),
+        }
+    }
+}
+
+/// Kind of failure, told apart by the exit status so scripts can branch on
+/// it; usage errors exit with 2
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Failure {
+    Other,
+    NotFound,
+    /// the zettelkasten is in a state that keeps the command from running,
+    /// like files changed since an undo entry was recorded
+    Conflict,
+    /// a file, query or argument that couldn't be read
+    Parse,
+    LockTimeout,
+}
+
+impl Failure {
+    fn exit_code(self) -> i32 {
+        match self {
+            Self::Other => 1,
+            Self::NotFound => 3,
+            Self::Conflict => 4,
+            Self::Parse => 5,
+            Self::LockTimeout => 6,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Other => "other",
+            Self::NotFound => "not-found",
+            Self::Conflict => "conflict",
+            Self::Parse => "parse",
+            Self::LockTimeout => "lock-timeout",
+        }
+    }
+
+    fn of_io(e: &std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => Self::NotFound,
+            std::io::ErrorKind::AlreadyExists => Self::Conflict,
+            std::io::ErrorKind::InvalidData => Self::Parse,
+            // what fsutil::Lock gives up with
+            std::io::ErrorKind::WouldBlock => Self::LockTimeout,
+            _ => Self::Other,
+        }
+    }
+
+    fn of_frontmatter(e: &frontmatter::Error) -> Self {
+        match e {
+            frontmatter::Error::IoError(e) => Self::of_io(e),
+            _ => Self::Parse,
+        }
+    }
+}
+
+impl Error {
+    fn failure(&self) -> Failure {
+        match self {
+            Self::NoDatabase
+            | Self::NoZettel(_)
+            | Self::UnknownCommand(_)
+            | Self::NoSqlite(_)
+            | Self::UnknownKind(_)
+            | Self::UnknownTemplate(_)
+            | Self::NoBibliography
+            | Self::UnknownReference(_)
+            | Self::NoDatabaseIn(_) => Failure::NotFound,
+            Self::Ambiguous(..)
+            | Self::TitleTaken(..)
+            | Self::UndoError(undo::Error::Changed(_))
+            | Self::RenameIdError(renameid::Error::Taken(_))
+            | Self::MergeError(merge::Error::ArchiveTaken(_)) => Failure::Conflict,
+            Self::IoError(e)
+            | Self::DatabaseError(database::Error::IoError(e))
+            | Self::UndoError(undo::Error::IoError(e))
+            | Self::ZettelkastenError(zettelkasten::Error::IoError(e))
+            | Self::Unreadable(_, e) => Failure::of_io(e),
+            Self::FrontmatterError(e)
+            | Self::MetaEditError(metaedit::Error::FrontmatterError(e)) => {
+                Failure::of_frontmatter(e)
+            }
+            Self::DatabaseError(
+                database::Error::SerializationError(_)
+                | database::Error::JsonSerializationError(_)
+                | database::Error::TomlDeserializationError(_)
+                | database::Error::CborDeserializationError(_),
+            )
+            | Self::ZettelkastenError(zettelkasten::Error::SerializationError(_))
+            | Self::QueryError(_)
+            | Self::RegexError(_)
+            | Self::MetaEditError(metaedit::Error::InvalidAssignment(_))
+            | Self::MetaGrepError(metagrep::Error::InvalidPredicate(_))
+            | Self::MetaGrepError(metagrep::Error::RegexError(_))
+            | Self::DatabaseError(database::Error::UnsafeKindDir(_))
+            | Self::NoTitle
+            | Self::NotZkUrl(_)
+            | Self::InvalidKastenName(_) => Failure::Parse,
+            _ => Failure::Other,
+        }
+    }
+}
+
+type Result = std::result::Result<(), Error>;
+
+fn main() {
+    let args = Args::parse();
+    let output = args.output;
+    if let Err(e) = run(args) {
+        let failure = e.failure();
+        match output {
+            Output::Text => eprintln!("error: {}", e),
+            Output::Json => println!(
+                "{}",
+                serde_json::json!({
+                    "error": {
+                        "kind": failure.name(),
+                        "code": failure.exit_code(),
+                        "message": e.to_string(),
+                    }
+                })
+            ),
+        }
+        std::process::exit(failure.exit_code());
+    }
+}
+
+fn run(args: Args) -> Result {
+    init_logging(args.verbose, args.quiet);
+    let flags = args.flags();
+    let mut db = database::file::Database::new(args.root_dir)?;
+    // a dry run that tries to commit fails rather than changing anything
+    if args.read_only || args.cmd.is_dry_run() {
+        db.set_read_only();
+    }
+    if db.is_read_only() && !args.cmd.is_read_only() {
+        return Err(database::Error::ReadOnly.into());
+    }
+    if !args.cmd.is_read_only() && !matches!(args.cmd, Command::Init(_) | Command::Undo { .. }) {
+        let label: Vec<String> = std::env::args_os()
+            .skip(1)
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        db.record_undo(label.join(" "));
+    }
+    let mode = prompt::Mode::detect(args.yes, args.non_interactive);
+    let mut cmd = args.cmd;
+    if let Some(id) = cmd.id_mut() {
+        *id = resolve_id(&db, id, chrono::Local::now())?;
+    }
+    match cmd {
+        Command::Init(args) => init(db, args, mode)?,
+        Command::New(args) => {
+            let now = chrono::Local::now();
+            match (args.cite.clone(), args.from_file.clone()) {
+                (_, Some(path)) => new_from_file(db, &path, &args, now)?,
+                (Some(key), _) => new_citation(db, key, args, now, mode)?,
+                (None, None) => new(db, args, now, mode)?,
+            }
+        }
+        Command::Sync(args) => sync(db, args, mode)?,
+        Command::Diff => {
+            let args = SyncArgs {
+                dry_run: true,
+                ..Default::default()
+            };
+            sync(db, args, mode)?
+        }
+        Command::Verify { ids } => verify(db, ids)?,
+        Command::Fix(args) => fix(db, args)?,
+        Command::Undo { list, force } => undo(db, list, force)?,
+        Command::List(args) => list(db, args)?,
+        Command::Count { query } => count(db, query)?,
+        Command::Summary => summary(db)?,
+        Command::Stats(args) => stats(db, args, chrono::Local::now())?,
+        Command::Review(args) => review(db, args, chrono::Local::now(), mode)?,
+        Command::Export(args) => export(db, args)?,
+        Command::Import(args) => import(db, args, chrono::Local::now())?,
+        Command::Share(args) => share(db, args)?,
+        Command::Compile(args) => compile(db, args)?,
+        Command::CitedBy { source } => cited_by(db, source)?,
+        Command::Publish(args) => match args.cmd {
+            PublishCommand::List => publish_list(db)?,
+        },
+        Command::Board(args) => board(db, args)?,
+        Command::Digest(args) => digest(db, args, chrono::Local::now())?,
+        Command::Cite(args) => match args.cmd {
+            CiteCommand::List { missing } => cite_list(db, missing)?,
+        },
+        Command::Reading(args) => match args.cmd {
+            ReadingCommand::Add { source, title } => {
+                reading_add(db, source, title, chrono::Local::now(), mode)?
+            }
+            ReadingCommand::List { status } => reading_list(db, status)?,
+            ReadingCommand::Set { id, status } => {
+                reading_set(db, id, status, chrono::Local::now())?
+            }
+        },
+        Command::Assets(args) => match args.cmd {
+            AssetsCommand::Gc { force } => assets_gc(db, force)?,
+        },
+        Command::Links(args) => match args.cmd {
+            LinksCommand::Check(args) => links_check(db, args)?,
+        },
+        Command::Show(args) => show(db, args)?,
+        Command::Outline { id } => outline(db, id)?,
+        Command::Toc(args) => toc(db, args, chrono::Local::now())?,
+        Command::Index(args) => index(db, args, chrono::Local::now())?,
+        Command::Merge(args) => merge(db, args, chrono::Local::now())?,
+        Command::RenameId(args) => rename_id(db, args, chrono::Local::now())?,
+        Command::Split(args) => split(db, args, chrono::Local::now())?,
+        Command::Duplicate(args) => duplicate(db, args, chrono::Local::now())?,
+        Command::Dedupe(args) => dedupe(db, args, chrono::Local::now(), mode)?,
+        Command::Meta(args) => {
+            let (edits, args) = match args.cmd {
+                MetaCommand::Edit { id } => return meta_edit_one(db, id, chrono::Local::now()),
+                MetaCommand::Set { assignments, args } => (
+                    assignments
+                        .iter()
+                        .map(|a| metaedit::Edit::parse_assignment(a))
+                        .collect::<std::result::Result<_, _>>()?,
+                    args,
+                ),
+                MetaCommand::Unset { keys, args } => (
+                    keys.into_iter()
+                        .map(|key| metaedit::Edit::checked(metaedit::Edit::Unset(key)))
+                        .collect::<std::result::Result<_, _>>()?,
+                    args,
+                ),
+                MetaCommand::RenameKey { from, to, args } => (
+                    vec![metaedit::Edit::checked(metaedit::Edit::RenameKey(
+                        from, to,
+                    ))?],
+                    args,
+                ),
+            };
+            meta_edit(db, edits, args, chrono::Local::now())?
+        }
+        Command::Autolink(args) => autolink(db, args, chrono::Local::now(), mode)?,
+        Command::Reorganize(args) => reorganize(db, args)?,
+        Command::Grep(args) => grep(db, args)?,
+        Command::GrepFrontmatter(args) => grep_frontmatter(db, args)?,
+        Command::Search(args) => search(db, args)?,
+        Command::Reindex => reindex(db)?,
+        Command::Last(args) => last(db, args)?,
+        Command::Seq(args) => seq(db, args)?,
+        Command::Backlinks(args) => backlinks(db, args)?,
+        Command::Prev(args) => step(db, args, navigate::Direction::Prev)?,
+        Command::Next(args) => step(db, args, navigate::Direction::Next)?,
+        Command::Open { id } => open(db, id)?,
+        Command::Pick(args) => pick(db, args, chrono::Local::now(), mode)?,
+        Command::OpenUrl { register: true, .. } => register_url_handler(db)?,
+        Command::OpenUrl { url, .. } => open_url(db, url.unwrap_or_default())?,
+        Command::Config(args) => match args.cmd {
+            ConfigCommand::Show { resolved } => config_show(db, resolved, flags)?,
+        },
+        Command::Kasten(args) => match args.cmd {
+            KastenCommand::Add { name, path } => kasten_add(db, name, path)?,
+            KastenCommand::List => kasten_list(db)?,
+        },
+        Command::Touch(args) => touch(db, args, chrono::Local::now())?,
+        Command::Encrypt { id } => crypt(db, id, true, chrono::Local::now())?,
+        Command::Decrypt { id } => crypt(db, id, false, chrono::Local::now())?,
+        Command::Capture(args) => capture(db, args, chrono::Local::now(), mode)?,
+        Command::Snapshot(args) => snapshot(db, args.cmd, chrono::Local::now(), mode)?,
+        Command::Remote(args) => match args.cmd {
+            RemoteCommand::Sync { url } => remote_sync(db, url)?,
+        },
+        Command::MigrateDb {
+            to,
+            encrypt,
+            decrypt,
+            check,
+        } => {
+            let encrypted = match (encrypt, decrypt) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            };
+            migrate_db(db, to, encrypted, check)?
+        }
+        Command::Daemon(args) => daemon(db, args)?,
+        Command::Serve(args) => serve(db, args)?,
+        Command::Api(_) => {
+            let stdin = std::io::stdin();
+            api(db, stdin.lock(), std::io::stdout())?;
+        }
+        Command::Lsp => {
+            let stdin = std::io::stdin();
+            lsp::Server::new(db)?.run(stdin.lock(), std::io::stdout())?;
+        }
+        Command::External(args) => external(db, args, mode)?,
+        Command::Tag(args) => {
+            let (from, to, dry_run) = match args.cmd {
+                TagCommand::Rename { old, new, dry_run } => (vec![old], new, dry_run),
+                TagCommand::Merge {
+                    tags,
+                    into,
+                    dry_run,
+                } => (tags, into, dry_run),
+                TagCommand::Suggest { id, limit } => return tag_suggest(db, id, limit),
+                TagCommand::Related { tag } => return tag_related(db, tag),
+            };
+            let args = MetaEditArgs {
+                filter: Default::default(),
+                dry_run,
+            };
+            let edits = vec![metaedit::Edit::ReplaceTags(from, to)];
+            meta_edit(db, edits, args, chrono::Local::now())?
+        }
+    }
+    Ok(())
+}
+
+fn new(db: impl Database, args: NewArgs, date: DateTime, mode: prompt::Mode) -> Result {
+    let title = match (args.title, args.title_template) {
+        (Some(title), _) => title,
+        (None, Some(name)) => {
+            let config = match db.get_zk()? {
+                Some(zk) => zk.config,
+                None => return Err(Error::NoDatabase),
+            };
+            match config.title_templates.get(&name) {
+                Some(template) => template.clone(),
+                None => return Err(Error::UnknownTemplate(name)),
+            }
+        }
+        (None, None) => unreachable!("clap requires a title or a template"),
+    };
+    let mut frontmatter = HashMap::new();
+    if let Some(follows) = args.follows {
+        frontmatter.insert("follows".to_owned(), follows);
+    }
+    let new = NewZettel {
+        title,
+        frontmatter,
+        kind: args.kind,
+        dry_run: args.dry_run,
+        ..Default::default()
+    };
+    new_with_frontmatter(db, new, date, mode)
+}
+
+fn capture(db: impl Database, args: CaptureArgs, date: DateTime, mode: prompt::Mode) -> Result {
+    let text = if args.clipboard {
+        capture::read_clipboard()?
+    } else {
+        std::io::read_to_string(std::io::stdin())?
+    };
+    if text.trim().is_empty() {
+        tracing::warn!("nothing to capture");
+        return Ok(());
+    }
+    let title = match args.title.or_else(|| capture::title_from(&text)) {
+        Some(title) => title,
+        None => return Err(Error::NoTitle),
+    };
+    let new = NewZettel {
+        title,
+        content: text,
+        ..Default::default()
+    };
+    new_with_frontmatter(db, new, date, mode)
+}
+
+fn new_from_file(db: impl Database, path: &Path, args: &NewArgs, now: DateTime) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    if args.dry_run {
+        zk.dry_run();
+    }
+    let sources = ingest::sources(path, args.recursive)?;
+    let ids = ingest::ingest(&db, &mut zk, &sources, args.copy, now)?;
+    if args.dry_run {
+        print_planned(&db, &zk);
+        return Ok(());
+    }
+    db.commit(&zk)?;
+    history::record(db.root_dir(), ids.iter().map(String::as_str));
+    let created: Vec<(&str, &ZettelMeta)> = ids
+        .iter()
+        .map(|id| (id.as_str(), &zk.zettels[id]))
+        .collect();
+    hooks::run(db.root_dir(), &zk.config.hooks, hooks::Event::New, &created);
+    for (id, meta) in created {
+        println!("{}  {}", id, meta.title);
+    }
+    Ok(())
+}
+
+fn snapshot(db: impl Database, cmd: SnapshotCommand, now: DateTime, mode: prompt::Mode) -> Result {
+    let root_dir = db.root_dir();
+    // snapshots are for going back when the database is broken too
+    let symlinks = match db.get_zk() {
+        Ok(Some(zk)) => zk.config.symlinks.unwrap_or_default(),
+        _ => config::SymlinkPolicy::default(),
+    };
+    let print_changes = |changes: &[(String, snapshot::Change)]| {
+        for (path, change) in changes {
+            let marker = match change {
+                snapshot::Change::Added => '+',
+                snapshot::Change::Removed => '-',
+                snapshot::Change::Modified => '~',
+            };
+            println!("{} {}", marker, path);
+        }
+    };
+    match cmd {
+        SnapshotCommand::Create { label } => {
+            let snapshot = snapshot::create(root_dir, symlinks, label.as_deref(), now)?;
+            println!("Created snapshot {}.", snapshot.name);
+        }
+        SnapshotCommand::List => {
+            for snapshot in snapshot::list(root_dir)? {
+                println!("{}  ({} KiB)", snapshot.name, snapshot.size.div_ceil(1024));
+            }
+        }
+        SnapshotCommand::Diff { from, to } => {
+            let from = snapshot::find(root_dir, &from)?.files()?;
+            let to = match to {
+                Some(to) => snapshot::find(root_dir, &to)?.files()?,
+                None => snapshot::current(root_dir, symlinks)?,
+            };
+            print_changes(&snapshot::diff(&from, &to));
+        }
+        SnapshotCommand::Restore { name } => {
+            let snapshot = snapshot::find(root_dir, &name)?;
+            let prompt = format!("Restore the files as they were in {}?", snapshot.name);
+            if !mode.confirm(&prompt)? {
+                return Ok(());
+            }
+            let backup = snapshot::create(root_dir, symlinks, Some("before-restore"), now)?;
+            println!("Saved the current files as snapshot {}.", backup.name);
+            print_changes(&snapshot::restore(root_dir, symlinks, &snapshot)?);
+        }
+    }
+    Ok(())
+}
+
+fn remote_sync(db: impl Database, url: Option<String>) -> Result {
+    let root_dir = db.root_dir();
+    let mut manifest = storage::Manifest::load(root_dir)?.unwrap_or_default();
+    if let Some(url) = url {
+        if url != manifest.url {
+            // nothing is known about another remote yet
+            manifest = storage::Manifest {
+                url,
+                ..Default::default()
+            };
+        }
+    }
+    if manifest.url.is_empty() {
+        tracing::warn!("no remote yet; give the url of a WebDAV collection");
+        return Ok(());
+    }
+    let user = std::env::var("ZK_REMOTE_USER").ok();
+    let password = std::env::var("ZK_REMOTE_PASSWORD").unwrap_or_default();
+    let remote = storage::WebDav::new(
+        &manifest.url,
+        user.as_deref().map(|user| (user, password.as_str())),
+    );
+    let local = storage::Local {
+        root_dir: root_dir.to_path_buf(),
+    };
+    let report = storage::sync(&local, &remote, &mut manifest)?;
+    manifest.save(root_dir)?;
+    for path in &report.pulled {
+        println!("pulled  {}", path);
+    }
+    for path in &report.pushed {
+        println!("pushed  {}", path);
+    }
+    for path in &report.conflicts {
+        println!("changed on both sides, skipped  {}", path);
+    }
+    if report == storage::Report::default() {
+        println!("Up to date with {}.", manifest.url);
+    }
+    Ok(())
+}
+
+fn daemon(db: database::file::Database, args: DaemonArgs) -> Result {
+    if db.get_zk()?.is_none() {
+        return Err(Error::NoDatabase);
+    }
+    let socket = args
+        .socket
+        .unwrap_or_else(|| daemon::default_socket(db.root_dir()));
+    daemon::Daemon::new(db)?.serve(&socket)?;
+    Ok(())
+}
+
+/// captures must carry the token in $ZK_CAPTURE_TOKEN as
+/// `Authorization: Bearer <token>`
+fn serve(db: database::file::Database, args: ServeArgs) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let token = match std::env::var("ZK_CAPTURE_TOKEN") {
+        Ok(token) if !token.trim().is_empty() => token.trim().to_owned(),
+        _ => return Err(Error::NoCaptureToken),
+    };
+    let kind = args.kind.or_else(|| {
+        zk.config
+            .kinds
+            .contains_key("inbox")
+            .then(|| "inbox".to_owned())
+    });
+    if let Some(kind) = kind.as_ref().filter(|k| !zk.config.kinds.contains_key(*k)) {
+        return Err(Error::UnknownKind(kind.clone()));
+    }
+    let listener = std::net::TcpListener::bind(&args.listen)?;
+    println!("listening on http://{}", listener.local_addr()?);
+    let mut server = serve::Server::new(db, token);
+    server.kind = kind;
+    server.serve(listener)?;
+    Ok(())
+}
+
+/// run the `zk-<name>` plugin for an unknown subcommand, exiting with its
+/// status
+///
+/// it gets `--root-dir` ahead of the remaining arguments, and the root
+/// directory, database file and a JSON context in `ZK_ROOT_DIR`,
+/// `ZK_DATABASE` and `ZK_CONTEXT`
+fn external(db: database::file::Database, args: Vec<OsString>, mode: prompt::Mode) -> Result {
+    let (name, rest) = args.split_first().expect("clap passes the name");
+    let name = name.to_string_lossy().into_owned();
+    match plugin(&db, &name, rest, mode)?.status() {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::UnknownCommand(name)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// answer the daemon's requests from `input` on `output`, for editor plugins
+/// running zk as a child process
+fn api(db: impl Database, input: impl std::io::BufRead, output: impl std::io::Write) -> Result {
+    daemon::Daemon::new(db)?.answer_lines(input, output)?;
+    Ok(())
+}
+
+/// the command running plugin `zk-<name>` with `args`
+fn plugin(
+    db: &database::file::Database,
+    name: &str,
+    args: &[OsString],
+    mode: prompt::Mode,
+) -> std::result::Result<std::process::Command, Error> {
+    let context = serde_json::json!({
+        "root_dir": db.root_dir(),
+        "database": db.path(),
+        "initialized": db.get_zk()?.is_some(),
+        "read_only": db.is_read_only(),
+        "interactive": mode.is_interactive(),
+        "version": env!("CARGO_PKG_VERSION"),
+    });
+    let mut command = std::process::Command::new(format!("zk-{}", name));
+    command
+        .arg("--root-dir")
+        .arg(db.root_dir())
+        .args(args)
+        .env("ZK_ROOT_DIR", db.root_dir())
+        .env("ZK_DATABASE", db.path())
+        .env("ZK_CONTEXT", context.to_string());
+    Ok(command)
+}
+
+/// the level to log at unless ZK_LOG is set
+fn log_level(verbose: u8, quiet: bool) -> &'static str {
+    match (quiet, verbose) {
+        (true, _) => "error",
+        (false, 0) => "warn",
+        (false, 1) => "info",
+        (false, 2) => "debug",
+        (false, _) => "trace",
+    }
+}
+
+/// send diagnostics to stderr, keeping stdout for results
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = log_level(verbose, quiet);
+    let filter = tracing_subscriber::EnvFilter::try_from_env("ZK_LOG")
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(format!("zk={}", level)));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::IsTerminal::is_terminal(&std::io::stderr()))
+        .with_target(false)
+        .without_time()
+        .init();
+}
+
+/// create the database, asking how the kasten should be set up unless
+/// `--defaults` is given or zk can't prompt
+fn init(db: database::file::Database, args: InitArgs, mode: prompt::Mode) -> Result {
+    let mut zk = Zettelkasten::default();
+    let adopt = |db: database::file::Database| {
+        if !args.adopt {
+            return Ok(());
+        }
+        let args = SyncArgs {
+            assign_ids: true,
+            adopt: true,
+            ..Default::default()
+        };
+        sync(db, args, mode)
+    };
+    if args.defaults || !mode.is_interactive() {
+        zk.meta.storage = args.storage.unwrap_or_default();
+        let db = db.with_kind(args.format.unwrap_or_default());
+        db.commit(&zk)?;
+        return adopt(db);
+    }
+    let format = match args.format {
+        Some(format) => format,
+        None => choose("Database format", database::file::DatabaseKind::default())?,
+    };
+    zk.meta.storage = match args.storage {
+        Some(storage) => storage,
+        None => choose("Keep zettel metadata in", zettelkasten::Storage::default())?,
+    };
+    let id_scheme = choose("Ids of new zettels", zettel::IdScheme::default())?;
+    zk.config.id_scheme = (id_scheme != zettel::IdScheme::default()).then_some(id_scheme);
+    let template: String = dialoguer::Input::new()
+        .with_prompt("File names ({date}, {title} and {id} are filled in)")
+        .default(config::DEFAULT_FILENAME_TEMPLATE.to_owned())
+        .interact_text()?;
+    zk.config.filename_template = Some(template).filter(|t| t != config::DEFAULT_FILENAME_TEMPLATE);
+    let mut keys: Vec<String> = zk.default_frontmatter.keys().cloned().collect();
+    keys.sort();
+    let kept = dialoguer::MultiSelect::new()
+        .with_prompt("Frontmatter of new zettels")
+        .items(&keys)
+        .defaults(&vec![true; keys.len()])
+        .interact()?;
+    for (n, key) in keys.iter().enumerate() {
+        if !kept.contains(&n) {
+            zk.default_frontmatter.remove(key);
+        }
+    }
+    let dirs: String = dialoguer::Input::new()
+        .with_prompt("Subdirectories to create, separated by commas")
+        .allow_empty(true)
+        .interact_text()?;
+    for dir in dirs.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        std::fs::create_dir_all(db.root_dir().join(dir))?;
+    }
+    let git = dialoguer::Confirm::new()
+        .with_prompt("Initialize a git repository?")
+        .default(false)
+        .interact()?;
+    let db = db.with_kind(format);
+    db.commit(&zk)?;
+    if git {
+        let status = std::process::Command::new("git")
+            .arg("init")
+            .current_dir(db.root_dir())
+            .status()?;
+        if !status.success() {
+            tracing::warn!("git init failed; the kasten was created without a repository");
+        }
+    }
+    adopt(db)
+}
+
+/// one of the variants of `T`, starting at `default`
+fn choose<T: clap::ValueEnum + PartialEq>(prompt: &str, default: T) -> std::io::Result<T> {
+    let variants = T::value_variants();
+    let names: Vec<&str> = variants
+        .iter()
+        .filter_map(|v| v.to_possible_value())
+        .map(|v| v.get_name())
+        .collect();
+    let choice = dialoguer::Select::new()
+        .with_prompt(prompt)
+        .items(&names)
+        .default(variants.iter().position(|v| *v == default).unwrap_or(0))
+        .interact()?;
+    Ok(variants[choice].clone())
+}
+
+/// A zettel for [`new_with_frontmatter`] to create
+#[derive(Debug, Default)]
+struct NewZettel {
+    title: String,
+    /// fields on top of the defaults and those of its kind
+    frontmatter: HashMap<String, String>,
+    kind: Option<String>,
+    content: String,
+    /// print the changes instead of making them
+    dry_run: bool,
+}
+
+/// create a zettel whose frontmatter has extra fields on top of the defaults
+/// and those of its kind
+fn new_with_frontmatter(
+    db: impl Database,
+    new: NewZettel,
+    date: DateTime,
+    mode: prompt::Mode,
+) -> Result {
+    let NewZettel {
+        title,
+        frontmatter: extra_frontmatter,
+        kind,
+        content,
+        dry_run,
+    } = new;
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => {
+            if mode.confirm("Database does not exist. Create it?")? {
+                Default::default()
+            } else {
+                return Err(Error::NoDatabase);
+            }
+        }
+    };
+    let title = dates::expand(
+        &title,
+        date,
+        zk.config.timezone.unwrap_or_default(),
+        zk.config.locale.unwrap_or_default(),
+    );
+    if let Some(follows) = extra_frontmatter.get("follows") {
+        if !zk.zettels.contains_key(follows) {
+            return Err(Error::NoZettel(follows.to_string()));
+        }
+    }
+    let id = zk.new_id(date);
+    zk.check_title(&id, &title)
+        .map_err(|others| Error::TitleTaken(title.clone(), others))?;
+    let mut zettel = match kind {
+        Some(kind) => match zk.config.kinds.get(&kind) {
+            Some(kind) => db.new_zettel_of_kind(&zk.config, kind, &title, &id, date)?,
+            None => return Err(Error::UnknownKind(kind)),
+        },
+        None => db.new_zettel(&zk.config, &title, &id, date)?,
+    };
+    if zk.meta.storage == zettelkasten::Storage::Frontmatter {
+        let zone = zk.config.timezone.unwrap_or_default();
+        zettel
+            .extra_frontmatter
+            .insert("created".to_owned(), zone.show(date).to_rfc3339());
+    }
+    let literal_fields: serde_yaml::Mapping = extra_frontmatter
+        .iter()
+        .filter(|(_, val)| !val.starts_with('@'))
+        .map(|(key, val)| (key.as_str().into(), val.as_str().into()))
+        .collect();
+    zettel.meta.update_from_frontmatter(&literal_fields);
+    zettel.extra_frontmatter.extend(extra_frontmatter);
+    zettel.meta.update_from_body(&content);
+    zettel.content = content;
+    if dry_run {
+        zk.dry_run();
+    }
+    zk.transaction(|tx| tx.add(db.root_dir(), &zettel))?;
+    if dry_run {
+        print_planned(&db, &zk);
+        return Ok(());
+    }
+    if let Err(e) = db.commit(&zk) {
+        // the file would be untracked
+        std::fs::remove_file(zettel.meta.full_path(db.root_dir()))?;
+        return Err(e.into());
+    }
+    history::record(db.root_dir(), [id.as_str()]);
+    let hooks = &zk.config.hooks;
+    hooks::run(
+        db.root_dir(),
+        hooks,
+        hooks::Event::New,
+        &[(&id, &zettel.meta)],
+    );
+    Ok(())
+}
+
+/// Settings of a sync shared by every directory
+struct SyncContext {
+    /// metadata from before the sync when the database is rebuilt
+    previous: HashMap<zettel::Id, ZettelMeta>,
+    policy: reconcile::Policy,
+    ignore: ignore::Ignore,
+    assign_ids: bool,
+    reassign: bool,
+    /// where each zettel was before the sync
+    original_paths: HashMap<zettel::Id, PathBuf>,
+    /// where each id was found so far
+    seen: HashMap<zettel::Id, PathBuf>,
+    /// files and directories synced so far, to go through each only once
+    /// however many links lead to it
+    visited: HashSet<fsutil::FileId>,
+    /// files and directories that couldn't be synced
+    errors: Vec<(PathBuf, Error)>,
+    mode: prompt::Mode,
+    /// leave files and the database alone
+    dry_run: bool,
+    /// files without an id, left out of the database
+    untracked: Vec<PathBuf>,
+    /// give markdown files without frontmatter some, see [`SyncArgs::adopt`]
+    adopt: bool,
+    /// files given an id by this sync, counted when adopting
+    adopted: usize,
+    /// files left out that aren't notes, counted when adopting
+    skipped: Vec<PathBuf>,
+    /// notes left out that can't be read as zettels, counted when adopting
+    attention: Vec<PathBuf>,
+}
+
+fn sync(db: impl Database, args: SyncArgs, mode: prompt::Mode) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let ignore = ignore::Ignore::load(db.root_dir())?;
+    let before = zk.zettels.clone();
+    let original_paths = zk
+        .zettels
+        .iter()
+        .map(|(id, meta)| (id.clone(), meta.full_path(db.root_dir())))
+        .collect();
+    // when frontmatter is the source of truth the database is rebuilt from
+    // the zettels found, so metadata of missing files goes away
+    let previous = match zk.meta.storage {
+        zettelkasten::Storage::Frontmatter => std::mem::take(&mut zk.zettels),
+        _ => HashMap::new(),
+    };
+    let mut ctx = SyncContext {
+        previous,
+        policy: args
+            .policy
+            .or(zk.config.conflict_policy)
+            .unwrap_or_default(),
+        ignore,
+        assign_ids: args.assign_ids,
+        reassign: args.reassign,
+        original_paths,
+        seen: HashMap::new(),
+        visited: HashSet::from([fsutil::file_id(db.root_dir())?]),
+        errors: Vec::new(),
+        mode,
+        dry_run: args.dry_run,
+        untracked: Vec::new(),
+        adopt: args.adopt,
+        adopted: 0,
+        skipped: Vec::new(),
+        attention: Vec::new(),
+    };
+    sync_dir(&db, &mut zk, &mut ctx, db.root_dir());
+    if !ctx.errors.is_empty() {
+        tracing::warn!("couldn't sync {} files or directories", ctx.errors.len());
+        for (path, e) in &ctx.errors {
+            tracing::warn!("couldn't sync {}: {}", relative(&db, path), e);
+        }
+    }
+    if args.dry_run {
+        let lines = status(&db, &before, &zk, &ctx);
+        if lines.is_empty() {
+            println!("Nothing changed since the last commit.");
+        }
+        for line in lines {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+    zk.meta.synced = Some(chrono::Local::now());
+    db.commit(&zk)?;
+    let mut changed: Vec<(&str, &ZettelMeta)> = zk
+        .zettels
+        .iter()
+        .filter(|(id, meta)| before.get(*id) != Some(*meta))
+        .map(|(id, meta)| (id.as_str(), meta))
+        .collect();
+    changed.sort_by_key(|(id, _)| *id);
+    let changed_ids: Vec<&str> = changed.iter().map(|(id, _)| *id).collect();
+    let index = search::refresh(&zk, db.root_dir(), db.key(), &changed_ids);
+    if let Err(e) = index.save(db.root_dir(), db.key()) {
+        tracing::warn!("couldn't update the search index: {}", e);
+    }
+    hooks::run(
+        db.root_dir(),
+        &zk.config.hooks,
+        hooks::Event::Sync,
+        &changed,
+    );
+    if args.adopt {
+        let attention: Vec<String> = ctx
+            .attention
+            .iter()
+            .chain(ctx.errors.iter().map(|(path, _)| path))
+            .map(|path| relative(&db, path))
+            .collect();
+        println!(
+            "Registered {} notes, skipped {} other files; {} need attention.",
+            ctx.adopted,
+            ctx.skipped.len(),
+            attention.len()
+        );
+        for path in attention {
+            println!("  {}", path);
+        }
+    }
+    Ok(())
+}
+
+/// update metadata from the frontmatter of zettels in dir and its
+/// subdirectories, collecting errors in the context instead of giving up
+fn sync_dir(db: &impl Database, zk: &mut Zettelkasten, ctx: &mut SyncContext, dir: &Path) {
+    let dir_entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            ctx.errors.push((dir.to_path_buf(), e.into()));
+            return;
+        }
+    };
+    for entry in dir_entries {
+        let result = entry
+            .map_err(|e| (dir.to_path_buf(), e.into()))
+            .and_then(|entry| sync_entry(db, zk, ctx, &entry).map_err(|e| (entry.path(), e)));
+        if let Err(e) = result {
+            ctx.errors.push(e);
+        }
+    }
+}
+
+fn sync_entry(
+    db: &impl Database,
+    zk: &mut Zettelkasten,
+    ctx: &mut SyncContext,
+    entry: &std::fs::DirEntry,
+) -> Result {
+    let path = entry.path();
+    let file_name = entry.file_name();
+    let file_name = file_name.to_string_lossy();
+    // skip the database as well as hidden files such as the .zk directory
+    if file_name.starts_with("_zettel") || file_name.starts_with('.') {
+        return Ok(());
+    }
+    tracing::debug!("syncing {}", relative(db, &path));
+    if ctx
+        .ignore
+        .is_ignored(path.strip_prefix(db.root_dir()).unwrap())
+    {
+        return Ok(());
+    }
+    if entry.file_type()?.is_symlink() {
+        if zk.config.symlinks.unwrap_or_default() == config::SymlinkPolicy::Skip {
+            return Ok(());
+        }
+        let root = std::fs::canonicalize(db.root_dir())?;
+        match std::fs::canonicalize(&path) {
+            Ok(target) if target.starts_with(&root) => {}
+            Ok(_) => {
+                tracing::warn!(
+                    "skipping {} which links outside the root directory",
+                    relative(db, &path)
+                );
+                return Ok(());
+            }
+            Err(e) => {
+                tracing::warn!("skipping {}: {}", relative(db, &path), e);
+                return Ok(());
+            }
+        }
+    }
+    // links can lead to the same file twice, or back to a parent directory
+    if !ctx.visited.insert(fsutil::file_id(&path)?) {
+        if !path.is_dir() {
+            tracing::warn!(
+                "skipping {} which is another link to a file already synced",
+                relative(db, &path)
+            );
+        }
+        return Ok(());
+    }
+    if path.is_dir() {
+        if path != db.root_dir().join(zk.config.assets_dir()) {
+            sync_dir(db, zk, ctx, &path);
+        }
+        return Ok(());
+    }
+    // paths are stored as strings
+    let relative_path = match fsutil::to_slash(path.strip_prefix(db.root_dir()).unwrap()) {
+        Some(relative_path) => relative_path,
+        None => {
+            let e = std::io::Error::new(std::io::ErrorKind::InvalidData, "path is not UTF-8");
+            return Err(e.into());
+        }
+    };
+    let is_markdown = path
+        .extension()
+        .is_some_and(|ext| ext == "md" || ext == "markdown");
+    // an id the file's name holds, for when its frontmatter lost it
+    let recovered = zk.config.id_in_file_name(&path, |id| {
+        zk.zettels.contains_key(id) || ctx.previous.contains_key(id)
+    });
+    let (mut fm, body) = match frontmatter::parse_yaml_path(&path) {
+        Ok(parsed) => parsed,
+        Err(frontmatter::Error::MissingInitialDelimiter)
+            if ctx.adopt && is_markdown || recovered.is_some() =>
+        {
+            (serde_yaml::Mapping::new(), std::fs::read_to_string(&path)?)
+        }
+        Err(e) if recovered.is_some() => {
+            let id = recovered.unwrap();
+            tracing::warn!(
+                "keeping {} as {} from its file name; its frontmatter can't be read: {}",
+                relative(db, &path),
+                id,
+                e
+            );
+            if let Some(meta) = ctx.previous.get(&id).cloned() {
+                zk.zettels.entry(id.clone()).or_insert(meta);
+            }
+            if let Some(meta) = zk.zettels.get_mut(&id) {
+                meta.path = relative_path;
+            }
+            ctx.seen.entry(id).or_insert_with(|| path.clone());
+            ctx.attention.push(path);
+            return Ok(());
+        }
+        Err(e) => {
+            tracing::warn!(
+                "skipping {} due to frontmatter error: {}",
+                path.display(),
+                e
+            );
+            if is_markdown || frontmatter::Format::of(&path) == frontmatter::Format::Org {
+                ctx.attention.push(path);
+            } else {
+                ctx.skipped.push(path);
+            }
+            return Ok(());
+        }
+    };
+    let aliases = zk.config.key_aliases.clone();
+    frontmatter::unalias(&mut fm, &aliases);
+    let file_modified: DateTime = entry.metadata()?.modified()?.into();
+    if let Some(id) = recovered.filter(|_| !fm.contains_key(&"id".into())) {
+        tracing::info!(
+            "recovered id {} of {} from its file name",
+            id,
+            relative(db, &path)
+        );
+        fm.insert("id".into(), id.into());
+        if !ctx.dry_run {
+            let fm = frontmatter::aliased(&fm, &aliases);
+            std::fs::write(&path, frontmatter::write_for(&path, &fm, &body)?)?;
+        }
+    }
+    let id: zettel::Id = {
+        let id = fm.get(&"id".into());
+        if id.is_none() && ctx.assign_ids && !ctx.dry_run {
+            if ctx.adopt && !fm.contains_key(&"title".into()) {
+                fm.insert("title".into(), ingest::title(&path, &fm, &body).into());
+            }
+            let id = add_with_new_id(db, zk, &path, &mut fm, &body, file_modified)?;
+            tracing::info!("assigned id {} to {}", id, relative(db, &path));
+            ctx.seen.insert(id.clone(), path.clone());
+            ctx.adopted += 1;
+            return Ok(());
+        } else if id.is_none() {
+            if ctx.dry_run {
+                ctx.untracked.push(path.clone());
+                return Ok(());
+            }
+            tracing::warn!(
+                "skipping {} due to missing key 'id' in frontmatter; add one with --assign-ids",
+                path.display()
+            );
+            return Ok(());
+        } else {
+            let id = id.unwrap().as_str();
+            if id.is_none() {
+                tracing::warn!(
+                    "skipping {} due to 'id' in frontmatter not being a 'string'",
+                    path.display()
+                );
+                ctx.attention.push(path);
+                return Ok(());
+            }
+            id.unwrap().to_owned()
+        }
+    };
+    if let Some(first) = ctx.seen.get(&id).cloned() {
+        // the file the database knew keeps the id, otherwise the first
+        let copy = match ctx.original_paths.get(&id) == Some(&path) {
+            true => first.clone(),
+            false => path.clone(),
+        };
+        tracing::warn!(
+            "duplicate id {} in {} and {}",
+            id,
+            relative(db, &first),
+            relative(db, &path)
+        );
+        if ctx.reassign && !ctx.dry_run {
+            let (mut fm, body) = frontmatter::parse_yaml_path(&copy)?;
+            frontmatter::unalias(&mut fm, &aliases);
+            let file_modified = std::fs::metadata(&copy)?.modified()?.into();
+            let new_id = add_with_new_id(db, zk, &copy, &mut fm, &body, file_modified)?;
+            tracing::info!("assigned id {} to {}", new_id, relative(db, &copy));
+            ctx.seen.insert(new_id, copy.clone());
+        } else {
+            tracing::warn!(
+                "leaving {} out of the database; give it a new id with --reassign",
+                relative(db, &copy)
+            );
+        }
+        if copy == path {
+            return Ok(());
+        }
+    }
+    ctx.seen.insert(id.clone(), path.clone());
+    let frontmatter_truth = zk.meta.storage == zettelkasten::Storage::Frontmatter;
+    if frontmatter_truth && !zk.zettels.contains_key(&id) {
+        let meta = ctx
+            .previous
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| ZettelMeta::new(&id, "", "", chrono::Local::now()));
+        zk.zettels.insert(id.clone(), meta);
+    }
+    let current_meta = zk.zettels.get_mut(&id);
+    if current_meta.is_none() {
+        tracing::warn!(
+            "no metadata with id {} for zettel at {}; skipping",
+            id,
+            path.display(),
+        );
+        return Ok(());
+    }
+    let current_meta = current_meta.unwrap();
+    current_meta.path = relative_path;
+    // frontmatter can't conflict with a database that only caches it
+    let conflicts = match frontmatter_truth {
+        true => Vec::new(),
+        false => reconcile::conflicts(current_meta, &fm),
+    };
+    let mut keep_database = false;
+    for conflict in &conflicts {
+        let side = match ctx.policy.winner(file_modified > current_meta.modified) {
+            Some(side) => side,
+            // the file wins when there's no one to ask, as it does by default
+            None if !ctx.mode.is_interactive() => reconcile::Side::File,
+            None => ask_conflict(&path, conflict)?,
+        };
+        if side == reconcile::Side::Database {
+            conflict.keep_database(&mut fm);
+            keep_database = true;
+        }
+        tracing::info!(
+            "{}: kept {} from the {}",
+            current_meta.path,
+            conflict.key,
+            match side {
+                reconcile::Side::File => "file",
+                reconcile::Side::Database => "database",
+            }
+        );
+    }
+    if keep_database && !ctx.dry_run {
+        let fm = frontmatter::aliased(&fm, &aliases);
+        std::fs::write(&path, frontmatter::write_for(&path, &fm, &body)?)?;
+    }
+    current_meta.update_from_frontmatter(&fm);
+    // the links and word count of encrypted bodies are kept from before
+    if !crypt::is_encrypted(&body) {
+        current_meta.update_from_body(&body);
+    } else {
+        current_meta.update_hash(&body);
+    }
+    if frontmatter_truth {
+        current_meta.read_state(&fm, file_modified);
+    }
+    Ok(())
+}
+
+/// how the zettels found by a sync differ from those `before` it, a line
+/// per change ordered by id, with untracked files last
+fn status(
+    db: &impl Database,
+    before: &HashMap<zettel::Id, ZettelMeta>,
+    zk: &Zettelkasten,
+    ctx: &SyncContext,
+) -> Vec<String> {
+    let root_dir = db.root_dir();
+    let path = |meta: &ZettelMeta| fsutil::to_slash(&meta.relative_path(root_dir)).unwrap();
+    let mut ids: Vec<&zettel::Id> = before.keys().chain(zk.zettels.keys()).collect();
+    ids.sort();
+    ids.dedup();
+    let mut lines = Vec::new();
+    for id in ids {
+        // the database keeps the metadata of missing files unless it is
+        // rebuilt from frontmatter
+        let after = zk.zettels.get(id).filter(|_| ctx.seen.contains_key(id));
+        let (before, after) = match (before.get(id), after) {
+            (None, Some(after)) => {
+                lines.push(format!("new       {}  {}", id, path(after)));
+                continue;
+            }
+            (Some(before), None) => {
+                lines.push(format!("deleted   {}  {}", id, path(before)));
+                continue;
+            }
+            (Some(before), Some(after)) => (before, after),
+            (None, None) => continue,
+        };
+        let (from, to) = (path(before), path(after));
+        let mut changed = false;
+        if from != to {
+            lines.push(format!("moved     {}  {} -> {}", id, from, to));
+            changed = true;
+        }
+        if before.title != after.title {
+            lines.push(format!(
+                "retitled  {}  {} -> {}",
+                id, before.title, after.title
+            ));
+            changed = true;
+        }
+        let mut unmoved = before.clone();
+        unmoved.path = after.path.clone();
+        unmoved.title = after.title.clone();
+        if !changed && unmoved != *after {
+            lines.push(format!("modified  {}  {}", id, to));
+        }
+    }
+    let mut untracked: Vec<String> = ctx.untracked.iter().map(|p| relative(db, p)).collect();
+    untracked.sort();
+    for path in untracked {
+        lines.push(format!("untracked {}", path));
+    }
+    lines
+}
+
+/// `path` relative to the root directory, for messages
+fn relative(db: &impl Database, path: &Path) -> String {
+    let path = path.strip_prefix(db.root_dir()).unwrap_or(path);
+    fsutil::to_slash(path).unwrap_or_else(|| path.display().to_string())
+}
+
+/// print the changes the transactions of a dry run would have made, a line
+/// each, files first
+fn print_planned(db: &impl Database, zk: &Zettelkasten) {
+    use zettelkasten::Planned;
+    if zk.planned().is_empty() {
+        println!("Nothing would change.");
+    }
+    for planned in zk.planned() {
+        match planned {
+            Planned::Write(path) => println!("write {}", relative(db, path)),
+            Planned::Rename(from, to) => {
+                println!("move {} -> {}", relative(db, from), relative(db, to))
+            }
+            Planned::Remove(path) => println!("remove {}", relative(db, path)),
+            Planned::Event(events::Event::ZettelAdded { id }) => println!("add zettel {}", id),
+            Planned::Event(events::Event::ZettelMoved { id, from, to }) => {
+                println!("move zettel {}: {} -> {}", id, from, to)
+            }
+            Planned::Event(events::Event::TitleChanged { id, from, to }) => {
+                println!("retitle zettel {}: {} -> {}", id, from, to)
+            }
+            Planned::Event(events::Event::ZettelRemoved { id }) => {
+                println!("remove zettel {}", id)
+            }
+            Planned::Event(events::Event::Committed) => {}
+            Planned::Update(id) => println!("update zettel {}", id),
+        }
+    }
+}
+
+/// write a fresh id into the frontmatter of the file at `path` and add it to
+/// the database with metadata taken from the file
+fn add_with_new_id(
+    db: &impl Database,
+    zk: &mut Zettelkasten,
+    path: &Path,
+    fm: &mut serde_yaml::Mapping,
+    body: &str,
+    file_modified: DateTime,
+) -> std::result::Result<zettel::Id, Error> {
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    let mut meta = ZettelMeta::new("", &stem, &relative(db, path), file_modified);
+    meta.read_state(fm, file_modified);
+    meta.update_from_frontmatter(fm);
+    meta.update_from_body(body);
+    let id = zk.new_id(meta.created);
+    fm.insert("id".into(), id.as_str().into());
+    let aliased = frontmatter::aliased(fm, &zk.config.key_aliases);
+    std::fs::write(path, frontmatter::write_for(path, &aliased, body)?)?;
+    zk.zettels.insert(id.clone(), meta);
+    Ok(id)
+}
+
+/// which side of a conflict to keep, asked interactively
+fn ask_conflict(
+    path: &Path,
+    conflict: &reconcile::Conflict,
+) -> std::result::Result<reconcile::Side, Error> {
+    let show = |value: &Option<serde_yaml::Value>| match value {
+        Some(value) => serde_yaml::to_string(value)
+            .map(|s| s.trim_start_matches("---").trim().replace('\n', " "))
+            .unwrap_or_default(),
+        None => "(not set)".to_owned(),
+    };
+    let choice = dialoguer::Select::new()
+        .with_prompt(format!(
+            "{} differs between {} and the database",
+            conflict.key,
+            path.display()
+        ))
+        .items(&[
+            format!("file: {}", show(&conflict.file)),
+            format!("database: {}", show(&conflict.database)),
+        ])
+        .default(0)
+        .interact()?;
+    Ok(match choice {
+        0 => reconcile::Side::File,
+        _ => reconcile::Side::Database,
+    })
+}
+
+fn list(db: impl Database, args: ListArgs) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let query = args.filter.to_query()?;
+    let mut zettels: Vec<(&zettel::Id, &ZettelMeta)> = zk
+        .zettels
+        .iter()
+        .filter(|(id, meta)| query.matches(id, meta, db.root_dir()))
+        .filter(|(_, meta)| args.min_words.is_none_or(|min| meta.word_count >= min))
+        .filter(|(_, meta)| args.max_words.is_none_or(|max| meta.word_count <= max))
+        .collect();
+    zettels.sort_by_key(|(_, meta)| meta.created);
+    let zone = zk.config.timezone.unwrap_or_default();
+    let locale = zk.config.locale.unwrap_or_default();
+    let date_format = zk
+        .config
+        .date_format
+        .as_deref()
+        .unwrap_or(config::DEFAULT_DATE_FORMAT);
+    if args.dates && dates::format(zone.show(chrono::Local::now()), date_format, locale).is_none() {
+        tracing::warn!("invalid date_format {:?}", date_format);
+        return Ok(());
+    }
+    for (id, meta) in zettels {
+        let date = if args.dates {
+            // checked above
+            let date = dates::format(zone.show(meta.created), date_format, locale).unwrap();
+            format!("{}  ", date)
+        } else {
+            String::new()
+        };
+        println!(
+            "{}  {}{}  ({} words, {} min)",
+            id,
+            date,
+            meta.title,
+            meta.word_count,
+            meta.reading_time()
+        );
+    }
+    Ok(())
+}
+
+fn digest(db: impl Database, args: DigestArgs, now: DateTime) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    // `7d` reads as `-7d`, seven days ago
+    let since = match args.since.trim() {
+        s if s.starts_with(|c: char| c.is_ascii_digit()) && s.ends_with(['d', 'w', 'm', 'y']) => {
+            format!("-{}", s)
+        }
+        s => s.to_owned(),
+    };
+    let since = match dates::parse_range(&since, now.date().naive_local()) {
+        Some(range) => range.start,
+        None => {
+            println!(
+                "Invalid --since {}, expected e.g. 7d, 2w, last week or 2023-05-14.",
+                args.since
+            );
+            return Ok(());
+        }
+    };
+    print!("{}", digest::digest(&zk, since, now).render(args.format));
+    Ok(())
+}
+
+fn tag_suggest(db: impl Database, id: zettel::Id, limit: usize) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let meta = match zk.zettels.get(&id) {
+        Some(meta) => meta,
+        None => {
+            return Err(Error::NoZettel(id.to_string()));
+        }
+    };
+    let (_, body) = frontmatter::parse_yaml_path(meta.full_path(db.root_dir()))?;
+    let suggestions = tags::suggest(&zk, &id, &body);
+    if suggestions.is_empty() {
+        println!("No tags to suggest.");
+    }
+    for suggestion in suggestions.iter().take(limit) {
+        let mut reasons = Vec::new();
+        if suggestion.in_body {
+            reasons.push("in body".to_owned());
+        }
+        if suggestion.linked > 0 {
+            reasons.push(format!("{} linked", suggestion.linked));
+        }
+        if suggestion.co_occurring > 0 {
+            reasons.push(format!("{} sharing tags", suggestion.co_occurring));
+        }
+        println!("{}  ({})", suggestion.tag, reasons.join(", "));
+    }
+    Ok(())
+}
+
+fn tag_related(db: impl Database, tag: String) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let related = tags::related(&zk, &tag);
+    if related.is_empty() {
+        println!("No tags found with {}.", tag);
+    }
+    for (other, count) in related {
+        println!("{}  {}", count, other);
+    }
+    Ok(())
+}
+
+fn count(db: impl Database, query: Option<String>) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let query = match query {
+        Some(query) => query::parse(&query)?,
+        None => query::Query::All,
+    };
+    let count = zk
+        .zettels
+        .iter()
+        .filter(|(id, meta)| query.matches(id, meta, db.root_dir()))
+        .count();
+    println!("{}", count);
+    Ok(())
+}
+
+fn summary(db: impl Database) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let zone = zk.config.timezone.unwrap_or_default();
+    println!("{}", summary::summarize(&zk).line(zone));
+    Ok(())
+}
+
+fn stats(db: impl Database, args: StatsArgs, now: DateTime) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let zone = zk.config.timezone.unwrap_or_default();
+    if args.heatmap {
+        let today = zone.show(now).date().naive_local();
+        let map = heatmap::heatmap(&zk, today, args.weeks, args.activity, zone);
+        print!("{}", map.render(args.format));
+        return Ok(());
+    }
+    let summary = summary::summarize(&zk);
+    let words: usize = zk.zettels.values().map(|meta| meta.word_count).sum();
+    let links: usize = zk.zettels.values().map(|meta| meta.links.len()).sum();
+    println!("Zettels: {}", summary.zettels);
+    println!("Words:   {}", words);
+    println!("Tags:    {}", summary.tags);
+    println!("Links:   {}", links);
+    println!("Orphans: {}", summary.orphans);
+    Ok(())
+}
+
+fn board(db: impl Database, args: BoardArgs) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let query = args.filter.to_query()?;
+    let board = board::board(&zk, db.root_dir(), &query, &args.by, &args.columns)?;
+    print!("{}", board.render(args.format));
+    Ok(())
+}
+
+fn review(db: impl Database, args: ReviewArgs, now: DateTime, mode: prompt::Mode) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    // zettels that were never reviewed are due immediately
+    let mut due: Vec<(zettel::Id, DateTime)> = zk
+        .zettels
+        .iter()
+        .map(|(id, meta)| {
+            (
+                id.clone(),
+                meta.review.as_ref().map_or(meta.created, |s| s.due()),
+            )
+        })
+        .filter(|(_, due)| *due <= now)
+        .collect();
+    due.sort_by_key(|(_, due)| *due);
+    if let Some(limit) = args.limit {
+        due.truncate(limit);
+    }
+    if due.is_empty() {
+        println!("No zettels are due for review.");
+        return Ok(());
+    }
+    if !mode.is_interactive() {
+        println!(
+            "{} zettels are due for review; run `zk review` in a terminal to review them.",
+            due.len()
+        );
+        return Ok(());
+    }
+    let total = due.len();
+    let zone = zk.config.timezone.unwrap_or_default();
+    for (n, (id, _)) in due.into_iter().enumerate() {
+        let meta = zk.zettels.get_mut(&id).unwrap();
+        let path = meta.full_path(db.root_dir());
+        let body = match frontmatter::parse_yaml_path(&path) {
+            Ok((_, body)) => body,
+            Err(e) => {
+                tracing::warn!("skipping {} due to frontmatter error: {}", meta.path, e);
+                continue;
+            }
+        };
+        println!("\n[{}/{}] {} ({})\n", n + 1, total, meta.title, id);
+        println!("{}", body.trim());
+        let mut items = review::GRADES.to_vec();
+        items.push("quit");
+        let choice = dialoguer::Select::new()
+            .with_prompt("How well did you recall this zettel?")
+            .items(&items)
+            .default(4)
+            .interact()?;
+        if choice == review::GRADES.len() {
+            break;
+        }
+        meta.review = Some(review::Schedule::grade(
+            meta.review.as_ref(),
+            choice as u8,
+            now,
+        ));
+        if zk.meta.storage == zettelkasten::Storage::Frontmatter {
+            let (mut fm, body) = frontmatter::parse_yaml_path(&path)?;
+            frontmatter::unalias(&mut fm, &zk.config.key_aliases);
+            meta.write_state(&mut fm, zone);
+            let fm = frontmatter::aliased(&fm, &zk.config.key_aliases);
+            std::fs::write(&path, frontmatter::write_for(&path, &fm, &body)?)?;
+        }
+        let due = zone.show(meta.review.as_ref().unwrap().due());
+        println!("next review on {}", due.format("%Y-%m-%d"));
+        db.commit(&zk)?;
+    }
+    Ok(())
+}
+
+fn export(db: impl Database, args: ExportArgs) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let query = args.filter.to_query()?;
+    zk.zettels.retain(|id, meta| {
+        query.matches(id, meta, db.root_dir()) && (!args.published || meta.is_public())
+    });
+    if args.format == export::Format::Sqlite {
+        let path = match args.out {
+            Some(path) => path,
+            None => {
+                tracing::warn!("give the database file to write with --out");
+                return Ok(());
+            }
+        };
+        let sqlite = std::env::var("ZK_SQLITE").unwrap_or_else(|_| export::sql::SQLITE.to_owned());
+        return export_sqlite(&zk, db.root_dir(), &path, sqlite);
+    }
+    let mut out: Box<dyn std::io::Write> = match args.out {
+        Some(path) => Box::new(std::fs::File::create(path)?),
+        None => Box::new(std::io::stdout()),
+    };
+    match args.format {
+        export::Format::Sql | export::Format::Sqlite => {
+            export::sql::write(&zk, db.root_dir(), &mut out)?
+        }
+        export::Format::Tiddlywiki => tiddlywiki::write(&zk, db.root_dir(), &mut out)?,
+        export::Format::Ical => {
+            let events = export::ical::events(&zk, db.root_dir())?;
+            export::ical::write(&events, chrono::Local::now(), &mut out)?;
+        }
+        export::Format::Dot => {
+            let kastens = if args.all_kastens {
+                let mut kastens = kastens::load(db.root_dir(), zk)?;
+                for kasten in kastens.iter_mut().filter(|_| args.published) {
+                    kasten.zk.zettels.retain(|_, meta| meta.is_public());
+                }
+                kastens
+            } else {
+                vec![kastens::Kasten {
+                    name: None,
+                    root_dir: db.root_dir().to_path_buf(),
+                    zk,
+                }]
+            };
+            export::dot::write(&kastens, &mut out)?;
+        }
+    }
+    Ok(())
+}
+
+/// load the SQL export of `zk` into a new SQLite database at `path`
+/// load the export into a new database at `path` with the `sqlite` program
+fn export_sqlite(zk: &Zettelkasten, root_dir: &Path, path: &Path, sqlite: String) -> Result {
+    // built beside `path` and moved over it once loaded, so a failed export
+    // leaves what was there before
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, uuid::Uuid::new_v4().simple()));
+    let mut child = match std::process::Command::new(&sqlite)
+        .arg(&tmp)
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(Error::NoSqlite(sqlite)),
+        Err(e) => return Err(e.into()),
+    };
+    let mut stdin = std::io::BufWriter::new(child.stdin.take().unwrap());
+    let written = export::sql::write(zk, root_dir, &mut stdin)
+        .map_err(Error::from)
+        .and_then(|()| Ok(std::io::Write::flush(&mut stdin)?));
+    drop(stdin);
+    let loaded = match child.wait()?.success() {
+        true => written,
+        false => Err(Error::SqliteFailed(sqlite)),
+    };
+    let exported = loaded.and_then(|()| Ok(std::fs::rename(&tmp, path)?));
+    if exported.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    exported
+}
+
+fn import(db: impl Database, args: ImportArgs, now: DateTime) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let dry_run = args.dry_run;
+    if dry_run {
+        zk.dry_run();
+    }
+    let ids = match args {
+        ImportArgs {
+            cmd: Some(ImportCommand::Dayone { path }),
+            ..
+        } => {
+            let export = dayone::Export::load(&path)?;
+            dayone::import(&db, &mut zk, &export)?
+        }
+        ImportArgs {
+            cmd: Some(ImportCommand::Html { dir }),
+            ..
+        } => {
+            let rows = html::sources(&dir)?
+                .iter()
+                .map(|path| html::row(path))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            import::import(&db, &mut zk, &rows, &[], now)?
+        }
+        ImportArgs {
+            cmd: Some(ImportCommand::Tiddlywiki { path }),
+            ..
+        } => {
+            let (rows, map) = tiddlywiki::rows(&zk, &tiddlywiki::read(&path)?, now);
+            import::import(&db, &mut zk, &rows, &map, now)?
+        }
+        ImportArgs {
+            path: Some(path),
+            format: Some(format),
+            map,
+            ..
+        } => {
+            let text = std::fs::read_to_string(path)?;
+            let rows = import::rows(&text, format)?;
+            import::import(&db, &mut zk, &rows, &import::mapping(&map)?, now)?
+        }
+        // clap requires a file and format without a subcommand
+        _ => unreachable!(),
+    };
+    if dry_run {
+        print_planned(&db, &zk);
+        return Ok(());
+    }
+    db.commit(&zk)?;
+    history::record(db.root_dir(), ids.iter().map(String::as_str));
+    let created: Vec<(&str, &ZettelMeta)> = ids
+        .iter()
+        .map(|id| (id.as_str(), &zk.zettels[id]))
+        .collect();
+    hooks::run(db.root_dir(), &zk.config.hooks, hooks::Event::New, &created);
+    for (id, meta) in created {
+        println!("{}  {}", id, meta.title);
+    }
+    Ok(())
+}
+
+fn compile(db: impl Database, args: CompileArgs) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    if let Some(id) = args.ids.iter().find(|id| !zk.zettels.contains_key(*id)) {
+        return Err(Error::NoZettel(id.to_string()));
+    }
+    let (ids, default_order) = if args.ids.is_empty() {
+        let query = args.filter.to_query()?;
+        let ids = zk
+            .zettels
+            .iter()
+            .filter(|(id, meta)| query.matches(id, meta, db.root_dir()))
+            .map(|(id, _)| id.clone())
+            .collect();
+        (ids, compile::Order::Date)
+    } else {
+        (args.ids, compile::Order::List)
+    };
+    if ids.is_empty() {
+        println!("No zettels match.");
+        return Ok(());
+    }
+    let ids = compile::order(&zk, ids, args.order.unwrap_or(default_order));
+    let doc = compile::compile(&zk, db.root_dir(), &ids, args.title.as_deref())?;
+    match args.out {
+        Some(path) => {
+            std::fs::write(&path, doc)?;
+            println!("wrote {} zettels to {}", ids.len(), path.display());
+        }
+        None => print!("{}", doc),
+    }
+    Ok(())
+}
+
+fn share(db: impl Database, args: ShareArgs) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    if !zk.zettels.contains_key(&args.id) {
+        return Err(Error::NoZettel(args.id.to_string()));
+    }
+    if args.out.exists() && std::fs::read_dir(&args.out)?.next().is_some() {
+        tracing::warn!("{} is not empty", args.out.display());
+        return Ok(());
+    }
+    let bundle = share::share(&zk, db.root_dir(), &args.id, args.depth, &args.out)?;
+    for link in &bundle.skipped {
+        println!("not bundled: {}", link);
+    }
+    println!(
+        "wrote {} zettels and {} files to {}",
+        bundle.zettels.len(),
+        bundle.assets.len(),
+        args.out.display()
+    );
+    Ok(())
+}
+
+fn publish_list(db: impl Database) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let audit = publish::audit(&zk, db.root_dir());
+    for id in &audit.published {
+        let meta = &zk.zettels[id];
+        println!("{}  {}  ({})", id, meta.title, meta.path);
+    }
+    for (from, to) in &audit.private_links {
+        println!(
+            "links to private: {} -> {}  {}",
+            from, to, zk.zettels[to].title
+        );
+    }
+    for (from, to) in &audit.private_embeds {
+        println!(
+            "embeds private: {} -> {}  {}",
+            from, to, zk.zettels[to].title
+        );
+    }
+    for id in &audit.unreadable {
+        println!("unreadable: {}", zk.zettels[id].path);
+    }
+    println!(
+        "{} of {} zettels are published.",
+        audit.published.len(),
+        zk.zettels.len()
+    );
+    Ok(())
+}
+
+/// read the bibliography configured for the zettelkasten
+fn bibliography(
+    db: &impl Database,
+    zk: &Zettelkasten,
+) -> std::result::Result<Vec<bibtex::Entry>, Error> {
+    match &zk.config.bibliography {
+        Some(path) => Ok(bibtex::parse_path(db.root_dir().join(path))?),
+        None => Err(Error::NoBibliography),
+    }
+}
+
+fn new_citation(
+    db: impl Database,
+    key: String,
+    args: NewArgs,
+    date: DateTime,
+    mode: prompt::Mode,
+) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let entries = bibliography(&db, &zk)?;
+    let kind = args.kind.or_else(|| {
+        let literature = "literature".to_owned();
+        zk.config
+            .kinds
+            .contains_key(&literature)
+            .then_some(literature)
+    });
+    let entry = match entries.into_iter().find(|entry| entry.key == key) {
+        Some(entry) => entry,
+        None => return Err(Error::UnknownReference(key)),
+    };
+    let title = args
+        .title
+        .or_else(|| entry.field("title").map(str::to_owned))
+        .unwrap_or_else(|| key.clone());
+    let mut frontmatter = HashMap::new();
+    for field in [
+        "author",
+        "editor",
+        "year",
+        "publisher",
+        "journal",
+        "doi",
+        "url",
+    ] {
+        if let Some(val) = entry.field(field) {
+            frontmatter.insert(field.to_owned(), val.to_owned());
+        }
+    }
+    frontmatter.insert("cite".to_owned(), key);
+    if let Some(follows) = args.follows {
+        frontmatter.insert("follows".to_owned(), follows);
+    }
+    let new = NewZettel {
+        title,
+        frontmatter,
+        kind,
+        dry_run: args.dry_run,
+        ..Default::default()
+    };
+    new_with_frontmatter(db, new, date, mode)
+}
+
+fn cite_list(db: impl Database, missing: bool) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let entries = bibliography(&db, &zk)?;
+    let mut notes: HashMap<&str, Vec<&str>> = HashMap::new();
+    for (id, meta) in &zk.zettels {
+        if let Some(key) = &meta.cite {
+            notes.entry(key.as_str()).or_default().push(id.as_str());
+        }
+    }
+    for entry in &entries {
+        let title = entry.field("title").unwrap_or("");
+        match notes.get_mut(entry.key.as_str()) {
+            Some(ids) if !missing => {
+                ids.sort();
+                println!("{}  {}  [{}]", entry.key, title, ids.join(", "));
+            }
+            Some(_) => (),
+            None => println!("{}  {}  (no note)", entry.key, title),
+        }
+    }
+    Ok(())
+}
+
+fn reading_add(
+    db: impl Database,
+    source: String,
+    title: Option<String>,
+    date: DateTime,
+    mode: prompt::Mode,
+) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let title = match title {
+        Some(title) => title,
+        None if reading::is_url(&source) => source.clone(),
+        None => {
+            let entries = bibliography(&db, &zk)?;
+            match entries.iter().find(|entry| entry.key == source) {
+                Some(entry) => entry.field("title").unwrap_or(&source).to_owned(),
+                None => return Err(Error::UnknownReference(source)),
+            }
+        }
+    };
+    let frontmatter = HashMap::from([
+        ("tags".to_owned(), reading::TAG.to_owned()),
+        ("status".to_owned(), reading::Status::Unread.to_string()),
+        ("source".to_owned(), source),
+    ]);
+    let new = NewZettel {
+        title,
+        frontmatter,
+        ..Default::default()
+    };
+    new_with_frontmatter(db, new, date, mode)
+}
+
+fn reading_list(db: impl Database, status: Option<reading::Status>) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    for item in reading::items(&zk, db.root_dir())? {
+        if status.is_some_and(|status| status != item.status) {
+            continue;
+        }
+        match &item.source {
+            Some(source) => println!(
+                "{}  [{}]  {}  ({})",
+                item.id, item.status, item.title, source
+            ),
+            None => println!("{}  [{}]  {}", item.id, item.status, item.title),
+        }
+    }
+    Ok(())
+}
+
+fn reading_set(
+    db: impl Database,
+    id: zettel::Id,
+    status: reading::Status,
+    now: DateTime,
+) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let path = match zk.zettels.get(&id) {
+        Some(meta) if meta.tags.iter().any(|t| t == reading::TAG) => meta.full_path(db.root_dir()),
+        Some(_) => {
+            tracing::warn!("zettel {} is not on the reading list", id);
+            return Ok(());
+        }
+        None => {
+            return Err(Error::NoZettel(id.to_string()));
+        }
+    };
+    let (mut fm, body) = frontmatter::parse_yaml_path(&path)?;
+    if reading::Status::of(&fm) == status {
+        println!("Already {}.", status);
+        return Ok(());
+    }
+    let body = match status {
+        reading::Status::Done => {
+            let source = fm.get(&"source".into()).and_then(|s| s.as_str());
+            let key = source.filter(|s| !reading::is_url(s)).map(str::to_owned);
+            let entries = match (&key, &zk.config.bibliography) {
+                (Some(_), Some(path)) => bibtex::parse_path(db.root_dir().join(path))?,
+                _ => Vec::new(),
+            };
+            let entry = entries
+                .iter()
+                .find(|entry| Some(&entry.key) == key.as_ref());
+            reading::finish(&mut fm, &body, entry)
+        }
+        _ => {
+            fm.insert("status".into(), status.as_str().into());
+            body
+        }
+    };
+    let contents = frontmatter::write_for(&path, &fm, &body)?;
+    zk.transaction(|tx| {
+        tx.write(&path, contents.as_str());
+        let meta = tx.zettels.get_mut(&id).unwrap();
+        meta.update_from_frontmatter(&fm);
+        meta.update_from_body(&body);
+        meta.modified = now;
+        Ok::<_, Error>(())
+    })?;
+    db.commit(&zk)?;
+    println!("Marked {} as {}.", id, status);
+    Ok(())
+}
+
+fn cited_by(db: impl Database, source: String) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let ids = citations::cited_by(&zk, &source);
+    if ids.is_empty() {
+        println!("No zettel cites {}.", source);
+    }
+    for id in ids {
+        println!("{}  {}", id, zk.zettels[&id].title);
+    }
+    Ok(())
+}
+
+fn assets_gc(db: impl Database, force: bool) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let report = assets::scan(&zk, db.root_dir())?;
+    for path in &report.unreadable {
+        println!("unreadable: {}", path);
+    }
+    for missing in &report.missing {
+        println!(
+            "missing: {} (linked from {}:{})",
+            missing.target, missing.path, missing.line
+        );
+    }
+    for path in &report.unreferenced {
+        println!("unreferenced: {}", path.display());
+    }
+    if !force {
+        return Ok(());
+    }
+    if !report.unreadable.is_empty() {
+        tracing::warn!("not deleting anything since some zettels could not be read");
+        return Ok(());
+    }
+    for path in &report.unreferenced {
+        std::fs::remove_file(db.root_dir().join(path))?;
+    }
+    println!("deleted {} unreferenced assets", report.unreferenced.len());
+    Ok(())
+}
+
+fn verify(db: impl Database, ids: Vec<zettel::Id>) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    if let Some(id) = ids.iter().find(|id| !zk.zettels.contains_key(*id)) {
+        return Err(Error::NoZettel(id.to_string()));
+    }
+    let report = verify::verify(&zk, db.root_dir(), &ids);
+    for (id, problem) in &report.problems {
+        println!("{}: {} ({})", problem, zk.zettels[id].path, id);
+    }
+    if ids.is_empty() {
+        for ids in zk.duplicate_titles().values() {
+            let ids: Vec<&str> = ids.iter().map(|id| id.as_str()).collect();
+            println!(
+                "duplicate title: {} ({})",
+                zk.zettels[ids[0]].title,
+                ids.join(", ")
+            );
+        }
+    }
+    if !report.unhashed.is_empty() {
+        println!(
+            "{} zettels have no hash yet; `sync` records them.",
+            report.unhashed.len()
+        );
+    }
+    println!(
+        "{} verified, {} failed.",
+        report.verified,
+        report.problems.len()
+    );
+    Ok(())
+}
+
+fn fix(db: impl Database, args: FixArgs) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let fixes = fix::Fixes {
+        duplicates: args.duplicates || args.all,
+        paths: args.paths || args.all,
+        ids: args.ids || args.all,
+        dates: args.dates || args.all,
+        hashes: args.hashes || args.all,
+    };
+    if args.dry_run {
+        zk.dry_run();
+    }
+    let done = fix::fix(&mut zk, db.root_dir(), fixes)?;
+    if done.is_empty() {
+        println!("Nothing to fix.");
+        return Ok(());
+    }
+    for fix in &done {
+        println!("{}", fix);
+    }
+    if args.dry_run {
+        return Ok(());
+    }
+    db.commit(&zk)?;
+    println!("Made {} fixes.", done.len());
+    Ok(())
+}
+
+fn undo(db: impl Database, list: bool, force: bool) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let mut entries = undo::list(db.root_dir(), db.key())?;
+    if list {
+        for entry in entries.iter().rev() {
+            println!(
+                "{}  {}",
+                entry
+                    .time
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M"),
+                entry.label
+            );
+        }
+        return Ok(());
+    }
+    let entry = match entries.pop() {
+        Some(entry) => entry,
+        None => {
+            println!("Nothing to undo.");
+            return Ok(());
+        }
+    };
+    let reverted = undo::undo(&mut zk, db.root_dir(), &entry, force)?;
+    db.commit(&zk)?;
+    undo::discard(db.root_dir(), &entry)?;
+    println!("Undid `{}`.", entry.label);
+    for path in reverted {
+        println!("  {}", path);
+    }
+    Ok(())
+}
+
+fn links_check(db: impl Database, args: LinksCheckArgs) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let report = assets::scan(&zk, db.root_dir())?;
+    for path in &report.unreadable {
+        println!("unreadable: {}", path);
+    }
+    for missing in &report.missing {
+        println!(
+            "missing: {} (linked from {}:{})",
+            missing.target, missing.path, missing.line
+        );
+    }
+    for broken in &report.broken_anchors {
+        println!(
+            "missing anchor: {} (linked from {}:{})",
+            broken.target, broken.path, broken.line
+        );
+    }
+    if !args.external {
+        return Ok(());
+    }
+    let mut occurrences = Vec::new();
+    let mut ids: Vec<_> = zk.zettels.keys().collect();
+    ids.sort();
+    for id in ids {
+        let meta = &zk.zettels[id];
+        let path = meta.full_path(db.root_dir());
+        if let Ok((_, body)) = frontmatter::parse_yaml_path(&path) {
+            let urls = link::urls(&body);
+            if urls.is_empty() {
+                continue;
+            }
+            let first_line = frontmatter::first_body_line(&path)?;
+            for (line, url) in urls {
+                occurrences.push((meta, line + first_line - 1, url));
+            }
+        }
+    }
+    let urls: Vec<String> = occurrences.iter().map(|(_, _, url)| url.clone()).collect();
+    let cache_path = db.root_dir().join(".zk").join("link-cache.yaml");
+    let mut cache = linkcheck::Cache::load(&cache_path)?;
+    let checker = linkcheck::Checker {
+        jobs: args.jobs,
+        max_age: chrono::Duration::hours(args.max_age),
+        timeout: std::time::Duration::from_secs(args.timeout),
+    };
+    let statuses = checker.check(&urls, &mut cache, chrono::Local::now());
+    if !db.is_read_only() {
+        cache.save(&cache_path)?;
+    }
+    for (meta, line, url) in &occurrences {
+        let status = &statuses[url];
+        if !status.is_alive() {
+            println!(
+                "dead: {} [{}] (linked from {}:{}, {})",
+                url, status, meta.path, line, meta.title
+            );
+        }
+    }
+    Ok(())
+}
+
+fn show(db: impl Database, args: ShowArgs) -> Result {
+    if args.render {
+        let zk = match db.get_zk()? {
+            Some(zk) => zk,
+            None => return Err(Error::NoDatabase),
+        };
+        if !zk.zettels.contains_key(&args.id) {
+            return Err(Error::NoZettel(args.id.to_string()));
+        }
+        let mut transcluder = transclude::Transcluder::new(&zk, db.root_dir());
+        transcluder.max_depth = args.depth;
+        let body = render::resolve_wikilinks(&transcluder.render(&args.id)?, &zk);
+        print!("{}", render::Renderer::for_stdout().render(&body));
+    } else {
+        let meta = get_one(&db, &args.id)?;
+        let zettel = zettel::ZettelHandle::new(db.root_dir(), &args.id, &meta);
+        let age = match crypt::is_encrypted_file(&zettel)? {
+            true => db.get_zk()?.and_then(|zk| zk.config.age),
+            false => None,
+        };
+        match age {
+            Some(age) if age.identity.is_some() => {
+                print!("{}", age.read_decrypted(&zettel.path())?)
+            }
+            _ => {
+                zettel.copy_to(&mut std::io::stdout().lock())?;
+            }
+        }
+    }
+    if !db.is_read_only() {
+        history::record(db.root_dir(), [args.id.as_str()]);
+    }
+    Ok(())
+}
+
+/// the id of the zettel `spec` points at; the error holds the candidates
+/// when there are several
+fn resolve_id(
+    db: &impl Database,
+    spec: &str,
+    now: DateTime,
+) -> std::result::Result<zettel::Id, Error> {
+    if db.get(spec)?.is_some() {
+        return Ok(spec.to_owned());
+    }
+    let zk = db.get_zk()?.ok_or(Error::NoDatabase)?;
+    let zone = zk.config.timezone.unwrap_or_default();
+    let today = zone.show(now).date().naive_local();
+    match resolve::resolve(&zk, spec, today, zone) {
+        resolve::Resolved::Found(id) => Ok(id),
+        resolve::Resolved::Missing => Err(Error::NoZettel(spec.to_owned())),
+        resolve::Resolved::Ambiguous(ids) => {
+            let candidates = ids
+                .into_iter()
+                .map(|id| {
+                    let title = zk.zettels[&id].title.clone();
+                    (id, title)
+                })
+                .collect();
+            Err(Error::Ambiguous(spec.to_owned(), candidates))
+        }
+    }
+}
+
+/// metadata of zettel `id`, looked up without reading the whole database
+/// when it can be
+fn get_one(db: &impl Database, id: &str) -> std::result::Result<ZettelMeta, Error> {
+    if let Some(meta) = db.get(id)? {
+        return Ok(meta);
+    }
+    match db.get_zk()? {
+        None => Err(Error::NoDatabase),
+        Some(_) => Err(Error::NoZettel(id.to_owned())),
+    }
+}
+
+fn outline(db: impl Database, id: zettel::Id) -> Result {
+    let meta = get_one(&db, &id)?;
+    let (_, body) = frontmatter::parse_yaml_path(meta.full_path(db.root_dir()))?;
+    print!("{}", outline::format_outline(&outline::headings(&body)));
+    Ok(())
+}
+
+fn toc(db: impl Database, args: TocArgs, now: DateTime) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let toc = outline::toc(&zk, db.root_dir(), &args.subdir);
+    match args.write {
+        Some(path) => {
+            let title = format!("Contents of {}", args.subdir.display());
+            write_index(&db, &mut zk, &path, &title, "toc", &toc, now)?;
+            db.commit(&zk)?;
+        }
+        None => print!("{}", toc),
+    }
+    Ok(())
+}
+
+fn index(db: impl Database, args: IndexArgs, now: DateTime) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let index = outline::tag_index(&zk, &args.tag);
+    match args.write {
+        Some(path) => {
+            let title = format!("Index of {}", args.tag);
+            write_index(&db, &mut zk, &path, &title, "index", &index, now)?;
+            db.commit(&zk)?;
+        }
+        None => print!("{}", index),
+    }
+    Ok(())
+}
+
+fn merge(db: impl Database, args: MergeArgs, now: DateTime) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    for id in [&args.survivor, &args.absorbed] {
+        if !zk.zettels.contains_key(id) {
+            return Err(Error::NoZettel(id.to_string()));
+        }
+    }
+    let disposal = if args.archive {
+        merge::Disposal::Archive
+    } else {
+        merge::Disposal::Delete
+    };
+    let absorbed = zk.zettels[&args.absorbed].clone();
+    if args.dry_run {
+        zk.dry_run();
+    }
+    let merged = merge::merge(
+        &mut zk,
+        db.root_dir(),
+        &args.survivor,
+        &args.absorbed,
+        args.frontmatter,
+        disposal,
+        now,
+    )?;
+    if args.dry_run {
+        print_planned(&db, &zk);
+        return Ok(());
+    }
+    db.commit(&zk)?;
+    history::record(db.root_dir(), [args.survivor.as_str()]);
+    let deleted = [(args.absorbed.as_str(), &absorbed)];
+    hooks::run(
+        db.root_dir(),
+        &zk.config.hooks,
+        hooks::Event::Delete,
+        &deleted,
+    );
+    println!("Merged {} into {}.", args.absorbed, args.survivor);
+    for id in merged.relinked {
+        println!("relinked {}", id);
+    }
+    if let Some(path) = merged.archived {
+        println!("archived {}", path.display());
+    }
+    Ok(())
+}
+
+fn rename_id(db: impl Database, args: RenameIdArgs, now: DateTime) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    if !zk.zettels.contains_key(&args.old) {
+        return Err(Error::NoZettel(args.old.to_string()));
+    }
+    if args.dry_run {
+        zk.dry_run();
+    }
+    let renamed = renameid::rename_id(&mut zk, db.root_dir(), &args.old, &args.new, now)?;
+    if args.dry_run {
+        print_planned(&db, &zk);
+        return Ok(());
+    }
+    db.commit(&zk)?;
+    history::record(db.root_dir(), [args.new.as_str()]);
+    println!("Renamed {} to {}.", args.old, args.new);
+    for id in renamed.relinked {
+        println!("relinked {}", id);
+    }
+    if let Some(path) = renamed.moved {
+        println!("moved to {}", relative(&db, &path));
+    }
+    Ok(())
+}
+
+fn split(db: impl Database, args: SplitArgs, now: DateTime) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    if !zk.zettels.contains_key(&args.id) {
+        return Err(Error::NoZettel(args.id.to_string()));
+    }
+    if args.dry_run {
+        zk.dry_run();
+    }
+    let ids = split::split(&db, &mut zk, &args.id, args.level, now)?;
+    if ids.is_empty() {
+        println!("No headings of level {} to split at.", args.level);
+        return Ok(());
+    }
+    if args.dry_run {
+        print_planned(&db, &zk);
+        return Ok(());
+    }
+    db.commit(&zk)?;
+    history::record(
+        db.root_dir(),
+        ids.iter().map(String::as_str).chain([args.id.as_str()]),
+    );
+    let created: Vec<(&str, &ZettelMeta)> = ids
+        .iter()
+        .map(|id| (id.as_str(), &zk.zettels[id]))
+        .collect();
+    hooks::run(db.root_dir(), &zk.config.hooks, hooks::Event::New, &created);
+    for id in ids {
+        println!("{}\t{}", id, zk.zettels[&id].title);
+    }
+    Ok(())
+}
+
+fn duplicate(db: impl Database, args: DuplicateArgs, now: DateTime) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let original = match zk.zettels.get(&args.id) {
+        Some(meta) => meta,
+        None => {
+            return Err(Error::NoZettel(args.id.to_string()));
+        }
+    };
+    let title = args
+        .title
+        .unwrap_or_else(|| format!("{} (copy)", original.title));
+    let options = duplicate::Options {
+        keep: args.keep,
+        source: args.source,
+    };
+    if args.dry_run {
+        zk.dry_run();
+    }
+    let id = duplicate::duplicate(&db, &mut zk, &args.id, &title, &options, now)?;
+    if args.dry_run {
+        print_planned(&db, &zk);
+        return Ok(());
+    }
+    db.commit(&zk)?;
+    history::record(db.root_dir(), [id.as_str()]);
+    hooks::run(
+        db.root_dir(),
+        &zk.config.hooks,
+        hooks::Event::New,
+        &[(&id, &zk.zettels[&id])],
+    );
+    println!("{}\t{}", id, zk.zettels[&id].title);
+    Ok(())
+}
+
+fn dedupe(db: impl Database, args: DedupeArgs, now: DateTime, mode: prompt::Mode) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let report = dedupe::find(&zk, db.root_dir(), args.threshold);
+    for id in &report.unreadable {
+        tracing::warn!("skipping {} due to frontmatter error", zk.zettels[id].path);
+    }
+    if report.groups.is_empty() {
+        println!("No duplicates found.");
+        return Ok(());
+    }
+    let total = report.groups.len();
+    for (n, group) in report.groups.into_iter().enumerate() {
+        println!(
+            "\n[{}/{}] {:.0}% similar",
+            n + 1,
+            total,
+            group.similarity * 100.0
+        );
+        for id in &group.ids {
+            let meta = &zk.zettels[id];
+            println!("  {}\t{}\t{}", id, meta.title, meta.path);
+        }
+        if args.list || !mode.is_interactive() {
+            continue;
+        }
+        let mut items = Vec::new();
+        for id in &group.ids {
+            items.push(format!("keep {} and merge the others into it", id));
+            items.push(format!("keep {} and delete the others", id));
+        }
+        items.push("skip".to_owned());
+        items.push("quit".to_owned());
+        let choice = dialoguer::Select::new()
+            .with_prompt("What should happen to these zettels?")
+            .items(&items)
+            .default(items.len() - 2)
+            .interact()?;
+        if choice == items.len() - 1 {
+            break;
+        }
+        if choice == items.len() - 2 {
+            continue;
+        }
+        let keep = &group.ids[choice / 2];
+        let mut deleted = Vec::new();
+        for id in group.ids.iter().filter(|id| *id != keep) {
+            deleted.push((id.as_str(), zk.zettels[id].clone()));
+            if choice % 2 == 0 {
+                merge::merge(
+                    &mut zk,
+                    db.root_dir(),
+                    keep,
+                    id,
+                    merge::Winner::Survivor,
+                    merge::Disposal::Delete,
+                    now,
+                )?;
+            } else if let Some(meta) = zk.zettels.remove(id) {
+                std::fs::remove_file(meta.full_path(db.root_dir()))?;
+            }
+        }
+        db.commit(&zk)?;
+        let deleted: Vec<(&str, &ZettelMeta)> =
+            deleted.iter().map(|(id, meta)| (*id, meta)).collect();
+        hooks::run(
+            db.root_dir(),
+            &zk.config.hooks,
+            hooks::Event::Delete,
+            &deleted,
+        );
+    }
+    Ok(())
+}
+
+fn meta_edit(
+    db: impl Database,
+    edits: Vec<metaedit::Edit>,
+    args: MetaEditArgs,
+    now: DateTime,
+) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let query = args.filter.to_query()?;
+    let changes = metaedit::plan(&zk, db.root_dir(), &query, &edits)?;
+    for change in &changes {
+        let title = change
+            .frontmatter
+            .get(&"title".into())
+            .and_then(serde_yaml::Value::as_str);
+        if let Some(title) = title.filter(|title| *title != zk.zettels[&change.id].title) {
+            zk.check_title(&change.id, title)
+                .map_err(|others| Error::TitleTaken(title.to_owned(), others))?;
+            // later changes are checked against this one
+            zk.zettels.get_mut(&change.id).unwrap().title = title.to_owned();
+        }
+    }
+    for change in &changes {
+        println!(
+            "{}",
+            zk.zettels[&change.id]
+                .relative_path(db.root_dir())
+                .display()
+        );
+        print!("{}", change.diff);
+    }
+    if changes.is_empty() {
+        println!("No zettels changed.");
+        return Ok(());
+    }
+    if args.dry_run {
+        return Ok(());
+    }
+    zk.transaction(|tx| {
+        for change in &changes {
+            tx.write(&change.path, change.contents.as_str());
+            let meta = tx.zettels.get_mut(&change.id).unwrap();
+            meta.update_from_frontmatter(&change.frontmatter);
+            meta.modified = now;
+        }
+        Ok::<_, Error>(())
+    })?;
+    db.commit(&zk)?;
+    println!("Updated {} zettels.", changes.len());
+    Ok(())
+}
+
+fn reorganize(db: impl Database, args: ReorganizeArgs) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let root_dir = db.root_dir();
+    let plan = reorganize::plan(&zk, root_dir, args.by)?;
+    for mv in &plan.moves {
+        println!("{} -> {}", mv.from.display(), mv.to.display());
+    }
+    for rewrite in &plan.rewrites {
+        let path = zk.zettels[&rewrite.id].relative_path(root_dir);
+        for (old, new) in &rewrite.links {
+            println!("{}: {} -> {}", path.display(), old, new);
+        }
+    }
+    for conflict in &plan.conflicts {
+        println!(
+            "Leaving {}: {} is taken.",
+            conflict.from.display(),
+            conflict.to.display()
+        );
+    }
+    if plan.moves.is_empty() {
+        println!("No zettels to move.");
+        return Ok(());
+    }
+    if args.dry_run {
+        return Ok(());
+    }
+    reorganize::apply(&mut zk, root_dir, &plan)?;
+    db.commit(&zk)?;
+    // directories the moves emptied, deepest first
+    let mut dirs: Vec<&Path> = plan
+        .moves
+        .iter()
+        .flat_map(|mv| mv.from.ancestors().skip(1))
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .collect();
+    dirs.sort_by_key(|dir| (std::cmp::Reverse(dir.components().count()), *dir));
+    dirs.dedup();
+    for dir in dirs {
+        // fails unless the directory is empty
+        let _ = std::fs::remove_dir(root_dir.join(dir));
+    }
+    println!("Moved {} zettels.", plan.moves.len());
+    Ok(())
+}
+
+fn autolink(db: impl Database, args: AutolinkArgs, now: DateTime, mode: prompt::Mode) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    if zk.config.autolink.is_empty() {
+        println!("No keywords to link. Add them to `config.autolink` in _zettel.yaml.");
+        return Ok(());
+    }
+    let missing: Vec<&str> = zk
+        .config
+        .autolink
+        .values()
+        .filter(|id| !zk.zettels.contains_key(*id))
+        .map(String::as_str)
+        .collect();
+    if let Some(id) = missing.first() {
+        return Err(Error::NoZettel(id.to_string()));
+    }
+    let query = args.filter.to_query()?;
+    let changes = autolink::plan(&zk, db.root_dir(), &query, &zk.config.autolink)?;
+    if changes.is_empty() {
+        println!("No mentions to link.");
+        return Ok(());
+    }
+    let mut confirmed = Vec::new();
+    for change in changes {
+        let path = zk.zettels[&change.id].relative_path(db.root_dir());
+        println!("{}", path.display());
+        for mention in &change.mentions {
+            println!(
+                "  {}: {} -> [[{}]]",
+                mention.line, mention.text, mention.target
+            );
+        }
+        if args.apply && mode.confirm(&format!("Link mentions in {}?", path.display()))? {
+            confirmed.push(change);
+        }
+    }
+    if !args.apply || confirmed.is_empty() {
+        return Ok(());
+    }
+    zk.transaction(|tx| {
+        for change in &confirmed {
+            tx.write(&change.path, change.contents.as_str());
+            let meta = tx.zettels.get_mut(&change.id).unwrap();
+            meta.update_from_body(&change.body);
+            meta.modified = now;
+        }
+        Ok::<_, Error>(())
+    })?;
+    db.commit(&zk)?;
+    println!("Linked mentions in {} zettels.", confirmed.len());
+    Ok(())
+}
+
+fn migrate_db(
+    db: database::file::Database,
+    to: Option<database::file::DatabaseKind>,
+    encrypted: Option<bool>,
+    check: bool,
+) -> Result {
+    let version = match db.stored_version()? {
+        Some(version) => version,
+        None => return Err(Error::NoDatabase),
+    };
+    let current = database::migrate::CURRENT_VERSION;
+    let upgrade = version < current;
+    let encrypted = encrypted.filter(|encrypted| *encrypted != db.is_encrypted());
+    let to = match (to, encrypted) {
+        (Some(to), _) if to != db.kind() => Some(to),
+        (_, Some(_)) => Some(db.kind()),
+        _ => None,
+    };
+    if check {
+        println!("Database is at version {}.", version);
+        if upgrade {
+            println!("It would be upgraded to version {}.", current);
+        }
+        if let Some(to) = to.filter(|to| *to != db.kind()) {
+            println!(
+                "It would move from {} to {}.",
+                db.kind().file_name(),
+                to.file_name()
+            );
+        }
+        match encrypted {
+            Some(true) => println!("It would be encrypted."),
+            Some(false) => println!("It would be decrypted."),
+            None => {}
+        }
+        return Ok(());
+    }
+    if encrypted == Some(true) && std::env::var_os(database::file::KEY_FILE_VAR).is_none() {
+        println!(
+            "Set {} to an age identity file to encrypt with.",
+            database::file::KEY_FILE_VAR
+        );
+        return Ok(());
+    }
+    match to {
+        Some(to) => {
+            let from = db.path();
+            let encrypt = encrypted.unwrap_or(db.is_encrypted());
+            let db = db.migrate(to, encrypt)?;
+            let name = |path: PathBuf| path.file_name().unwrap().to_string_lossy().into_owned();
+            println!("Moved database from {} to {}.", name(from), name(db.path()));
+            if encrypted.is_some() {
+                // rewritten for the new encryption, or lack of it
+                let zk = db.get_zk()?.unwrap();
+                search::Index::build(&zk, db.root_dir()).save(db.root_dir(), db.key())?;
+                let stale = search::Index::path(db.root_dir(), !db.is_encrypted());
+                if stale.is_file() {
+                    std::fs::remove_file(stale)?;
+                }
+            }
+        }
+        None if upgrade => db.commit(&db.get_zk()?.unwrap())?,
+        None => println!("Database is up to date."),
+    }
+    if upgrade {
+        println!("Upgraded database from version {} to {}.", version, current);
+    }
+    Ok(())
+}
+
+/// store changed metadata of zettel `id` in the database and mirror it in
+/// the zettel's frontmatter
+fn write_meta(
+    db: &impl Database,
+    mut zk: Zettelkasten,
+    id: zettel::Id,
+    mut meta: ZettelMeta,
+    now: DateTime,
+) -> Result {
+    let path = meta.full_path(db.root_dir());
+    let (mut fm, body) = frontmatter::parse_yaml_path(&path)?;
+    frontmatter::unalias(&mut fm, &zk.config.key_aliases);
+    meta.id = id.clone();
+    meta.modified = now;
+    let zone = zk.config.timezone.unwrap_or_default();
+    let locale = zk.config.locale.unwrap_or_default();
+    meta.update_frontmatter(&mut fm, &zk.default_frontmatter, zone, locale)?;
+    if zk.meta.storage == zettelkasten::Storage::Frontmatter {
+        meta.write_state(&mut fm, zone);
+    }
+    meta.update_hash(&body);
+    let fm = frontmatter::aliased(&fm, &zk.config.key_aliases);
+    std::fs::write(&path, frontmatter::write_for(&path, &fm, &body)?)?;
+    zk.zettels.insert(id.clone(), meta);
+    db.commit(&zk)?;
+    history::record(db.root_dir(), [id.as_str()]);
+    Ok(())
+}
+
+fn touch(db: impl Database, args: TouchArgs, now: DateTime) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let mut meta = match zk.zettels.get(&args.id) {
+        Some(meta) => meta.clone(),
+        None => {
+            return Err(Error::NoZettel(args.id.to_string()));
+        }
+    };
+    if let Some(created) = &args.created {
+        let invalid = || query::Error::InvalidDate(created.clone());
+        let date = dates::parse_range(created, now.date().naive_local())
+            .ok_or_else(invalid)?
+            .start;
+        meta.created = chrono::TimeZone::from_local_datetime(
+            &chrono::Local,
+            &date.and_time(meta.created.time()),
+        )
+        .earliest()
+        .ok_or_else(invalid)?;
+    }
+    write_meta(&db, zk, args.id, meta, now)
+}
+
+fn crypt(db: impl Database, id: zettel::Id, encrypt: bool, now: DateTime) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let mut meta = match zk.zettels.get(&id) {
+        Some(meta) => meta.clone(),
+        None => {
+            return Err(Error::NoZettel(id.to_string()));
+        }
+    };
+    let age = zk.config.age.as_ref().ok_or(Error::NoAgeKeys)?;
+    let path = meta.full_path(db.root_dir());
+    if encrypt {
+        if !age.encrypt_file(&path)? {
+            tracing::warn!("zettel {} is encrypted already", id);
+            return Ok(());
+        }
+    } else {
+        match age.decrypt_file(&path)? {
+            Some(body) => meta.update_from_body(&body),
+            None => {
+                tracing::warn!("zettel {} isn't encrypted", id);
+                return Ok(());
+            }
+        }
+    }
+    search::forget(db.root_dir(), db.key(), &id)?;
+    write_meta(&db, zk, id, meta, now)
+}
+
+fn meta_edit_one(db: impl Database, id: zettel::Id, now: DateTime) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let meta = match zk.zettels.get(&id) {
+        Some(meta) => meta.clone(),
+        None => {
+            return Err(Error::NoZettel(id.to_string()));
+        }
+    };
+    let cmd = editor().ok_or(Error::NoEditor)?;
+    let yaml = serde_yaml::to_string(&meta).map_err(frontmatter::Error::from)?;
+    let edited = match edit_temp_file(cmd, "yaml", &yaml)? {
+        (status, edited) if status.success() => edited,
+        _ => return Err(Error::EditorFailed),
+    };
+    let mut edited: ZettelMeta = match serde_yaml::from_str(&edited) {
+        Ok(edited) => edited,
+        Err(e) => {
+            tracing::warn!("invalid metadata, left unchanged: {}", e);
+            return Ok(());
+        }
+    };
+    edited.id = id.clone();
+    if edited == meta {
+        println!("No changes.");
+        return Ok(());
+    }
+    if !edited.full_path(db.root_dir()).is_file() {
+        tracing::warn!("no file at {}, metadata left unchanged", edited.path);
+        return Ok(());
+    }
+    if let Some(follows) = edited
+        .follows
+        .as_ref()
+        .filter(|f| !zk.zettels.contains_key(*f))
+    {
+        return Err(Error::NoZettel(follows.to_string()));
+    }
+    write_meta(&db, zk, id, edited, now)
+}
+
+fn search(db: impl Database, args: SearchArgs) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let index = match search::Index::load(db.root_dir(), db.key()) {
+        Ok(Some(index)) if index.is_current() => index,
+        _ => {
+            let index = search::refresh(&zk, db.root_dir(), db.key(), &[]);
+            if !db.is_read_only() {
+                index.save(db.root_dir(), db.key())?;
+            }
+            index
+        }
+    };
+    let mut found = index.search(&args.words.join(" "));
+    if let Some(limit) = args.limit {
+        found.truncate(limit);
+    }
+    if found.is_empty() {
+        println!("No zettels found.");
+    }
+    for (id, _) in found {
+        match zk.zettels.get(&id) {
+            Some(meta) => println!("{}  {}", id, meta.title),
+            // indexed before it was removed without a sync
+            None => tracing::debug!("{} is in the search index only", id),
+        }
+    }
+    Ok(())
+}
+
+fn reindex(db: impl Database) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let index = search::Index::build(&zk, db.root_dir());
+    index.save(db.root_dir(), db.key())?;
+    println!("Indexed {} zettels.", zk.zettels.len());
+    Ok(())
+}
+
+/// the resolved form shows defaults before `init` too
+fn config_show(
+    db: impl Database,
+    resolved: bool,
+    flags: Vec<(String, serde_yaml::Value)>,
+) -> Result {
+    let config = db.get_zk()?.map(|zk| zk.config).unwrap_or_default();
+    let yaml = |value: &serde_yaml::Value| -> std::result::Result<String, Error> {
+        let text = serde_yaml::to_string(value).map_err(frontmatter::Error::from)?;
+        Ok(text.trim_start_matches("---").trim().to_owned())
+    };
+    if !resolved {
+        let value = serde_yaml::to_value(&config).map_err(frontmatter::Error::from)?;
+        println!("{}", yaml(&value)?);
+        return Ok(());
+    }
+    let env = ENV_SETTINGS.iter().filter_map(|var| {
+        let value = std::env::var(var).ok()?;
+        Some((var.to_string(), value.into(), config::Source::Env))
+    });
+    let flags = flags
+        .into_iter()
+        .map(|(flag, value)| (format!("--{}", flag), value, config::Source::Flag));
+    for (key, value, source) in config.resolved().into_iter().chain(env).chain(flags) {
+        let text = yaml(&value)?;
+        if value.is_mapping() || value.is_sequence() {
+            println!("{}:  # {}", key, source);
+            for line in text.lines() {
+                println!("  {}", line);
+            }
+        } else {
+            println!("{}: {}  # {}", key, text, source);
+        }
+    }
+    Ok(())
+}
+
+/// works before `init` too, with the default config
+fn grep_frontmatter(db: impl Database, args: GrepFrontmatterArgs) -> Result {
+    let config = db.get_zk()?.map(|zk| zk.config).unwrap_or_default();
+    let predicates = args
+        .predicates
+        .iter()
+        .map(|p| p.parse())
+        .collect::<std::result::Result<Vec<metagrep::Predicate>, _>>()?;
+    for found in metagrep::grep(&config, db.root_dir(), &predicates)? {
+        if !args.values {
+            println!("{}", found.path);
+            continue;
+        }
+        let mut keys: Vec<&str> = Vec::new();
+        for key in predicates.iter().map(|p| p.key()) {
+            if !keys.contains(&key) {
+                keys.push(key);
+            }
+        }
+        let values: Vec<String> = keys
+            .into_iter()
+            .filter_map(|key| Some(format!("{}: {}", key, found.value(key)?)))
+            .collect();
+        println!("{}  {}", found.path, values.join("  "));
+    }
+    Ok(())
+}
+
+fn grep(db: impl Database, args: GrepArgs) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let re = regex::RegexBuilder::new(&args.pattern)
+        .case_insensitive(args.ignore_case)
+        .build()?;
+    let query = args.filter.to_query()?;
+    let ignore = ignore::Ignore::load(db.root_dir())?;
+    let mut zettels: Vec<(PathBuf, &zettel::Id, &ZettelMeta)> = zk
+        .zettels
+        .iter()
+        .filter(|(id, meta)| query.matches(id, meta, db.root_dir()))
+        .map(|(id, meta)| (meta.relative_path(db.root_dir()), id, meta))
+        .filter(|(path, _, _)| !ignore.is_ignored(path))
+        .collect();
+    zettels.sort_by(|a, b| a.0.cmp(&b.0));
+    for (path, id, meta) in zettels {
+        let text = match std::fs::read_to_string(db.root_dir().join(&path)) {
+            Ok(text) => text,
+            Err(e) => {
+                tracing::warn!("skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        let body = match frontmatter::parse_yaml(&mut std::io::BufReader::new(text.as_bytes())) {
+            Ok((_, body)) => body,
+            Err(e) => {
+                tracing::warn!(
+                    "skipping {} due to frontmatter error: {}",
+                    path.display(),
+                    e
+                );
+                continue;
+            }
+        };
+        let matches = grep::matching_lines(&re, &body);
+        if matches.is_empty() {
+            continue;
+        }
+        if args.files_with_matches {
+            println!("{}", path.display());
+            continue;
+        }
+        let first_line = text.lines().count() - body.lines().count() + 1;
+        println!("{}  {}  ({})", id, meta.title, path.display());
+        print!(
+            "{}",
+            grep::format_matches(&body, &matches, args.context, first_line)
+        );
+        println!();
+    }
+    Ok(())
+}
+
+/// run `cmd` on a new temporary file holding `contents` that only the user
+/// can read, returning its status and what the file holds afterwards; the
+/// name is random so nothing can be waiting at it in the shared directory
+fn edit_temp_file(
+    mut cmd: std::process::Command,
+    extension: &str,
+    contents: &str,
+) -> std::io::Result<(std::process::ExitStatus, String)> {
+    let name = format!("zk-{}.{}", uuid::Uuid::new_v4().simple(), extension);
+    let path = std::env::temp_dir().join(name);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(&path)?, contents.as_bytes())?;
+    let edited = cmd
+        .arg(&path)
+        .status()
+        .and_then(|status| Ok((status, std::fs::read_to_string(&path)?)));
+    std::fs::remove_file(&path)?;
+    edited
+}
+
+/// command running $VISUAL or $EDITOR, if either is set
+fn editor() -> Option<std::process::Command> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .ok()?;
+    let mut words = editor.split_whitespace();
+    let mut cmd = std::process::Command::new(words.next()?);
+    cmd.args(words);
+    Some(cmd)
+}
+
+fn last(db: impl Database, args: LastArgs) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let mut history = history::History::load(db.root_dir())?;
+    history.retain_known(&zk.zettels);
+    history.ids.truncate(args.n);
+    if history.ids.is_empty() {
+        println!("No zettels were touched yet.");
+        return Ok(());
+    }
+    match editor() {
+        Some(cmd) if !args.list => {
+            // oldest first so the most recent ends up in front in most editors
+            let ids: Vec<&str> = history.ids.iter().rev().map(String::as_str).collect();
+            edit_zettels(&db, &zk, cmd, &ids)?
+        }
+        _ => {
+            for id in &history.ids {
+                let meta = &zk.zettels[id];
+                println!(
+                    "{}  {}  ({})",
+                    id,
+                    meta.title,
+                    meta.relative_path(db.root_dir()).display()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// open zettels `ids` in `cmd`, editing encrypted ones as decrypted copies
+fn edit_zettels(
+    db: &impl Database,
+    zk: &Zettelkasten,
+    mut cmd: std::process::Command,
+    ids: &[&str],
+) -> Result {
+    let age = zk.config.age.as_ref().filter(|age| age.identity.is_some());
+    let mut plaintexts = Vec::new();
+    for id in ids {
+        let zettel = zettel::ZettelHandle::new(db.root_dir(), id, &zk.zettels[*id]);
+        match age {
+            Some(age) if crypt::is_encrypted_file(&zettel)? => {
+                let plaintext = crypt::Plaintext::new(age, &zettel.path())?;
+                cmd.arg(&plaintext.path);
+                plaintexts.push(plaintext);
+            }
+            _ => {
+                cmd.arg(zettel.path());
+            }
+        }
+    }
+    cmd.status()?;
+    for plaintext in plaintexts {
+        plaintext.finish(age.unwrap())?;
+    }
+    Ok(())
+}
+
+fn step(db: impl Database, args: StepArgs, direction: navigate::Direction) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let id = match args.id {
+        Some(id) => id,
+        None => {
+            let mut history = history::History::load(db.root_dir())?;
+            history.retain_known(&zk.zettels);
+            match history.ids.into_iter().next() {
+                Some(id) => id,
+                None => {
+                    println!("No zettels were touched yet. Give an id.");
+                    return Ok(());
+                }
+            }
+        }
+    };
+    let found = match navigate::step(&zk, &id, direction, args.journal) {
+        Some(found) => found,
+        None => {
+            let which = match direction {
+                navigate::Direction::Prev => "before",
+                navigate::Direction::Next => "after",
+            };
+            println!("No zettel {} {}.", which, id);
+            return Ok(());
+        }
+    };
+    if !db.is_read_only() {
+        history::record(db.root_dir(), [found.as_str()]);
+    }
+    match editor() {
+        Some(cmd) if args.open => edit_zettels(&db, &zk, cmd, &[&found])?,
+        _ => println!("{}  {}", found, zk.zettels[&found].title),
+    }
+    Ok(())
+}
+
+fn seq(db: impl Database, args: SeqArgs) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    if !zk.zettels.contains_key(&args.id) {
+        return Err(Error::NoZettel(args.id.to_string()));
+    }
+    if args.next || args.prev {
+        let (prev, next) = sequence::neighbours(&zk, &args.id);
+        if let Some(id) = if args.next { next } else { prev } {
+            println!("{}  {}", id, zk.zettels[&id].title);
+        }
+        return Ok(());
+    }
+    for entry in sequence::chain(&zk, &args.id) {
+        let marker = if entry.id == args.id { '>' } else { ' ' };
+        println!(
+            "{} {}{:<8} {}  {}",
+            marker,
+            "  ".repeat(entry.depth),
+            entry.label,
+            entry.id,
+            zk.zettels[&entry.id].title
+        );
+    }
+    Ok(())
+}
+
+fn backlinks(db: impl Database, args: BacklinksArgs) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    if !zk.zettels.contains_key(&args.id) {
+        return Err(Error::NoZettel(args.id.to_string()));
+    }
+    let kastens = if args.all_kastens {
+        kastens::load(db.root_dir(), zk)?
+    } else {
+        vec![kastens::Kasten {
+            name: None,
+            root_dir: db.root_dir().to_path_buf(),
+            zk,
+        }]
+    };
+    for (k, id) in kastens::backlinks(&kastens, 0, &args.id) {
+        let kasten = &kastens[k];
+        let meta = &kasten.zk.zettels[&id];
+        // sections of the zettel the links point at, if any
+        let mut anchors: Vec<String> =
+            frontmatter::parse_yaml_path(meta.full_path(&kasten.root_dir))
+                .map(|(_, body)| link::wikilinks(&body))
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|l| {
+                    kastens::find(&kastens, k, &l.target)
+                        .is_some_and(|(to, i)| to == 0 && i == args.id)
+                })
+                .filter_map(|l| l.anchor.map(|anchor| format!("#{}", anchor)))
+                .collect();
+        anchors.sort();
+        anchors.dedup();
+        if anchors.is_empty() {
+            println!("{}  {}", kasten.qualified(&id), meta.title);
+        } else {
+            println!(
+                "{}  {}  ({})",
+                kasten.qualified(&id),
+                meta.title,
+                anchors.join(", ")
+            );
+        }
     }
+    Ok(())
 }
 
-impl From<zettel::Error> for Error {
-    fn from(e: zettel::Error) -> Self {
-        Self::ZettelError(e)
+fn pick(db: impl Database, args: PickArgs, now: DateTime, mode: prompt::Mode) -> Result {
+    let mut zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    let query = args.query.join(" ");
+    if args.create_if_missing && !query.trim().is_empty() && !pick::has_title(&zk, &query) {
+        let title = query.trim().to_owned();
+        let new = NewZettel {
+            title,
+            ..Default::default()
+        };
+        new_with_frontmatter(&db, new, now, mode)?;
+        zk = db.get_zk()?.unwrap_or(zk);
     }
+    let items = pick::items(&zk, db.root_dir(), &query, args.arg);
+    print!("{}", pick::render(&items, args.format));
+    Ok(())
 }
 
-impl From<zettelkasten::Error> for Error {
-    fn from(e: zettelkasten::Error) -> Self {
-        Self::ZettelkastenError(e)
+fn open(db: impl Database, id: zettel::Id) -> Result {
+    let meta = get_one(&db, &id)?;
+    open_path(&meta.full_path(db.root_dir()))?;
+    if !db.is_read_only() {
+        history::record(db.root_dir(), [id.as_str()]);
     }
+    Ok(())
 }
 
-impl std::error::Error for Error {}
-
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::IoError(e) => e.fmt(f),
-            Self::YamlDatabaseError(e) => e.fmt(f),
-            Self::ZettelError(e) => e.fmt(f),
-            Self::ZettelkastenError(e) => e.fmt(f),
+fn open_url(db: impl Database, url: String) -> Result {
+    let zk = match db.get_zk()? {
+        Some(zk) => zk,
+        None => return Err(Error::NoDatabase),
+    };
+    // the system opener can't jump to a heading, so the anchor is dropped
+    let target = match opener::parse_url(&url) {
+        Some((target, _anchor)) => target,
+        None => return Err(Error::NotZkUrl(url)),
+    };
+    let kastens = kastens::load(db.root_dir(), zk)?;
+    let (k, id) = match kastens::find(&kastens, 0, &target) {
+        Some(found) => found,
+        None => {
+            return Err(Error::NoZettel(target.to_string()));
         }
+    };
+    let kasten = &kastens[k];
+    open_path(&kasten.zk.zettels[&id].full_path(&kasten.root_dir))?;
+    if k == 0 && !db.is_read_only() {
+        history::record(db.root_dir(), [id.as_str()]);
     }
+    Ok(())
 }
 
-type Result = std::result::Result<(), Error>;
+/// hand `path` to the system opener
+fn open_path(path: &Path) -> Result {
+    let mut cmd = opener::system_opener();
+    match cmd.arg(path).status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(Error::OpenerFailed(format!(
+            "{:?} failed ({})",
+            cmd.get_program(),
+            status
+        ))),
+        Err(e) => Err(Error::OpenerFailed(format!(
+            "couldn't run {:?}: {}",
+            cmd.get_program(),
+            e
+        ))),
+    }
+}
 
-fn main() -> Result {
-    let args = Args::parse();
-    let db = database::yaml::Database::new(args.root_dir)?;
-    match args.cmd {
-        Command::Init => {
-            let zk = Zettelkasten::default();
-            db.commit(zk)?;
-        }
-        Command::New(args) => new(db, args.title, chrono::Local::now())?,
-        Command::Sync => sync(db)?,
+fn register_url_handler(db: impl Database) -> Result {
+    let data_dir = match std::env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".local/share"),
+            None => return Err(Error::NoDataDir),
+        },
+    };
+    let apps_dir = data_dir.join("applications");
+    std::fs::create_dir_all(&apps_dir)?;
+    let entry = opener::desktop_entry(&std::env::current_exe()?, &db.root_dir().canonicalize()?);
+    let path = apps_dir.join(opener::DESKTOP_FILE);
+    std::fs::write(&path, entry)?;
+    println!("wrote {}", path.display());
+    let status = std::process::Command::new("xdg-mime")
+        .args(["default", opener::DESKTOP_FILE, "x-scheme-handler/zk"])
+        .status();
+    if !status.is_ok_and(|status| status.success()) {
+        println!(
+            "Couldn't run `xdg-mime default {} x-scheme-handler/zk`; run it once xdg-utils is installed.",
+            opener::DESKTOP_FILE
+        );
     }
     Ok(())
 }
 
-fn new(db: database::yaml::Database, title: String, date: DateTime) -> Result {
+fn kasten_add(db: impl Database, name: String, path: PathBuf) -> Result {
     let mut zk = match db.get_zk()? {
         Some(zk) => zk,
-        None => {
-            if dialoguer::Confirm::new()
-                .with_prompt("Database does not exist. Create it?")
-                .interact()?
-            {
-                Default::default()
-            } else {
-                return Ok(())
-            }
-        }
+        None => return Err(Error::NoDatabase),
     };
-    use rand::Rng;
-    let id: String = rand::thread_rng()
-        .sample_iter(&rand::distributions::Alphanumeric)
-        .take(18)
-        .map(char::from)
-        .collect();
-    let zettel = db.new_zettel(&title, &id, date)?;
-    zk.add(&zettel)?;
-    db.commit(&zk).or_else(|e| {
-        println!("couldn't commit to database: {}", e);
-        std::fs::remove_file(&zettel.meta.path)
-    })?;
-    Ok(())
+    if name.is_empty() || name.contains([':', '|', ']']) || name.contains(char::is_whitespace) {
+        return Err(Error::InvalidKastenName(name));
+    }
+    let other = match path.canonicalize() {
+        Ok(path) => database::file::Database::new(path)?,
+        Err(e) => return Err(Error::Unreadable(path, e)),
+    };
+    if other.get_zk()?.is_none() {
+        return Err(Error::NoDatabaseIn(path));
+    }
+    zk.config
+        .kastens
+        .insert(name, other.root_dir().to_path_buf());
+    Ok(db.commit(&zk)?)
 }
 
-fn sync(db: database::yaml::Database) -> Result {
-    let mut zk = match db.get_zk()? {
+fn kasten_list(db: impl Database) -> Result {
+    let zk = match db.get_zk()? {
         Some(zk) => zk,
-        None => {
-            println!("Database does not exist. Use `init` first.");
-            return Ok(())
-        }
+        None => return Err(Error::NoDatabase),
     };
-    let dir_entries = std::fs::read_dir(db.root_dir())?;
-    for entry in dir_entries {
-        let entry: std::fs::DirEntry = entry.unwrap();
-        let path = entry.path();
-        if path
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_owned()
-            .starts_with("_zettel")
-        {
-            continue;
+    for (name, path) in &zk.config.kastens {
+        println!("{}  {}", name, path.display());
+    }
+    Ok(())
+}
+
+/// write `content` into the managed section of the zettel at `path`,
+/// creating and registering the zettel first if there is none
+fn write_index(
+    db: &impl Database,
+    zk: &mut Zettelkasten,
+    path: &Path,
+    title: &str,
+    section: &str,
+    content: &str,
+    now: DateTime,
+) -> Result {
+    let full_path = db.root_dir().join(path);
+    let existing = zk
+        .zettels
+        .iter_mut()
+        .find(|(_, meta)| meta.full_path(db.root_dir()) == full_path);
+    let id = match existing {
+        Some((id, meta)) => {
+            let text = std::fs::read_to_string(&full_path)?;
+            std::fs::write(&full_path, section::replace(&text, section, content))?;
+            meta.modified = now;
+            id.clone()
         }
-        let fm = match frontmatter::parse_yaml_path(&path) {
-            Ok(meta) => meta,
-            Err(e) => {
-                println!(
-                    "skipping {} due to frontmatter error: {}",
-                    path.to_str().unwrap(),
-                    e
-                );
-                continue;
-            }
-        };
-        let id: zettel::Id = {
-            let id = fm.get(&"id".into());
-            if id.is_none() {
-                println!(
-                    "skipping {} due to missing key 'id' in frontmatter",
-                    path.to_str().unwrap()
-                );
-                continue;
-            }
-            let id = id.unwrap().as_str();
-            if id.is_none() {
-                println!(
-                    "skipping {} due to 'id' in frontmatter not being a 'string'",
-                    path.to_str().unwrap()
-                );
-                continue;
+        None => {
+            let mut zettel = db.new_zettel(&zk.config, title, zk.new_id(now), now)?;
+            zettel.meta.path = fsutil::to_stored(db.root_dir(), &full_path)
+                .ok_or_else(|| database::Error::NotUtf8(full_path.clone()))?;
+            zettel.content = section::replace("", section, content);
+            if let Some(dir) = full_path.parent() {
+                std::fs::create_dir_all(dir)?;
             }
-            id.unwrap().to_owned()
-        };
-        let current_meta = zk.zettels.get_mut(&id);
-        if current_meta.is_none() {
-            println!(
-                "no metadata with id {} for zettel at {}; skipping",
-                id,
-                path.to_str().unwrap(),
-            );
-            continue;
+            zk.add(db.root_dir(), &zettel)?;
+            zettel.meta.id
         }
-        let current_meta = current_meta.unwrap();
-        current_meta.path = path
-            .strip_prefix(&db.root_dir())
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_owned();
-        if let Some(title) = fm.get(&"title".into()).and_then(|t| t.as_str()) {
-            current_meta.title = title.to_owned()
-        }
-    }
-    Ok(db.commit(&zk)?)
+    };
+    history::record(db.root_dir(), [id.as_str()]);
+    Ok(())
 }
 
 #[cfg(test)]
@@ -205,32 +5038,831 @@ mod test {
     use super::*;
     use chrono::prelude::*;
 
+    #[test]
+    fn arguments_are_consistent() {
+        use clap::CommandFactory;
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn verbosity_flags() {
+        let level = |args: &[&str]| {
+            let args = Args::try_parse_from(args).unwrap();
+            log_level(args.verbose, args.quiet)
+        };
+        assert_eq!(level(&["zk", "list"]), "warn");
+        assert_eq!(level(&["zk", "list", "-v"]), "info");
+        assert_eq!(level(&["zk", "-vv", "list"]), "debug");
+        assert_eq!(level(&["zk", "list", "-vvvv"]), "trace");
+        assert_eq!(level(&["zk", "list", "--quiet"]), "error");
+        assert!(Args::try_parse_from(["zk", "list", "--quiet", "-v"]).is_err());
+    }
+
+    #[test]
+    fn relative_dates_are_values() {
+        let args = Args::try_parse_from(["zk", "list", "--since", "-7d", "--until", "-1d"]);
+        match args.map(|args| args.cmd) {
+            Ok(Command::List(list)) => {
+                assert_eq!(list.filter.since.as_deref(), Some("-7d"));
+                assert_eq!(list.filter.until.as_deref(), Some("-1d"));
+            }
+            other => panic!("expected a list command, got {:?}", other),
+        }
+        let args = Args::try_parse_from(["zk", "digest", "--since", "-2w"]);
+        assert!(matches!(args.map(|args| args.cmd), Ok(Command::Digest(d)) if d.since == "-2w"));
+    }
+
+    #[test]
+    fn init_adopts_notes() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let root_dir = tmp_dir.path();
+        std::fs::write(root_dir.join("plain.md"), "# A heading\n\ntext\n")?;
+        std::fs::write(root_dir.join("broken.md"), "---\ntitle: [\n---\n")?;
+        std::fs::write(root_dir.join("other.txt"), "not a note\n")?;
+        let db = database::file::Database::new(root_dir.to_path_buf())?;
+        let args = InitArgs {
+            format: None,
+            storage: None,
+            defaults: true,
+            adopt: true,
+        };
+        init(db, args, prompt::Mode::No)?;
+        let db = database::file::Database::new(root_dir.to_path_buf())?;
+        let zk = db.get_zk()?.unwrap();
+        assert_eq!(zk.zettels.len(), 1);
+        let meta = zk.zettels.values().next().unwrap();
+        assert_eq!(meta.title, "A heading");
+        let (fm, _) = frontmatter::parse_yaml_path(root_dir.join("plain.md"))?;
+        assert!(fm.contains_key(&"id".into()));
+        assert!(frontmatter::parse_yaml_path(root_dir.join("broken.md")).is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sqlite_export_keeps_the_old_file_on_failure() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let out = tmp_dir.path().join("zk.db");
+        std::fs::write(&out, "old")?;
+        let zk = Zettelkasten::default();
+        let export = |sqlite: &str| export_sqlite(&zk, tmp_dir.path(), &out, sqlite.to_owned());
+        let missing = export("zk-no-such-sqlite").unwrap_err();
+        assert!(matches!(missing, Error::NoSqlite(_)));
+        assert_eq!(missing.failure().exit_code(), 3);
+        assert!(matches!(export("false"), Err(Error::SqliteFailed(_))));
+        assert_eq!(std::fs::read_to_string(&out)?, "old");
+        assert_eq!(std::fs::read_dir(tmp_dir.path())?.count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn failures_have_exit_codes() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        let code = |result: Result| result.unwrap_err().failure().exit_code();
+        let list_args = |args: &[&str]| match Args::parse_from(args).cmd {
+            Command::List(args) => args,
+            cmd => panic!("parsed {:?}", cmd),
+        };
+        assert_eq!(code(list(&db, list_args(&["zk", "list"]))), 3);
+        db.commit(&Zettelkasten::default())?;
+        assert_eq!(code(outline(&db, "missing".to_owned())), 3);
+        let query = list_args(&["zk", "list", "--query", "tag:("]);
+        assert_eq!(code(list(&db, query)), 5);
+        let locked = std::io::Error::new(std::io::ErrorKind::WouldBlock, "locked");
+        assert_eq!(code(Err(locked.into())), 6);
+        let changed = undo::Error::Changed(vec!["a.md".to_owned()]);
+        assert_eq!(code(Err(changed.into())), 4);
+        let archived = merge::Error::ArchiveTaken(PathBuf::from(".zk/archive/a.md"));
+        assert_eq!(code(Err(archived.into())), 4);
+        let new_args = |args: &[&str]| match Args::parse_from(args).cmd {
+            Command::New(args) => args,
+            cmd => panic!("parsed {:?}", cmd),
+        };
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let unknown_kind = new_args(&["zk", "new", "--kind", "nope", "Title"]);
+        assert_eq!(code(new(&db, unknown_kind, dt, prompt::Mode::No)), 3);
+        let unknown_template = new_args(&["zk", "new", "--title-template", "nope"]);
+        assert_eq!(code(new(&db, unknown_template, dt, prompt::Mode::No)), 3);
+        let cite = new_citation(
+            &db,
+            "key".to_owned(),
+            Default::default(),
+            dt,
+            prompt::Mode::No,
+        );
+        assert!(matches!(cite, Err(Error::NoBibliography)));
+        let mut zk = Zettelkasten::default();
+        zk.add(db.root_dir(), db.new_zettel(&zk.config, "A", "a", dt)?)?;
+        db.commit(&zk)?;
+        assert!(matches!(
+            crypt(&db, "a".to_owned(), true, dt),
+            Err(Error::NoAgeKeys)
+        ));
+        assert!(db.get_zk()?.unwrap().zettels.contains_key("a"));
+        zk.config.title_policy = Some(config::TitlePolicy::Refuse);
+        db.commit(&zk)?;
+        let same_title = new_args(&["zk", "new", "a"]);
+        assert_eq!(code(new(&db, same_title, dt, prompt::Mode::No)), 4);
+        assert_eq!(db.get_zk()?.unwrap().zettels.len(), 1);
+        let kasten = |name: &str, path: &Path| kasten_add(&db, name.to_owned(), path.to_owned());
+        assert_eq!(code(kasten("has space", tmp_dir.path())), 5);
+        assert_eq!(code(kasten("other", &tmp_dir.path().join("missing"))), 3);
+        assert_eq!(code(kasten("other", tmp_dir.path())), 3);
+        assert_eq!(code(open_url(&db, "https://example.com".to_owned())), 5);
+        let missing_root = database::file::Database::new(tmp_dir.path().join("missing"));
+        assert_eq!(code(missing_root.map(drop).map_err(Error::from)), 3);
+        Ok(())
+    }
+
     #[test]
     fn create_and_sync() -> Result {
         let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
         let dir_path = tmp_dir.path().to_path_buf();
-        let db = database::yaml::Database::new(dir_path.clone())?;
+        let db = database::file::Database::new(dir_path.clone())?;
         let zk = Zettelkasten::default();
-        db.commit(zk)?;
+        db.commit(&zk)?;
         let dt = chrono::Local.timestamp(1431648000, 0);
-        super::new(db, "my blog post".to_owned(), dt)?;
+        super::new(
+            db,
+            NewArgs {
+                title: Some("my blog post".to_owned()),
+                ..Default::default()
+            },
+            dt,
+            prompt::Mode::No,
+        )?;
         let mut zettel_path = dir_path.clone();
         let dt_str = dt.format("%Y-%m-%d-my-blog-post.md").to_string();
         zettel_path.push(dt_str);
         assert!(zettel_path.exists());
-        let meta = frontmatter::parse_yaml_path(&zettel_path).unwrap();
+        let (meta, _) = frontmatter::parse_yaml_path(&zettel_path).unwrap();
         let id = meta.get(&"id".into()).unwrap();
         let title = meta.get(&"title".into()).unwrap();
         let date = meta.get(&"date".into()).unwrap();
         let mut new_zettel_path = dir_path.clone();
         new_zettel_path.push(dt.format("new_path.md").to_string());
         std::fs::copy(&zettel_path, &new_zettel_path)?;
-        let db = database::yaml::Database::new(dir_path.clone())?;
-        super::sync(db)?;
-        let meta = frontmatter::parse_yaml_path(&new_zettel_path).unwrap();
+        let db = database::file::Database::new(dir_path.clone())?;
+        super::sync(db, SyncArgs::default(), prompt::Mode::No)?;
+        let (meta, _) = frontmatter::parse_yaml_path(&new_zettel_path).unwrap();
         assert_eq!(meta.get(&"id".into()), Some(id));
         assert_eq!(meta.get(&"title".into()), Some(title));
         assert_eq!(meta.get(&"date".into()), Some(date));
         Ok(())
     }
+
+    #[test]
+    fn new_of_kind() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        let kind = config::Kind {
+            dir: Some(PathBuf::from("journal")),
+            frontmatter: HashMap::from([
+                ("mood".to_owned(), String::new()),
+                ("date".to_owned(), "@created".to_owned()),
+            ]),
+        };
+        zk.config.kinds.insert("journal".to_owned(), kind);
+        db.commit(&zk)?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        super::new(
+            &db,
+            NewArgs {
+                title: Some("today".to_owned()),
+                kind: Some("journal".to_owned()),
+                ..Default::default()
+            },
+            dt,
+            prompt::Mode::No,
+        )?;
+        let zk = db.get_zk()?.unwrap();
+        let meta = zk.zettels.values().next().unwrap();
+        let path = meta.full_path(tmp_dir.path());
+        assert!(path.starts_with(tmp_dir.path().join("journal")));
+        let (fm, _) = frontmatter::parse_yaml_path(path)?;
+        assert_eq!(fm.get(&"mood".into()), Some(&"".into()));
+        assert!(fm.contains_key(&"id".into()));
+        let unknown = super::new(
+            &db,
+            NewArgs {
+                title: Some("unknown".to_owned()),
+                kind: Some("dream".to_owned()),
+                ..Default::default()
+            },
+            dt,
+            prompt::Mode::No,
+        );
+        assert!(matches!(unknown, Err(Error::UnknownKind(kind)) if kind == "dream"));
+        assert_eq!(db.get_zk()?.unwrap().zettels.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn new_dry_run() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        db.commit(&Zettelkasten::default())?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let args = NewArgs {
+            title: Some("maybe".to_owned()),
+            dry_run: true,
+            ..Default::default()
+        };
+        super::new(&db, args, dt, prompt::Mode::No)?;
+        assert!(db.get_zk()?.unwrap().zettels.is_empty());
+        assert!(!tmp_dir.path().join("2015-05-14-maybe.md").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn sync_counts_words() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let dir_path = tmp_dir.path().to_path_buf();
+        let db = database::file::Database::new(dir_path.clone())?;
+        db.commit(&Zettelkasten::default())?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        super::new(
+            db,
+            NewArgs {
+                title: Some("word count".to_owned()),
+                ..Default::default()
+            },
+            dt,
+            prompt::Mode::No,
+        )?;
+        let mut zettel_path = dir_path.clone();
+        zettel_path.push(dt.format("%Y-%m-%d-word-count.md").to_string());
+        let mut data = std::fs::read_to_string(&zettel_path)?;
+        data.push_str("one two three\nfour five\n");
+        std::fs::write(&zettel_path, data)?;
+        let db = database::file::Database::new(dir_path)?;
+        super::sync(db, SyncArgs::default(), prompt::Mode::No)?;
+        let db = database::file::Database::new(tmp_dir.path().to_path_buf())?;
+        let zk = db.get_zk()?.unwrap();
+        let meta = zk.zettels.values().next().unwrap();
+        assert_eq!(meta.word_count, 5);
+        assert_eq!(meta.reading_time(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn new_literature_note() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let dir_path = tmp_dir.path().to_path_buf();
+        std::fs::write(
+            dir_path.join("refs.bib"),
+            "@book{knuth1984, author = {Donald E. Knuth}, title = {Literate Programming}, year = 1984}",
+        )?;
+        let db = database::file::Database::new(dir_path.clone())?;
+        let mut zk = Zettelkasten::default();
+        zk.config.bibliography = Some("refs.bib".into());
+        db.commit(&zk)?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        super::new_citation(
+            db,
+            "knuth1984".to_owned(),
+            Default::default(),
+            dt,
+            prompt::Mode::No,
+        )?;
+        let zettel_path = dir_path.join("2015-05-14-Literate-Programming.md");
+        let (meta, _) = frontmatter::parse_yaml_path(&zettel_path).unwrap();
+        assert_eq!(meta.get(&"cite".into()), Some(&"knuth1984".into()));
+        assert_eq!(meta.get(&"author".into()), Some(&"Donald E. Knuth".into()));
+        assert_eq!(meta.get(&"year".into()), Some(&"1984".into()));
+        let db = database::file::Database::new(dir_path)?;
+        let zk = db.get_zk()?.unwrap();
+        let meta = zk.zettels.values().next().unwrap();
+        assert_eq!(meta.cite.as_deref(), Some("knuth1984"));
+        Ok(())
+    }
+
+    #[test]
+    fn touch_moves_created_date() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let dir_path = tmp_dir.path().to_path_buf();
+        let db = database::memory::Database::new(dir_path.clone());
+        db.commit(&Zettelkasten::default())?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        super::new(
+            &db,
+            NewArgs {
+                title: Some("touched".to_owned()),
+                ..Default::default()
+            },
+            dt,
+            prompt::Mode::No,
+        )?;
+        let id = db.get_zk()?.unwrap().zettels.keys().next().unwrap().clone();
+        let args = TouchArgs {
+            id: id.clone(),
+            created: Some("2015-05-01".to_owned()),
+        };
+        let later = chrono::Local.ymd(2015, 6, 1).and_hms(9, 0, 0);
+        super::touch(&db, args, later)?;
+        let meta = &db.get_zk()?.unwrap().zettels[&id];
+        assert_eq!(
+            meta.created,
+            chrono::Local.ymd(2015, 5, 1).and_hms(12, 0, 0)
+        );
+        assert_eq!(meta.modified, later);
+        let (fm, _) = frontmatter::parse_yaml_path(dir_path.join("2015-05-14-touched.md")).unwrap();
+        assert_eq!(fm.get(&"date".into()), Some(&"2015-05-01".into()));
+        assert_eq!(fm.get(&"id".into()), Some(&id.as_str().into()));
+        Ok(())
+    }
+
+    #[test]
+    fn frontmatter_rebuilds_database() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        zk.meta.storage = zettelkasten::Storage::Frontmatter;
+        db.commit(&zk)?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        super::new(
+            &db,
+            NewArgs {
+                title: Some("kept".to_owned()),
+                ..Default::default()
+            },
+            dt,
+            prompt::Mode::No,
+        )?;
+        super::new(
+            &db,
+            NewArgs {
+                title: Some("deleted".to_owned()),
+                ..Default::default()
+            },
+            dt,
+            prompt::Mode::No,
+        )?;
+        let old = db.get_zk()?.unwrap();
+        std::fs::remove_file(tmp_dir.path().join("2015-05-14-deleted.md"))?;
+        // start over from an empty database
+        db.commit(&zk)?;
+        super::sync(&db, SyncArgs::default(), prompt::Mode::No)?;
+        let zk = db.get_zk()?.unwrap();
+        assert_eq!(zk.zettels.len(), 1);
+        let (id, meta) = zk.zettels.iter().next().unwrap();
+        assert_eq!(meta.title, "kept");
+        assert_eq!(meta.created, dt);
+        assert_eq!(old.zettels[id].path, meta.path);
+        Ok(())
+    }
+
+    #[test]
+    fn sync_assigns_ids() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        zk.config.id_scheme = Some(zettel::IdScheme::Timestamp);
+        db.commit(&zk)?;
+        let path = tmp_dir.path().join("plain.md");
+        std::fs::write(&path, "---\ntitle: Plain\ntags: [a]\n---\none two\n")?;
+        super::sync(&db, SyncArgs::default(), prompt::Mode::No)?;
+        assert!(db.get_zk()?.unwrap().zettels.is_empty());
+        let args = SyncArgs {
+            assign_ids: true,
+            ..Default::default()
+        };
+        super::sync(&db, args, prompt::Mode::No)?;
+        let zk = db.get_zk()?.unwrap();
+        let (id, meta) = zk.zettels.iter().next().unwrap();
+        assert_eq!(id.len(), "YYYYMMDDHHMMSS".len());
+        assert_eq!(meta.title, "Plain");
+        assert_eq!(meta.tags, ["a"]);
+        assert_eq!(meta.word_count, 2);
+        let (fm, _) = frontmatter::parse_yaml_path(&path)?;
+        assert_eq!(fm.get(&"id".into()), Some(&id.as_str().into()));
+        Ok(())
+    }
+
+    #[test]
+    fn sync_reassigns_duplicate_ids() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        db.commit(&Zettelkasten::default())?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        super::new(
+            &db,
+            NewArgs {
+                title: Some("original".to_owned()),
+                ..Default::default()
+            },
+            dt,
+            prompt::Mode::No,
+        )?;
+        let original = tmp_dir.path().join("2015-05-14-original.md");
+        let copy = tmp_dir.path().join("copy.md");
+        std::fs::copy(&original, &copy)?;
+        super::sync(&db, SyncArgs::default(), prompt::Mode::No)?;
+        let zk = db.get_zk()?.unwrap();
+        assert_eq!(zk.zettels.len(), 1);
+        let (id, meta) = zk.zettels.iter().next().unwrap();
+        assert_eq!(meta.path, "2015-05-14-original.md");
+        let args = SyncArgs {
+            reassign: true,
+            ..Default::default()
+        };
+        super::sync(&db, args, prompt::Mode::No)?;
+        assert_eq!(db.get_zk()?.unwrap().zettels.len(), 2);
+        let (fm, _) = frontmatter::parse_yaml_path(&copy)?;
+        assert_ne!(fm.get(&"id".into()), Some(&id.as_str().into()));
+        let (fm, _) = frontmatter::parse_yaml_path(&original)?;
+        assert_eq!(fm.get(&"id".into()), Some(&id.as_str().into()));
+        Ok(())
+    }
+
+    #[test]
+    fn frontmatter_key_aliases() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        for (key, alias) in [("id", "uid"), ("tags", "keywords")] {
+            zk.config
+                .key_aliases
+                .insert(key.to_owned(), alias.to_owned());
+        }
+        db.commit(&zk)?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        super::new(
+            &db,
+            NewArgs {
+                title: Some("aliased".to_owned()),
+                ..Default::default()
+            },
+            dt,
+            prompt::Mode::No,
+        )?;
+        let path = tmp_dir.path().join("2015-05-14-aliased.md");
+        let (fm, _) = frontmatter::parse_yaml_path(&path)?;
+        assert!(fm.contains_key(&"uid".into()));
+        assert!(!fm.contains_key(&"id".into()));
+        let text = std::fs::read_to_string(&path)?;
+        std::fs::write(&path, text.replace("\n\n---\n", "\nkeywords: [a]\n---\n"))?;
+        std::fs::write(tmp_dir.path().join("loose.md"), "---\ntitle: Loose\n---\n")?;
+        let args = SyncArgs {
+            assign_ids: true,
+            ..Default::default()
+        };
+        super::sync(&db, args, prompt::Mode::No)?;
+        let zk = db.get_zk()?.unwrap();
+        assert_eq!(zk.zettels.len(), 2);
+        let meta = zk.zettels.values().find(|m| m.title == "aliased").unwrap();
+        assert_eq!(meta.tags, ["a"]);
+        let (fm, _) = frontmatter::parse_yaml_path(tmp_dir.path().join("loose.md"))?;
+        assert!(fm.contains_key(&"uid".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn diff_lists_changes() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        db.commit(&Zettelkasten::default())?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        for title in ["kept", "moved", "gone", "tagged"] {
+            let args = NewArgs {
+                title: Some(title.to_owned()),
+                ..Default::default()
+            };
+            super::new(&db, args, dt, prompt::Mode::No)?;
+        }
+        super::sync(&db, SyncArgs::default(), prompt::Mode::No)?;
+        let before = db.get_zk()?.unwrap();
+        let id = |title: &str| {
+            let (id, _) = before
+                .zettels
+                .iter()
+                .find(|(_, m)| m.title == title)
+                .unwrap();
+            id.clone()
+        };
+        let file = |title: &str| tmp_dir.path().join(format!("2015-05-14-{}.md", title));
+        std::fs::create_dir(tmp_dir.path().join("dir"))?;
+        std::fs::rename(file("moved"), tmp_dir.path().join("dir/moved.md"))?;
+        std::fs::remove_file(file("gone"))?;
+        let kept = std::fs::read_to_string(file("kept"))?;
+        std::fs::write(file("kept"), kept.replace("title: kept", "title: Kept"))?;
+        let tagged = std::fs::read_to_string(file("tagged"))?;
+        std::fs::write(
+            file("tagged"),
+            tagged.replace("\n\n---\n", "\ntags: [a]\n---\n"),
+        )?;
+        std::fs::write(tmp_dir.path().join("loose.md"), "---\ntitle: Loose\n---\n")?;
+        let args = SyncArgs {
+            dry_run: true,
+            ..Default::default()
+        };
+        let mut zk = db.get_zk()?.unwrap();
+        let mut ctx = SyncContext {
+            previous: HashMap::new(),
+            policy: reconcile::Policy::default(),
+            ignore: ignore::Ignore::default(),
+            assign_ids: false,
+            reassign: false,
+            original_paths: HashMap::new(),
+            seen: HashMap::new(),
+            visited: HashSet::new(),
+            errors: Vec::new(),
+            mode: prompt::Mode::No,
+            dry_run: args.dry_run,
+            untracked: Vec::new(),
+            adopt: false,
+            adopted: 0,
+            skipped: Vec::new(),
+            attention: Vec::new(),
+        };
+        sync_dir(&db, &mut zk, &mut ctx, tmp_dir.path());
+        let mut expected = vec![
+            (id("gone"), "deleted   {}  2015-05-14-gone.md".to_owned()),
+            (id("kept"), "retitled  {}  kept -> Kept".to_owned()),
+            (
+                id("moved"),
+                "moved     {}  2015-05-14-moved.md -> dir/moved.md".to_owned(),
+            ),
+            (
+                id("tagged"),
+                "modified  {}  2015-05-14-tagged.md".to_owned(),
+            ),
+        ];
+        expected.sort();
+        let mut expected: Vec<String> = expected
+            .into_iter()
+            .map(|(id, line)| line.replace("{}", &id))
+            .collect();
+        expected.push("untracked loose.md".to_owned());
+        assert_eq!(status(&db, &before.zettels, &zk, &ctx), expected);
+        super::sync(&db, args, prompt::Mode::No)?;
+        assert_eq!(db.get_zk()?.unwrap(), before);
+        Ok(())
+    }
+
+    #[test]
+    fn adopting_counts_this_run() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        db.commit(&Zettelkasten::default())?;
+        let args = NewArgs {
+            title: Some("registered".to_owned()),
+            ..Default::default()
+        };
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        super::new(&db, args, dt, prompt::Mode::No)?;
+        std::fs::write(tmp_dir.path().join("plain.md"), "# Plain\n")?;
+        let mut zk = db.get_zk()?.unwrap();
+        let mut ctx = SyncContext {
+            previous: HashMap::new(),
+            policy: reconcile::Policy::default(),
+            ignore: ignore::Ignore::default(),
+            assign_ids: true,
+            reassign: false,
+            original_paths: HashMap::new(),
+            seen: HashMap::new(),
+            visited: HashSet::new(),
+            errors: Vec::new(),
+            mode: prompt::Mode::No,
+            dry_run: false,
+            untracked: Vec::new(),
+            adopt: true,
+            adopted: 0,
+            skipped: Vec::new(),
+            attention: Vec::new(),
+        };
+        sync_dir(&db, &mut zk, &mut ctx, tmp_dir.path());
+        assert_eq!(zk.zettels.len(), 2);
+        assert_eq!(ctx.adopted, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn sync_recovers_ids_from_file_names() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        zk.config.filename_id = Some(config::FilenameId::Prefix);
+        db.commit(&zk)?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        for title in ["stripped", "damaged"] {
+            let args = NewArgs {
+                title: Some(title.to_owned()),
+                ..Default::default()
+            };
+            super::new(&db, args, dt, prompt::Mode::No)?;
+        }
+        let before = db.get_zk()?.unwrap();
+        let path = |title: &str| {
+            let meta = before.zettels.values().find(|m| m.title == title).unwrap();
+            assert!(meta.path.ends_with(&format!("-{}.md", title)));
+            meta.full_path(db.root_dir())
+        };
+        std::fs::write(path("stripped"), "body\n")?;
+        std::fs::write(path("damaged"), "---\nid: [\n---\n")?;
+        super::sync(&db, SyncArgs::default(), prompt::Mode::No)?;
+        let after = db.get_zk()?.unwrap();
+        for (id, meta) in &before.zettels {
+            assert_eq!(after.zettels[id].title, meta.title);
+            assert!(meta.path.ends_with(&after.zettels[id].path));
+        }
+        let (fm, body) = frontmatter::parse_yaml_path(path("stripped"))?;
+        assert!(before
+            .zettels
+            .contains_key(fm.get(&"id".into()).unwrap().as_str().unwrap()));
+        assert_eq!(body, "body\n");
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sync_follows_symlinks_once() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let outside = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let root = tmp_dir.path().join("kasten");
+        std::fs::create_dir_all(root.join("sub"))?;
+        let db = database::memory::Database::new(root.clone());
+        db.commit(&Zettelkasten::default())?;
+        std::fs::write(root.join("sub/a.md"), "---\ntitle: A\n---\n")?;
+        std::fs::write(outside.path().join("b.md"), "---\ntitle: B\n---\n")?;
+        std::os::unix::fs::symlink(&root, root.join("sub/loop"))?;
+        std::os::unix::fs::symlink(root.join("sub/a.md"), root.join("alias.md"))?;
+        std::os::unix::fs::symlink(outside.path(), root.join("outside"))?;
+        let args = SyncArgs {
+            assign_ids: true,
+            ..Default::default()
+        };
+        super::sync(&db, args, prompt::Mode::No)?;
+        let zk = db.get_zk()?.unwrap();
+        let titles: Vec<&str> = zk.zettels.values().map(|m| m.title.as_str()).collect();
+        assert_eq!(titles, ["A"]);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn edits_a_private_temp_file() -> Result {
+        let mut cmd = std::process::Command::new("sh");
+        let script = r#"test -n "$(find "$1" -perm 600)" && echo "path: $1" >> "$1""#;
+        cmd.args(["-c", script, "sh"]);
+        let (status, edited) = edit_temp_file(cmd, "yaml", "title: A\n")?;
+        assert!(status.success());
+        let path = edited.strip_prefix("title: A\npath: ").unwrap().trim_end();
+        assert!(path.ends_with(".yaml"));
+        assert!(!Path::new(path).exists());
+        let (status, _) = edit_temp_file(std::process::Command::new("false"), "yaml", "")?;
+        assert!(!status.success());
+        Ok(())
+    }
+
+    #[test]
+    fn api_over_stdio() -> Result {
+        assert!(Args::try_parse_from(["zk", "api"]).is_err());
+        assert!(Args::try_parse_from(["zk", "api", "--stdio"]).is_ok());
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::file::Database::new(tmp_dir.path().to_path_buf())?;
+        db.commit(&Zettelkasten::default())?;
+        let input = concat!(
+            "{\"jsonrpc\": \"2.0\", \"id\": 1, \"method\": \"new\", \"params\": {\"title\": \"A\"}}\n",
+            "\n",
+            "{\"jsonrpc\": \"2.0\", \"id\": 2, \"method\": \"list\", \"params\": {}}\n",
+        );
+        let mut output = Vec::new();
+        api(&db, input.as_bytes(), &mut output)?;
+        let messages: Vec<serde_json::Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let responses: Vec<&serde_json::Value> =
+            messages.iter().filter(|m| m.get("id").is_some()).collect();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["result"]["title"], "A");
+        let id = responses[0]["result"]["id"].as_str().unwrap();
+        assert_eq!(responses[1]["result"][0]["id"], id);
+        let zk = db.get_zk()?.unwrap();
+        assert!(zk.zettels[id].full_path(tmp_dir.path()).is_file());
+        Ok(())
+    }
+
+    #[test]
+    fn plugins_get_the_kasten() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::file::Database::new(tmp_dir.path().to_path_buf())?;
+        db.commit(&Zettelkasten::default())?;
+        let args = [OsString::from("--flag"), OsString::from("value")];
+        let command = plugin(&db, "hello", &args, prompt::Mode::No)?;
+        assert_eq!(command.get_program(), "zk-hello");
+        let text = |s: &std::ffi::OsStr| s.to_string_lossy().into_owned();
+        let root_dir = text(tmp_dir.path().as_os_str());
+        let args: Vec<String> = command.get_args().map(text).collect();
+        assert_eq!(args, ["--root-dir", &root_dir, "--flag", "value"]);
+        let env: HashMap<String, String> = command
+            .get_envs()
+            .filter_map(|(k, v)| Some((text(k), text(v?))))
+            .collect();
+        assert_eq!(env["ZK_ROOT_DIR"], root_dir);
+        assert_eq!(env["ZK_DATABASE"], text(db.path().as_os_str()));
+        let context: serde_json::Value = serde_json::from_str(&env["ZK_CONTEXT"]).unwrap();
+        assert_eq!(context["initialized"], true);
+        assert_eq!(context["interactive"], false);
+        let missing = vec![OsString::from("surely-no-such-plugin")];
+        assert!(matches!(
+            external(db, missing, prompt::Mode::No),
+            Err(Error::UnknownCommand(name)) if name == "surely-no-such-plugin"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn tag_rename_keeps_other_tags() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        db.commit(&Zettelkasten::default())?;
+        let path = tmp_dir.path().join("a.md");
+        std::fs::write(&path, "---\ntitle: A\ntags: [2021, draft]\n---\nbody\n")?;
+        let args = SyncArgs {
+            assign_ids: true,
+            ..Default::default()
+        };
+        super::sync(&db, args, prompt::Mode::No)?;
+        let rename = metaedit::Edit::ReplaceTags(vec!["draft".to_owned()], "final".to_owned());
+        let now = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let args = MetaEditArgs {
+            filter: Default::default(),
+            dry_run: false,
+        };
+        meta_edit(&db, vec![rename], args, now)?;
+        let (fm, body) = frontmatter::parse_yaml_path(&path)?;
+        assert_eq!(
+            fm.get(&"tags".into()),
+            Some(&serde_yaml::from_str("[2021, final]").unwrap())
+        );
+        assert_eq!(body, "body\n");
+        let zk = db.get_zk()?.unwrap();
+        assert_eq!(zk.zettels.values().next().unwrap().tags, ["2021", "final"]);
+        Ok(())
+    }
+
+    #[test]
+    fn ambiguous_ids_name_the_candidates() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        let now = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        for (id, title) in [("a", "Reading notes"), ("b", "Readme")] {
+            let zettel = db.new_zettel(&zk.config, title, id, now)?;
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        db.commit(&zk)?;
+        assert_eq!(resolve_id(&db, "title^reading", now)?, "a");
+        let err = resolve_id(&db, "title^rea", now).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2 zettels match title^rea:\na  Reading notes\nb  Readme"
+        );
+        assert_eq!(err.failure().exit_code(), 4);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sync_continues_past_bad_entries() -> Result {
+        use std::os::unix::ffi::OsStrExt;
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        db.commit(&Zettelkasten::default())?;
+        let bad = std::ffi::OsStr::from_bytes(b"bad\xff.md");
+        std::fs::write(tmp_dir.path().join(bad), "---\ntitle: Bad\n---\n")?;
+        std::fs::write(tmp_dir.path().join("good.md"), "---\ntitle: Good\n---\n")?;
+        let args = SyncArgs {
+            assign_ids: true,
+            ..Default::default()
+        };
+        super::sync(&db, args, prompt::Mode::No)?;
+        let zk = db.get_zk()?.unwrap();
+        let titles: Vec<&str> = zk.zettels.values().map(|m| m.title.as_str()).collect();
+        assert_eq!(titles, ["Good"]);
+        Ok(())
+    }
+
+    #[test]
+    fn sync_skips_empty_files() -> Result {
+        let tmp_dir = tempdir::TempDir::new("zk_command_test").expect("couldn't create temp dir");
+        let db = database::memory::Database::new(tmp_dir.path().to_path_buf());
+        db.commit(&Zettelkasten::default())?;
+        std::fs::write(tmp_dir.path().join("empty.md"), "")?;
+        std::fs::write(tmp_dir.path().join("good.md"), "---\ntitle: Good\n---\n")?;
+        let args = SyncArgs {
+            assign_ids: true,
+            ..Default::default()
+        };
+        super::sync(&db, args, prompt::Mode::No)?;
+        let zk = db.get_zk()?.unwrap();
+        let titles: Vec<&str> = zk.zettels.values().map(|m| m.title.as_str()).collect();
+        assert_eq!(titles, ["Good"]);
+        Ok(())
+    }
 }
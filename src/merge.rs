@@ -0,0 +1,275 @@
+use crate::{frontmatter, link, zettel, zettelkasten, zettelkasten::Zettelkasten, DateTime};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    FrontmatterError(frontmatter::Error),
+    ZettelkastenError(zettelkasten::Error),
+    UnknownZettel(zettel::Id),
+    SameZettel,
+    /// the archive already holds a file named like the absorbed one
+    ArchiveTaken(PathBuf),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl From<zettelkasten::Error> for Error {
+    fn from(e: zettelkasten::Error) -> Self {
+        Self::ZettelkastenError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::ZettelkastenError(e) => e.fmt(f),
+            Self::UnknownZettel(id) => write!(f, "no zettel with id {}", id),
+            Self::SameZettel => f.write_str("cannot merge a zettel into itself"),
+            Self::ArchiveTaken(path) => write!(f, "{} exists already", path.display()),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Whose frontmatter values are kept when both zettels set a key
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Winner {
+    Survivor,
+    Absorbed,
+}
+
+/// What happens to the absorbed zettel's file
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Disposal {
+    Delete,
+    /// move into `.zk/archive`
+    Archive,
+}
+
+/// Summary of a merge
+#[derive(Debug, PartialEq)]
+pub struct Merged {
+    /// zettels whose links to the absorbed zettel were rewritten
+    pub relinked: Vec<zettel::Id>,
+    /// where the absorbed file was archived to, if it was
+    pub archived: Option<PathBuf>,
+}
+
+/// append the body of `absorbed` to `survivor`, point links to `absorbed`
+/// at `survivor` and remove `absorbed` from the zettelkasten
+///
+/// zettels wikilinking to `absorbed` are found with the link index, so the
+/// zettelkasten should be synced first; relative markdown links to its file
+/// are rewritten to the survivor's file as well. Files are changed in one
+/// transaction, so if one of them fails the others are put back too.
+pub fn merge(
+    zk: &mut Zettelkasten,
+    root_dir: &Path,
+    survivor: &str,
+    absorbed: &str,
+    winner: Winner,
+    disposal: Disposal,
+    now: DateTime,
+) -> Result<Merged> {
+    if survivor == absorbed {
+        return Err(Error::SameZettel);
+    }
+    let path_of = |id: &str| -> Result<PathBuf> {
+        zk.zettels
+            .get(id)
+            .map(|meta| meta.full_path(root_dir))
+            .ok_or_else(|| Error::UnknownZettel(id.to_owned()))
+    };
+    let survivor_path = path_of(survivor)?;
+    let absorbed_path = path_of(absorbed)?;
+    let archive_path = root_dir
+        .join(".zk")
+        .join("archive")
+        .join(absorbed_path.file_name().unwrap());
+    // renaming would replace the file archived earlier
+    if disposal == Disposal::Archive && archive_path.symlink_metadata().is_ok() {
+        return Err(Error::ArchiveTaken(archive_path));
+    }
+    let (survivor_fm, survivor_body) = frontmatter::parse_yaml_path(&survivor_path)?;
+    let (absorbed_fm, absorbed_body) = frontmatter::parse_yaml_path(&absorbed_path)?;
+    let (mut fm, overlay) = match winner {
+        Winner::Survivor => (absorbed_fm, survivor_fm),
+        Winner::Absorbed => (survivor_fm, absorbed_fm),
+    };
+    fm.extend(overlay);
+    frontmatter::unalias(&mut fm, &zk.config.key_aliases);
+    fm.insert("id".into(), survivor.into());
+    let moved = HashMap::from([(
+        link::normalize(&absorbed_path),
+        link::normalize(&survivor_path),
+    )]);
+    let (survivor_body, _) = link::rebase(&survivor_body, &survivor_path, &survivor_path, &moved);
+    let (absorbed_body, _) = link::rebase(&absorbed_body, &absorbed_path, &survivor_path, &moved);
+    let mut body = survivor_body.trim_end().to_owned();
+    body.push_str("\n\n");
+    body.push_str(absorbed_body.trim_start());
+    let body = link::rewrite_wikilinks(&body, absorbed, survivor);
+    let mut others: Vec<&zettel::Id> = zk
+        .zettels
+        .keys()
+        .filter(|id| *id != survivor && *id != absorbed)
+        .collect();
+    others.sort();
+    let mut relinked = Vec::new();
+    let mut relinked_files = Vec::new();
+    for id in others {
+        let path = path_of(id)?;
+        let wikilinked = zk.zettels[id].links.iter().any(|l| l == absorbed);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if wikilinked => return Err(e.into()),
+            Err(_) => continue,
+        };
+        let text = match wikilinked {
+            true => link::rewrite_wikilinks(&text, absorbed, survivor),
+            false => text,
+        };
+        let (text, links) = link::rebase(&text, &path, &path, &moved);
+        if wikilinked || !links.is_empty() {
+            relinked.push(id.clone());
+            relinked_files.push((path, text));
+        }
+    }
+    zk.transaction(|tx| {
+        let fm = frontmatter::aliased(&fm, &tx.zk().config.key_aliases);
+        let contents = frontmatter::write_for(&survivor_path, &fm, &body)?;
+        tx.write(survivor_path, contents);
+        for (path, text) in relinked_files {
+            tx.write(path, text);
+        }
+        let archived = match disposal {
+            Disposal::Delete => {
+                tx.remove(root_dir, absorbed);
+                None
+            }
+            Disposal::Archive => {
+                tx.zettels.remove(absorbed);
+                tx.rename(&absorbed_path, &archive_path);
+                Some(archive_path)
+            }
+        };
+        let meta = tx.zettels.get_mut(survivor).unwrap();
+        meta.update_from_frontmatter(&fm);
+        meta.update_from_body(&body);
+        meta.modified = now;
+        for id in &relinked {
+            let meta = tx.zettels.get_mut(id).unwrap();
+            for target in meta.links.iter_mut().filter(|l| *l == absorbed) {
+                *target = survivor.to_owned();
+            }
+            meta.links.sort();
+            meta.links.dedup();
+            meta.modified = now;
+        }
+        Ok(Merged { relinked, archived })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        database::{memory::Database, Database as _},
+        testutil::{self, Kasten},
+    };
+    use chrono::prelude::*;
+
+    #[test]
+    fn merge_and_relink() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_merge_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        for (id, body) in [
+            ("a", "A body, see [[b]]"),
+            ("b", "B body"),
+            ("c", "C links [[b|to b]] and [[a]]"),
+        ] {
+            let mut zettel = db.new_zettel(&Default::default(), id, id, dt)?;
+            zettel.content = body.to_owned();
+            zettel.meta.update_from_body(body);
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        let b_file = Path::new(&zk.zettels["b"].path).file_name().unwrap();
+        let b_file = b_file.to_str().unwrap().replace(' ', "%20");
+        let mut d = db.new_zettel(&Default::default(), "d", "d", dt)?;
+        d.content = format!("D cites [b]({}#part)", b_file);
+        zk.add(db.root_dir(), &d)?;
+        let merged = merge(
+            &mut zk,
+            db.root_dir(),
+            "a",
+            "b",
+            Winner::Survivor,
+            Disposal::Archive,
+            dt,
+        )?;
+        assert_eq!(merged.relinked, vec!["c".to_owned(), "d".to_owned()]);
+        assert!(merged.archived.unwrap().exists());
+        assert!(!zk.zettels.contains_key("b"));
+        let (fm, body) = frontmatter::parse_yaml_path(zk.zettels["a"].full_path(db.root_dir()))?;
+        assert_eq!(fm.get(&"title".into()), Some(&"a".into()));
+        assert_eq!(fm.get(&"id".into()), Some(&"a".into()));
+        assert_eq!(body, "A body, see [[a]]\n\nB body\n");
+        assert_eq!(zk.zettels["c"].links, vec!["a".to_owned()]);
+        let text = std::fs::read_to_string(zk.zettels["c"].full_path(db.root_dir()))?;
+        assert!(text.contains("C links [[a|to b]] and [[a]]"));
+        let a_file = Path::new(&zk.zettels["a"].path).file_name().unwrap();
+        let a_file = a_file.to_str().unwrap().replace(' ', "%20");
+        let text = std::fs::read_to_string(zk.zettels["d"].full_path(db.root_dir()))?;
+        assert!(text.contains(&format!("D cites [b]({}#part)", a_file)));
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_what_is_archived_already() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = Kasten::new();
+        kasten.add("a", "A", "A body\n");
+        let mut zk = kasten.add("b", "B", "B body\n");
+        let b_path = zk.zettels["b"].full_path(kasten.root_dir());
+        let archived = kasten
+            .root_dir()
+            .join(".zk/archive")
+            .join(b_path.file_name().unwrap());
+        std::fs::create_dir_all(archived.parent().unwrap())?;
+        std::fs::write(&archived, "an older B")?;
+        let merged = merge(
+            &mut zk,
+            kasten.root_dir(),
+            "a",
+            "b",
+            Winner::Survivor,
+            Disposal::Archive,
+            testutil::date(),
+        );
+        assert!(matches!(merged, Err(Error::ArchiveTaken(path)) if path == archived));
+        assert_eq!(std::fs::read_to_string(&archived)?, "an older B");
+        assert!(b_path.is_file());
+        assert!(zk.zettels.contains_key("b"));
+        Ok(())
+    }
+}
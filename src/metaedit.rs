@@ -0,0 +1,276 @@
+use crate::{frontmatter, query, zettel, zettelkasten::Zettelkasten};
+use serde_yaml::{Mapping, Value};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    FrontmatterError(frontmatter::Error),
+    /// keys that would desync the database, like `id`
+    ProtectedKey(String),
+    InvalidAssignment(String),
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::ProtectedKey(key) => write!(f, "frontmatter key {} can't be edited", key),
+            Self::InvalidAssignment(s) => write!(f, "expected key=value, got {}", s),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// keys managed by zk itself
+const PROTECTED_KEYS: [&str; 1] = ["id"];
+
+/// A change to the frontmatter of a zettel
+#[derive(Debug, PartialEq)]
+pub enum Edit {
+    Set(String, Value),
+    Unset(String),
+    /// keeps the key's position; nothing happens if the new key is taken
+    RenameKey(String, String),
+    /// replace any of the tags with another, writing tags as a list; the
+    /// other tags keep their YAML type
+    ReplaceTags(Vec<String>, String),
+}
+
+impl Edit {
+    /// parse `key=value`, reading the value as YAML so lists and numbers
+    /// keep their type
+    pub fn parse_assignment(s: &str) -> Result<Self> {
+        let (key, value) = s
+            .split_once('=')
+            .filter(|(key, _)| !key.is_empty())
+            .ok_or_else(|| Error::InvalidAssignment(s.to_owned()))?;
+        let value = serde_yaml::from_str(value).unwrap_or_else(|_| value.into());
+        Self::checked(Self::Set(key.to_owned(), value))
+    }
+
+    /// `edit`, unless it touches a protected key
+    pub fn checked(edit: Self) -> Result<Self> {
+        let keys = match &edit {
+            Self::Set(key, _) | Self::Unset(key) => vec![key],
+            Self::RenameKey(from, to) => vec![from, to],
+            Self::ReplaceTags(..) => vec![],
+        };
+        match keys
+            .into_iter()
+            .find(|k| PROTECTED_KEYS.contains(&k.as_str()))
+        {
+            Some(key) => Err(Error::ProtectedKey(key.clone())),
+            None => Ok(edit),
+        }
+    }
+
+    /// apply the edit, returning whether anything changed
+    pub fn apply(&self, fm: &mut Mapping) -> bool {
+        match self {
+            Self::Set(key, value) => {
+                fm.insert(key.as_str().into(), value.clone()).as_ref() != Some(value)
+            }
+            Self::Unset(key) => fm.remove(&key.as_str().into()).is_some(),
+            Self::RenameKey(from, to) => {
+                if !fm.contains_key(&from.as_str().into()) || fm.contains_key(&to.as_str().into()) {
+                    return false;
+                }
+                *fm = std::mem::take(fm)
+                    .into_iter()
+                    .map(|(k, v)| match k.as_str() {
+                        Some(k) if k == from => (to.as_str().into(), v),
+                        _ => (k, v),
+                    })
+                    .collect();
+                true
+            }
+            Self::ReplaceTags(from, to) => {
+                let tags: Vec<Value> = match fm.get(&"tags".into()) {
+                    Some(Value::Sequence(tags)) => tags.clone(),
+                    _ => zettel::tags(fm).into_iter().map(Value::from).collect(),
+                };
+                let replaced = |tag: &Value| zettel::scalar(tag).is_some_and(|t| from.contains(&t));
+                if !tags.iter().any(replaced) {
+                    return false;
+                }
+                let mut new_tags: Vec<Value> = Vec::new();
+                for tag in tags {
+                    let tag = if replaced(&tag) {
+                        to.as_str().into()
+                    } else {
+                        tag
+                    };
+                    if !new_tags.contains(&tag) {
+                        new_tags.push(tag);
+                    }
+                }
+                fm.insert("tags".into(), Value::Sequence(new_tags));
+                true
+            }
+        }
+    }
+}
+
+/// A zettel whose frontmatter is changed by a set of edits
+#[derive(Debug)]
+pub struct Change {
+    pub id: zettel::Id,
+    pub path: PathBuf,
+    pub frontmatter: Mapping,
+    /// new contents of the file
+    pub contents: String,
+    /// line diff of the frontmatter
+    pub diff: String,
+}
+
+/// changes from applying `edits` to every zettel matching `query`, in
+/// order of creation; zettels the edits don't change are left out
+pub fn plan(
+    zk: &Zettelkasten,
+    root_dir: &Path,
+    query: &query::Query,
+    edits: &[Edit],
+) -> Result<Vec<Change>> {
+    let mut ids: Vec<&zettel::Id> = zk
+        .zettels
+        .iter()
+        .filter(|(id, meta)| query.matches(id, meta, root_dir))
+        .map(|(id, _)| id)
+        .collect();
+    ids.sort_by_key(|id| (zk.zettels[*id].created, *id));
+    let mut changes = Vec::new();
+    for id in ids {
+        let path = zk.zettels[id].full_path(root_dir);
+        let (mut fm, body) = frontmatter::parse_yaml_path(&path)?;
+        let old = frontmatter::write_yaml(&fm, "")?;
+        let mut changed = false;
+        for edit in edits {
+            changed |= edit.apply(&mut fm);
+        }
+        if !changed {
+            continue;
+        }
+        let new = frontmatter::write_yaml(&fm, "")?;
+        changes.push(Change {
+            id: id.clone(),
+            contents: frontmatter::write_for(&path, &fm, &body)?,
+            path,
+            frontmatter: fm,
+            diff: diff_lines(&old, &new),
+        });
+    }
+    Ok(changes)
+}
+
+/// lines of `new` prefixed with ` ` or `+` and removed lines of `old`
+/// prefixed with `-`, from a longest common subsequence of the lines
+fn diff_lines(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    // lcs[i][j] is the length of the lcs of old[i..] and new[j..]
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let (mut i, mut j) = (0, 0);
+    let mut out = String::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            out.push_str(&format!(" {}\n", old[i]));
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out.push_str(&format!("-{}\n", old[i]));
+            i += 1;
+        } else {
+            out.push_str(&format!("+{}\n", new[j]));
+            j += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn set_unset_rename() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_metaedit_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        for (id, tag) in [("1", "a"), ("2", "b")] {
+            let mut zettel = db.new_zettel(&Default::default(), id, id, dt)?;
+            zettel
+                .extra_frontmatter
+                .insert("status".to_owned(), "draft".to_owned());
+            zettel.meta.tags = vec![tag.to_owned()];
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        assert!(Edit::parse_assignment("id=2").is_err());
+        let filter = query::parse("tag:a")?;
+        let edits = [
+            Edit::parse_assignment("tags=[a, c]")?,
+            Edit::Unset("date".to_owned()),
+            Edit::RenameKey("status".to_owned(), "state".to_owned()),
+        ];
+        let changes = plan(&zk, db.root_dir(), &filter, &edits)?;
+        assert_eq!(changes.len(), 1);
+        let change = &changes[0];
+        assert_eq!(change.id, "1");
+        assert!(change.diff.contains("-date: 2015-05-14\n"));
+        assert!(change.diff.contains("+state: draft\n"));
+        let (fm, _) =
+            frontmatter::parse_yaml(&mut std::io::BufReader::new(change.contents.as_bytes()))?;
+        assert_eq!(fm.get(&"tags".into()), Some(&vec!["a", "c"].into()));
+        assert_eq!(fm.get(&"state".into()), Some(&"draft".into()));
+        assert_eq!(fm.get(&"title".into()), Some(&"1".into()));
+        assert!(plan(&zk, db.root_dir(), &filter, &edits[1..2])?.len() == 1);
+        let mut fm = Mapping::new();
+        fm.insert("tags".into(), "a, b c".into());
+        let merge = Edit::ReplaceTags(vec!["a".to_owned(), "b".to_owned()], "c".to_owned());
+        assert!(merge.apply(&mut fm));
+        assert_eq!(fm.get(&"tags".into()), Some(&vec!["c"].into()));
+        assert!(!merge.apply(&mut fm));
+        Ok(())
+    }
+
+    #[test]
+    fn rename_and_merge_tags() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tags = |fm: &Mapping| fm.get(&"tags".into()).cloned();
+        let yaml =
+            |s: &str| -> std::result::Result<Value, serde_yaml::Error> { serde_yaml::from_str(s) };
+        let mut fm = Mapping::new();
+        fm.insert("tags".into(), yaml("[2021, draft, true, {a: b}]")?);
+        let rename = Edit::ReplaceTags(vec!["draft".to_owned()], "final".to_owned());
+        assert!(rename.apply(&mut fm));
+        assert_eq!(tags(&fm), Some(yaml("[2021, final, true, {a: b}]")?));
+        let merge = Edit::ReplaceTags(vec!["2021".to_owned(), "true".to_owned()], "old".to_owned());
+        assert!(merge.apply(&mut fm));
+        assert_eq!(tags(&fm), Some(yaml("[old, final, {a: b}]")?));
+        let missing = Edit::ReplaceTags(vec!["draft".to_owned()], "final".to_owned());
+        assert!(!missing.apply(&mut fm));
+        let mut fm = Mapping::new();
+        assert!(!missing.apply(&mut fm));
+        assert_eq!(tags(&fm), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+//! Predicates on the frontmatter of files as they are on disk, whether or
+//! not the database knows about them
+//!
+//! a predicate is `key == value`, `key != value`, `key ~= regex`, one of
+//! `<`, `<=`, `>` and `>=`, or a bare `key` that is there or `!key` that
+//! isn't. Values can be quoted. Sequences match `==`, `~=` and the orderings
+//! when any of their items does, and `!=` when none does.
+
+use crate::{config::Config, frontmatter, fsutil, ignore};
+use serde_yaml::{Mapping, Value};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    RegexError(regex::Error),
+    InvalidPredicate(String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Self {
+        Self::RegexError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::RegexError(e) => e.fmt(f),
+            Self::InvalidPredicate(p) => write!(
+                f,
+                "invalid predicate {}, expected e.g. `status == draft` or `!id`",
+                p
+            ),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+enum Op {
+    Eq(String),
+    Ne(String),
+    Matches(regex::Regex),
+    Lt(String),
+    Le(String),
+    Gt(String),
+    Ge(String),
+    Present,
+    Missing,
+}
+
+/// A test of one frontmatter key
+#[derive(Debug)]
+pub struct Predicate {
+    key: String,
+    op: Op,
+}
+
+impl std::str::FromStr for Predicate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidPredicate(s.to_owned());
+        // two-character operators first, so `<=` isn't read as `<`
+        let found = ["==", "!=", "~=", "<=", ">=", "<", ">"]
+            .into_iter()
+            .filter_map(|op| s.find(op).map(|at| (at, op)))
+            .min_by_key(|(at, op)| (*at, std::cmp::Reverse(op.len())));
+        let (key, op) = match found {
+            Some((at, op)) => {
+                let key = s[..at].trim();
+                let value = unquote(s[at + op.len()..].trim()).to_owned();
+                let op = match op {
+                    "==" => Op::Eq(value),
+                    "!=" => Op::Ne(value),
+                    "~=" => Op::Matches(regex::Regex::new(&value)?),
+                    "<=" => Op::Le(value),
+                    ">=" => Op::Ge(value),
+                    "<" => Op::Lt(value),
+                    _ => Op::Gt(value),
+                };
+                (key, op)
+            }
+            None => match s.trim().strip_prefix('!') {
+                Some(key) => (key.trim(), Op::Missing),
+                None => (s.trim(), Op::Present),
+            },
+        };
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            key: key.to_owned(),
+            op,
+        })
+    }
+}
+
+fn unquote(s: &str) -> &str {
+    ['"', '\'']
+        .into_iter()
+        .find_map(|q| s.strip_prefix(q)?.strip_suffix(q))
+        .unwrap_or(s)
+}
+
+/// `value` as the text a predicate compares with, `None` for mappings
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Null => Some(String::new()),
+        _ => None,
+    }
+}
+
+/// `a` against `b`, as numbers when both are
+fn compare(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+impl Predicate {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn matches(&self, fm: &Mapping) -> bool {
+        let value = fm.get(&self.key.as_str().into());
+        let values: Vec<String> = match value {
+            Some(Value::Sequence(items)) => items.iter().filter_map(scalar).collect(),
+            Some(value) => scalar(value).into_iter().collect(),
+            None => Vec::new(),
+        };
+        let any = |f: &dyn Fn(&str) -> bool| values.iter().any(|v| f(v));
+        use std::cmp::Ordering::*;
+        match &self.op {
+            Op::Present => value.is_some(),
+            Op::Missing => value.is_none(),
+            Op::Eq(b) => any(&|a| a == b),
+            Op::Ne(b) => !any(&|a| a == b),
+            Op::Matches(re) => any(&|a| re.is_match(a)),
+            Op::Lt(b) => any(&|a| compare(a, b) == Less),
+            Op::Le(b) => any(&|a| compare(a, b) != Greater),
+            Op::Gt(b) => any(&|a| compare(a, b) == Greater),
+            Op::Ge(b) => any(&|a| compare(a, b) != Less),
+        }
+    }
+}
+
+/// A file whose frontmatter matched
+#[derive(Debug)]
+pub struct Match {
+    /// relative to the root directory, with `/` separators
+    pub path: String,
+    /// frontmatter with zk's own key names
+    pub frontmatter: Mapping,
+}
+
+impl Match {
+    /// value of `key` on one line, `None` if there is no such key; nested
+    /// mappings are left out
+    pub fn value(&self, key: &str) -> Option<String> {
+        Some(match self.frontmatter.get(&key.into())? {
+            Value::Sequence(items) => {
+                let items: Vec<String> = items.iter().filter_map(scalar).collect();
+                format!("[{}]", items.join(", "))
+            }
+            value => scalar(value).unwrap_or_else(|| "{...}".to_owned()),
+        })
+    }
+}
+
+/// files under `root_dir` that sync would look at whose frontmatter
+/// satisfies every one of `predicates`, ordered by path; files that can't
+/// be parsed are skipped
+pub fn grep(config: &Config, root_dir: &Path, predicates: &[Predicate]) -> Result<Vec<Match>> {
+    let ignore = ignore::Ignore::load(root_dir)?;
+    let mut found = Vec::new();
+    walk(config, root_dir, &ignore, root_dir, predicates, &mut found)?;
+    Ok(found)
+}
+
+fn walk(
+    config: &Config,
+    root_dir: &Path,
+    ignore: &ignore::Ignore,
+    dir: &Path,
+    predicates: &[Predicate],
+    found: &mut Vec<Match>,
+) -> Result<()> {
+    let mut entries: Vec<std::fs::DirEntry> =
+        std::fs::read_dir(dir)?.collect::<std::io::Result<_>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let relative = path.strip_prefix(root_dir).unwrap();
+        if name.starts_with("_zettel") || name.starts_with('.') || ignore.is_ignored(relative) {
+            continue;
+        }
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if path != root_dir.join(config.assets_dir()) {
+                walk(config, root_dir, ignore, &path, predicates, found)?;
+            }
+            continue;
+        }
+        if !file_type.is_file() {
+            continue;
+        }
+        let (path, mut fm) = match (
+            fsutil::to_slash(relative),
+            frontmatter::parse_yaml_path(&path),
+        ) {
+            (Some(path), Ok((fm, _))) => (path, fm),
+            (_, Err(e)) => {
+                tracing::debug!("skipping {}: {}", relative.display(), e);
+                continue;
+            }
+            _ => continue,
+        };
+        frontmatter::unalias(&mut fm, &config.key_aliases);
+        if predicates.iter().all(|p| p.matches(&fm)) {
+            found.push(Match {
+                path,
+                frontmatter: fm,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn predicates_on_files() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_metagrep_test")?;
+        let root_dir = tmp_dir.path();
+        std::fs::create_dir(root_dir.join("sub"))?;
+        std::fs::write(
+            root_dir.join("a.md"),
+            "---\nstatus: draft\npriority: 10\ntags: [x, y]\n---\n",
+        )?;
+        std::fs::write(
+            root_dir.join("sub/b.md"),
+            "---\nstate: done\npriority: 9\n---\n",
+        )?;
+        std::fs::write(root_dir.join("c.md"), "---\ntitle: C\n---\n")?;
+        std::fs::write(root_dir.join("d.txt"), "no frontmatter\n")?;
+        let mut config = Config::default();
+        config
+            .key_aliases
+            .insert("status".to_owned(), "state".to_owned());
+        let grep = |predicates: &[&str]| -> std::result::Result<Vec<String>, Error> {
+            let predicates = predicates
+                .iter()
+                .map(|p| p.parse())
+                .collect::<Result<Vec<Predicate>>>()?;
+            Ok(grep(&config, root_dir, &predicates)?
+                .into_iter()
+                .map(|m| m.path)
+                .collect())
+        };
+        assert_eq!(grep(&["status == draft"])?, ["a.md"]);
+        assert_eq!(grep(&["status != 'draft'"])?, ["c.md", "sub/b.md"]);
+        assert_eq!(grep(&["priority > 9"])?, ["a.md"]);
+        assert_eq!(grep(&["priority<=9"])?, ["sub/b.md"]);
+        assert_eq!(grep(&["tags == y", "status ~= ^dr"])?, ["a.md"]);
+        assert_eq!(grep(&["!priority"])?, ["c.md"]);
+        assert_eq!(grep(&["status"])?.len(), 2);
+        let found = super::grep(&config, root_dir, &["tags".parse()?])?;
+        assert_eq!(found[0].value("tags").as_deref(), Some("[x, y]"));
+        assert_eq!(found[0].value("priority").as_deref(), Some("10"));
+        assert!(matches!(
+            "a b == c".parse::<Predicate>(),
+            Err(Error::InvalidPredicate(_))
+        ));
+        Ok(())
+    }
+}
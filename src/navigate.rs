@@ -0,0 +1,88 @@
+use crate::{sequence, zettel, zettelkasten::Zettelkasten};
+use chrono::NaiveDate;
+
+/// Which way `zk prev` and `zk next` go
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Prev,
+    Next,
+}
+
+/// the date in the title of a daily note, written `YYYY-MM-DD`
+pub fn daily_date(title: &str) -> Option<NaiveDate> {
+    let re = regex::Regex::new(r"\b(\d{4})-(\d{2})-(\d{2})\b").unwrap();
+    let caps = re.captures(title)?;
+    NaiveDate::from_ymd_opt(
+        caps[1].parse().ok()?,
+        caps[2].parse().ok()?,
+        caps[3].parse().ok()?,
+    )
+}
+
+/// the daily note dated closest before or after that of daily note `id`
+pub fn daily_step(zk: &Zettelkasten, id: &str, direction: Direction) -> Option<zettel::Id> {
+    let date = daily_date(&zk.zettels.get(id)?.title)?;
+    let dated = zk
+        .zettels
+        .iter()
+        .filter(|(other, _)| *other != id)
+        .filter_map(|(other, meta)| Some((daily_date(&meta.title)?, other)));
+    match direction {
+        Direction::Prev => dated.filter(|(d, _)| *d < date).max(),
+        Direction::Next => dated.filter(|(d, _)| *d > date).min(),
+    }
+    .map(|(_, other)| other.clone())
+}
+
+/// the zettel before or after `id`: its neighbour in its sequence, or the
+/// neighbouring daily note when there is none; only daily notes when
+/// `journal` is set
+pub fn step(
+    zk: &Zettelkasten,
+    id: &str,
+    direction: Direction,
+    journal: bool,
+) -> Option<zettel::Id> {
+    if !journal {
+        let (prev, next) = sequence::neighbours(zk, id);
+        let found = match direction {
+            Direction::Prev => prev,
+            Direction::Next => next,
+        };
+        if found.is_some() {
+            return found;
+        }
+    }
+    daily_step(zk, id, direction)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ZettelMeta;
+    use chrono::prelude::*;
+
+    #[test]
+    fn sequences_then_journal() {
+        let now = chrono::Local.ymd(2023, 5, 17).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for (id, title, follows) in [
+            ("d1", "Journal 2023-05-14", None),
+            ("d3", "Journal 2023-05-17", None),
+            ("d2", "Journal 2023-05-15", None),
+            ("idea", "Idea from the 15th", Some("d2")),
+            ("x", "Loose", None),
+        ] {
+            let mut meta = ZettelMeta::new(id, title, "z.md", now);
+            meta.follows = follows.map(str::to_owned);
+            zk.zettels.insert(id.to_owned(), meta);
+        }
+        let step = |id, direction, journal| step(&zk, id, direction, journal);
+        assert_eq!(step("d2", Direction::Next, false).as_deref(), Some("idea"));
+        assert_eq!(step("d2", Direction::Next, true).as_deref(), Some("d3"));
+        assert_eq!(step("d2", Direction::Prev, false).as_deref(), Some("d1"));
+        assert_eq!(step("idea", Direction::Prev, false).as_deref(), Some("d2"));
+        assert_eq!(step("d3", Direction::Next, false), None);
+        assert_eq!(step("x", Direction::Next, false), None);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::render::ZETTEL_SCHEME;
+use std::path::Path;
+
+/// name of the desktop entry registered as handler of `zk://` urls
+pub const DESKTOP_FILE: &str = "zk-url.desktop";
+
+/// command opening a file or url with the application the system associates
+/// with it, `$ZK_OPENER` if set
+pub fn system_opener() -> std::process::Command {
+    if let Some(opener) = std::env::var("ZK_OPENER")
+        .ok()
+        .filter(|o| !o.trim().is_empty())
+    {
+        let mut words = opener.split_whitespace();
+        let mut cmd = std::process::Command::new(words.next().unwrap());
+        cmd.args(words);
+        return cmd;
+    }
+    if cfg!(target_os = "macos") {
+        std::process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut cmd = std::process::Command::new("cmd");
+        cmd.args(["/C", "start", ""]);
+        cmd
+    } else {
+        std::process::Command::new("xdg-open")
+    }
+}
+
+/// target and anchor of a `zk://id`, `zk://kasten:id#Heading` or `zk:id` url
+///
+/// `%xx` escapes are decoded since browsers and other apps add them
+pub fn parse_url(url: &str) -> Option<(String, Option<String>)> {
+    let rest = url.trim().strip_prefix(ZETTEL_SCHEME)?;
+    let rest = rest.strip_prefix("//").unwrap_or(rest);
+    let rest = percent_decode(rest);
+    let (target, anchor) = match rest.split_once('#') {
+        Some((target, anchor)) => (target, Some(anchor.to_owned())),
+        None => (rest.as_str(), None),
+    };
+    let target = target.trim_end_matches('/');
+    if target.is_empty() {
+        return None;
+    }
+    Some((target.to_owned(), anchor.filter(|a| !a.is_empty())))
+}
+
+/// decode `%xx` escapes, keeping malformed ones as they are
+pub fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| s.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                out.push(byte);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// freedesktop entry running `exe open-url` on the zettelkasten in
+/// `root_dir` for every `zk:` url
+pub fn desktop_entry(exe: &Path, root_dir: &Path) -> String {
+    format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name=zk\n\
+         Exec=\"{}\" --root-dir \"{}\" open-url %u\n\
+         MimeType=x-scheme-handler/zk;\n\
+         NoDisplay=true\n\
+         Terminal=false\n",
+        exe.display(),
+        root_dir.display()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        assert_eq!(parse_url("zk://abc"), Some(("abc".to_owned(), None)));
+        assert_eq!(parse_url("zk:abc/"), Some(("abc".to_owned(), None)));
+        assert_eq!(
+            parse_url("zk://work:abc#Part%20one"),
+            Some(("work:abc".to_owned(), Some("Part one".to_owned())))
+        );
+        assert_eq!(parse_url("zk://"), None);
+        assert_eq!(parse_url("https://abc"), None);
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    #[test]
+    fn entry_runs_open_url() {
+        let entry = desktop_entry(Path::new("/bin/zk"), Path::new("/notes"));
+        assert!(entry.contains("Exec=\"/bin/zk\" --root-dir \"/notes\" open-url %u\n"));
+        assert!(entry.contains("MimeType=x-scheme-handler/zk;\n"));
+    }
+}
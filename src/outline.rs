@@ -0,0 +1,236 @@
+use crate::{zettel, zettelkasten::Zettelkasten, ZettelMeta};
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use std::path::{Path, PathBuf};
+
+/// A markdown heading
+#[derive(Debug, PartialEq, Clone)]
+pub struct Heading {
+    /// 1 for `#`, 2 for `##` and so on
+    pub level: usize,
+    pub text: String,
+    /// line number starting at 1
+    pub line: usize,
+}
+
+/// headings of a markdown body, ignoring `#` inside code blocks
+pub fn headings(body: &str) -> Vec<Heading> {
+    let mut headings = Vec::new();
+    let mut current: Option<Heading> = None;
+    for (event, range) in Parser::new(body).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                current = Some(Heading {
+                    level: level as usize,
+                    text: String::new(),
+                    line: body[..range.start].lines().count() + 1,
+                })
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = current.as_mut() {
+                    heading.text.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => headings.extend(current.take()),
+            _ => (),
+        }
+    }
+    headings
+}
+
+/// part of `body` a `[[id#anchor]]` link points at, if it still exists
+///
+/// a heading anchor matches a heading with the same [`slug`] and covers the
+/// section up to the next heading at the same or a shallower level. a
+/// `^block` anchor matches the line ending in `^block`, returned without it.
+///
+/// [`slug`]: crate::link::slug
+pub fn anchored(body: &str, anchor: &str) -> Option<String> {
+    if let Some(block) = anchor.strip_prefix('^') {
+        let marker = format!(" ^{}", block);
+        return body
+            .lines()
+            .find_map(|line| line.trim_end().strip_suffix(marker.as_str()))
+            .map(str::to_owned);
+    }
+    let slug = crate::link::slug(anchor);
+    let headings = headings(body);
+    let (n, heading) = headings
+        .iter()
+        .enumerate()
+        .find(|(_, h)| crate::link::slug(&h.text) == slug)?;
+    let end = headings[n + 1..]
+        .iter()
+        .find(|h| h.level <= heading.level)
+        .map(|h| h.line - 1);
+    let lines: Vec<&str> = body.lines().collect();
+    let end = end.unwrap_or(lines.len()).min(lines.len());
+    Some(
+        lines[heading.line - 1..end]
+            .join("\n")
+            .trim_end()
+            .to_owned(),
+    )
+}
+
+/// indented list of headings, with the shallowest heading unindented
+pub fn format_outline(headings: &[Heading]) -> String {
+    let min_level = headings.iter().map(|h| h.level).min().unwrap_or(1);
+    headings
+        .iter()
+        .map(|h| format!("{}- {}\n", "  ".repeat(h.level - min_level), h.text))
+        .collect()
+}
+
+/// nested list linking to every zettel under `subdir`, grouped by directory
+pub fn toc(zk: &Zettelkasten, root_dir: &Path, subdir: &Path) -> String {
+    let subdir = crate::link::normalize(subdir);
+    let mut entries: Vec<(PathBuf, &zettel::Id, &ZettelMeta)> = zk
+        .zettels
+        .iter()
+        .filter_map(|(id, meta)| {
+            let path = meta.relative_path(root_dir);
+            let path = path.strip_prefix(&subdir).ok()?.to_path_buf();
+            Some((path, id, meta))
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        a.0.parent()
+            .cmp(&b.0.parent())
+            .then_with(|| a.2.title.cmp(&b.2.title))
+    });
+    let mut out = String::new();
+    let mut prev_dirs: Vec<String> = Vec::new();
+    for (path, id, meta) in entries {
+        let dirs: Vec<String> = path
+            .parent()
+            .into_iter()
+            .flat_map(|p| p.components())
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let common = dirs
+            .iter()
+            .zip(&prev_dirs)
+            .take_while(|(a, b)| a == b)
+            .count();
+        for (depth, dir) in dirs.iter().enumerate().skip(common) {
+            out.push_str(&format!("{}- {}/\n", "  ".repeat(depth), dir));
+        }
+        out.push_str(&format!(
+            "{}- [[{}|{}]]\n",
+            "  ".repeat(dirs.len()),
+            id,
+            meta.title
+        ));
+        prev_dirs = dirs;
+    }
+    out
+}
+
+/// structure note body linking every zettel tagged `tag`, grouped under
+/// headings for the other tags they carry
+///
+/// zettels without other tags are listed first
+pub fn tag_index(zk: &Zettelkasten, tag: &str) -> String {
+    let mut tagged: Vec<(&zettel::Id, &ZettelMeta)> = zk
+        .zettels
+        .iter()
+        .filter(|(_, meta)| meta.tags.iter().any(|t| t == tag))
+        .collect();
+    tagged.sort_by(|a, b| a.1.title.cmp(&b.1.title));
+    let mut groups: std::collections::BTreeMap<&str, Vec<_>> = Default::default();
+    let mut out = String::new();
+    for (id, meta) in tagged {
+        let mut subtopics = meta.tags.iter().filter(|t| *t != tag).peekable();
+        if subtopics.peek().is_none() {
+            out.push_str(&format!("- [[{}|{}]]\n", id, meta.title));
+        }
+        for subtopic in subtopics {
+            groups.entry(subtopic).or_default().push((id, meta));
+        }
+    }
+    for (subtopic, zettels) in groups {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("## {}\n\n", subtopic));
+        for (id, meta) in zettels {
+            out.push_str(&format!("- [[{}|{}]]\n", id, meta.title));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn outline_of_body() {
+        let body =
+            "intro\n## Part one\ntext\n```\n# not a heading\n```\n### Detail `x`\n## Part two\n";
+        let headings = headings(body);
+        assert_eq!(headings.len(), 3);
+        assert_eq!(headings[1].text, "Detail x");
+        assert_eq!(headings[1].line, 7);
+        assert_eq!(
+            format_outline(&headings),
+            "- Part one\n  - Detail x\n- Part two\n"
+        );
+    }
+
+    #[test]
+    fn anchors_in_body() {
+        let body = "# Top\n## Part one\ntext ^quote\n### Detail\nmore\n## Part two\nend\n";
+        assert_eq!(
+            anchored(body, "part one").as_deref(),
+            Some("## Part one\ntext ^quote\n### Detail\nmore")
+        );
+        assert_eq!(
+            anchored(body, "Part-Two").as_deref(),
+            Some("## Part two\nend")
+        );
+        assert_eq!(anchored(body, "^quote").as_deref(), Some("text"));
+        assert_eq!(anchored(body, "Part three"), None);
+        assert_eq!(anchored(body, "^gone"), None);
+    }
+
+    #[test]
+    fn toc_groups_by_directory() {
+        let mut zk = Zettelkasten::default();
+        let now = chrono::Local::now();
+        for (id, title, path) in [
+            ("1", "Beta", "notes/b.md"),
+            ("2", "Alpha", "notes/a.md"),
+            ("3", "Deep", "notes/x/y/deep.md"),
+            ("4", "Outside", "other/o.md"),
+            ("5", "Sibling", "notes/x/sibling.md"),
+        ] {
+            zk.zettels
+                .insert(id.to_owned(), ZettelMeta::new(id, title, path, now));
+        }
+        assert_eq!(
+            toc(&zk, Path::new("/root"), Path::new("./notes")),
+            "- [[2|Alpha]]\n- [[1|Beta]]\n- x/\n  - [[5|Sibling]]\n  - y/\n    - [[3|Deep]]\n"
+        );
+    }
+
+    #[test]
+    fn index_groups_by_other_tags() {
+        let mut zk = Zettelkasten::default();
+        let now = chrono::Local::now();
+        for (id, title, tags) in [
+            ("1", "Plan", vec!["project-x"]),
+            ("2", "Schema", vec!["project-x", "design"]),
+            ("3", "API", vec!["design", "project-x", "backend"]),
+            ("4", "Unrelated", vec!["design"]),
+        ] {
+            let mut meta = ZettelMeta::new(id, title, "z.md", now);
+            meta.tags = tags.into_iter().map(str::to_owned).collect();
+            zk.zettels.insert(id.to_owned(), meta);
+        }
+        assert_eq!(
+            tag_index(&zk, "project-x"),
+            "- [[1|Plan]]\n\n## backend\n\n- [[3|API]]\n\n## design\n\n- [[3|API]]\n- [[2|Schema]]\n"
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use crate::{zettel, zettelkasten::Zettelkasten, ZettelMeta};
+use std::path::Path;
+
+/// Output formats of `zk pick` for launchers
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// Alfred script filter JSON, also read by Raycast
+    Alfred,
+    /// rofi script mode lines, with the argument as row info
+    Rofi,
+}
+
+/// What a launcher hands back when an item is chosen
+#[derive(Debug, Clone, Copy, PartialEq, Default, clap::ValueEnum)]
+pub enum Arg {
+    #[default]
+    Id,
+    Path,
+}
+
+/// A zettel offered to a launcher
+#[derive(Debug, PartialEq)]
+pub struct Item {
+    pub id: zettel::Id,
+    pub title: String,
+    /// path relative to the root directory, followed by the tags
+    pub subtitle: String,
+    pub arg: String,
+}
+
+/// whether every word of `query` appears in `title`, ignoring case
+pub fn matches(title: &str, query: &str) -> bool {
+    let title = title.to_lowercase();
+    query
+        .split_whitespace()
+        .all(|word| title.contains(&word.to_lowercase()))
+}
+
+/// whether some zettel is titled exactly `query`, ignoring case and
+/// surrounding whitespace
+pub fn has_title(zk: &Zettelkasten, query: &str) -> bool {
+    let query = query.trim().to_lowercase();
+    zk.zettels
+        .values()
+        .any(|meta| meta.title.trim().to_lowercase() == query)
+}
+
+/// zettels with titles matching `query`, most recently modified first
+pub fn items(zk: &Zettelkasten, root_dir: &Path, query: &str, arg: Arg) -> Vec<Item> {
+    let mut zettels: Vec<(&zettel::Id, &ZettelMeta)> = zk
+        .zettels
+        .iter()
+        .filter(|(_, meta)| matches(&meta.title, query))
+        .collect();
+    zettels.sort_by(|a, b| b.1.modified.cmp(&a.1.modified).then(a.0.cmp(b.0)));
+    zettels
+        .into_iter()
+        .map(|(id, meta)| {
+            let path = meta.relative_path(root_dir);
+            let mut subtitle = path.display().to_string();
+            for tag in &meta.tags {
+                subtitle.push_str(&format!(" #{}", tag));
+            }
+            Item {
+                id: id.clone(),
+                title: meta.title.clone(),
+                subtitle,
+                arg: match arg {
+                    Arg::Id => id.clone(),
+                    Arg::Path => meta.full_path(root_dir).display().to_string(),
+                },
+            }
+        })
+        .collect()
+}
+
+/// `items` as the launcher reading `format` expects them
+pub fn render(items: &[Item], format: Format) -> String {
+    match format {
+        Format::Alfred => {
+            let items: Vec<serde_json::Value> = items
+                .iter()
+                .map(|item| {
+                    serde_json::json!({
+                        "uid": item.id,
+                        "title": item.title,
+                        "subtitle": item.subtitle,
+                        "arg": item.arg,
+                        "autocomplete": item.title,
+                    })
+                })
+                .collect();
+            let mut out = serde_json::json!({ "items": items }).to_string();
+            out.push('\n');
+            out
+        }
+        // rofi treats `\0` and `\x1f` in a line as the start of row options
+        Format::Rofi => items
+            .iter()
+            .map(|item| {
+                format!(
+                    "{}  ({})\0info\x1f{}\n",
+                    item.title.replace(['\0', '\n'], " "),
+                    item.subtitle.replace(['\0', '\n'], " "),
+                    item.arg
+                )
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn zk() -> Zettelkasten {
+        let mut zk = Zettelkasten::default();
+        let now = chrono::Local::now();
+        for (n, (id, title)) in [("1", "Rust traits"), ("2", "Trait objects in rust")]
+            .into_iter()
+            .enumerate()
+        {
+            let mut meta = ZettelMeta::new(id, title, &format!("/notes/{}.md", id), now);
+            meta.modified = now + chrono::Duration::seconds(n as i64);
+            meta.tags = vec!["rust".to_owned()];
+            zk.zettels.insert(id.to_owned(), meta);
+        }
+        zk
+    }
+
+    #[test]
+    fn alfred_items() {
+        let zk = zk();
+        let both = items(&zk, Path::new("/notes"), "RUST trait", Arg::Id);
+        assert_eq!(
+            both.iter().map(|i| i.id.as_str()).collect::<Vec<_>>(),
+            ["2", "1"]
+        );
+        assert!(items(&zk, Path::new("/notes"), "objects", Arg::Id).len() == 1);
+        assert!(has_title(&zk, " rust Traits"));
+        assert!(!has_title(&zk, "rust"));
+        let json: serde_json::Value = serde_json::from_str(&render(&both, Format::Alfred)).unwrap();
+        assert_eq!(json["items"][1]["title"], "Rust traits");
+        assert_eq!(json["items"][1]["subtitle"], "1.md #rust");
+        assert_eq!(json["items"][1]["arg"], "1");
+    }
+
+    #[test]
+    fn rofi_rows() {
+        let items = items(&zk(), Path::new("/notes"), "objects", Arg::Path);
+        assert_eq!(
+            render(&items, Format::Rofi),
+            "Trait objects in rust  (2.md #rust)\0info\x1f/notes/2.md\n"
+        );
+    }
+}
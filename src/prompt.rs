@@ -0,0 +1,54 @@
+use std::io::IsTerminal;
+
+/// How zk answers the questions it would otherwise ask
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// prompt on the terminal
+    Ask,
+    /// take every action a confirmation asks about
+    Yes,
+    /// take the default of every question, declining confirmations
+    No,
+}
+
+impl Mode {
+    /// `Ask` unless a flag says otherwise or stdin isn't a terminal, as when
+    /// running from a script, cron or an editor
+    pub fn detect(yes: bool, non_interactive: bool) -> Self {
+        if yes {
+            Self::Yes
+        } else if non_interactive || !std::io::stdin().is_terminal() {
+            Self::No
+        } else {
+            Self::Ask
+        }
+    }
+
+    pub fn is_interactive(self) -> bool {
+        self == Self::Ask
+    }
+
+    /// answer to a yes or no question
+    pub fn confirm(self, prompt: &str) -> std::io::Result<bool> {
+        match self {
+            Self::Ask => dialoguer::Confirm::new().with_prompt(prompt).interact(),
+            Self::Yes => Ok(true),
+            Self::No => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn answers_without_asking() -> std::io::Result<()> {
+        assert_eq!(Mode::detect(true, true), Mode::Yes);
+        assert_eq!(Mode::detect(false, true), Mode::No);
+        assert!(Mode::Yes.confirm("create?")?);
+        assert!(!Mode::No.confirm("create?")?);
+        assert!(!Mode::No.is_interactive());
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{frontmatter, link, zettel, zettelkasten::Zettelkasten};
+use std::path::Path;
+
+/// What publishing the public zettels of a zettelkasten would expose
+#[derive(Debug, Default, PartialEq)]
+pub struct Audit {
+    /// zettels marked for publishing, ordered by id
+    pub published: Vec<zettel::Id>,
+    /// public zettels linking to private ones, whose ids and titles would
+    /// show up in the links
+    pub private_links: Vec<(zettel::Id, zettel::Id)>,
+    /// public zettels embedding private ones, whose bodies would be
+    /// published along with them
+    pub private_embeds: Vec<(zettel::Id, zettel::Id)>,
+    /// public zettels that couldn't be read to look for embeds
+    pub unreadable: Vec<zettel::Id>,
+}
+
+/// find the public zettels of `zk` and what of the private ones they expose
+pub fn audit(zk: &Zettelkasten, root_dir: &Path) -> Audit {
+    let mut audit = Audit::default();
+    let is_private = |id: &String| zk.zettels.get(id).is_some_and(|meta| !meta.is_public());
+    let mut ids: Vec<&zettel::Id> = zk
+        .zettels
+        .iter()
+        .filter(|(_, meta)| meta.is_public())
+        .map(|(id, _)| id)
+        .collect();
+    ids.sort();
+    for id in ids {
+        audit.published.push(id.clone());
+        let meta = &zk.zettels[id];
+        let body = match frontmatter::parse_yaml_path(meta.full_path(root_dir)) {
+            Ok((_, body)) => body,
+            Err(_) => {
+                audit.unreadable.push(id.clone());
+                String::new()
+            }
+        };
+        let mut embeds: Vec<zettel::Id> = link::wikilinks(&body)
+            .into_iter()
+            .filter(|l| l.embed && is_private(&l.target))
+            .map(|l| l.target)
+            .collect();
+        embeds.sort();
+        embeds.dedup();
+        for to in &meta.links {
+            if is_private(to) && !embeds.contains(to) {
+                audit.private_links.push((id.clone(), to.clone()));
+            }
+        }
+        for to in embeds {
+            audit.private_embeds.push((id.clone(), to));
+        }
+    }
+    audit
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn audit_public_zettels() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_publish_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for (id, visibility, body) in [
+            (
+                "post",
+                "publish: true",
+                "see [[draft]], [[other]] and ![[diary]]",
+            ),
+            ("other", "visibility: public", ""),
+            ("draft", "publish: no", ""),
+            ("diary", "", "private"),
+        ] {
+            let mut zettel = db.new_zettel(&zk.config, id, id, dt)?;
+            zettel.content = body.to_owned();
+            zettel.meta.update_from_body(body);
+            let fm: serde_yaml::Mapping = serde_yaml::from_str(&format!("{{{}}}", visibility))?;
+            zettel.meta.update_from_frontmatter(&fm);
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        assert_eq!(zk.zettels["draft"].publish, Some(false));
+        assert_eq!(zk.zettels["diary"].publish, None);
+        let audit = audit(&zk, tmp_dir.path());
+        let pair = |a: &str, b: &str| (a.to_owned(), b.to_owned());
+        assert_eq!(audit.published, ["other", "post"]);
+        assert_eq!(audit.private_links, [pair("post", "draft")]);
+        assert_eq!(audit.private_embeds, [pair("post", "diary")]);
+        Ok(())
+    }
+}
@@ -0,0 +1,600 @@
+use crate::{
+    dates::{self, DateRange},
+    zettel, ZettelMeta,
+};
+use chrono::NaiveDate;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    SyntaxError(String),
+    RegexError(regex::Error),
+    InvalidDate(String),
+}
+
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Self {
+        Self::RegexError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SyntaxError(msg) => write!(f, "invalid query: {}", msg),
+            Self::RegexError(e) => e.fmt(f),
+            Self::InvalidDate(date) => write!(
+                f,
+                "invalid date {}, expected e.g. 2023-05-14, 2023-Q2, last week or -7d",
+                date
+            ),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Comparison of a date with the days of a date in a query
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateOp {
+    Before,
+    BeforeOrOn,
+    On,
+    AfterOrOn,
+    After,
+}
+
+impl DateOp {
+    fn compare(self, date: NaiveDate, query: DateRange) -> bool {
+        match self {
+            Self::Before => date < query.start,
+            Self::BeforeOrOn => date <= query.end,
+            Self::On => query.contains(date),
+            Self::AfterOrOn => date >= query.start,
+            Self::After => date > query.end,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Query {
+    All,
+    Tag(String),
+    /// lowercase substring of the title
+    TitleContains(String),
+    TitleMatches(regex::Regex),
+    Created(DateOp, DateRange),
+    Modified(DateOp, DateRange),
+    Path(String),
+    LinksTo(zettel::Id),
+    Id(zettel::Id),
+    And(Box<Query>, Box<Query>),
+    Or(Box<Query>, Box<Query>),
+    Not(Box<Query>),
+}
+
+impl Query {
+    pub fn matches(&self, id: &str, meta: &ZettelMeta, root_dir: &Path) -> bool {
+        match self {
+            Self::All => true,
+            Self::Tag(tag) => meta.tags.iter().any(|t| t == tag),
+            Self::TitleContains(s) => meta.title.to_lowercase().contains(s),
+            Self::TitleMatches(re) => re.is_match(&meta.title),
+            Self::Created(op, date) => op.compare(meta.created.date().naive_local(), *date),
+            Self::Modified(op, date) => op.compare(meta.modified.date().naive_local(), *date),
+            Self::Path(pattern) => {
+                let path = meta.relative_path(root_dir);
+                if pattern.contains(['*', '?']) {
+                    let path = path.to_string_lossy().replace('\\', "/");
+                    glob_match(pattern, &path)
+                } else {
+                    path.starts_with(crate::link::normalize(Path::new(pattern)))
+                }
+            }
+            Self::LinksTo(target) => meta.links.iter().any(|l| l == target),
+            Self::Id(i) => i == id,
+            Self::And(a, b) => a.matches(id, meta, root_dir) && b.matches(id, meta, root_dir),
+            Self::Or(a, b) => a.matches(id, meta, root_dir) || b.matches(id, meta, root_dir),
+            Self::Not(q) => !q.matches(id, meta, root_dir),
+        }
+    }
+
+    fn and(self, other: Self) -> Self {
+        match self {
+            Self::All => other,
+            q => Self::And(Box::new(q), Box::new(other)),
+        }
+    }
+}
+
+/// parse a query; an empty query matches every zettel
+///
+/// A query is a sequence of terms combined with `AND`, `OR`, `NOT` and
+/// parentheses; terms next to each other are combined with `AND`.
+///
+/// - `tag:foo` has tag `foo`
+/// - `title:word` title contains `word`, ignoring case
+/// - `title:~regex` title matches a regular expression
+/// - `created:>2023-01-01` and `modified:<=2023-01-01`, with `>`, `>=`,
+///   `<`, `<=` or no operator for the exact day; dates can also be months,
+///   quarters or relative like `created:-7d` or `created:"last week"`, see
+///   [`dates::parse_range`]
+/// - `path:2022/**` path relative to the root directory matches a glob, or
+///   is inside a directory when there are no wildcards
+/// - `links-to:<id>` links to a zettel with `[[id]]`
+/// - `id:<id>`
+/// - any other word must be contained in the title
+///
+/// Values with spaces or parentheses can be quoted: `title:"two words"`,
+/// and so can words that would be operators: `"AND"`. Syntax errors give
+/// the column they are at.
+pub fn parse(s: &str) -> Result<Query> {
+    parse_at(s, chrono::Local::today().naive_local())
+}
+
+/// parse a query with relative dates counted from `today`
+pub fn parse_at(s: &str, today: NaiveDate) -> Result<Query> {
+    let tokens = tokenize(s)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        today,
+    };
+    if parser.tokens.is_empty() {
+        return Ok(Query::All);
+    }
+    let query = parser.or()?;
+    match parser.tokens.get(parser.pos) {
+        Some(token) => Err(token.unexpected()),
+        None => Ok(query),
+    }
+}
+
+/// A word of a query, with quotes removed, or a parenthesis
+#[derive(Debug, PartialEq)]
+struct Token {
+    text: String,
+    /// column of its first character, counting from 1
+    column: usize,
+    /// whether any of it was quoted, so `"AND"` is a word
+    quoted: bool,
+}
+
+impl Token {
+    /// the operator or parenthesis this token is
+    fn operator(&self) -> Option<&str> {
+        match self.text.as_str() {
+            "(" | ")" | "AND" | "OR" | "NOT" if !self.quoted => Some(&self.text),
+            _ => None,
+        }
+    }
+
+    fn unexpected(&self) -> Error {
+        Error::SyntaxError(format!(
+            "unexpected {} at column {}",
+            self.text, self.column
+        ))
+    }
+}
+
+/// the words and parentheses of a query
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().zip(1..).peekable();
+    while let Some(&(c, column)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            tokens.push(Token {
+                text: c.to_string(),
+                column,
+                quoted: false,
+            });
+            chars.next();
+        } else {
+            let mut token = Token {
+                text: String::new(),
+                column,
+                quoted: false,
+            };
+            while let Some(&(c, quote_column)) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' {
+                    break;
+                }
+                chars.next();
+                if c == '"' {
+                    token.quoted = true;
+                    loop {
+                        match chars.next() {
+                            Some(('"', _)) => break,
+                            Some((c, _)) => token.text.push(c),
+                            None => {
+                                let message = format!("unclosed quote at column {}", quote_column);
+                                return Err(Error::SyntaxError(message));
+                            }
+                        }
+                    }
+                } else {
+                    token.text.push(c);
+                }
+            }
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    today: NaiveDate,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).and_then(Token::operator)
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos == self.tokens.len()
+    }
+
+    fn or(&mut self) -> Result<Query> {
+        let mut query = self.and()?;
+        while self.peek() == Some("OR") {
+            self.pos += 1;
+            query = Query::Or(Box::new(query), Box::new(self.and()?));
+        }
+        Ok(query)
+    }
+
+    fn and(&mut self) -> Result<Query> {
+        let mut query = self.not()?;
+        loop {
+            match self.peek() {
+                _ if self.at_end() => return Ok(query),
+                Some("AND") => self.pos += 1,
+                Some("OR") | Some(")") => return Ok(query),
+                _ => (),
+            }
+            query = Query::And(Box::new(query), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Query> {
+        if self.peek() == Some("NOT") {
+            self.pos += 1;
+            return Ok(Query::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Query> {
+        let token = match self.tokens.get(self.pos) {
+            Some(token) => token,
+            None => return Err(Error::SyntaxError("unexpected end of query".to_owned())),
+        };
+        self.pos += 1;
+        match token.operator() {
+            Some("(") => {
+                let column = token.column;
+                let query = self.or()?;
+                if self.peek() != Some(")") {
+                    let message = format!("missing ) for ( at column {}", column);
+                    return Err(Error::SyntaxError(message));
+                }
+                self.pos += 1;
+                Ok(query)
+            }
+            Some(")" | "AND" | "OR") => Err(token.unexpected()),
+            _ => term(&token.text, self.today).map_err(|e| match e {
+                Error::SyntaxError(message) => {
+                    Error::SyntaxError(format!("{} at column {}", message, token.column))
+                }
+                e => e,
+            }),
+        }
+    }
+}
+
+fn term(token: &str, today: NaiveDate) -> Result<Query> {
+    let (field, value) = match token.split_once(':') {
+        Some(pair) => pair,
+        None => return Ok(Query::TitleContains(token.to_lowercase())),
+    };
+    Ok(match field {
+        "tag" => Query::Tag(value.to_owned()),
+        "title" => match value.strip_prefix('~') {
+            Some(re) => Query::TitleMatches(regex::Regex::new(re)?),
+            None => Query::TitleContains(value.to_lowercase()),
+        },
+        "created" => {
+            let (op, date) = date_comparison(value, today)?;
+            Query::Created(op, date)
+        }
+        "modified" => {
+            let (op, date) = date_comparison(value, today)?;
+            Query::Modified(op, date)
+        }
+        "path" => Query::Path(value.to_owned()),
+        "links-to" => Query::LinksTo(value.to_owned()),
+        "id" => Query::Id(value.to_owned()),
+        _ => return Err(Error::SyntaxError(format!("unknown field {}", field))),
+    })
+}
+
+fn date_comparison(value: &str, today: NaiveDate) -> Result<(DateOp, DateRange)> {
+    let (op, date) = [
+        (">=", DateOp::AfterOrOn),
+        ("<=", DateOp::BeforeOrOn),
+        (">", DateOp::After),
+        ("<", DateOp::Before),
+        ("=", DateOp::On),
+    ]
+    .into_iter()
+    .find_map(|(prefix, op)| value.strip_prefix(prefix).map(|date| (op, date)))
+    .unwrap_or((DateOp::On, value));
+    let date =
+        dates::parse_range(date, today).ok_or_else(|| Error::InvalidDate(date.to_owned()))?;
+    Ok((op, date))
+}
+
+/// match a path against a glob where `*` and `?` stay within a directory
+/// and `**` crosses directories
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    glob_match_bytes(pattern.as_bytes(), path.as_bytes())
+}
+
+fn glob_match_bytes(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            glob_match_bytes(rest, path)
+                || (0..path.len())
+                    .any(|i| path[i] == b'/' && glob_match_bytes(rest, &path[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| glob_match_bytes(rest, &path[i..])),
+        [b'*', rest @ ..] => (0..=path.len())
+            .take_while(|i| *i == 0 || path[i - 1] != b'/')
+            .any(|i| glob_match_bytes(rest, &path[i..])),
+        [b'?', rest @ ..] => {
+            !path.is_empty() && path[0] != b'/' && glob_match_bytes(rest, &path[1..])
+        }
+        [c, rest @ ..] => path.first() == Some(c) && glob_match_bytes(rest, &path[1..]),
+    }
+}
+
+/// Command line options selecting zettels; all given options must match
+#[derive(Debug, Default, clap::Args)]
+pub struct Filter {
+    /// Only zettels matching this query, e.g. `tag:a AND NOT path:archive/**`
+    #[clap(long, short)]
+    pub query: Option<String>,
+    /// Only zettels with this tag
+    #[clap(long)]
+    pub tag: Option<String>,
+    /// Only zettels under this directory, relative to the root directory
+    #[clap(long)]
+    pub subdir: Option<PathBuf>,
+    /// Only zettels created on or after this date, e.g. 2023-05-14,
+    /// 2023-Q2, "last month" or -7d
+    #[clap(long, allow_hyphen_values = true)]
+    pub since: Option<String>,
+    /// Only zettels created on or before this date
+    #[clap(long, allow_hyphen_values = true)]
+    pub until: Option<String>,
+}
+
+impl Filter {
+    pub fn to_query(&self) -> Result<Query> {
+        let today = chrono::Local::today().naive_local();
+        let mut query = match &self.query {
+            Some(q) => parse(q)?,
+            None => Query::All,
+        };
+        let date = |s: &String| -> Result<DateRange> {
+            dates::parse_range(s, today).ok_or_else(|| Error::InvalidDate(s.clone()))
+        };
+        if let Some(tag) = &self.tag {
+            query = query.and(Query::Tag(tag.clone()));
+        }
+        if let Some(subdir) = &self.subdir {
+            query = query.and(Query::Path(subdir.to_string_lossy().into_owned()));
+        }
+        if let Some(since) = &self.since {
+            query = query.and(Query::Created(DateOp::AfterOrOn, date(since)?));
+        }
+        if let Some(until) = &self.until {
+            query = query.and(Query::Created(DateOp::BeforeOrOn, date(until)?));
+        }
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::prelude::*;
+
+    #[test]
+    fn parse_and_match() -> Result<()> {
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut meta = ZettelMeta::new("1", "Rust Ownership", "2015/notes/one.md", dt);
+        meta.tags = vec!["a".to_owned()];
+        meta.links = vec!["2".to_owned()];
+        let root = Path::new("/root");
+        let matches = |q: &str| -> Result<bool> { Ok(parse(q)?.matches("1", &meta, root)) };
+        assert!(matches("")?);
+        assert!(matches("tag:a")?);
+        assert!(matches("tag:b OR rust")?);
+        assert!(!matches("tag:a NOT title:~^Rust")?);
+        assert!(matches("title:\"rust own\" links-to:2")?);
+        assert!(matches("created:>=2015-05-14 AND created:<2015-05-15")?);
+        assert!(!matches("created:>2015-05-14")?);
+        assert!(matches("created:2015-Q2 created:<2015-06")?);
+        let today = NaiveDate::from_ymd(2015, 5, 20);
+        assert!(parse_at("created:\"last week\"", today)?.matches("1", &meta, root));
+        assert!(!parse_at("created:>-3d", today)?.matches("1", &meta, root));
+        assert!(matches("path:2015/** path:2015 path:*/notes/*.md")?);
+        assert!(!matches("path:*.md")?);
+        assert!(matches("(tag:b OR id:1) AND NOT (tag:c)")?);
+        assert!(parse("tag:a AND").is_err());
+        assert!(parse("(tag:a").is_err());
+        assert!(parse("nope:x").is_err());
+        let filter = Filter {
+            query: Some("rust".to_owned()),
+            subdir: Some("./2015".into()),
+            since: Some("2015-05-15".to_owned()),
+            ..Default::default()
+        };
+        assert!(!filter.to_query()?.matches("1", &meta, root));
+        Ok(())
+    }
+
+    fn meta() -> ZettelMeta {
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut meta = ZettelMeta::new("1", "Rust Ownership", "2015/notes/one.md", dt);
+        meta.modified = chrono::Local.ymd(2015, 6, 1).and_hms(9, 0, 0);
+        meta.tags = vec!["a".to_owned()];
+        meta.links = vec!["2".to_owned()];
+        meta
+    }
+
+    fn matches(q: &str) -> bool {
+        parse(q).unwrap().matches("1", &meta(), Path::new("/root"))
+    }
+
+    fn syntax_error(q: &str) -> String {
+        match parse(q) {
+            Err(e @ Error::SyntaxError(_)) => e.to_string(),
+            other => panic!("parsed {:?} as {:?}", q, other),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        // NOT binds tighter than AND, and AND tighter than OR
+        assert!(matches("tag:b tag:c OR tag:a"));
+        assert!(matches("tag:a OR tag:b tag:c"));
+        assert!(matches("tag:a OR tag:b AND tag:c"));
+        assert!(!matches("(tag:a OR tag:b) AND tag:c"));
+        assert!(matches("NOT tag:a OR tag:a"));
+        assert!(!matches("NOT (tag:a OR tag:a)"));
+        assert!(matches("NOT tag:b tag:a"));
+        assert!(matches("NOT NOT tag:a"));
+        assert!(matches("((tag:a))"));
+    }
+
+    #[test]
+    fn quoting() {
+        assert!(matches("title:\"rust own\""));
+        assert!(matches("\"own\"ership"));
+        assert!(matches("tag:\"a\""));
+        assert!(!matches("\"rust ownership\" \"NOT\""));
+        assert!(matches("\"rust ownership\""));
+        assert!(!matches("\"AND\""));
+        assert!(matches("\"(\"rust OR tag:a"));
+        assert!(!matches("\"(\""));
+    }
+
+    #[test]
+    fn error_positions() {
+        let error = |q| {
+            syntax_error(q)
+                .trim_start_matches("invalid query: ")
+                .to_owned()
+        };
+        assert_eq!(error("tag:a )"), "unexpected ) at column 7");
+        assert_eq!(error("AND tag:a"), "unexpected AND at column 1");
+        assert_eq!(error("tag:a OR OR tag:b"), "unexpected OR at column 10");
+        assert_eq!(error("tag:a AND"), "unexpected end of query");
+        assert_eq!(error("NOT"), "unexpected end of query");
+        assert_eq!(error("(tag:a OR (tag:b)"), "missing ) for ( at column 1");
+        assert_eq!(error("tag:a nope:x"), "unknown field nope at column 7");
+        assert_eq!(error("tag:a title:\"x"), "unclosed quote at column 13");
+        assert_eq!(error("ü )"), "unexpected ) at column 3");
+        assert!(matches!(parse("created:soon"), Err(Error::InvalidDate(d)) if d == "soon"));
+        assert!(matches!(parse("title:~\"[\""), Err(Error::RegexError(_))));
+    }
+
+    #[test]
+    fn filters() {
+        assert!(matches("tag:a"));
+        assert!(!matches("tag:A"));
+        assert!(matches("title:OWNER"));
+        assert!(matches("ownership"));
+        assert!(!matches("title:borrow"));
+        assert!(matches("title:~Own.+p$"));
+        assert!(!matches("title:~^own"));
+        assert!(matches("created:2015-05-14"));
+        assert!(matches("created:=2015-05"));
+        assert!(matches("created:<2015-05-15"));
+        assert!(!matches("created:<2015-05-14"));
+        assert!(matches("created:<=2015-05-14"));
+        assert!(matches("created:>=2015-05-14"));
+        assert!(!matches("created:>2015-05-14"));
+        assert!(matches("created:>2015-04"));
+        assert!(matches("modified:2015-06-01"));
+        assert!(matches("modified:>2015-05"));
+        assert!(!matches("modified:<=2015-05"));
+        let today = NaiveDate::from_ymd(2015, 6, 3);
+        let (root, meta) = (Path::new("/root"), meta());
+        let relative = |q: &str| parse_at(q, today).unwrap().matches("1", &meta, root);
+        assert!(relative("modified:>=-7d"));
+        assert!(!relative("created:>=-7d"));
+        assert!(relative("created:\"last month\""));
+        assert!(matches("path:2015"));
+        assert!(matches("path:2015/notes/"));
+        assert!(!matches("path:2015/no"));
+        assert!(matches("path:**/one.md"));
+        assert!(matches("path:2015/*/one.md"));
+        assert!(!matches("path:2015/*.md"));
+        assert!(matches("path:2015/notes/on?.md"));
+        assert!(matches("links-to:2"));
+        assert!(!matches("links-to:1"));
+        assert!(matches("id:1"));
+        assert!(!matches("id:2"));
+    }
+
+    #[test]
+    fn filter_options() -> Result<()> {
+        let (root, meta) = (Path::new("/root"), meta());
+        let matches =
+            |filter: Filter| -> Result<bool> { Ok(filter.to_query()?.matches("1", &meta, root)) };
+        assert!(matches(Filter::default())?);
+        let tag = |tag: &str| Filter {
+            tag: Some(tag.to_owned()),
+            ..Default::default()
+        };
+        assert!(matches(tag("a"))?);
+        assert!(!matches(tag("b"))?);
+        let subdir = |dir: &str| Filter {
+            subdir: Some(dir.into()),
+            ..Default::default()
+        };
+        assert!(matches(subdir("2015/notes"))?);
+        assert!(!matches(subdir("2016"))?);
+        let dates = |since: Option<&str>, until: Option<&str>| Filter {
+            since: since.map(str::to_owned),
+            until: until.map(str::to_owned),
+            ..Default::default()
+        };
+        assert!(matches(dates(Some("2015-05-14"), Some("2015-05-14")))?);
+        assert!(matches(dates(Some("2015-05"), None))?);
+        assert!(!matches(dates(None, Some("2015-05-13")))?);
+        assert!(!matches(dates(Some("2015-Q3"), None))?);
+        assert!(matches!(
+            dates(Some("soon"), None).to_query(),
+            Err(Error::InvalidDate(_))
+        ));
+        let both = Filter {
+            query: Some("tag:a".to_owned()),
+            tag: Some("b".to_owned()),
+            ..Default::default()
+        };
+        assert!(!matches(both)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use crate::{bibtex, frontmatter, zettel, zettelkasten::Zettelkasten};
+use serde_yaml::Mapping;
+use std::path::Path;
+
+/// tag of the zettels on the reading list
+pub const TAG: &str = "reading";
+
+/// bibliography fields copied into a finished item, like `zk cite`
+const FIELDS: [&str; 7] = [
+    "author",
+    "editor",
+    "year",
+    "publisher",
+    "journal",
+    "doi",
+    "url",
+];
+
+/// body given to a finished item that has none of its own
+const TEMPLATE: &str = "## Summary\n\n## Key ideas\n\n## Quotes\n";
+
+/// Where an item on the reading list is, from its `status` frontmatter key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, clap::ValueEnum)]
+pub enum Status {
+    Unread,
+    Reading,
+    Done,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Unread => "unread",
+            Self::Reading => "reading",
+            Self::Done => "done",
+        }
+    }
+
+    /// status of an item, unread unless set to something zk knows
+    pub fn of(fm: &Mapping) -> Self {
+        match fm.get(&"status".into()).and_then(|s| s.as_str()) {
+            Some("reading") => Self::Reading,
+            Some("done") => Self::Done,
+            _ => Self::Unread,
+        }
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An item on the reading list
+#[derive(Debug, PartialEq)]
+pub struct Item {
+    pub id: zettel::Id,
+    pub title: String,
+    pub status: Status,
+    /// url or citation key of what is to be read
+    pub source: Option<String>,
+}
+
+/// whether `source` is a url rather than a citation key
+pub fn is_url(source: &str) -> bool {
+    source.contains("://")
+}
+
+/// zettels tagged [`TAG`], oldest first
+pub fn items(zk: &Zettelkasten, root_dir: &Path) -> Result<Vec<Item>, frontmatter::Error> {
+    let mut tagged: Vec<_> = zk
+        .zettels
+        .iter()
+        .filter(|(_, meta)| meta.tags.iter().any(|t| t == TAG))
+        .collect();
+    tagged.sort_by_key(|(id, meta)| (meta.created, *id));
+    let mut items = Vec::new();
+    for (id, meta) in tagged {
+        let (fm, _) = frontmatter::parse_yaml_path(meta.full_path(root_dir))?;
+        items.push(Item {
+            id: id.clone(),
+            title: meta.title.clone(),
+            status: Status::of(&fm),
+            source: fm
+                .get(&"source".into())
+                .and_then(|s| s.as_str())
+                .map(str::to_owned),
+        });
+    }
+    Ok(items)
+}
+
+/// turn a finished item into a literature note: mark it done, cite its
+/// reference with the fields of `entry` its frontmatter doesn't have yet,
+/// and give it a template to take notes in if its body is empty
+///
+/// returns the new body
+pub fn finish(fm: &mut Mapping, body: &str, entry: Option<&bibtex::Entry>) -> String {
+    fm.insert("status".into(), Status::Done.as_str().into());
+    if let Some(entry) = entry {
+        fm.insert("cite".into(), entry.key.as_str().into());
+        for field in FIELDS {
+            if let Some(value) = entry.field(field) {
+                if !fm.contains_key(&field.into()) {
+                    fm.insert(field.into(), value.into());
+                }
+            }
+        }
+    }
+    let source = fm.get(&"source".into()).and_then(|s| s.as_str());
+    if let Some(url) = source.filter(|s| is_url(s)).map(str::to_owned) {
+        if !fm.contains_key(&"url".into()) {
+            fm.insert("url".into(), url.into());
+        }
+    }
+    if body.trim().is_empty() {
+        TEMPLATE.to_owned()
+    } else {
+        body.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn list_and_finish() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_reading_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for (n, (id, tags, status, source)) in [
+            ("b", "reading", "reading", "knuth84"),
+            ("a", "reading", "unread", "https://example.com/post"),
+            ("c", "other", "done", "x"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let date = dt + chrono::Duration::hours(n as i64);
+            let mut zettel = db.new_zettel(&zk.config, id, id, date)?;
+            for (key, value) in [("tags", tags), ("status", status), ("source", source)] {
+                zettel
+                    .extra_frontmatter
+                    .insert(key.to_owned(), value.to_owned());
+            }
+            zettel.meta.tags = vec![tags.to_owned()];
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        let items = items(&zk, tmp_dir.path())?;
+        assert_eq!(
+            items
+                .iter()
+                .map(|item| (item.id.as_str(), item.status))
+                .collect::<Vec<_>>(),
+            [("b", Status::Reading), ("a", Status::Unread)]
+        );
+        assert_eq!(items[1].source.as_deref(), Some("https://example.com/post"));
+
+        let entry = &bibtex::parse("@book{knuth84, title={Literate Programming}, year={1984}}")?[0];
+        let mut fm: Mapping = serde_yaml::from_str("{source: knuth84, year: 1992}")?;
+        assert_eq!(finish(&mut fm, "\n", Some(entry)), TEMPLATE);
+        assert_eq!(Status::of(&fm), Status::Done);
+        assert_eq!(fm[&"cite".into()], "knuth84");
+        assert_eq!(fm[&"year".into()], 1992);
+        let mut fm: Mapping = serde_yaml::from_str("{source: 'https://example.com/post'}")?;
+        assert_eq!(finish(&mut fm, "notes", None), "notes");
+        assert_eq!(fm[&"url".into()], "https://example.com/post");
+        Ok(())
+    }
+}
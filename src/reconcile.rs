@@ -0,0 +1,111 @@
+use crate::ZettelMeta;
+use serde::{Deserialize, Serialize};
+use serde_yaml::{Mapping, Value};
+use std::collections::HashMap;
+
+/// frontmatter keys that mirror fields of the metadata
+const MIRRORED_KEYS: [&str; 4] = ["title", "cite", "follows", "tags"];
+
+/// How `sync` settles fields that differ between frontmatter and database
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Policy {
+    /// take the value from the frontmatter
+    #[default]
+    FileWins,
+    /// write the value from the database back into the frontmatter
+    DbWins,
+    /// take the value from whichever was modified last
+    NewestWins,
+    /// ask for every field
+    Prompt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    File,
+    Database,
+}
+
+impl Policy {
+    /// side that wins under this policy, `None` if the user has to be asked
+    pub fn winner(self, file_is_newer: bool) -> Option<Side> {
+        match self {
+            Self::FileWins => Some(Side::File),
+            Self::DbWins => Some(Side::Database),
+            Self::NewestWins if file_is_newer => Some(Side::File),
+            Self::NewestWins => Some(Side::Database),
+            Self::Prompt => None,
+        }
+    }
+}
+
+/// A mirrored field whose frontmatter and database values differ; `None`
+/// when a side doesn't set it
+#[derive(Debug, PartialEq)]
+pub struct Conflict {
+    pub key: &'static str,
+    pub file: Option<Value>,
+    pub database: Option<Value>,
+}
+
+impl Conflict {
+    /// make the frontmatter agree with the database
+    pub fn keep_database(&self, fm: &mut Mapping) {
+        match &self.database {
+            Some(value) => fm.insert(self.key.into(), value.clone()),
+            None => fm.remove(&self.key.into()),
+        };
+    }
+}
+
+/// mirrored fields of `meta` that `fm` disagrees with
+pub fn conflicts(meta: &ZettelMeta, fm: &Mapping) -> Vec<Conflict> {
+    let mut expected = Mapping::new();
+    // no templates, so only the mirrored keys are written and ids don't matter
+    meta.update_frontmatter(
+        &mut expected,
+        &HashMap::new(),
+        Default::default(),
+        Default::default(),
+    )
+    .expect("no templates to fill in");
+    MIRRORED_KEYS
+        .into_iter()
+        // a file without a title keeps the one in the database
+        .filter(|key| *key != "title" || fm.contains_key(&(*key).into()))
+        .filter_map(|key| {
+            let file = fm.get(&key.into()).cloned();
+            let database = expected.get(&key.into()).cloned();
+            (file != database).then_some(Conflict {
+                key,
+                file,
+                database,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::prelude::*;
+
+    #[test]
+    fn conflicting_fields() -> Result<(), serde_yaml::Error> {
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut meta = ZettelMeta::new("a", "Title", "a.md", dt);
+        meta.tags = vec!["x".to_owned()];
+        let mut fm: Mapping = serde_yaml::from_str("title: Title\ntags: [y]\ncite: knuth")?;
+        let found = conflicts(&meta, &fm);
+        let keys: Vec<&str> = found.iter().map(|c| c.key).collect();
+        assert_eq!(keys, ["cite", "tags"]);
+        for conflict in &found {
+            conflict.keep_database(&mut fm);
+        }
+        assert!(conflicts(&meta, &fm).is_empty());
+        assert_eq!(fm.get(&"cite".into()), None);
+        assert_eq!(Policy::NewestWins.winner(false), Some(Side::Database));
+        Ok(())
+    }
+}
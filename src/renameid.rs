@@ -0,0 +1,273 @@
+use crate::{frontmatter, link, zettel, zettelkasten, zettelkasten::Zettelkasten, DateTime};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    FrontmatterError(frontmatter::Error),
+    ZettelkastenError(zettelkasten::Error),
+    UnknownZettel(zettel::Id),
+    Taken(zettel::Id),
+    InvalidId(String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl From<zettelkasten::Error> for Error {
+    fn from(e: zettelkasten::Error) -> Self {
+        Self::ZettelkastenError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::ZettelkastenError(e) => e.fmt(f),
+            Self::UnknownZettel(id) => write!(f, "no zettel with id {}", id),
+            Self::Taken(id) => write!(f, "id {} is taken by another zettel", id),
+            Self::InvalidId(id) => write!(
+                f,
+                "{:?} can't be an id, it would break links to the zettel",
+                id
+            ),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Summary of an id change
+#[derive(Debug, PartialEq)]
+pub struct Renamed {
+    /// zettels whose links, `follows` or `cites` pointed at the old id
+    pub relinked: Vec<zettel::Id>,
+    /// where the zettel's file went, if its name held the old id
+    pub moved: Option<PathBuf>,
+}
+
+/// give zettel `old` the id `new`, in its frontmatter and the database, and
+/// point wikilinks, `follows` and `cites` of other zettels at it
+///
+/// zettels referring to `old` are found with the link index, so the
+/// zettelkasten should be synced first. When `filename_id` is set and the
+/// file's name holds the old id it is renamed, and relative markdown links
+/// to it follow. Files are changed in one transaction, so if one of them
+/// fails the others are put back too.
+pub fn rename_id(
+    zk: &mut Zettelkasten,
+    root_dir: &Path,
+    old: &str,
+    new: &str,
+    now: DateTime,
+) -> Result<Renamed> {
+    let breaks_links = |c: char| c.is_whitespace() || "[]|#/\\:".contains(c);
+    if new.is_empty() || new.contains(breaks_links) {
+        return Err(Error::InvalidId(new.to_owned()));
+    }
+    if zk.zettels.contains_key(new) {
+        return Err(Error::Taken(new.to_owned()));
+    }
+    let meta = zk
+        .zettels
+        .get(old)
+        .ok_or_else(|| Error::UnknownZettel(old.to_owned()))?;
+    let path = meta.full_path(root_dir);
+    let target = match zk.config.id_in_file_name(&path, |id| id == old) {
+        Some(_) => {
+            let name = path.file_name().unwrap().to_string_lossy();
+            Some(path.with_file_name(name.replacen(old, new, 1)))
+        }
+        None => None,
+    };
+    let moved: HashMap<PathBuf, PathBuf> = target
+        .iter()
+        .map(|to| (link::normalize(&path), link::normalize(to)))
+        .collect();
+    let aliases = zk.config.key_aliases.clone();
+    let (mut fm, body) = frontmatter::parse_yaml_path(&path)?;
+    frontmatter::unalias(&mut fm, &aliases);
+    fm.insert("id".into(), new.into());
+    let to = target.as_deref().unwrap_or(&path);
+    let (body, _) = link::rebase(&body, &path, to, &moved);
+    let body = link::rewrite_wikilinks(&body, old, new);
+    let contents = frontmatter::write_for(to, &frontmatter::aliased(&fm, &aliases), &body)?;
+    let mut others: Vec<&zettel::Id> = zk.zettels.keys().filter(|id| *id != old).collect();
+    others.sort();
+    let mut relinked = Vec::new();
+    let mut relinked_files = Vec::new();
+    for id in others {
+        let meta = &zk.zettels[id];
+        let other_path = meta.full_path(root_dir);
+        let wikilinked = meta.links.iter().any(|l| l == old);
+        let in_frontmatter =
+            meta.follows.as_deref() == Some(old) || meta.cites.iter().any(|c| c == old);
+        let text = match std::fs::read_to_string(&other_path) {
+            Ok(text) => text,
+            Err(e) if wikilinked || in_frontmatter => return Err(e.into()),
+            Err(_) => continue,
+        };
+        let text = match in_frontmatter {
+            true => {
+                let (mut fm, body) = frontmatter::parse_yaml_path(&other_path)?;
+                frontmatter::unalias(&mut fm, &aliases);
+                retarget(&mut fm, old, new);
+                frontmatter::write_for(&other_path, &frontmatter::aliased(&fm, &aliases), &body)?
+            }
+            false => text,
+        };
+        let text = match wikilinked {
+            true => link::rewrite_wikilinks(&text, old, new),
+            false => text,
+        };
+        let (text, links) = link::rebase(&text, &other_path, &other_path, &moved);
+        if wikilinked || in_frontmatter || !links.is_empty() {
+            relinked.push(id.clone());
+            relinked_files.push((other_path, text));
+        }
+    }
+    zk.transaction(|tx| {
+        tx.write(&path, contents);
+        if let Some(target) = &target {
+            tx.rename(&path, target);
+        }
+        for (path, text) in relinked_files {
+            tx.write(path, text);
+        }
+        let mut meta = tx.zettels.remove(old).unwrap();
+        meta.id = new.to_owned();
+        if let Some(target) = &target {
+            let name = target.file_name().unwrap().to_string_lossy();
+            let path = Path::new(&meta.path).with_file_name(name.as_ref());
+            meta.path = path.to_string_lossy().replace('\\', "/");
+        }
+        meta.update_from_body(&body);
+        meta.modified = now;
+        tx.zettels.insert(new.to_owned(), meta);
+        for id in &relinked {
+            let meta = tx.zettels.get_mut(id).unwrap();
+            for link in meta.links.iter_mut().filter(|l| *l == old) {
+                *link = new.to_owned();
+            }
+            meta.links.sort();
+            meta.links.dedup();
+            if meta.follows.as_deref() == Some(old) {
+                meta.follows = Some(new.to_owned());
+            }
+            for cite in meta.cites.iter_mut().filter(|c| *c == old) {
+                *cite = new.to_owned();
+            }
+            meta.modified = now;
+        }
+        Ok::<_, Error>(())
+    })?;
+    for id in zk.config.autolink.values_mut().filter(|id| *id == old) {
+        *id = new.to_owned();
+    }
+    Ok(Renamed {
+        relinked,
+        moved: target,
+    })
+}
+
+/// point `follows` and `cites` entries naming `old` at `new`
+fn retarget(fm: &mut serde_yaml::Mapping, old: &str, new: &str) {
+    if fm.get(&"follows".into()).and_then(|f| f.as_str()) == Some(old) {
+        fm.insert("follows".into(), new.into());
+    }
+    let cites = zettel::cites(fm);
+    if cites.iter().any(|c| c == old) {
+        let cites: Vec<String> = cites
+            .into_iter()
+            .map(|c| if c == old { new.to_owned() } else { c })
+            .collect();
+        fm.insert("cites".into(), cites.into());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        config::FilenameId,
+        database::{memory::Database, Database as _},
+    };
+    use chrono::prelude::*;
+
+    #[test]
+    fn rename_and_relink() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_renameid_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        zk.config.filename_id = Some(FilenameId::Prefix);
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        for (id, body) in [
+            ("old", "Old body, see [[c]]"),
+            ("b", "B links [[old|the old one]]"),
+            ("c", "C"),
+        ] {
+            let mut zettel = db.new_zettel(&zk.config, id, id, dt)?;
+            zettel.content = body.to_owned();
+            zettel.meta.update_from_body(body);
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        let old_file = Path::new(&zk.zettels["old"].path).file_name().unwrap();
+        let old_file = old_file.to_str().unwrap().to_owned();
+        let mut d = db.new_zettel(&zk.config, "d", "d", dt)?;
+        d.content = format!("D reads [old]({})", old_file);
+        for (key, value) in [("follows", "old"), ("cites", "key, old")] {
+            d.extra_frontmatter.insert(key.to_owned(), value.to_owned());
+        }
+        d.meta.follows = Some("old".to_owned());
+        d.meta.cites = vec!["key".to_owned(), "old".to_owned()];
+        zk.add(db.root_dir(), &d)?;
+        zk.config
+            .autolink
+            .insert("Old".to_owned(), "old".to_owned());
+        assert!(matches!(
+            rename_id(&mut zk, db.root_dir(), "old", "c", dt),
+            Err(Error::Taken(_))
+        ));
+        assert!(matches!(
+            rename_id(&mut zk, db.root_dir(), "old", "a b", dt),
+            Err(Error::InvalidId(_))
+        ));
+        let renamed = rename_id(&mut zk, db.root_dir(), "old", "new", dt)?;
+        assert_eq!(renamed.relinked, vec!["b".to_owned(), "d".to_owned()]);
+        assert!(!zk.zettels.contains_key("old"));
+        let meta = &zk.zettels["new"];
+        assert_eq!(meta.path, "new-old.md");
+        let path = meta.full_path(db.root_dir());
+        assert_eq!(renamed.moved.as_deref(), Some(path.as_path()));
+        let (fm, body) = frontmatter::parse_yaml_path(&path)?;
+        assert_eq!(fm.get(&"id".into()), Some(&"new".into()));
+        assert_eq!(body, "Old body, see [[c]]\n");
+        assert_eq!(zk.zettels["b"].links, vec!["new".to_owned()]);
+        let text = std::fs::read_to_string(zk.zettels["b"].full_path(db.root_dir()))?;
+        assert!(text.contains("B links [[new|the old one]]"));
+        let d = &zk.zettels["d"];
+        assert_eq!(d.follows.as_deref(), Some("new"));
+        assert_eq!(d.cites, vec!["key".to_owned(), "new".to_owned()]);
+        let (fm, body) = frontmatter::parse_yaml_path(d.full_path(db.root_dir()))?;
+        assert_eq!(fm.get(&"follows".into()), Some(&"new".into()));
+        assert_eq!(body, "D reads [old](new-old.md)\n");
+        assert_eq!(zk.config.autolink["Old"], "new");
+        Ok(())
+    }
+}
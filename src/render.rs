@@ -0,0 +1,298 @@
+use crate::{link, zettelkasten::Zettelkasten};
+use pulldown_cmark::{CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+/// url scheme used for links between zettels once wikilinks are resolved
+pub const ZETTEL_SCHEME: &str = "zk:";
+
+/// replace `[[id]]` links with markdown links labelled with the zettel's title
+///
+/// links to unknown ids and embeds are left untouched
+pub fn resolve_wikilinks(body: &str, zk: &Zettelkasten) -> String {
+    let links = link::wikilinks(body);
+    let mut out = String::new();
+    for (n, line) in body.lines().enumerate() {
+        let mut last = 0;
+        for link in links.iter().filter(|l| l.line == n + 1 && !l.embed) {
+            let meta = match zk.zettels.get(&link.target) {
+                Some(meta) => meta,
+                None => continue,
+            };
+            let label = link.label.as_deref().unwrap_or(&meta.title);
+            let label = label.replace('[', "\\[").replace(']', "\\]");
+            out.push_str(&line[last..link.span.start]);
+            let anchor = match &link.anchor {
+                Some(anchor) => format!("#{}", link::slug(anchor)),
+                None => String::new(),
+            };
+            out.push_str(&format!(
+                "[{}]({}{}{})",
+                label, ZETTEL_SCHEME, link.target, anchor
+            ));
+            last = link.span.end;
+        }
+        out.push_str(&line[last..]);
+        out.push('\n');
+    }
+    out
+}
+
+/// Renders markdown for display in a terminal
+pub struct Renderer {
+    /// use ANSI escape codes; without them structure is kept with plain text markers
+    pub color: bool,
+}
+
+impl Renderer {
+    /// color is enabled when stdout is a terminal and `NO_COLOR` is unset
+    pub fn for_stdout() -> Self {
+        use std::io::IsTerminal;
+        Self {
+            color: std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        }
+    }
+
+    pub fn render(&self, markdown: &str) -> String {
+        let mut w = Writer {
+            out: String::new(),
+            color: self.color,
+            styles: Vec::new(),
+            prefixes: Vec::new(),
+            at_line_start: true,
+            lists: Vec::new(),
+            links: Vec::new(),
+        };
+        let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+        for event in Parser::new_ext(markdown, options) {
+            w.event(event);
+        }
+        let mut out = w.out.trim_end().to_owned();
+        out.push('\n');
+        out
+    }
+}
+
+struct Writer {
+    out: String,
+    color: bool,
+    /// active SGR parameters, reapplied after a reset
+    styles: Vec<&'static str>,
+    /// written at the start of every line, e.g. for quotes and list items
+    prefixes: Vec<String>,
+    at_line_start: bool,
+    /// next number of each open list, `None` for bullet lists
+    lists: Vec<Option<u64>>,
+    /// destinations of open links
+    links: Vec<String>,
+}
+
+impl Writer {
+    fn text(&mut self, text: &str) {
+        for (i, chunk) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.out.push('\n');
+                self.at_line_start = true;
+            }
+            if chunk.is_empty() {
+                continue;
+            }
+            if self.at_line_start {
+                let prefix: String = self.prefixes.concat();
+                self.out.push_str(&prefix);
+                self.at_line_start = false;
+            }
+            self.out.push_str(chunk);
+        }
+    }
+
+    fn ensure_newline(&mut self) {
+        if !self.at_line_start {
+            self.text("\n");
+        }
+    }
+
+    fn end_block(&mut self) {
+        self.ensure_newline();
+        let prefix = self.prefixes.concat();
+        self.out.push_str(prefix.trim_end());
+        self.out.push('\n');
+    }
+
+    fn push_style(&mut self, style: &'static str) {
+        if self.color {
+            self.styles.push(style);
+            self.out.push_str(&format!("\x1b[{}m", style));
+        }
+    }
+
+    fn pop_style(&mut self) {
+        if self.color {
+            self.styles.pop();
+            self.out.push_str("\x1b[0m");
+            for style in &self.styles {
+                self.out.push_str(&format!("\x1b[{}m", style));
+            }
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        match event {
+            Event::Start(tag) => self.start(tag),
+            Event::End(tag) => self.end(tag),
+            Event::Text(text) => self.text(&text),
+            Event::Code(code) => {
+                if self.color {
+                    self.push_style("33");
+                    self.text(&code);
+                    self.pop_style();
+                } else {
+                    self.text(&format!("`{}`", code));
+                }
+            }
+            Event::Html(html) | Event::InlineHtml(html) => self.text(&html),
+            Event::SoftBreak | Event::HardBreak => self.text("\n"),
+            Event::Rule => {
+                self.ensure_newline();
+                self.text(&"─".repeat(40));
+                self.end_block();
+            }
+            Event::TaskListMarker(checked) => self.text(if checked { "[x] " } else { "[ ] " }),
+            _ => (),
+        }
+    }
+
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Heading { level, .. } => {
+                self.ensure_newline();
+                if self.color {
+                    self.push_style(if level == HeadingLevel::H1 {
+                        "1;4;36"
+                    } else {
+                        "1;36"
+                    });
+                } else {
+                    self.text(&format!("{} ", "#".repeat(level as usize)));
+                }
+            }
+            Tag::BlockQuote(_) => {
+                self.ensure_newline();
+                self.prefixes.push("│ ".to_owned());
+            }
+            Tag::CodeBlock(kind) => {
+                self.ensure_newline();
+                if let CodeBlockKind::Fenced(lang) = kind {
+                    if !lang.is_empty() && !self.color {
+                        self.text(&format!("    ({})\n", lang));
+                    }
+                }
+                self.prefixes.push("    ".to_owned());
+                self.push_style("33");
+            }
+            Tag::List(start) => {
+                self.ensure_newline();
+                self.lists.push(start);
+            }
+            Tag::Item => {
+                self.ensure_newline();
+                let bullet = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "• ".to_owned(),
+                };
+                self.text(&bullet);
+                self.prefixes.push(" ".repeat(bullet.chars().count()));
+            }
+            Tag::Emphasis => self.push_style("3"),
+            Tag::Strong => self.push_style("1"),
+            Tag::Strikethrough => self.push_style("9"),
+            Tag::Link { dest_url, .. } => {
+                self.links.push(dest_url.to_string());
+                self.push_style("4");
+            }
+            Tag::Image { .. } => self.text("[image: "),
+            _ => (),
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Paragraph => self.end_block(),
+            TagEnd::Heading(_) => {
+                self.pop_style();
+                self.end_block();
+            }
+            TagEnd::BlockQuote(_) => {
+                // replace the quoted blank line after the last block with an unquoted one
+                self.ensure_newline();
+                let quoted_blank = format!("{}\n", self.prefixes.concat().trim_end());
+                if self.out.ends_with(&quoted_blank) {
+                    self.out.truncate(self.out.len() - quoted_blank.len());
+                }
+                self.prefixes.pop();
+                self.end_block();
+            }
+            TagEnd::CodeBlock => {
+                self.pop_style();
+                self.prefixes.pop();
+                self.end_block();
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.end_block();
+                }
+            }
+            TagEnd::Item => {
+                self.ensure_newline();
+                self.prefixes.pop();
+            }
+            TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough => self.pop_style(),
+            TagEnd::Link => {
+                self.pop_style();
+                let url = self.links.pop().unwrap_or_default();
+                if !url.starts_with(ZETTEL_SCHEME) {
+                    self.push_style("2");
+                    self.text(&format!(" <{}>", url));
+                    self.pop_style();
+                }
+            }
+            TagEnd::Image => self.text("]"),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn render_plain() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_render_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        zk.add(
+            db.root_dir(),
+            db.new_zettel(&Default::default(), "Other Note", "other", dt)?,
+        )?;
+        let markdown = "# Title\n\nSee [[other]] and [[nope]], *really*.\n\n\
+                        - one\n- two\n  1. nested\n\n> quoted\n\n```rust\nlet x = 1;\n```\n";
+        let resolved = resolve_wikilinks(markdown, &zk);
+        assert!(resolved.contains("[Other Note](zk:other)"));
+        let plain = Renderer { color: false }.render(&resolved);
+        assert_eq!(
+            plain,
+            "# Title\n\nSee Other Note and [[nope]], really.\n\n\
+             • one\n• two\n  1. nested\n\n│ quoted\n\n    (rust)\n    let x = 1;\n"
+        );
+        let colored = Renderer { color: true }.render(&resolved);
+        assert!(colored.contains("\x1b[1;4;36mTitle\x1b[0m"));
+        assert!(colored.contains("\x1b[3mreally\x1b[0m"));
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+use crate::{
+    config::{Config, Kind},
+    frontmatter, fsutil, link, zettel,
+    zettelkasten::Zettelkasten,
+    ZettelMeta,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+/// Directory scheme `zk reorganize` moves zettels into
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum By {
+    /// `YYYY/MM/` of creation
+    Year,
+    /// the first tag, with `/` in nested tags making subdirectories
+    Tag,
+    /// the directory of the kind in `config.kinds`
+    Kind,
+}
+
+/// A zettel's file and where it goes, both under the root directory
+#[derive(Debug, PartialEq)]
+pub struct Move {
+    pub id: zettel::Id,
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// A zettel whose relative links the moves would break, with its file's
+/// new contents
+#[derive(Debug)]
+pub struct Rewrite {
+    pub id: zettel::Id,
+    /// the file before it is moved
+    pub path: PathBuf,
+    /// old and new targets
+    pub links: Vec<(String, String)>,
+    pub contents: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub moves: Vec<Move>,
+    pub rewrites: Vec<Rewrite>,
+    /// zettels left where they are because their new path is taken
+    pub conflicts: Vec<Move>,
+}
+
+/// the kind of a zettel with frontmatter `fm` at `path`: the kind whose
+/// fixed frontmatter it has, or else the kind whose directory it is in
+pub fn kind_of<'a>(config: &'a Config, fm: &serde_yaml::Mapping, path: &Path) -> Option<&'a Kind> {
+    let fixed = |kind: &Kind| -> Vec<(String, String)> {
+        kind.frontmatter
+            .iter()
+            .filter(|(_, value)| !value.contains("{{"))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    };
+    config
+        .kinds
+        .values()
+        .find(|kind| {
+            let fixed = fixed(kind);
+            !fixed.is_empty()
+                && fixed.iter().all(|(key, value)| {
+                    fm.get(&key.as_str().into()).and_then(|v| v.as_str()) == Some(value)
+                })
+        })
+        .or_else(|| {
+            config.kinds.values().find(|kind| {
+                kind.dir
+                    .as_ref()
+                    .is_some_and(|dir| path.parent().is_some_and(|p| p.starts_with(dir)))
+            })
+        })
+}
+
+/// directory under the root directory zettel `meta`, at `path` under the root
+/// directory with frontmatter `fm`, belongs in
+pub fn dir_for(
+    config: &Config,
+    meta: &ZettelMeta,
+    fm: &serde_yaml::Mapping,
+    path: &Path,
+    by: By,
+) -> PathBuf {
+    match by {
+        By::Year => {
+            let zone = config.timezone.unwrap_or_default();
+            PathBuf::from(zone.show(meta.created).format("%Y/%m").to_string())
+        }
+        By::Tag => meta
+            .tags
+            .first()
+            .map(|tag| {
+                tag.split('/')
+                    .filter(|part| !part.trim().is_empty())
+                    .map(fsutil::sanitize_file_name)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        By::Kind => kind_of(config, fm, path)
+            .and_then(|kind| kind.dir.clone())
+            .unwrap_or_default(),
+    }
+}
+
+/// moves of every zettel of `zk` into the directories of `by`, keeping file
+/// names, and the rewrites of relative markdown links to and from moved files
+///
+/// a zettel whose new path is taken by another file or zettel stays where it
+/// is. Wikilinks point at ids and are left alone.
+pub fn plan(zk: &Zettelkasten, root_dir: &Path, by: By) -> Result<Plan, frontmatter::Error> {
+    let mut ids: Vec<&zettel::Id> = zk.zettels.keys().collect();
+    ids.sort_by_key(|id| (zk.zettels[*id].created, *id));
+    let mut files = HashMap::new();
+    for id in &ids {
+        let meta = &zk.zettels[*id];
+        files.insert(*id, frontmatter::parse_yaml_path(meta.full_path(root_dir))?);
+    }
+    let mut plan = Plan::default();
+    let mut targets = HashSet::new();
+    for id in &ids {
+        let meta = &zk.zettels[*id];
+        let from = link::normalize(&meta.relative_path(root_dir));
+        let name = match from.file_name() {
+            Some(name) => name,
+            None => continue,
+        };
+        let to = dir_for(&zk.config, meta, &files[*id].0, &from, by).join(name);
+        if to == from {
+            continue;
+        }
+        let taken = root_dir.join(&to).exists() || !targets.insert(to.clone());
+        let mv = Move {
+            id: (*id).clone(),
+            from,
+            to,
+        };
+        match taken {
+            true => plan.conflicts.push(mv),
+            false => plan.moves.push(mv),
+        }
+    }
+    let moved: HashMap<PathBuf, PathBuf> = plan
+        .moves
+        .iter()
+        .map(|mv| (mv.from.clone(), mv.to.clone()))
+        .collect();
+    for id in ids {
+        let meta = &zk.zettels[id];
+        let from = link::normalize(&meta.relative_path(root_dir));
+        let to = moved.get(&from).unwrap_or(&from);
+        let path = meta.full_path(root_dir);
+        let text = std::fs::read_to_string(&path)?;
+        let (contents, links) = link::rebase(&text, &from, to, &moved);
+        if !links.is_empty() {
+            plan.rewrites.push(Rewrite {
+                id: id.clone(),
+                path,
+                links,
+                contents,
+            });
+        }
+    }
+    Ok(plan)
+}
+
+/// apply `plan` to `zk` in one transaction: rewrite links, then move files
+/// and record their new paths
+pub fn apply(
+    zk: &mut Zettelkasten,
+    root_dir: &Path,
+    plan: &Plan,
+) -> Result<(), crate::zettelkasten::Error> {
+    zk.transaction(|tx| {
+        for rewrite in &plan.rewrites {
+            tx.write(&rewrite.path, rewrite.contents.as_str());
+        }
+        for mv in &plan.moves {
+            tx.rename(root_dir.join(&mv.from), root_dir.join(&mv.to));
+            if let Some(meta) = tx.zettels.get_mut(&mv.id) {
+                meta.path = fsutil::to_slash(&mv.to).unwrap_or_default();
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn moves_and_relinks() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_reorganize_test")?;
+        let root_dir = tmp_dir.path().to_path_buf();
+        std::fs::create_dir_all(root_dir.join("assets"))?;
+        std::fs::write(root_dir.join("assets/fig.png"), "")?;
+        let db = Database::new(root_dir.clone());
+        let mut zk = Zettelkasten::default();
+        zk.config.timezone = Some("utc".parse().unwrap());
+        let path = |p: &str| fsutil::to_slash(&root_dir.join(p)).unwrap();
+        let at = |y, m| Utc.ymd(y, m, 14).and_hms(12, 0, 0).with_timezone(&Local);
+        let mut a = db.new_zettel(&zk.config, "A", "a", at(2021, 3))?;
+        a.content = "![fig](assets/fig.png) and [b](b.md#part) and [web](https://x.org)\n\
+                     [ref]: b.md\n"
+            .to_owned();
+        a.meta.path = path("a.md");
+        zk.add(db.root_dir(), &a)?;
+        let mut b = db.new_zettel(&zk.config, "B", "b", at(2022, 11))?;
+        b.meta.path = path("b.md");
+        b.content = "see [[a]]\n".to_owned();
+        zk.add(db.root_dir(), &b)?;
+        let mut c = db.new_zettel(&zk.config, "C", "c", at(2022, 11))?;
+        c.meta.path = path("sub/b.md");
+        std::fs::create_dir_all(root_dir.join("sub"))?;
+        zk.add(db.root_dir(), &c)?;
+
+        let plan = plan(&zk, &root_dir, By::Year)?;
+        let moves: Vec<(&str, &Path)> = plan
+            .moves
+            .iter()
+            .map(|mv| (mv.id.as_str(), mv.to.as_path()))
+            .collect();
+        assert_eq!(
+            moves,
+            [
+                ("a", Path::new("2021/03/a.md")),
+                ("b", Path::new("2022/11/b.md"))
+            ]
+        );
+        assert_eq!(plan.conflicts.len(), 1);
+        assert_eq!(plan.conflicts[0].id, "c");
+        assert_eq!(plan.rewrites.len(), 1);
+        apply(&mut zk, &root_dir, &plan)?;
+        let (_, body) = frontmatter::parse_yaml_path(root_dir.join("2021/03/a.md"))?;
+        assert_eq!(
+            body,
+            "![fig](../../assets/fig.png) and [b](../../2022/11/b.md#part) and [web](https://x.org)\n\
+             [ref]: ../../2022/11/b.md\n"
+        );
+        assert_eq!(zk.zettels["b"].path, "2022/11/b.md");
+        assert!(root_dir.join("2022/11/b.md").exists());
+        assert!(!root_dir.join("b.md").exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//! Finding a zettel from what a person would type instead of its id
+//!
+//! - an id, which always wins
+//! - `last:` for the zettel modified most recently, `last:2` for the one
+//!   before it and so on
+//! - `title^prefix` for the zettel whose title starts with `prefix`, in any
+//!   case
+//! - a date such as `today`, `yesterday` or `2023-05-14` for the zettel
+//!   created then; of several, the one with the date in its title, like a
+//!   daily note
+
+use crate::{dates, zettel, zettelkasten::Zettelkasten, ZettelMeta};
+use chrono::NaiveDate;
+
+/// What a spec points at
+#[derive(Debug, PartialEq)]
+pub enum Resolved {
+    Found(zettel::Id),
+    Missing,
+    /// several zettels match, ordered by creation
+    Ambiguous(Vec<zettel::Id>),
+}
+
+impl Resolved {
+    fn from_matches(mut matches: Vec<(&zettel::Id, &ZettelMeta)>) -> Self {
+        matches.sort_by_key(|(id, meta)| (meta.created, *id));
+        match matches.as_slice() {
+            [] => Self::Missing,
+            [(id, _)] => Self::Found((*id).clone()),
+            _ => Self::Ambiguous(matches.into_iter().map(|(id, _)| id.clone()).collect()),
+        }
+    }
+}
+
+/// the zettel of `zk` that `spec` points at, with dates relative to
+/// `today` and days as seen in `zone`
+pub fn resolve(zk: &Zettelkasten, spec: &str, today: NaiveDate, zone: dates::Zone) -> Resolved {
+    if zk.zettels.contains_key(spec) {
+        return Resolved::Found(spec.to_owned());
+    }
+    if let Some(n) = spec.strip_prefix("last:") {
+        let n: usize = match n.trim() {
+            "" => 1,
+            n => match n.parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Resolved::Missing,
+            },
+        };
+        let mut recent: Vec<_> = zk.zettels.iter().collect();
+        recent.sort_by(|a, b| b.1.modified.cmp(&a.1.modified).then(a.0.cmp(b.0)));
+        return match recent.get(n - 1) {
+            Some((id, _)) => Resolved::Found((*id).clone()),
+            None => Resolved::Missing,
+        };
+    }
+    if let Some(prefix) = spec.strip_prefix("title^") {
+        let prefix = prefix.trim().to_lowercase();
+        return Resolved::from_matches(
+            zk.zettels
+                .iter()
+                .filter(|(_, meta)| meta.title.to_lowercase().starts_with(&prefix))
+                .collect(),
+        );
+    }
+    let range = match dates::parse_range(spec, today) {
+        Some(range) => range,
+        None => return Resolved::Missing,
+    };
+    let created: Vec<_> = zk
+        .zettels
+        .iter()
+        .filter(|(_, meta)| range.contains(zone.show(meta.created).date().naive_local()))
+        .collect();
+    if created.len() > 1 && range.start == range.end {
+        let day = range.start.format("%Y-%m-%d").to_string();
+        let dated: Vec<_> = created
+            .iter()
+            .filter(|(_, meta)| meta.title.contains(&day))
+            .copied()
+            .collect();
+        if dated.len() == 1 {
+            return Resolved::from_matches(dated);
+        }
+    }
+    Resolved::from_matches(created)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::prelude::*;
+
+    #[test]
+    fn specs() {
+        let utc: dates::Zone = "utc".parse().unwrap();
+        let at = |d: u32, h: u32| {
+            chrono::Utc
+                .ymd(2023, 5, d)
+                .and_hms(h, 0, 0)
+                .with_timezone(&Local)
+        };
+        let mut zk = Zettelkasten::default();
+        for (id, title, created, modified) in [
+            ("a", "Daily 2023-05-16", at(16, 8), at(16, 8)),
+            ("b", "Reading notes", at(16, 9), at(17, 9)),
+            ("c", "Readme", at(17, 8), at(17, 8)),
+        ] {
+            let mut meta = ZettelMeta::new(id, title, "z.md", created);
+            meta.modified = modified;
+            zk.zettels.insert(id.to_owned(), meta);
+        }
+        let today = NaiveDate::from_ymd(2023, 5, 17);
+        let resolve = |spec: &str| resolve(&zk, spec, today, utc);
+        let found = |id: &str| Resolved::Found(id.to_owned());
+        assert_eq!(resolve("b"), found("b"));
+        assert_eq!(resolve("yesterday"), found("a"));
+        assert_eq!(resolve("today"), found("c"));
+        assert_eq!(resolve("2023-05-01"), Resolved::Missing);
+        assert_eq!(resolve("last:"), found("b"));
+        assert_eq!(resolve("last:2"), found("c"));
+        assert_eq!(resolve("last:9"), Resolved::Missing);
+        assert_eq!(resolve("title^reading"), found("b"));
+        assert_eq!(
+            resolve("title^Rea"),
+            Resolved::Ambiguous(vec!["b".to_owned(), "c".to_owned()])
+        );
+        assert_eq!(resolve("nothing"), Resolved::Missing);
+    }
+}
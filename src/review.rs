@@ -0,0 +1,85 @@
+use crate::{dates, DateTime};
+use serde::{Deserialize, Serialize};
+
+/// ease factor given to a zettel on its first review
+pub const INITIAL_EASE: f64 = 2.5;
+/// ease factor never drops below this
+pub const MIN_EASE: f64 = 1.3;
+
+/// Spaced repetition schedule of a zettel, updated with SM-2
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    #[serde(with = "dates::utc")]
+    pub last_reviewed: DateTime,
+    /// days between last_reviewed and the next review
+    pub interval: u32,
+    /// number of consecutive successful reviews
+    pub repetitions: u32,
+    pub ease: f64,
+}
+
+impl Schedule {
+    pub fn due(&self) -> DateTime {
+        self.last_reviewed + chrono::Duration::days(self.interval.into())
+    }
+
+    /// reschedule after a review graded from 0 (blackout) to 5 (perfect recall)
+    pub fn grade(schedule: Option<&Self>, quality: u8, now: DateTime) -> Self {
+        let quality = quality.min(5);
+        let (interval, repetitions, ease) = match schedule {
+            Some(s) => (s.interval, s.repetitions, s.ease),
+            None => (0, 0, INITIAL_EASE),
+        };
+        let (interval, repetitions) = if quality < 3 {
+            (1, 0)
+        } else {
+            let interval = match repetitions {
+                0 => 1,
+                1 => 6,
+                _ => (f64::from(interval) * ease).round() as u32,
+            };
+            (interval, repetitions + 1)
+        };
+        let penalty = f64::from(5 - quality);
+        let ease = (ease + 0.1 - penalty * (0.08 + penalty * 0.02)).max(MIN_EASE);
+        Self {
+            last_reviewed: now,
+            interval,
+            repetitions,
+            ease,
+        }
+    }
+}
+
+/// Grades offered when reviewing, indexed by quality
+pub const GRADES: [&str; 6] = [
+    "0 - complete blackout",
+    "1 - wrong, but recognized it",
+    "2 - wrong, but it seemed easy",
+    "3 - right, with serious difficulty",
+    "4 - right, after some hesitation",
+    "5 - perfect recall",
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::prelude::*;
+
+    #[test]
+    fn sm2_intervals() {
+        let now = chrono::Local.ymd(2022, 7, 1).and_hms(12, 0, 0);
+        let first = Schedule::grade(None, 4, now);
+        assert_eq!(first.interval, 1);
+        assert_eq!(first.ease, INITIAL_EASE);
+        let second = Schedule::grade(Some(&first), 5, first.due());
+        assert_eq!(second.interval, 6);
+        let third = Schedule::grade(Some(&second), 4, second.due());
+        assert_eq!(third.interval, 16);
+        assert!(third.due() > second.due());
+        let lapse = Schedule::grade(Some(&third), 1, third.due());
+        assert_eq!(lapse.interval, 1);
+        assert_eq!(lapse.repetitions, 0);
+        assert!(lapse.ease >= MIN_EASE && lapse.ease < third.ease);
+    }
+}
@@ -0,0 +1,353 @@
+use crate::{crypt, zettel, zettelkasten::Zettelkasten};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    SerializationError(serde_json::Error),
+    CryptError(crypt::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::SerializationError(e)
+    }
+}
+
+impl From<crypt::Error> for Error {
+    fn from(e: crypt::Error) -> Self {
+        Self::CryptError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::SerializationError(e) => e.fmt(f),
+            Self::CryptError(e) => e.fmt(f),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// version of the index layout; indexes of other versions are rebuilt
+pub const VERSION: u32 = 1;
+
+/// Words of zettel bodies and the zettels they appear in, kept under
+/// `.zk/index` and updated by `sync` for the zettels that changed
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Index {
+    pub version: u32,
+    /// number of terms in each indexed zettel
+    docs: BTreeMap<zettel::Id, usize>,
+    /// occurrences of each term by zettel
+    terms: BTreeMap<String, BTreeMap<zettel::Id, u32>>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            docs: BTreeMap::new(),
+            terms: BTreeMap::new(),
+        }
+    }
+}
+
+/// lowercase words of `text`, in order
+pub fn terms(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+impl Index {
+    /// path of the index file under the root directory, which is encrypted
+    /// along with the database
+    pub fn path(root_dir: &Path, encrypted: bool) -> PathBuf {
+        let name = if encrypted {
+            "terms.json.age"
+        } else {
+            "terms.json"
+        };
+        root_dir.join(".zk").join("index").join(name)
+    }
+
+    /// the stored index, decrypted with `key` if the database is encrypted;
+    /// `None` if there is none
+    pub fn load(root_dir: &Path, key: Option<&crypt::Age>) -> Result<Option<Self>> {
+        let path = Self::path(root_dir, key.is_some());
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let index = match key {
+            Some(key) => serde_json::from_slice(&key.decrypt_bytes(&std::fs::read(path)?)?)?,
+            None => serde_json::from_reader(BufReader::new(File::open(path)?))?,
+        };
+        Ok(Some(index))
+    }
+
+    /// store the index, encrypted with `key` if the database is encrypted
+    pub fn save(&self, root_dir: &Path, key: Option<&crypt::Age>) -> Result<()> {
+        let path = Self::path(root_dir, key.is_some());
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let data = serde_json::to_vec(self)?;
+        match key {
+            Some(key) => {
+                std::fs::write(path, key.encrypt_bytes(&data)?)?;
+                // don't leave words behind from before the encryption
+                let clear = Self::path(root_dir, false);
+                if clear.is_file() {
+                    std::fs::remove_file(clear)?;
+                }
+            }
+            None => std::fs::write(path, data)?,
+        }
+        Ok(())
+    }
+
+    /// whether the index was built by this version of zk
+    pub fn is_current(&self) -> bool {
+        self.version == VERSION
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.docs.contains_key(id)
+    }
+
+    /// index of every zettel, skipping those whose file can't be read
+    pub fn build(zk: &Zettelkasten, root_dir: &Path) -> Self {
+        let mut index = Self::default();
+        for zettel in zk.handles(root_dir) {
+            index.update_file(zettel);
+        }
+        index
+    }
+
+    /// index the body of zettel `id` in place of what was indexed for it
+    ///
+    /// encrypted bodies are kept as empty, so their armor isn't searched
+    /// and they aren't read again on every refresh
+    pub fn update(&mut self, id: &str, body: &str) {
+        self.remove(id);
+        if crypt::is_encrypted(body) {
+            self.docs.insert(id.to_owned(), 0);
+            return;
+        }
+        let mut counts: HashMap<String, u32> = HashMap::new();
+        let mut length = 0;
+        for term in terms(body) {
+            *counts.entry(term).or_default() += 1;
+            length += 1;
+        }
+        for (term, count) in counts {
+            self.terms
+                .entry(term)
+                .or_default()
+                .insert(id.to_owned(), count);
+        }
+        self.docs.insert(id.to_owned(), length);
+    }
+
+    /// index the body read from the file of `zettel`, dropping it from the
+    /// index if it can't be read
+    pub fn update_file(&mut self, zettel: zettel::ZettelHandle) {
+        match zettel.read() {
+            Ok((_, body)) => self.update(zettel.id, &body),
+            Err(e) => {
+                tracing::warn!("not indexing {}: {}", zettel.meta.path, e);
+                self.remove(zettel.id);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        if self.docs.remove(id).is_none() {
+            return;
+        }
+        self.terms.retain(|_, postings| {
+            postings.remove(id);
+            !postings.is_empty()
+        });
+    }
+
+    /// zettels containing every term of `query`, best matches first
+    ///
+    /// matches are ranked by tf-idf: terms count for more the more often
+    /// they appear in a zettel relative to its length and the fewer zettels
+    /// they appear in
+    pub fn search(&self, query: &str) -> Vec<(zettel::Id, f64)> {
+        let mut query: Vec<String> = terms(query).collect();
+        query.sort();
+        query.dedup();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let total = self.docs.len() as f64;
+        let mut scores: Option<HashMap<&zettel::Id, f64>> = None;
+        for term in &query {
+            let postings = match self.terms.get(term) {
+                Some(postings) => postings,
+                None => return Vec::new(),
+            };
+            let idf = (total / postings.len() as f64).ln() + 1.0;
+            let term_scores: HashMap<&zettel::Id, f64> = postings
+                .iter()
+                .map(|(id, count)| {
+                    let length = self.docs.get(id).copied().unwrap_or(1).max(1);
+                    (id, *count as f64 / length as f64 * idf)
+                })
+                .collect();
+            scores = Some(match scores {
+                None => term_scores,
+                Some(scores) => scores
+                    .into_iter()
+                    .filter_map(|(id, score)| Some((id, score + term_scores.get(id)?)))
+                    .collect(),
+            });
+        }
+        let mut found: Vec<(zettel::Id, f64)> = scores
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(id, score)| (id.clone(), score))
+            .collect();
+        found.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        found
+    }
+}
+
+/// drop zettel `id` from the stored index, if there is one, so it is read
+/// again on the next refresh; for bodies that were encrypted or decrypted
+/// since, whose words shouldn't stay behind or be missing
+pub fn forget(root_dir: &Path, key: Option<&crypt::Age>, id: &str) -> Result<()> {
+    if let Some(mut index) = Index::load(root_dir, key)? {
+        index.remove(id);
+        index.save(root_dir, key)?;
+    }
+    Ok(())
+}
+
+/// the stored index brought up to date with `zk`, or a new one if it is
+/// missing, unreadable or from another version
+///
+/// zettels in `changed` are read again, as are those missing from the
+/// index; zettels no longer in `zk` are dropped
+pub fn refresh(
+    zk: &Zettelkasten,
+    root_dir: &Path,
+    key: Option<&crypt::Age>,
+    changed: &[&str],
+) -> Index {
+    let mut index = match Index::load(root_dir, key) {
+        Ok(Some(index)) if index.is_current() => index,
+        Ok(Some(_)) => {
+            tracing::info!("rebuilding search index of an older version");
+            return Index::build(zk, root_dir);
+        }
+        Ok(None) => return Index::build(zk, root_dir),
+        Err(e) => {
+            tracing::warn!("rebuilding unreadable search index: {}", e);
+            return Index::build(zk, root_dir);
+        }
+    };
+    let stale: Vec<zettel::Id> = index
+        .docs
+        .keys()
+        .filter(|id| !zk.zettels.contains_key(*id))
+        .cloned()
+        .collect();
+    for id in stale {
+        index.remove(&id);
+    }
+    for zettel in zk.handles(root_dir) {
+        if changed.contains(&zettel.id) || !index.contains(zettel.id) {
+            index.update_file(zettel);
+        }
+    }
+    index
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        database::{memory::Database, Database as _},
+        testutil::Kasten,
+    };
+    use chrono::prelude::*;
+
+    #[test]
+    fn ranks_and_updates() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_search_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for (id, body) in [
+            ("a", "Rust ownership and borrowing"),
+            ("b", "rust rust, and more Rust"),
+            ("c", "gardening"),
+        ] {
+            let mut zettel = db.new_zettel(&zk.config, id, id, dt)?;
+            zettel.content = body.to_owned();
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        let index = refresh(&zk, tmp_dir.path(), None, &[]);
+        let ids = |found: Vec<(zettel::Id, f64)>| -> Vec<zettel::Id> {
+            found.into_iter().map(|(id, _)| id).collect()
+        };
+        assert_eq!(ids(index.search("RUST")), ["b", "a"]);
+        assert_eq!(ids(index.search("rust borrowing")), ["a"]);
+        assert!(index.search("rust gardening").is_empty());
+        index.save(tmp_dir.path(), None)?;
+        let path = zk.zettels["c"].full_path(tmp_dir.path());
+        std::fs::write(&path, "---\ntitle: c\n---\nrust too\n")?;
+        zk.zettels.remove("a");
+        let index = refresh(&zk, tmp_dir.path(), None, &["c"]);
+        assert_eq!(ids(index.search("rust")), ["b", "c"]);
+        assert!(!index.contains("a"));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn forgets_encrypted_bodies() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = Kasten::new();
+        let root_dir = kasten.root_dir();
+        kasten.add("a", "Plain", "open words");
+        let zk = kasten.add("b", "Secret", "hidden words");
+        refresh(&zk, root_dir, None, &[]).save(root_dir, None)?;
+        let mut age = crypt::fake_age(root_dir)?;
+        age.recipients.push("age1example".to_owned());
+        assert!(age.encrypt_file(&zk.zettels["b"].full_path(root_dir))?);
+        forget(root_dir, None, "b")?;
+        let stored = std::fs::read_to_string(Index::path(root_dir, false))?;
+        assert!(!stored.contains("hidden"));
+        let index = refresh(&zk, root_dir, None, &[]);
+        assert!(index.contains("b"));
+        assert!(index.search("hidden").is_empty());
+        assert!(index.search("encrypted").is_empty());
+        let found: Vec<zettel::Id> = index
+            .search("words")
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(found, ["a"]);
+        Ok(())
+    }
+}
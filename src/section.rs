@@ -0,0 +1,49 @@
+/// replace the contents between `<!-- zk:<name>:start -->` and
+/// `<!-- zk:<name>:end -->`, appending the section if the markers are missing
+///
+/// text outside the markers is left untouched so the section can be
+/// regenerated in a file that is otherwise edited by hand
+pub fn replace(text: &str, name: &str, content: &str) -> String {
+    let start = format!("<!-- zk:{}:start -->", name);
+    let end = format!("<!-- zk:{}:end -->", name);
+    let mut content = content.to_owned();
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    if let Some(start_pos) = text.find(&start) {
+        if let Some(end_pos) = text[start_pos..].find(&end) {
+            let end_pos = start_pos + end_pos;
+            return format!(
+                "{}{}\n{}{}",
+                &text[..start_pos],
+                start,
+                content,
+                &text[end_pos..]
+            );
+        }
+    }
+    let mut text = text.to_owned();
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    format!("{}{}\n{}{}\n", text, start, content, end)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn append_then_replace() {
+        let text = replace("# Index\nkept", "toc", "- one");
+        assert_eq!(
+            text,
+            "# Index\nkept\n<!-- zk:toc:start -->\n- one\n<!-- zk:toc:end -->\n"
+        );
+        let text = text.replace("kept", "edited by hand") + "after\n";
+        assert_eq!(
+            replace(&text, "toc", "- two\n"),
+            "# Index\nedited by hand\n<!-- zk:toc:start -->\n- two\n<!-- zk:toc:end -->\nafter\n"
+        );
+    }
+}
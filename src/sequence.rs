@@ -0,0 +1,132 @@
+use crate::{zettel, zettelkasten::Zettelkasten};
+use std::collections::HashMap;
+
+/// A zettel in a sequence
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub id: zettel::Id,
+    /// 0 for the start of the sequence
+    pub depth: usize,
+    /// Luhmann-style position like `1a2`, alternating numbers and letters
+    /// with each branch
+    pub label: String,
+}
+
+/// zettel at the start of the sequence containing `id`
+pub fn first(zk: &Zettelkasten, id: &str) -> zettel::Id {
+    let mut current = id.to_owned();
+    let mut seen = vec![current.clone()];
+    while let Some(parent) = zk
+        .zettels
+        .get(&current)
+        .and_then(|meta| meta.follows.as_ref())
+        .filter(|parent| zk.zettels.contains_key(*parent))
+    {
+        // a cycle has no start, so stop where it closes
+        if seen.contains(parent) {
+            break;
+        }
+        seen.push(parent.clone());
+        current = parent.clone();
+    }
+    current
+}
+
+/// every zettel of the sequence containing `id`, depth first with
+/// branches ordered by creation
+pub fn chain(zk: &Zettelkasten, id: &str) -> Vec<Entry> {
+    let mut children: HashMap<&str, Vec<&zettel::Id>> = HashMap::new();
+    for (id, meta) in &zk.zettels {
+        if let Some(parent) = &meta.follows {
+            children.entry(parent.as_str()).or_default().push(id);
+        }
+    }
+    for ids in children.values_mut() {
+        ids.sort_by_key(|id| (zk.zettels[*id].created, *id));
+    }
+    let mut entries = Vec::new();
+    let mut stack = vec![(first(zk, id), 0, "1".to_owned())];
+    while let Some((id, depth, label)) = stack.pop() {
+        if entries.iter().any(|e: &Entry| e.id == id) {
+            continue;
+        }
+        let branches = children.get(id.as_str()).cloned().unwrap_or_default();
+        for (n, child) in branches.into_iter().enumerate().rev() {
+            stack.push((child.clone(), depth + 1, branch_label(&label, depth + 1, n)));
+        }
+        entries.push(Entry { id, depth, label });
+    }
+    entries
+}
+
+/// label of the `n`th branch at `depth`, letters at odd depths
+fn branch_label(parent: &str, depth: usize, n: usize) -> String {
+    if depth % 2 == 1 {
+        let mut letters = String::new();
+        let mut n = n;
+        loop {
+            letters.insert(0, (b'a' + (n % 26) as u8) as char);
+            if n < 26 {
+                break;
+            }
+            n = n / 26 - 1;
+        }
+        format!("{}{}", parent, letters)
+    } else {
+        format!("{}{}", parent, n + 1)
+    }
+}
+
+/// zettels before and after `id` when reading its sequence depth first
+pub fn neighbours(zk: &Zettelkasten, id: &str) -> (Option<zettel::Id>, Option<zettel::Id>) {
+    let chain = chain(zk, id);
+    let pos = match chain.iter().position(|e| e.id == id) {
+        Some(pos) => pos,
+        None => return (None, None),
+    };
+    let prev = pos.checked_sub(1).map(|p| chain[p].id.clone());
+    let next = chain.get(pos + 1).map(|e| e.id.clone());
+    (prev, next)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ZettelMeta;
+    use chrono::prelude::*;
+
+    #[test]
+    fn branching_sequence() {
+        let mut zk = Zettelkasten::default();
+        for (n, (id, follows)) in [
+            ("root", None),
+            ("a", Some("root")),
+            ("b", Some("root")),
+            ("a1", Some("a")),
+            ("other", None),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let dt = chrono::Local.ymd(2015, 5, 14 + n as u32).and_hms(12, 0, 0);
+            let mut meta = ZettelMeta::new(id, id, "z.md", dt);
+            meta.follows = follows.map(str::to_owned);
+            zk.zettels.insert(id.to_owned(), meta);
+        }
+        let chain: Vec<(String, String)> = chain(&zk, "a1")
+            .into_iter()
+            .map(|e| (e.id, e.label))
+            .collect();
+        let expected = [("root", "1"), ("a", "1a"), ("a1", "1a1"), ("b", "1b")];
+        assert_eq!(
+            chain,
+            expected.map(|(id, label)| (id.to_owned(), label.to_owned()))
+        );
+        assert_eq!(
+            neighbours(&zk, "a1"),
+            (Some("a".to_owned()), Some("b".to_owned()))
+        );
+        assert_eq!(neighbours(&zk, "other"), (None, None));
+        assert_eq!(branch_label("1", 1, 27), "1ab");
+    }
+}
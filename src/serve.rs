@@ -0,0 +1,484 @@
+use crate::{capture, database, database::Database, hooks, zettelkasten, DateTime};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    io::{BufRead, Read, Write},
+    time::Duration,
+};
+
+/// largest request body accepted, in bytes
+const MAX_BODY: usize = 1 << 20;
+
+/// largest request line and headers accepted, in bytes all told
+const MAX_HEAD: usize = 16 << 10;
+
+/// how long a connection may take to send its request or read the answer
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    DatabaseError(database::Error),
+    ZettelkastenError(zettelkasten::Error),
+    /// the request is not HTTP zk understands
+    BadRequest(String),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::DatabaseError(e) => e.fmt(f),
+            Self::ZettelkastenError(e) => e.fmt(f),
+            Self::BadRequest(message) => write!(f, "bad request: {}", message),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<database::Error> for Error {
+    fn from(e: database::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+impl From<zettelkasten::Error> for Error {
+    fn from(e: zettelkasten::Error) -> Self {
+        Self::ZettelkastenError(e)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// An HTTP request, with header names in lowercase
+#[derive(Debug, Default)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// read one request from a connection
+    pub fn read(reader: &mut impl BufRead) -> Result<Self> {
+        let mut head = reader.take(MAX_HEAD as u64);
+        let mut line = String::new();
+        read_line(&mut head, &mut line)?;
+        let mut words = line.split_whitespace();
+        let (method, path) = match (words.next(), words.next()) {
+            (Some(method), Some(path)) => (method.to_owned(), path.to_owned()),
+            _ => return Err(Error::BadRequest("no request line".to_owned())),
+        };
+        let mut headers = HashMap::new();
+        loop {
+            line.clear();
+            if read_line(&mut head, &mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_owned());
+            }
+        }
+        let length = match headers.get("content-length") {
+            Some(length) => length
+                .parse()
+                .map_err(|_| Error::BadRequest("invalid Content-Length".to_owned()))?,
+            None => 0,
+        };
+        if length > MAX_BODY {
+            return Err(Error::BadRequest("body too large".to_owned()));
+        }
+        let mut body = vec![0; length];
+        head.into_inner().read_exact(&mut body)?;
+        Ok(Self {
+            method,
+            path,
+            headers,
+            body,
+        })
+    }
+
+    /// fields of a JSON or url-encoded form body
+    fn fields(&self) -> Result<HashMap<String, String>> {
+        let content_type = self.headers.get("content-type").map_or("", String::as_str);
+        let body = std::str::from_utf8(&self.body)
+            .map_err(|_| Error::BadRequest("body is not UTF-8".to_owned()))?;
+        if content_type.starts_with("application/x-www-form-urlencoded") {
+            return Ok(body
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .map(|(key, value)| (form_decode(key), form_decode(value)))
+                .collect());
+        }
+        let value: Value = serde_json::from_str(body)
+            .map_err(|e| Error::BadRequest(format!("invalid JSON: {}", e)))?;
+        let object = value
+            .as_object()
+            .ok_or_else(|| Error::BadRequest("expected a JSON object".to_owned()))?;
+        Ok(object
+            .iter()
+            .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_owned())))
+            .collect())
+    }
+}
+
+/// read a line of the request head, which must end within its limit
+fn read_line(head: &mut impl BufRead, line: &mut String) -> Result<usize> {
+    let read = head.read_line(line).map_err(|e| match e.kind() {
+        std::io::ErrorKind::InvalidData => Error::BadRequest("head is not UTF-8".to_owned()),
+        _ => e.into(),
+    })?;
+    if read > 0 && !line.ends_with('\n') {
+        return Err(Error::BadRequest("head too large".to_owned()));
+    }
+    Ok(read)
+}
+
+fn form_decode(s: &str) -> String {
+    crate::opener::percent_decode(&s.replace('+', " "))
+}
+
+/// An HTTP response with a JSON body
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Response {
+    fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.to_string() }),
+        }
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            201 => "Created",
+            204 => "No Content",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            409 => "Conflict",
+            _ => "Internal Server Error",
+        };
+        let body = match self.body {
+            Value::Null => String::new(),
+            ref body => body.to_string(),
+        };
+        // bookmarklets post from the page they run on
+        write!(
+            writer,
+            "HTTP/1.1 {} {}\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n\
+             Access-Control-Allow-Origin: *\r\n\
+             Access-Control-Allow-Methods: POST\r\n\
+             Access-Control-Allow-Headers: Authorization, Content-Type\r\n\
+             Connection: close\r\n\r\n{}",
+            self.status,
+            reason,
+            body.len(),
+            body
+        )?;
+        writer.flush()
+    }
+}
+
+/// Answers HTTP requests, creating a zettel for each `POST /capture`
+pub struct Server<D: Database> {
+    db: D,
+    /// bearer token every capture must carry
+    token: String,
+    /// kind of the captured zettels, if any
+    pub kind: Option<String>,
+    /// read and write timeout of each connection
+    pub timeout: Duration,
+}
+
+impl<D: Database> Server<D> {
+    pub fn new(db: D, token: String) -> Self {
+        Self {
+            db,
+            token,
+            kind: None,
+            timeout: TIMEOUT,
+        }
+    }
+
+    fn authorized(&self, request: &Request) -> bool {
+        let given = request
+            .headers
+            .get("authorization")
+            .and_then(|auth| auth.strip_prefix("Bearer "))
+            .unwrap_or("")
+            .as_bytes();
+        let token = self.token.as_bytes();
+        // compare every byte so the time taken doesn't leak the token
+        given.len() == token.len()
+            && given.iter().zip(token).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+
+    pub fn handle(&self, request: &Request, now: DateTime) -> Response {
+        let path = request.path.split('?').next().unwrap_or_default();
+        match (request.method.as_str(), path) {
+            ("OPTIONS", _) => Response {
+                status: 204,
+                body: Value::Null,
+            },
+            ("POST", "/capture") if !self.authorized(request) => {
+                Response::error(401, "missing or wrong bearer token")
+            }
+            ("POST", "/capture") => match self.capture(request, now) {
+                Ok(response) => response,
+                Err(e @ Error::BadRequest(_)) => Response::error(400, e),
+                Err(e) => {
+                    tracing::error!("couldn't capture: {}", e);
+                    Response::error(500, e)
+                }
+            },
+            (_, "/capture") => Response::error(405, "use POST"),
+            _ => Response::error(404, "not found"),
+        }
+    }
+
+    fn capture(&self, request: &Request, now: DateTime) -> Result<Response> {
+        let fields = request.fields()?;
+        let text = fields
+            .get("markdown")
+            .or_else(|| fields.get("body"))
+            .cloned()
+            .unwrap_or_default();
+        let title = fields
+            .get("title")
+            .filter(|title| !title.trim().is_empty())
+            .map(|title| title.trim().to_owned())
+            .or_else(|| capture::title_from(&text));
+        let title = match title {
+            Some(title) => title,
+            None => return Ok(Response::error(400, "give a title or some markdown")),
+        };
+        let mut zk = match self.db.get_zk()? {
+            Some(zk) => zk,
+            None => return Ok(Response::error(500, "database does not exist")),
+        };
+        let id = zk.new_id(now);
+        if let Err(others) = zk.check_title(&id, &title) {
+            let message = format!("{} already titled {:?}", others.join(", "), title);
+            return Ok(Response::error(409, message));
+        }
+        let mut zettel = match &self.kind {
+            Some(kind) => match zk.config.kinds.get(kind) {
+                Some(kind) => self
+                    .db
+                    .new_zettel_of_kind(&zk.config, kind, &title, &id, now)?,
+                None => return Ok(Response::error(500, format!("no kind named {}", kind))),
+            },
+            None => self.db.new_zettel(&zk.config, &title, &id, now)?,
+        };
+        zettel.meta.update_from_body(&text);
+        zettel.content = text;
+        zk.add(self.db.root_dir(), &zettel)?;
+        zk.commit_entry(&self.db, &id)?;
+        hooks::run(
+            self.db.root_dir(),
+            &zk.config.hooks,
+            hooks::Event::New,
+            &[(&id, &zettel.meta)],
+        );
+        let path = zk.zettels[&id].relative_path(self.db.root_dir());
+        Ok(Response {
+            status: 201,
+            body: json!({ "id": id, "path": path }),
+        })
+    }
+
+    /// answer connections on `listener` one at a time, logging the ones
+    /// that fail
+    ///
+    /// a connection gets [`Self::timeout`] to send its request and read the
+    /// answer, so a stalled client holds up the others only that long
+    pub fn serve(&self, listener: std::net::TcpListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let result = stream.map_err(Error::from).and_then(|s| self.answer(s));
+            if let Err(e) = result {
+                tracing::warn!("connection failed: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// read a request from `stream` and answer it
+    pub fn answer(&self, mut stream: std::net::TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        let mut reader = std::io::BufReader::new(stream.try_clone()?);
+        let response = match Request::read(&mut reader) {
+            Ok(request) => {
+                tracing::info!("{} {}", request.method, request.path);
+                self.handle(&request, chrono::Local::now())
+            }
+            Err(e @ Error::BadRequest(_)) => Response::error(400, e),
+            // the client is gone or too slow to answer
+            Err(e) => return Err(e),
+        };
+        Ok(response.write(&mut stream)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testutil::{self, Kasten};
+    use std::net::{TcpListener, TcpStream};
+
+    fn request(raw: &str) -> Request {
+        Request::read(&mut raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn capture_endpoint() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = Kasten::new();
+        let db = &kasten.db;
+        let server = Server::new(db, "secret".to_owned());
+        let now = testutil::date();
+        let body = r##"{"title": "From phone", "markdown": "# ignored\nsome text"}"##;
+        let post = |auth: &str, content_type: &str, body: &str| {
+            request(&format!(
+                "POST /capture HTTP/1.1\r\nAuthorization: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+                auth,
+                content_type,
+                body.len(),
+                body
+            ))
+        };
+        let denied = server.handle(&post("Bearer wrong", "application/json", body), now);
+        assert_eq!(denied.status, 401);
+        let created = server.handle(&post("Bearer secret", "application/json", body), now);
+        assert_eq!(created.status, 201);
+        let id = created.body["id"].as_str().unwrap();
+        let zk = db.get_zk()?.unwrap();
+        assert_eq!(zk.zettels[id].title, "From phone");
+        let path = created.body["path"].as_str().unwrap();
+        assert!(kasten.root_dir().join(path).is_file());
+        let form = "markdown=Title+from+text%0Amore";
+        let created = server.handle(
+            &post("Bearer secret", "application/x-www-form-urlencoded", form),
+            now,
+        );
+        let id = created.body["id"].as_str().unwrap();
+        assert_eq!(db.get_zk()?.unwrap().zettels[id].title, "Title from text");
+        let empty = server.handle(&post("Bearer secret", "application/json", "{}"), now);
+        assert_eq!(empty.status, 400);
+        let missing = server.handle(&request("GET /other HTTP/1.1\r\n\r\n"), now);
+        assert_eq!(missing.status, 404);
+        Ok(())
+    }
+
+    #[test]
+    fn title_policy() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = Kasten::new();
+        let mut zk = kasten.add("a", "Taken", "");
+        zk.config.title_policy = Some(crate::config::TitlePolicy::Refuse);
+        kasten.db.commit(&zk)?;
+        let server = Server::new(&kasten.db, "secret".to_owned());
+        let body = r#"{"title": "taken"}"#;
+        let post = request(&format!(
+            "POST /capture HTTP/1.1\r\nAuthorization: Bearer secret\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ));
+        let refused = server.handle(&post, testutil::date());
+        assert_eq!(refused.status, 409);
+        assert_eq!(refused.body["error"], r#"a already titled "taken""#);
+        assert_eq!(kasten.db.get_zk()?.unwrap().zettels.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn strict_dirs() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = Kasten::new();
+        let mut zk = kasten.db.get_zk()?.unwrap();
+        zk.config.strict_dirs = Some(true);
+        let kind = crate::config::Kind {
+            dir: Some("inbox".into()),
+            ..Default::default()
+        };
+        zk.config.kinds.insert("inbox".to_owned(), kind);
+        kasten.db.commit(&zk)?;
+        let mut server = Server::new(&kasten.db, "secret".to_owned());
+        server.kind = Some("inbox".to_owned());
+        let body = r#"{"title": "Note"}"#;
+        let post = request(&format!(
+            "POST /capture HTTP/1.1\r\nAuthorization: Bearer secret\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ));
+        assert_eq!(server.handle(&post, testutil::date()).status, 500);
+        assert!(!kasten.root_dir().join("inbox").exists());
+        std::fs::create_dir(kasten.root_dir().join("inbox"))?;
+        assert_eq!(server.handle(&post, testutil::date()).status, 201);
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_requests() {
+        let requests: [&[u8]; 4] = [
+            b"\r\n",
+            b"POST /capture HTTP/1.1\r\nContent-Length: many\r\n\r\n",
+            b"POST /capture HTTP/1.1\r\nContent-Length: 99999999\r\n\r\n",
+            b"GET /\xff HTTP/1.1\r\n\r\n",
+        ];
+        for mut raw in requests {
+            let result = Request::read(&mut raw);
+            assert!(matches!(result, Err(Error::BadRequest(_))), "{:?}", raw);
+        }
+        let endless = format!("GET / HTTP/1.1\r\nX: {}", "a".repeat(MAX_HEAD));
+        assert!(matches!(
+            Request::read(&mut endless.as_bytes()),
+            Err(Error::BadRequest(_))
+        ));
+    }
+
+    /// a client connected to `server` through a fresh listener, and the
+    /// server's side of the connection
+    fn connect() -> std::io::Result<(TcpStream, TcpStream)> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let client = TcpStream::connect(listener.local_addr()?)?;
+        Ok((client, listener.accept()?.0))
+    }
+
+    #[test]
+    fn stalled_and_gone_clients() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = Kasten::new();
+        let mut server = Server::new(&kasten.db, "secret".to_owned());
+        server.timeout = Duration::from_millis(50);
+        // a client that never sends its request is dropped at the timeout
+        let (_idle, stream) = connect()?;
+        assert!(matches!(server.answer(stream), Err(Error::IoError(_))));
+        // one that hangs up halfway is dropped too
+        let (mut gone, stream) = connect()?;
+        gone.write_all(b"POST /capture HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc")?;
+        drop(gone);
+        assert!(matches!(server.answer(stream), Err(Error::IoError(_))));
+        // and the next one is answered
+        let (mut client, stream) = connect()?;
+        client.write_all(b"GET /other HTTP/1.1\r\n\r\n")?;
+        server.answer(stream)?;
+        let mut answer = String::new();
+        client.read_to_string(&mut answer)?;
+        assert!(answer.starts_with("HTTP/1.1 404"));
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{frontmatter, link, zettel, zettelkasten::Zettelkasten};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    FrontmatterError(frontmatter::Error),
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// What [`share`] wrote
+#[derive(Debug, Default, PartialEq)]
+pub struct Bundle {
+    /// zettels in the bundle, the shared one first
+    pub zettels: Vec<zettel::Id>,
+    /// local files the zettels link to, relative to the root directory
+    pub assets: Vec<PathBuf>,
+    /// local links to files outside the root directory or that don't exist,
+    /// left as they are
+    pub skipped: Vec<String>,
+}
+
+/// zettels bundled with `id`: those reached by following links at most
+/// `depth` times, and whatever any of them embeds however deep
+pub fn collect(
+    zk: &Zettelkasten,
+    root_dir: &Path,
+    id: &str,
+    depth: usize,
+) -> Result<Vec<zettel::Id>> {
+    let mut ids = vec![id.to_owned()];
+    let mut seen: HashSet<zettel::Id> = ids.iter().cloned().collect();
+    let mut queue = VecDeque::from([(id.to_owned(), 0)]);
+    while let Some((id, d)) = queue.pop_front() {
+        let (_, body) = frontmatter::parse_yaml_path(zk.zettels[&id].full_path(root_dir))?;
+        for link in link::wikilinks(&body) {
+            let next = if link.embed { d } else { d + 1 };
+            if next > depth || !zk.zettels.contains_key(&link.target) {
+                continue;
+            }
+            if seen.insert(link.target.clone()) {
+                ids.push(link.target.clone());
+                queue.push_back((link.target, next));
+            }
+        }
+    }
+    Ok(ids)
+}
+
+/// `body` with wikilinks to zettels in `paths` turned into relative markdown
+/// links, and other wikilinks into their labels
+fn rewrite(body: &str, from: &Path, paths: &HashMap<&str, PathBuf>, zk: &Zettelkasten) -> String {
+    let links = link::wikilinks(body);
+    let mut out = String::new();
+    for (n, line) in body.lines().enumerate() {
+        let mut last = 0;
+        for link in links.iter().filter(|l| l.line == n + 1) {
+            out.push_str(&line[last..link.span.start]);
+            last = link.span.end;
+            let title = zk.zettels.get(&link.target).map(|meta| meta.title.as_str());
+            let label = link
+                .label
+                .as_deref()
+                .or(title)
+                .unwrap_or(&link.target)
+                .to_owned();
+            match paths.get(link.target.as_str()) {
+                Some(to) => {
+                    let mut target = link::relative_target(from, to);
+                    if let Some(anchor) = &link.anchor {
+                        target.push('#');
+                        target.push_str(&link::slug(anchor));
+                    }
+                    out.push_str(&format!("[{}]({})", label, target));
+                }
+                None => out.push_str(&label),
+            }
+        }
+        out.push_str(&line[last..]);
+        out.push('\n');
+    }
+    out
+}
+
+/// write zettel `id`, the zettels [`collect`]ed with it and the local files
+/// they link to under `out_dir`, keeping their paths relative to the root
+/// directory so markdown links keep working
+pub fn share(
+    zk: &Zettelkasten,
+    root_dir: &Path,
+    id: &str,
+    depth: usize,
+    out_dir: &Path,
+) -> Result<Bundle> {
+    let mut bundle = Bundle {
+        zettels: collect(zk, root_dir, id, depth)?,
+        ..Default::default()
+    };
+    let paths: HashMap<&str, PathBuf> = bundle
+        .zettels
+        .iter()
+        .map(|id| (id.as_str(), zk.zettels[id].relative_path(root_dir)))
+        .collect();
+    let zettel_files: HashSet<PathBuf> = zk
+        .zettels
+        .values()
+        .map(|meta| link::normalize(&meta.full_path(root_dir)))
+        .collect();
+    let mut assets = HashSet::new();
+    for id in &bundle.zettels {
+        let relative = &paths[id.as_str()];
+        let source = root_dir.join(relative);
+        let (fm, body) = frontmatter::parse_yaml_path(&source)?;
+        let body = rewrite(&body, relative, &paths, zk);
+        for asset in link::markdown_links(&body).iter().filter(|l| l.is_local()) {
+            let target = asset.resolve(&source);
+            match target.strip_prefix(link::normalize(root_dir)) {
+                Ok(rel) if target.is_file() && !zettel_files.contains(&target) => {
+                    if assets.insert(rel.to_path_buf()) {
+                        bundle.assets.push(rel.to_path_buf());
+                    }
+                }
+                // links between bundled zettels were just rewritten
+                Ok(_) if zettel_files.contains(&target) => (),
+                _ => bundle.skipped.push(asset.target.clone()),
+            }
+        }
+        let dest = out_dir.join(relative);
+        if let Some(dir) = dest.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(&dest, frontmatter::write_for(&dest, &fm, &body)?)?;
+    }
+    for asset in &bundle.assets {
+        let dest = out_dir.join(asset);
+        if let Some(dir) = dest.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::copy(root_dir.join(asset), dest)?;
+    }
+    bundle.assets.sort();
+    Ok(bundle)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn bundle_with_links_and_assets() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_share_test")?;
+        let root_dir = tmp_dir.path().join("kasten");
+        std::fs::create_dir_all(root_dir.join("assets"))?;
+        std::fs::write(root_dir.join("assets/fig.png"), "png")?;
+        let db = Database::new(root_dir.clone());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for (id, body) in [
+            (
+                "a",
+                "see [[b#Part|part]] and ![[e]]\n![fig](assets/fig.png) [x](../outside.md)",
+            ),
+            ("b", "# Part\nnext [[c]]"),
+            ("c", "too far"),
+            ("e", "embedded"),
+        ] {
+            let mut zettel = db.new_zettel(&zk.config, id, id, dt)?;
+            zettel.content = body.to_owned();
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        let out_dir = tmp_dir.path().join("out");
+        let bundle = share(&zk, &root_dir, "a", 1, &out_dir)?;
+        assert_eq!(bundle.zettels, ["a", "b", "e"]);
+        assert_eq!(bundle.assets, [PathBuf::from("assets/fig.png")]);
+        assert_eq!(bundle.skipped, ["../outside.md"]);
+        let path = |id: &str| zk.zettels[id].relative_path(&root_dir);
+        let (_, a) = frontmatter::parse_yaml_path(out_dir.join(path("a")))?;
+        let b = path("b").to_string_lossy().into_owned();
+        let e = path("e").to_string_lossy().into_owned();
+        assert!(a.starts_with(&format!("see [part]({}#part) and [e]({})\n", b, e)));
+        let (_, b) = frontmatter::parse_yaml_path(out_dir.join(path("b")))?;
+        assert_eq!(b, "# Part\nnext c\n");
+        assert!(out_dir.join("assets/fig.png").is_file());
+        Ok(())
+    }
+}
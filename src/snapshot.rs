@@ -0,0 +1,509 @@
+//! Archives of a whole kasten kept under `.zk/snapshots`, for going back
+//! to an earlier state without version control
+//!
+//! snapshots are gzipped tarballs of every file under the root directory,
+//! database and index included, named after the time they were taken.
+
+use crate::{config::SymlinkPolicy, fsutil, DateTime};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    /// an archive that isn't a tarball zk can read
+    InvalidArchive(String),
+    /// a path too long for a tar header
+    NameTooLong(String),
+    UnknownSnapshot(String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::InvalidArchive(e) => write!(f, "invalid snapshot: {}", e),
+            Self::NameTooLong(path) => write!(f, "path too long to archive: {}", path),
+            Self::UnknownSnapshot(name) => write!(f, "no snapshot named {}", name),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// extension of snapshot files
+const EXTENSION: &str = ".tar.gz";
+
+/// directory holding the snapshots
+pub fn dir(root_dir: &Path) -> PathBuf {
+    root_dir.join(".zk").join("snapshots")
+}
+
+/// whether the file at slash separated `path` is left out of snapshots and
+/// left alone by restores
+fn is_excluded(path: &str) -> bool {
+    path.starts_with(".zk/snapshots/") || path.starts_with(".git/")
+}
+
+/// Contents of a kasten by slash separated path
+pub type Files = BTreeMap<String, Vec<u8>>;
+
+/// every file under `root_dir` that snapshots hold, by slash separated
+/// path; links are followed like `sync` does under `symlinks`, and files
+/// whose names aren't UTF-8 are skipped with a warning
+fn listing(root_dir: &Path, symlinks: SymlinkPolicy) -> Result<BTreeMap<String, PathBuf>> {
+    struct Walk<'a> {
+        root_dir: &'a Path,
+        canonical_root: PathBuf,
+        symlinks: SymlinkPolicy,
+        visited: HashSet<fsutil::FileId>,
+        found: BTreeMap<String, PathBuf>,
+    }
+    fn walk(w: &mut Walk, dir: &Path) -> Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let relative = path.strip_prefix(w.root_dir).unwrap();
+            let relative = match fsutil::to_slash(relative) {
+                Some(relative) => relative,
+                None => {
+                    tracing::warn!("skipping {} whose name isn't UTF-8", relative.display());
+                    continue;
+                }
+            };
+            if entry.file_type()?.is_symlink() {
+                if w.symlinks == SymlinkPolicy::Skip {
+                    continue;
+                }
+                match std::fs::canonicalize(&path) {
+                    Ok(target) if target.starts_with(&w.canonical_root) => {}
+                    Ok(_) => {
+                        tracing::warn!(
+                            "skipping {} which links outside the root directory",
+                            relative
+                        );
+                        continue;
+                    }
+                    Err(e) => {
+                        tracing::warn!("skipping {}: {}", relative, e);
+                        continue;
+                    }
+                }
+            }
+            // links can lead to the same file twice, or back to a parent directory
+            if !w.visited.insert(fsutil::file_id(&path)?) {
+                continue;
+            }
+            if path.is_dir() {
+                if !is_excluded(&format!("{}/", relative)) {
+                    walk(w, &path)?;
+                }
+            } else if path.is_file() {
+                w.found.insert(relative, path);
+            }
+        }
+        Ok(())
+    }
+    let mut w = Walk {
+        root_dir,
+        canonical_root: std::fs::canonicalize(root_dir)?,
+        symlinks,
+        visited: HashSet::from([fsutil::file_id(root_dir)?]),
+        found: BTreeMap::new(),
+    };
+    walk(&mut w, root_dir)?;
+    Ok(w.found)
+}
+
+/// every file under `root_dir` that snapshots hold
+pub fn current(root_dir: &Path, symlinks: SymlinkPolicy) -> Result<Files> {
+    listing(root_dir, symlinks)?
+        .into_iter()
+        .map(|(relative, path)| Ok((relative, std::fs::read(path)?)))
+        .collect()
+}
+
+/// write the files at `files` as a tarball to `out`, a file at a time
+fn write_tar(files: &BTreeMap<String, PathBuf>, mtime: i64, out: &mut impl Write) -> Result<()> {
+    for (path, full_path) in files {
+        let mut file = File::open(full_path)?;
+        let size = file.metadata()?.len();
+        let mut header = [0u8; 512];
+        // names over 100 bytes are split into a prefix of up to 155 bytes
+        // and a name at a slash, which is always a character boundary
+        let (prefix, name) = match path.len() {
+            0..=100 => ("", path.as_str()),
+            _ => match path
+                .match_indices('/')
+                .map(|(i, _)| i)
+                .take_while(|i| *i <= 155)
+                .last()
+            {
+                Some(i) if path.len() - i - 1 <= 100 => (&path[..i], &path[i + 1..]),
+                _ => return Err(Error::NameTooLong(path.clone())),
+            },
+        };
+        header[..name.len()].copy_from_slice(name.as_bytes());
+        let mut field = |start: usize, width: usize, value: u64| {
+            let text = format!("{:0w$o}\0", value, w = width - 1);
+            header[start..start + width].copy_from_slice(text.as_bytes());
+        };
+        field(100, 8, 0o644);
+        field(108, 8, 0);
+        field(116, 8, 0);
+        field(124, 12, size);
+        field(136, 12, mtime.max(0) as u64);
+        header[156] = b'0';
+        header[257..263].copy_from_slice(b"ustar\0");
+        header[263..265].copy_from_slice(b"00");
+        header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+        // the checksum is computed with its own field filled with spaces
+        header[148..156].copy_from_slice(b"        ");
+        let checksum: u32 = header.iter().map(|b| *b as u32).sum();
+        header[148..156].copy_from_slice(format!("{:06o}\0 ", checksum).as_bytes());
+        out.write_all(&header)?;
+        // the header holds the size, so a file changing meanwhile can't be
+        // written as it is now
+        if std::io::copy(&mut (&mut file).take(size), out)? != size {
+            let message = format!("{} changed while archiving", path);
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, message).into());
+        }
+        out.write_all(&vec![0; ((512 - size % 512) % 512) as usize])?;
+    }
+    out.write_all(&[0; 1024])?;
+    Ok(())
+}
+
+/// regular files in the tarball read from `input`
+///
+/// the archive is rejected as a whole if a name could lead outside the
+/// directory it is restored to, and sizes in headers are only trusted as far
+/// as the data is really there
+fn read_tar(input: &mut impl Read) -> Result<Files> {
+    let mut files = Files::new();
+    let mut header = [0u8; 512];
+    loop {
+        input.read_exact(&mut header)?;
+        if header.iter().all(|b| *b == 0) {
+            return Ok(files);
+        }
+        let text = |range: std::ops::Range<usize>| {
+            let field = &header[range];
+            let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+            String::from_utf8_lossy(&field[..end]).into_owned()
+        };
+        let (name, prefix) = (text(0..100), text(345..500));
+        let path = match prefix.is_empty() {
+            true => name,
+            false => format!("{}/{}", prefix, name),
+        };
+        let size = u64::from_str_radix(text(124..136).trim(), 8)
+            .map_err(|_| Error::InvalidArchive(format!("bad size for {}", path)))?;
+        let mut data = Vec::new();
+        if input.take(size).read_to_end(&mut data)? as u64 != size {
+            return Err(Error::InvalidArchive(format!("{} is cut off", path)));
+        }
+        let mut padding = vec![0; (512 - data.len() % 512) % 512];
+        input.read_exact(&mut padding)?;
+        if matches!(header[156], b'0' | 0) {
+            if fsutil::contained(&path).is_none() {
+                let message = format!("{} leads outside the kasten", path);
+                return Err(Error::InvalidArchive(message));
+            }
+            files.insert(path, data);
+        }
+    }
+}
+
+/// A snapshot under [`dir`]
+#[derive(Debug, PartialEq)]
+pub struct Snapshot {
+    /// file name without extension: the time it was taken and its label
+    pub name: String,
+    pub path: PathBuf,
+    /// size of the archive in bytes
+    pub size: u64,
+}
+
+impl Snapshot {
+    /// the files held by the snapshot
+    pub fn files(&self) -> Result<Files> {
+        read_tar(&mut GzDecoder::new(File::open(&self.path)?))
+    }
+}
+
+/// archive every file under `root_dir` into a new snapshot named after
+/// `now` and `label`
+pub fn create(
+    root_dir: &Path,
+    symlinks: SymlinkPolicy,
+    label: Option<&str>,
+    now: DateTime,
+) -> Result<Snapshot> {
+    let mut name = now.format("%Y%m%dT%H%M%S").to_string();
+    if let Some(label) = label {
+        name.push('-');
+        name.push_str(&fsutil::sanitize_file_name(&label.replace(' ', "-")));
+    }
+    let dir = dir(root_dir);
+    std::fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}{}", name, EXTENSION));
+    if path.exists() {
+        return Err(std::io::Error::from(std::io::ErrorKind::AlreadyExists).into());
+    }
+    let files = listing(root_dir, symlinks)?;
+    let mut out = GzEncoder::new(File::create(&path)?, Compression::default());
+    let written = write_tar(&files, now.timestamp(), &mut out).and_then(|()| Ok(out.finish()?));
+    if let Err(e) = written {
+        std::fs::remove_file(&path)?;
+        return Err(e);
+    }
+    let size = std::fs::metadata(&path)?.len();
+    Ok(Snapshot { name, path, size })
+}
+
+/// snapshots of `root_dir`, oldest first
+pub fn list(root_dir: &Path) -> Result<Vec<Snapshot>> {
+    let dir = dir(root_dir);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut snapshots = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+        if let Some(name) = file_name.strip_suffix(EXTENSION) {
+            snapshots.push(Snapshot {
+                name: name.to_owned(),
+                size: std::fs::metadata(&path)?.len(),
+                path,
+            });
+        }
+    }
+    snapshots.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(snapshots)
+}
+
+/// the snapshot called `name`, or the only one starting with it
+pub fn find(root_dir: &Path, name: &str) -> Result<Snapshot> {
+    let mut matching: Vec<Snapshot> = list(root_dir)?
+        .into_iter()
+        .filter(|s| s.name.starts_with(name))
+        .collect();
+    if let Some(i) = matching.iter().position(|s| s.name == name) {
+        return Ok(matching.swap_remove(i));
+    }
+    match matching.len() {
+        1 => Ok(matching.pop().unwrap()),
+        _ => Err(Error::UnknownSnapshot(name.to_owned())),
+    }
+}
+
+/// How a file differs between two states of a kasten
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// files that differ from `from` to `to`, by path
+pub fn diff(from: &Files, to: &Files) -> Vec<(String, Change)> {
+    let mut changes = Vec::new();
+    for (path, data) in from {
+        match to.get(path) {
+            None => changes.push((path.clone(), Change::Removed)),
+            Some(other) if other != data => changes.push((path.clone(), Change::Modified)),
+            Some(_) => {}
+        }
+    }
+    for path in to.keys().filter(|path| !from.contains_key(*path)) {
+        changes.push((path.clone(), Change::Added));
+    }
+    changes.sort();
+    changes
+}
+
+/// put the files under `root_dir` back the way they are in `snapshot`,
+/// removing those it doesn't hold; returns the changes made
+pub fn restore(
+    root_dir: &Path,
+    symlinks: SymlinkPolicy,
+    snapshot: &Snapshot,
+) -> Result<Vec<(String, Change)>> {
+    let files = snapshot.files()?;
+    let changes = diff(&current(root_dir, symlinks)?, &files);
+    for (path, change) in &changes {
+        let full_path = root_dir.join(fsutil::from_slash(path));
+        match change {
+            Change::Removed => std::fs::remove_file(full_path)?,
+            Change::Added | Change::Modified => {
+                std::fs::create_dir_all(full_path.parent().unwrap())?;
+                std::fs::write(full_path, &files[path])?;
+            }
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::prelude::*;
+
+    /// a snapshot holding `files` as given, bypassing the checks of `create`
+    fn crafted(root_dir: &Path, name: &str, files: &[(&str, &[u8])]) -> Result<Snapshot> {
+        let tmp_dir = tempdir::TempDir::new("zk_snapshot_test")?;
+        let mut listing = BTreeMap::new();
+        for (i, (path, data)) in files.iter().enumerate() {
+            let source = tmp_dir.path().join(i.to_string());
+            std::fs::write(&source, data)?;
+            listing.insert(path.to_string(), source);
+        }
+        std::fs::create_dir_all(dir(root_dir))?;
+        let path = dir(root_dir).join(format!("{}{}", name, EXTENSION));
+        let mut out = GzEncoder::new(File::create(&path)?, Compression::default());
+        write_tar(&listing, 0, &mut out)?;
+        out.finish()?;
+        find(root_dir, name)
+    }
+
+    #[test]
+    fn create_diff_restore() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_snapshot_test")?;
+        let root_dir = tmp_dir.path();
+        let long_name = format!("{}/note.md", "nested".repeat(20));
+        std::fs::create_dir_all(root_dir.join("nested".repeat(20)))?;
+        std::fs::write(root_dir.join(&long_name), "deep")?;
+        std::fs::write(root_dir.join("a.md"), "a")?;
+        std::fs::write(root_dir.join("_zettel.yaml"), "db")?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let first = create(root_dir, SymlinkPolicy::Follow, Some("before edits"), dt)?;
+        assert_eq!(first.name, "20150514T120000-before-edits");
+        assert_eq!(first.files()?, current(root_dir, SymlinkPolicy::Follow)?);
+        std::fs::write(root_dir.join("a.md"), "changed")?;
+        std::fs::write(root_dir.join("b.md"), "b")?;
+        std::fs::remove_file(root_dir.join("_zettel.yaml"))?;
+        let changes = diff(&first.files()?, &current(root_dir, SymlinkPolicy::Follow)?);
+        assert_eq!(
+            changes,
+            [
+                ("_zettel.yaml".to_owned(), Change::Removed),
+                ("a.md".to_owned(), Change::Modified),
+                ("b.md".to_owned(), Change::Added),
+            ]
+        );
+        create(
+            root_dir,
+            SymlinkPolicy::Follow,
+            None,
+            dt + chrono::Duration::hours(1),
+        )?;
+        assert_eq!(list(root_dir)?.len(), 2);
+        assert!(matches!(
+            find(root_dir, "2015"),
+            Err(Error::UnknownSnapshot(_))
+        ));
+        let first = find(root_dir, "20150514T12")?;
+        assert_eq!(restore(root_dir, SymlinkPolicy::Follow, &first)?.len(), 3);
+        assert_eq!(std::fs::read_to_string(root_dir.join("a.md"))?, "a");
+        assert!(!root_dir.join("b.md").exists());
+        assert_eq!(std::fs::read_to_string(root_dir.join(&long_name))?, "deep");
+        assert_eq!(list(root_dir)?.len(), 2);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_links_once_inside_the_root() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use std::os::unix::ffi::OsStrExt;
+        let tmp_dir = tempdir::TempDir::new("zk_snapshot_test")?;
+        let outside = tempdir::TempDir::new("zk_snapshot_test")?;
+        let root_dir = tmp_dir.path();
+        std::fs::create_dir(root_dir.join("sub"))?;
+        std::fs::write(root_dir.join("sub/a.md"), "a")?;
+        std::fs::write(outside.path().join("secret.md"), "secret")?;
+        std::os::unix::fs::symlink("..", root_dir.join("sub/loop"))?;
+        std::os::unix::fs::symlink(outside.path(), root_dir.join("outside"))?;
+        std::os::unix::fs::symlink(root_dir.join("sub/a.md"), root_dir.join("alias.md"))?;
+        let bad = std::ffi::OsStr::from_bytes(b"bad\xff.md");
+        std::fs::write(root_dir.join(bad), "bad")?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let followed = create(root_dir, SymlinkPolicy::Follow, None, dt)?;
+        let paths: Vec<String> = followed.files()?.into_keys().collect();
+        assert!(
+            paths == ["alias.md"] || paths == ["sub/a.md"],
+            "{:?}",
+            paths
+        );
+        std::fs::remove_file(followed.path)?;
+        let skipped = create(root_dir, SymlinkPolicy::Skip, None, dt)?;
+        let paths: Vec<String> = skipped.files()?.into_keys().collect();
+        assert_eq!(paths, ["sub/a.md"]);
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_names_leading_outside() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_snapshot_test")?;
+        let root_dir = tmp_dir.path().join("kasten");
+        std::fs::create_dir(&root_dir)?;
+        std::fs::write(root_dir.join("a.md"), "a")?;
+        let files: &[(&str, &[u8])] = &[("b.md", b"b"), ("../evil.md", b"evil")];
+        let snapshot = crafted(&root_dir, "crafted", files)?;
+        let restored = restore(&root_dir, SymlinkPolicy::Follow, &snapshot);
+        assert!(matches!(restored, Err(Error::InvalidArchive(_))));
+        assert!(!tmp_dir.path().join("evil.md").exists());
+        assert!(!root_dir.join("b.md").exists());
+        assert!(root_dir.join("a.md").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_cut_off_archives() {
+        let mut header = [0u8; 512];
+        header[..4].copy_from_slice(b"a.md");
+        header[124..136].copy_from_slice(b"77777777777\0");
+        header[156] = b'0';
+        let mut archive = header.to_vec();
+        archive.extend_from_slice(b"only this");
+        assert!(matches!(
+            read_tar(&mut archive.as_slice()),
+            Err(Error::InvalidArchive(_))
+        ));
+    }
+
+    #[test]
+    fn splits_long_names_between_characters() -> std::result::Result<(), Box<dyn std::error::Error>>
+    {
+        let tmp_dir = tempdir::TempDir::new("zk_snapshot_test")?;
+        let root_dir = tmp_dir.path();
+        // byte 156 falls inside an ä
+        let dir_name = format!("{}/abc", "x".repeat(60));
+        let long_name = format!("{}/{}.md", dir_name, "ä".repeat(45));
+        std::fs::create_dir_all(root_dir.join(&dir_name))?;
+        std::fs::write(root_dir.join(&long_name), "umlauts")?;
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let snapshot = create(root_dir, SymlinkPolicy::Follow, None, dt)?;
+        assert_eq!(snapshot.files()?[&long_name], b"umlauts");
+        let unsplittable = format!("{}.md", "ä".repeat(60));
+        std::fs::write(root_dir.join(&unsplittable), "")?;
+        let failed = create(root_dir, SymlinkPolicy::Follow, Some("again"), dt);
+        assert!(matches!(failed, Err(Error::NameTooLong(name)) if name == unsplittable));
+        assert_eq!(list(root_dir)?.len(), 1);
+        Ok(())
+    }
+}
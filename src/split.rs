@@ -0,0 +1,190 @@
+use crate::{
+    database, frontmatter, fsutil, outline, zettel, zettelkasten, zettelkasten::Zettelkasten,
+    DateTime,
+};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    FrontmatterError(frontmatter::Error),
+    DatabaseError(database::Error),
+    ZettelkastenError(zettelkasten::Error),
+    UnknownZettel(zettel::Id),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl From<database::Error> for Error {
+    fn from(e: database::Error) -> Self {
+        Self::DatabaseError(e)
+    }
+}
+
+impl From<zettelkasten::Error> for Error {
+    fn from(e: zettelkasten::Error) -> Self {
+        Self::ZettelkastenError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::DatabaseError(e) => e.fmt(f),
+            Self::ZettelkastenError(e) => e.fmt(f),
+            Self::UnknownZettel(id) => write!(f, "no zettel with id {}", id),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A part of a body starting at a heading
+#[derive(Debug, PartialEq)]
+pub struct Section {
+    /// text of the heading
+    pub title: String,
+    /// lines after the heading, without surrounding blank lines
+    pub content: String,
+    /// line numbers of the heading and of the last line, starting at 1
+    pub lines: (usize, usize),
+}
+
+/// sections of `body` started by headings of exactly `level`
+///
+/// a section ends at the next heading of the same or a higher level
+pub fn sections(body: &str, level: usize) -> Vec<Section> {
+    let lines: Vec<&str> = body.split_inclusive('\n').collect();
+    let headings = outline::headings(body);
+    let mut sections = Vec::new();
+    for (n, heading) in headings.iter().enumerate() {
+        if heading.level != level {
+            continue;
+        }
+        let end = headings[n + 1..]
+            .iter()
+            .find(|h| h.level <= level)
+            .map_or(lines.len(), |h| h.line - 1);
+        let content: String = lines[heading.line..end].concat();
+        let content = content.trim_matches('\n');
+        let mut content = content.to_owned();
+        if !content.is_empty() {
+            content.push('\n');
+        }
+        sections.push(Section {
+            title: heading.text.clone(),
+            content,
+            lines: (heading.line, end),
+        });
+    }
+    sections
+}
+
+/// move every section of zettel `id` started by a heading of `level` into
+/// a new zettel, replacing it with a link in the original
+///
+/// new zettels are created next to the original and named after the
+/// headings, all in one transaction. Returns the ids of the new zettels.
+pub fn split(
+    db: &impl database::Database,
+    zk: &mut Zettelkasten,
+    id: &str,
+    level: usize,
+    now: DateTime,
+) -> Result<Vec<zettel::Id>> {
+    let path = match zk.zettels.get(id) {
+        Some(meta) => meta.full_path(db.root_dir()),
+        None => return Err(Error::UnknownZettel(id.to_owned())),
+    };
+    let (fm, body) = frontmatter::parse_yaml_path(&path)?;
+    let sections = sections(&body, level);
+    let dir = path.parent().unwrap_or(db.root_dir());
+    let lines: Vec<&str> = body.split_inclusive('\n').collect();
+    zk.transaction(|tx| {
+        let mut new_body = String::new();
+        let mut next_line = 0;
+        let mut ids = Vec::new();
+        for (n, section) in sections.iter().enumerate() {
+            let (start, end) = section.lines;
+            new_body.push_str(&lines[next_line..start - 1].concat());
+            let scheme = tx.zk().config.id_scheme.unwrap_or_default();
+            let new_id = scheme.generate(now, |id| tx.zettels.contains_key(id));
+            let mut zettel = db.new_zettel(&tx.zk().config, &section.title, &new_id, now)?;
+            let file_name = Path::new(&zettel.meta.path).file_name().unwrap();
+            // `dir` is that of a zettel, and names from templates are UTF-8
+            zettel.meta.path = fsutil::to_stored(db.root_dir(), &dir.join(file_name)).unwrap();
+            zettel.meta.update_from_body(&section.content);
+            zettel.content = section.content.clone();
+            new_body.push_str(&format!("- [[{}|{}]]\n", new_id, section.title));
+            // keep a paragraph break before text that is not another link
+            let next_is_section = sections.get(n + 1).is_some_and(|s| s.lines.0 == end + 1);
+            if end < lines.len() && !next_is_section {
+                new_body.push('\n');
+            }
+            tx.add(db.root_dir(), &zettel)?;
+            ids.push(new_id);
+            next_line = end;
+        }
+        if ids.is_empty() {
+            return Ok(ids);
+        }
+        new_body.push_str(&lines[next_line..].concat());
+        tx.write(&path, frontmatter::write_yaml(&fm, &new_body)?);
+        let meta = tx.zettels.get_mut(id).unwrap();
+        meta.update_from_body(&new_body);
+        meta.modified = now;
+        Ok(ids)
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn split_by_heading() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_split_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zettel = db.new_zettel(&Default::default(), "Long", "long", dt)?;
+        zettel.content =
+            "intro\n\n## First\n\none\n\n### Detail\nmore\n\n## Second\ntwo\n\n# Outro\nbye"
+                .to_owned();
+        zk.add(db.root_dir(), &zettel)?;
+        let ids = split(&db, &mut zk, "long", 2, dt)?;
+        assert_eq!(ids.len(), 2);
+        let (_, body) = frontmatter::parse_yaml_path(zettel.meta.full_path(db.root_dir()))?;
+        assert_eq!(
+            body,
+            format!(
+                "intro\n\n- [[{}|First]]\n- [[{}|Second]]\n\n# Outro\nbye\n",
+                ids[0], ids[1]
+            )
+        );
+        assert_eq!(zk.zettels["long"].links.len(), 2);
+        let first = &zk.zettels[&ids[0]];
+        assert_eq!(first.title, "First");
+        assert_eq!(first.path, "2015-05-14-First.md");
+        let (fm, body) = frontmatter::parse_yaml_path(first.full_path(db.root_dir()))?;
+        assert_eq!(fm.get(&"id".into()), Some(&ids[0].as_str().into()));
+        assert_eq!(body, "one\n\n### Detail\nmore\n");
+        Ok(())
+    }
+}
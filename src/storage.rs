@@ -0,0 +1,608 @@
+//! Places a kasten's files can be kept, and mirroring between them
+//!
+//! zk always works on a local root directory; a kasten living on a WebDAV
+//! server is mirrored into one with [`sync`], which then serves as a cache
+//! for every other command.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    HttpError(Box<ureq::Error>),
+    SerializationError(serde_json::Error),
+    /// a server answer that couldn't be understood
+    InvalidResponse(String),
+    /// a path that would lead out of the kasten
+    UnsafePath(String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<ureq::Error> for Error {
+    fn from(e: ureq::Error) -> Self {
+        Self::HttpError(Box::new(e))
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::SerializationError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::HttpError(e) => e.fmt(f),
+            Self::SerializationError(e) => e.fmt(f),
+            Self::InvalidResponse(e) => write!(f, "invalid response from server: {}", e),
+            Self::UnsafePath(path) => write!(f, "{} would lead out of the kasten", path),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Files of a kasten addressed by slash separated paths relative to its
+/// root
+pub trait Storage {
+    /// every file with a tag that changes whenever the file does
+    ///
+    /// hidden files and directories are left out
+    fn list(&self) -> Result<BTreeMap<String, String>>;
+
+    fn read(&self, path: &str) -> Result<Vec<u8>>;
+
+    /// write the file, creating the directories it is in
+    fn write(&self, path: &str, data: &[u8]) -> Result<()>;
+
+    fn remove(&self, path: &str) -> Result<()>;
+}
+
+/// A directory on the local file system
+#[derive(Debug)]
+pub struct Local {
+    pub root_dir: PathBuf,
+}
+
+impl Local {
+    /// where `path` is under the root directory, which it must not lead out
+    /// of lexically or through symlinks
+    fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let unsafe_path = || Error::UnsafePath(path.to_owned());
+        let resolved = self
+            .root_dir
+            .join(crate::fsutil::contained(path).ok_or_else(unsafe_path)?);
+        let root = std::fs::canonicalize(&self.root_dir)?;
+        let existing = resolved
+            .ancestors()
+            .find(|dir| dir.exists())
+            .ok_or_else(unsafe_path)?;
+        match std::fs::canonicalize(existing)?.starts_with(&root) {
+            true => Ok(resolved),
+            false => Err(unsafe_path()),
+        }
+    }
+
+    fn list_dir(&self, dir: &Path, files: &mut BTreeMap<String, String>) -> Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            let meta = entry.metadata()?;
+            if meta.is_dir() {
+                self.list_dir(&path, files)?;
+                continue;
+            }
+            let relative = path.strip_prefix(&self.root_dir).unwrap();
+            let relative = match crate::fsutil::to_slash(relative) {
+                Some(relative) => relative,
+                None => continue,
+            };
+            let modified = meta
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos();
+            files.insert(relative, format!("{}-{}", meta.len(), modified));
+        }
+        Ok(())
+    }
+}
+
+impl Storage for Local {
+    fn list(&self) -> Result<BTreeMap<String, String>> {
+        let mut files = BTreeMap::new();
+        self.list_dir(&self.root_dir, &mut files)?;
+        Ok(files)
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        Ok(std::fs::read(self.resolve(path)?)?)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        let path = self.resolve(path)?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        Ok(std::fs::write(path, data)?)
+    }
+
+    fn remove(&self, path: &str) -> Result<()> {
+        Ok(std::fs::remove_file(self.resolve(path)?)?)
+    }
+}
+
+/// A collection on a WebDAV server
+#[derive(Debug)]
+pub struct WebDav {
+    /// url of the collection, ending in a slash
+    url: String,
+    agent: ureq::Agent,
+    /// value of the Authorization header
+    auth: Option<String>,
+}
+
+/// body of the PROPFIND requests listing collections
+const PROPFIND: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<propfind xmlns="DAV:"><prop><resourcetype/><getetag/><getcontentlength/><getlastmodified/></prop></propfind>"#;
+
+/// A file or collection in a PROPFIND response
+#[derive(Debug, PartialEq)]
+pub struct Resource {
+    /// path from the server root, decoded
+    pub href: String,
+    pub is_collection: bool,
+    /// etag, or size and modification time when the server gives none
+    pub tag: String,
+}
+
+/// `text` with every `%XX` escape decoded
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// `path` with everything but unreserved characters and slashes escaped
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// the resources in a PROPFIND multistatus response
+///
+/// XML namespaces prefixes are ignored, which is all WebDAV servers need
+pub fn parse_multistatus(xml: &str) -> Vec<Resource> {
+    let element = |name: &str| {
+        regex::Regex::new(&format!(
+            r"(?s)<(?:\w+:)?{0}\b[^>]*>(.*?)</(?:\w+:)?{0}>",
+            name
+        ))
+        .unwrap()
+    };
+    let (response, href, etag) = (element("response"), element("href"), element("getetag"));
+    let (length, modified) = (element("getcontentlength"), element("getlastmodified"));
+    let collection = regex::Regex::new(r"<(?:\w+:)?collection\s*/?>").unwrap();
+    let text = |re: &regex::Regex, xml: &str| {
+        re.captures(xml)
+            .map(|caps| caps[1].trim().to_owned())
+            .unwrap_or_default()
+    };
+    response
+        .captures_iter(xml)
+        .filter_map(|caps| {
+            let body = &caps[1];
+            let href = text(&href, body);
+            if href.is_empty() {
+                return None;
+            }
+            // absolute urls are cut down to their path
+            let path = match href.split_once("://") {
+                Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]).to_owned(),
+                None => href,
+            };
+            let tag = match text(&etag, body) {
+                tag if !tag.is_empty() => tag.replace("&quot;", "\""),
+                _ => format!("{}-{}", text(&length, body), text(&modified, body)),
+            };
+            Some(Resource {
+                href: percent_decode(&path),
+                is_collection: collection.is_match(body),
+                tag,
+            })
+        })
+        .collect()
+}
+
+/// the members of collection `dir` in a PROPFIND response, with their
+/// paths relative to the collection at `base` on the server
+///
+/// hidden members are left out, and so are ones whose decoded path could
+/// lead out of the kasten, with a warning
+fn members(xml: &str, base: &str, dir: &str) -> Result<Vec<(String, Resource)>> {
+    let mut members = Vec::new();
+    for resource in parse_multistatus(xml) {
+        let path = match resource.href.strip_prefix(base) {
+            Some(path) => path.trim_end_matches('/').to_owned(),
+            None => return Err(Error::InvalidResponse(resource.href)),
+        };
+        // the collection itself is listed along with its members
+        if path == dir.trim_end_matches('/') {
+            continue;
+        }
+        if crate::fsutil::contained(&path).is_none() {
+            tracing::warn!(
+                "skipping {} which would lead out of the kasten",
+                resource.href
+            );
+            continue;
+        }
+        let hidden = path.rsplit('/').next().is_some_and(|n| n.starts_with('.'));
+        if !hidden {
+            members.push((path, resource));
+        }
+    }
+    Ok(members)
+}
+
+impl WebDav {
+    /// the collection at `url`, with basic authentication if `user` is
+    /// given
+    pub fn new(url: &str, user: Option<(&str, &str)>) -> Self {
+        use base64::Engine;
+        let mut url = url.to_owned();
+        if !url.ends_with('/') {
+            url.push('/');
+        }
+        let auth = user.map(|(user, password)| {
+            let credentials = format!("{}:{}", user, password);
+            format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(credentials)
+            )
+        });
+        Self {
+            url,
+            agent: ureq::AgentBuilder::new().build(),
+            auth,
+        }
+    }
+
+    fn request(&self, method: &str, path: &str) -> ureq::Request {
+        let request = self
+            .agent
+            .request(method, &format!("{}{}", self.url, percent_encode(path)));
+        match &self.auth {
+            Some(auth) => request.set("Authorization", auth),
+            None => request,
+        }
+    }
+
+    /// path of the collection on the server, to take off of hrefs
+    fn base_path(&self) -> String {
+        let path = match self.url.split_once("://") {
+            Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]),
+            None => &self.url,
+        };
+        percent_decode(path)
+    }
+
+    fn list_collection(&self, dir: &str, files: &mut BTreeMap<String, String>) -> Result<()> {
+        let response = self
+            .request("PROPFIND", dir)
+            .set("Depth", "1")
+            .set("Content-Type", "application/xml")
+            .send_string(PROPFIND)?;
+        let xml = response.into_string()?;
+        for (path, resource) in members(&xml, &self.base_path(), dir)? {
+            if resource.is_collection {
+                self.list_collection(&format!("{}/", path), files)?;
+            } else {
+                files.insert(path, resource.tag);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Storage for WebDav {
+    fn list(&self) -> Result<BTreeMap<String, String>> {
+        let mut files = BTreeMap::new();
+        self.list_collection("", &mut files)?;
+        Ok(files)
+    }
+
+    fn read(&self, path: &str) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.request("GET", path)
+            .call()?
+            .into_reader()
+            .read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn write(&self, path: &str, data: &[u8]) -> Result<()> {
+        let mut dir = String::new();
+        for part in path
+            .split('/')
+            .rev()
+            .skip(1)
+            .collect::<Vec<_>>()
+            .into_iter()
+            .rev()
+        {
+            dir.push_str(part);
+            dir.push('/');
+            match self.request("MKCOL", &dir).call() {
+                // 405 means the collection exists already
+                Ok(_) | Err(ureq::Error::Status(405, _)) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        self.request("PUT", path).send_bytes(data)?;
+        Ok(())
+    }
+
+    fn remove(&self, path: &str) -> Result<()> {
+        match self.request("DELETE", path).call() {
+            Ok(_) | Err(ureq::Error::Status(404, _)) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// What the local and remote side were at after the last sync
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// url of the remote kasten
+    pub url: String,
+    /// tags of each file on the local and the remote side
+    pub files: BTreeMap<String, (String, String)>,
+}
+
+impl Manifest {
+    pub fn path(root_dir: &Path) -> PathBuf {
+        root_dir.join(".zk").join("remote.json")
+    }
+
+    /// the manifest of the last sync, `None` if there was none
+    pub fn load(root_dir: &Path) -> Result<Option<Self>> {
+        let path = Self::path(root_dir);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&std::fs::read(path)?)?))
+    }
+
+    pub fn save(&self, root_dir: &Path) -> Result<()> {
+        let path = Self::path(root_dir);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        Ok(std::fs::write(path, serde_json::to_vec_pretty(self)?)?)
+    }
+}
+
+/// Files changed by a [`sync`]
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    /// copied from the remote side, or removed locally if `None` was
+    pub pulled: Vec<String>,
+    /// copied to the remote side, or removed there
+    pub pushed: Vec<String>,
+    /// changed on both sides since the last sync, and left alone
+    pub conflicts: Vec<String>,
+}
+
+/// bring `local` and `remote` up to date with each other
+///
+/// files changed on one side since the last sync recorded in `manifest`
+/// are copied to the other, or removed there if they were removed. Files
+/// changed on both sides are reported as conflicts and left for the next
+/// sync once one side is settled.
+pub fn sync(
+    local: &impl Storage,
+    remote: &impl Storage,
+    manifest: &mut Manifest,
+) -> Result<Report> {
+    let local_files = local.list()?;
+    let remote_files = remote.list()?;
+    let paths: BTreeSet<&String> = local_files
+        .keys()
+        .chain(remote_files.keys())
+        .chain(manifest.files.keys())
+        .collect();
+    let mut report = Report::default();
+    for path in paths {
+        let (l, r) = (local_files.get(path), remote_files.get(path));
+        let last = manifest.files.get(path);
+        let local_changed = l != last.map(|(l, _)| l);
+        let remote_changed = r != last.map(|(_, r)| r);
+        match (local_changed, remote_changed) {
+            (false, false) => {}
+            (true, false) => {
+                match l {
+                    Some(_) => remote.write(path, &local.read(path)?)?,
+                    None => remote.remove(path)?,
+                }
+                report.pushed.push(path.clone());
+            }
+            (false, true) => {
+                match r {
+                    Some(_) => local.write(path, &remote.read(path)?)?,
+                    None => local.remove(path)?,
+                }
+                report.pulled.push(path.clone());
+            }
+            // the same file added on both sides, or removed on both
+            (true, true) if l.is_none() && r.is_none() => {}
+            (true, true) if last.is_none() && local.read(path)? == remote.read(path)? => {}
+            (true, true) => report.conflicts.push(path.clone()),
+        }
+    }
+    // tags of written files are only known once they are listed again
+    let local_files = local.list()?;
+    let remote_files = remote.list()?;
+    let mut files = BTreeMap::new();
+    for (path, l) in local_files {
+        let entry = match (report.conflicts.contains(&path), remote_files.get(&path)) {
+            (true, _) => manifest.files.get(&path).cloned(),
+            (false, Some(r)) => Some((l, r.clone())),
+            (false, None) => None,
+        };
+        if let Some(entry) = entry {
+            files.insert(path, entry);
+        }
+    }
+    manifest.files = files;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mirrors_changes() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_storage_test")?;
+        let side = |name: &str| -> std::io::Result<Local> {
+            let root_dir = tmp_dir.path().join(name);
+            std::fs::create_dir_all(&root_dir)?;
+            Ok(Local { root_dir })
+        };
+        let (local, remote) = (side("local")?, side("remote")?);
+        let mut manifest = Manifest::default();
+        local.write("a.md", b"a")?;
+        local.write(".zk/history", b"hidden")?;
+        remote.write("dir/b.md", b"b")?;
+        let report = sync(&local, &remote, &mut manifest)?;
+        assert_eq!(report.pushed, ["a.md"]);
+        assert_eq!(report.pulled, ["dir/b.md"]);
+        assert_eq!(remote.read("a.md")?, b"a");
+        assert!(!remote.root_dir.join(".zk").exists());
+        assert_eq!(sync(&local, &remote, &mut manifest)?, Report::default());
+        // tags of local files are their size and modification time
+        local.write("a.md", b"changed")?;
+        remote.remove("dir/b.md")?;
+        let report = sync(&local, &remote, &mut manifest)?;
+        assert_eq!(report.pushed, ["a.md"]);
+        assert_eq!(report.pulled, ["dir/b.md"]);
+        assert!(!local.root_dir.join("dir/b.md").exists());
+        assert_eq!(remote.read("a.md")?, b"changed");
+        local.write("a.md", b"local edit")?;
+        remote.write("a.md", b"remote edit, longer")?;
+        let report = sync(&local, &remote, &mut manifest)?;
+        assert_eq!(report.conflicts, ["a.md"]);
+        assert_eq!(local.read("a.md")?, b"local edit");
+        for path in ["../outside.md", "dir/../../outside.md", "/tmp/outside.md"] {
+            assert!(matches!(local.write(path, b"x"), Err(Error::UnsafePath(_))));
+            assert!(matches!(local.remove(path), Err(Error::UnsafePath(_))));
+        }
+        assert!(!tmp_dir.path().join("outside.md").exists());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stays_in_the_root_through_symlinks() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_storage_test")?;
+        let root_dir = tmp_dir.path().join("kasten");
+        std::fs::create_dir_all(&root_dir)?;
+        std::os::unix::fs::symlink(tmp_dir.path(), root_dir.join("out"))?;
+        let local = Local { root_dir };
+        assert!(matches!(
+            local.write("out/pwned.md", b"x"),
+            Err(Error::UnsafePath(_))
+        ));
+        assert!(!tmp_dir.path().join("pwned.md").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn skips_members_outside_the_collection() -> Result<()> {
+        let xml = r#"<d:multistatus xmlns:d="DAV:">
+  <d:response><d:href>/dav/kasten/</d:href></d:response>
+  <d:response><d:href>/dav/kasten/a.md</d:href></d:response>
+  <d:response><d:href>/dav/kasten/%2e%2e/%2e%2e/pwned.md</d:href></d:response>
+  <d:response><d:href>/dav/kasten/sub/%2E%2E/%2E%2E/x/</d:href>
+    <d:resourcetype><d:collection/></d:resourcetype></d:response>
+  <d:response><d:href>/dav/kasten/.hidden</d:href></d:response>
+</d:multistatus>"#;
+        let paths: Vec<String> = members(xml, "/dav/kasten/", "")?
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, ["a.md"]);
+        Ok(())
+    }
+
+    #[test]
+    fn parses_propfind_responses() {
+        let xml = r#"<?xml version="1.0"?>
+<d:multistatus xmlns:d="DAV:">
+  <d:response><d:href>/dav/kasten/</d:href>
+    <d:propstat><d:prop><d:resourcetype><d:collection/></d:resourcetype></d:prop></d:propstat>
+  </d:response>
+  <d:response><d:href>https://example.com/dav/kasten/my%20note.md</d:href>
+    <d:propstat><d:prop><d:resourcetype/><d:getetag>&quot;abc&quot;</d:getetag></d:prop></d:propstat>
+  </d:response>
+  <D:response xmlns:D="DAV:"><D:href>/dav/kasten/sub/</D:href>
+    <D:propstat><D:prop><D:resourcetype><D:collection /></D:resourcetype>
+    <D:getlastmodified>Thu, 14 May 2015</D:getlastmodified></D:prop></D:propstat>
+  </D:response>
+</d:multistatus>"#;
+        let resources = parse_multistatus(xml);
+        assert_eq!(resources.len(), 3);
+        assert!(resources[0].is_collection);
+        assert_eq!(
+            resources[1],
+            Resource {
+                href: "/dav/kasten/my note.md".to_owned(),
+                is_collection: false,
+                tag: "\"abc\"".to_owned(),
+            }
+        );
+        assert_eq!(resources[2].href, "/dav/kasten/sub/");
+        assert!(resources[2].is_collection);
+        assert_eq!(percent_encode("my note.md"), "my%20note.md");
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{dates, zettelkasten::Zettelkasten, DateTime};
+use std::collections::HashSet;
+
+/// An overview of a zettelkasten, taken from the database alone
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    pub zettels: usize,
+    /// distinct tags
+    pub tags: usize,
+    pub orphans: usize,
+    pub synced: Option<DateTime>,
+}
+
+/// number of zettels that neither link to nor are linked from another
+/// zettel
+pub fn orphans(zk: &Zettelkasten) -> usize {
+    let linked: HashSet<&str> = zk
+        .zettels
+        .values()
+        .flat_map(|meta| &meta.links)
+        .filter(|id| zk.zettels.contains_key(*id))
+        .map(String::as_str)
+        .collect();
+    zk.zettels
+        .iter()
+        .filter(|(id, meta)| {
+            !linked.contains(id.as_str())
+                && !meta
+                    .links
+                    .iter()
+                    .any(|to| to != *id && zk.zettels.contains_key(to))
+        })
+        .count()
+}
+
+pub fn summarize(zk: &Zettelkasten) -> Summary {
+    let tags: HashSet<&str> = zk
+        .zettels
+        .values()
+        .flat_map(|meta| &meta.tags)
+        .map(String::as_str)
+        .collect();
+    Summary {
+        zettels: zk.zettels.len(),
+        tags: tags.len(),
+        orphans: orphans(zk),
+        synced: zk.meta.synced,
+    }
+}
+
+impl Summary {
+    /// one line, with the time of the last sync shown in `zone`
+    pub fn line(&self, zone: dates::Zone) -> String {
+        let synced = match self.synced {
+            Some(dt) => format!("synced {}", zone.show(dt).format("%Y-%m-%d %H:%M")),
+            None => "never synced".to_owned(),
+        };
+        format!(
+            "{} zettels, {} tags, {} orphans, {}",
+            self.zettels, self.tags, self.orphans, synced
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ZettelMeta;
+    use chrono::prelude::*;
+
+    #[test]
+    fn one_line() {
+        let now = chrono::Local.ymd(2023, 5, 17).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for (id, tags, links) in [
+            ("a", vec!["x", "y"], vec!["b"]),
+            ("b", vec!["x"], vec![]),
+            ("c", vec![], vec!["gone"]),
+        ] {
+            let mut meta = ZettelMeta::new(id, id, "z.md", now);
+            meta.tags = tags.into_iter().map(str::to_owned).collect();
+            meta.links = links.into_iter().map(str::to_owned).collect();
+            zk.zettels.insert(id.to_owned(), meta);
+        }
+        let utc = "utc".parse().unwrap();
+        assert_eq!(
+            summarize(&zk).line(utc),
+            "3 zettels, 2 tags, 1 orphans, never synced"
+        );
+        zk.meta.synced = Some(now);
+        let synced = now.with_timezone(&chrono::Utc).format("%Y-%m-%d %H:%M");
+        assert_eq!(
+            summarize(&zk).line(utc),
+            format!("3 zettels, 2 tags, 1 orphans, synced {}", synced)
+        );
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{search, zettel, zettelkasten::Zettelkasten};
+use std::collections::{BTreeMap, HashSet};
+
+/// tags seen on the zettels tagged `tag`, with how many of them have each,
+/// most frequent first
+pub fn related(zk: &Zettelkasten, tag: &str) -> Vec<(String, usize)> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for meta in zk.zettels.values() {
+        if !meta.tags.iter().any(|t| t == tag) {
+            continue;
+        }
+        let tags: HashSet<&str> = meta.tags.iter().map(String::as_str).collect();
+        for other in tags.into_iter().filter(|t| *t != tag) {
+            *counts.entry(other).or_insert(0) += 1;
+        }
+    }
+    let mut related: Vec<(String, usize)> = counts
+        .into_iter()
+        .map(|(tag, count)| (tag.to_owned(), count))
+        .collect();
+    related.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    related
+}
+
+/// A tag a zettel doesn't have yet, and why it might
+#[derive(Debug, PartialEq)]
+pub struct Suggestion {
+    pub tag: String,
+    /// the words of the tag all appear in the body
+    pub in_body: bool,
+    /// zettels linking to or linked from the zettel that have the tag
+    pub linked: usize,
+    /// zettels that have the tag along with one of the zettel's tags
+    pub co_occurring: usize,
+}
+
+impl Suggestion {
+    pub fn score(&self) -> usize {
+        3 * usize::from(self.in_body) + 2 * self.linked + self.co_occurring
+    }
+}
+
+/// tags already used in `zk` that zettel `id` with `body` might be given,
+/// best first
+pub fn suggest(zk: &Zettelkasten, id: &str, body: &str) -> Vec<Suggestion> {
+    let meta = &zk.zettels[id];
+    let own: HashSet<&str> = meta.tags.iter().map(String::as_str).collect();
+    let words: HashSet<String> = search::terms(body).collect();
+    let neighbours: HashSet<&zettel::Id> = zk
+        .zettels
+        .iter()
+        .filter(|(other, m)| m.links.iter().any(|to| to == id) && *other != id)
+        .map(|(other, _)| other)
+        .chain(meta.links.iter().filter(|to| zk.zettels.contains_key(*to)))
+        .filter(|other| *other != id)
+        .collect();
+    let mut suggestions: BTreeMap<&str, Suggestion> = BTreeMap::new();
+    for (other, m) in zk.zettels.iter().filter(|(other, _)| *other != id) {
+        let shares_tag = m.tags.iter().any(|t| own.contains(t.as_str()));
+        for tag in m.tags.iter().filter(|t| !own.contains(t.as_str())) {
+            let suggestion = suggestions.entry(tag).or_insert_with(|| Suggestion {
+                tag: tag.clone(),
+                in_body: {
+                    let mut terms = search::terms(tag).peekable();
+                    terms.peek().is_some() && terms.all(|t| words.contains(&t))
+                },
+                linked: 0,
+                co_occurring: 0,
+            });
+            suggestion.linked += usize::from(neighbours.contains(other));
+            suggestion.co_occurring += usize::from(shares_tag);
+        }
+    }
+    let mut suggestions: Vec<Suggestion> = suggestions
+        .into_values()
+        .filter(|s| s.score() > 0)
+        .collect();
+    suggestions.sort_by(|a, b| b.score().cmp(&a.score()).then_with(|| a.tag.cmp(&b.tag)));
+    suggestions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ZettelMeta;
+    use chrono::prelude::*;
+
+    #[test]
+    fn related_and_suggested() {
+        let now = chrono::Local.ymd(2023, 5, 17).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for (id, tags, links) in [
+            ("a", vec!["rust"], vec!["b"]),
+            ("b", vec!["rust", "memory-safety"], vec![]),
+            ("c", vec!["rust", "async"], vec![]),
+            ("d", vec!["cooking"], vec![]),
+            ("e", vec!["rust", "async"], vec!["a"]),
+        ] {
+            let mut meta = ZettelMeta::new(id, id, "z.md", now);
+            meta.tags = tags.into_iter().map(str::to_owned).collect();
+            meta.links = links.into_iter().map(str::to_owned).collect();
+            zk.zettels.insert(id.to_owned(), meta);
+        }
+        assert_eq!(
+            related(&zk, "rust"),
+            [("async".to_owned(), 2), ("memory-safety".to_owned(), 1)]
+        );
+        let suggestions = suggest(&zk, "a", "Thoughts on cooking and Memory safety.");
+        let tags: Vec<(&str, usize)> = suggestions
+            .iter()
+            .map(|s| (s.tag.as_str(), s.score()))
+            .collect();
+        // async is on e, which links here, and on c; memory-safety is on b,
+        // which this links to, and in the body
+        assert_eq!(tags, [("memory-safety", 6), ("async", 4), ("cooking", 3)]);
+    }
+}
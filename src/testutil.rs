@@ -0,0 +1,44 @@
+//! A kasten in a temporary directory for tests
+
+use crate::{
+    database::{memory, Database as _},
+    zettelkasten::Zettelkasten,
+    DateTime,
+};
+use chrono::prelude::*;
+
+/// the time tests create zettels at
+pub fn date() -> DateTime {
+    chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0)
+}
+
+/// An in-memory database rooted in a directory that is removed on drop
+pub struct Kasten {
+    tmp_dir: tempdir::TempDir,
+    pub db: memory::Database,
+}
+
+impl Kasten {
+    /// an empty kasten, committed to its database
+    pub fn new() -> Self {
+        let tmp_dir = tempdir::TempDir::new("zk_test").expect("couldn't create temp dir");
+        let db = memory::Database::new(tmp_dir.path().to_path_buf());
+        db.commit(&Zettelkasten::default()).unwrap();
+        Self { tmp_dir, db }
+    }
+
+    pub fn root_dir(&self) -> &std::path::Path {
+        self.tmp_dir.path()
+    }
+
+    /// write a zettel with `body` and commit it, returning the zettelkasten
+    pub fn add(&self, id: &str, title: &str, body: &str) -> Zettelkasten {
+        let mut zk = self.db.get_zk().unwrap().unwrap();
+        let mut zettel = self.db.new_zettel(&zk.config, title, id, date()).unwrap();
+        zettel.content = body.to_owned();
+        zettel.meta.update_from_body(body);
+        zk.add(self.db.root_dir(), &zettel).unwrap();
+        self.db.commit(&zk).unwrap();
+        zk
+    }
+}
@@ -0,0 +1,364 @@
+//! Converting to and from TiddlyWiki's JSON export of tiddlers
+//!
+//! Titles, tags and fields of tiddlers become titles, tags and frontmatter of
+//! zettels and back. Wikilinks point at titles in TiddlyWiki, written
+//! `[[Title]]` or `[[text|Title]]`, and at ids in zettels, written `[[id]]` or
+//! `[[id|text]]`. The markup of bodies is left as it is, with exported
+//! tiddlers typed as markdown.
+
+use crate::{frontmatter, import, zettel, zettelkasten::Zettelkasten, DateTime};
+use regex::Regex;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::Path,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    JsonError(serde_json::Error),
+    FrontmatterError(frontmatter::Error),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::JsonError(e)
+    }
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::JsonError(e) => e.fmt(f),
+            Self::FrontmatterError(e) => e.fmt(f),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// fields of a tiddler by name; TiddlyWiki keeps every field as a string
+pub type Tiddler = BTreeMap<String, String>;
+
+/// field exported tiddlers keep the id of their zettel in, so that importing
+/// them again keeps it
+pub const ID_FIELD: &str = "zk-id";
+
+/// fields that aren't carried over to frontmatter
+const SKIPPED: [&str; 7] = [
+    "title", "text", "tags", "created", "modified", "type", "revision",
+];
+
+/// time of a TiddlyWiki timestamp like `20230517120000000`, which is in UTC
+pub fn parse_time(s: &str) -> Option<DateTime> {
+    let naive = chrono::NaiveDateTime::parse_from_str(s.get(..14)?, "%Y%m%d%H%M%S").ok()?;
+    Some(
+        chrono::DateTime::<chrono::Utc>::from_utc(naive, chrono::Utc).with_timezone(&chrono::Local),
+    )
+}
+
+pub fn format_time(dt: DateTime) -> String {
+    dt.with_timezone(&chrono::Utc)
+        .format("%Y%m%d%H%M%S000")
+        .to_string()
+}
+
+/// items of a TiddlyWiki list like `one [[two words]] three`
+pub fn parse_list(s: &str) -> Vec<String> {
+    let re = Regex::new(r"\[\[(.*?)\]\]|(\S+)").unwrap();
+    re.captures_iter(s)
+        .map(|caps| caps.get(1).or(caps.get(2)).unwrap().as_str().to_owned())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+pub fn format_list<'a>(items: impl IntoIterator<Item = &'a str>) -> String {
+    items
+        .into_iter()
+        .map(|item| {
+            if item.contains(char::is_whitespace) {
+                format!("[[{}]]", item)
+            } else {
+                item.to_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn links() -> Regex {
+    Regex::new(r"(!?)\[\[([^\[\]|]*)(?:\|([^\[\]]*))?\]\]").unwrap()
+}
+
+/// `text` of a tiddler with links to the titles of `ids` pointed at their
+/// ids, keeping what they show
+pub fn links_to_ids(text: &str, ids: &HashMap<String, zettel::Id>) -> String {
+    links()
+        .replace_all(text, |caps: &regex::Captures| {
+            let (shown, title) = match caps.get(3) {
+                Some(title) => (&caps[2], title.as_str()),
+                None => (&caps[2], &caps[2]),
+            };
+            match ids.get(title.trim()) {
+                Some(id) => format!("{}[[{}|{}]]", &caps[1], id, shown.trim()),
+                None => caps[0].to_owned(),
+            }
+        })
+        .into_owned()
+}
+
+/// `body` of a zettel with links to the ids of `titles` pointed at their
+/// titles, dropping anchors, and embeds made transclusions
+pub fn links_to_titles(body: &str, titles: &HashMap<&str, String>) -> String {
+    links()
+        .replace_all(body, |caps: &regex::Captures| {
+            let id = caps[2].split('#').next().unwrap_or_default().trim();
+            let title = match titles.get(id) {
+                Some(title) => title,
+                None => return caps[0].to_owned(),
+            };
+            match (&caps[1], caps.get(3)) {
+                ("!", _) => format!("{{{{{}}}}}", title),
+                (_, Some(label)) if label.as_str().trim() != title => {
+                    format!("[[{}|{}]]", label.as_str().trim(), title)
+                }
+                _ => format!("[[{}]]", title),
+            }
+        })
+        .into_owned()
+}
+
+/// rows for [`import::import`] of `tiddlers`, with the mapping to import them
+/// by, leaving out system tiddlers
+///
+/// each tiddler is given its exported id, when it has one that isn't taken,
+/// or else a new one, so that links between tiddlers can be pointed at ids
+pub fn rows(
+    zk: &Zettelkasten,
+    tiddlers: &[Tiddler],
+    now: DateTime,
+) -> (Vec<import::Row>, Vec<(String, String)>) {
+    let tiddlers: Vec<&Tiddler> = tiddlers
+        .iter()
+        .filter(|t| {
+            t.get("title")
+                .is_some_and(|title| !title.starts_with("$:/"))
+        })
+        .collect();
+    let scheme = zk.config.id_scheme.unwrap_or_default();
+    let mut taken: HashSet<zettel::Id> = HashSet::new();
+    let mut ids = HashMap::new();
+    for tiddler in &tiddlers {
+        let created = tiddler.get("created").and_then(|c| parse_time(c));
+        let id = match tiddler.get(ID_FIELD) {
+            Some(id) if !zk.zettels.contains_key(id) && !taken.contains(id) => id.clone(),
+            _ => scheme.generate(created.unwrap_or(now), |id| {
+                zk.zettels.contains_key(id) || taken.contains(id)
+            }),
+        };
+        taken.insert(id.clone());
+        ids.insert(tiddler["title"].clone(), id);
+    }
+    let mut fields = BTreeSet::new();
+    let rows = tiddlers
+        .into_iter()
+        .map(|tiddler| {
+            let mut row = import::Row::new();
+            let title = &tiddler["title"];
+            row.insert("id".to_owned(), ids[title].clone());
+            row.insert("title".to_owned(), title.clone());
+            let text = tiddler.get("text").map(String::as_str).unwrap_or_default();
+            row.insert("body".to_owned(), links_to_ids(text, &ids));
+            if let Some(created) = tiddler.get("created").and_then(|c| parse_time(c)) {
+                row.insert("created".to_owned(), created.to_rfc3339());
+            }
+            if let Some(tags) = tiddler.get("tags") {
+                row.insert("tags".to_owned(), parse_list(tags).join(","));
+            }
+            for (field, value) in tiddler {
+                let kept = !SKIPPED.contains(&field.as_str())
+                    && ![ID_FIELD, "id", "body"].contains(&field.as_str());
+                if kept {
+                    fields.insert(field.clone());
+                    row.insert(field.clone(), value.clone());
+                }
+            }
+            row
+        })
+        .collect();
+    let mut map: Vec<(String, String)> = ["id", "title", "body", "created", "tags"]
+        .into_iter()
+        .map(|key| (key.to_owned(), key.to_owned()))
+        .collect();
+    map.extend(fields.into_iter().map(|field| (field.clone(), field)));
+    (rows, map)
+}
+
+/// tiddlers of the zettels of `zk`, in order of creation
+///
+/// scalar frontmatter becomes fields and lists become TiddlyWiki lists. A
+/// title shared by several zettels is told apart by the id of all but the
+/// first.
+pub fn tiddlers(zk: &Zettelkasten, root_dir: &Path) -> Result<Vec<Tiddler>> {
+    let mut handles: Vec<_> = zk.handles(root_dir).collect();
+    handles.sort_by_key(|zettel| (zettel.meta.created, zettel.id));
+    let mut titles: HashMap<&str, String> = HashMap::new();
+    let mut used = HashSet::new();
+    for zettel in &handles {
+        let mut title = zettel.meta.title.clone();
+        if !used.insert(title.clone()) {
+            title = format!("{} ({})", title, zettel.id);
+            used.insert(title.clone());
+        }
+        titles.insert(zettel.id, title);
+    }
+    let mut tiddlers = Vec::new();
+    for zettel in handles {
+        let (fm, body) = frontmatter::parse_yaml_path(zettel.path())?;
+        let mut tiddler = Tiddler::new();
+        for (key, value) in &fm {
+            let key = match key.as_str() {
+                Some(key) if !SKIPPED.contains(&key) && key != "id" => key,
+                _ => continue,
+            };
+            let value = match value {
+                serde_yaml::Value::String(s) => s.clone(),
+                serde_yaml::Value::Bool(b) => b.to_string(),
+                serde_yaml::Value::Number(n) => n.to_string(),
+                serde_yaml::Value::Sequence(items) => {
+                    let items: Vec<String> = items
+                        .iter()
+                        .filter_map(|item| serde_yaml::to_string(item).ok())
+                        .map(|item| item.trim_start_matches("---\n").trim().to_owned())
+                        .collect();
+                    format_list(items.iter().map(String::as_str))
+                }
+                _ => continue,
+            };
+            tiddler.insert(key.to_lowercase(), value);
+        }
+        tiddler.insert("title".to_owned(), titles[zettel.id].clone());
+        tiddler.insert("text".to_owned(), links_to_titles(&body, &titles));
+        tiddler.insert("type".to_owned(), "text/x-markdown".to_owned());
+        tiddler.insert("created".to_owned(), format_time(zettel.meta.created));
+        tiddler.insert("modified".to_owned(), format_time(zettel.meta.modified));
+        if !zettel.meta.tags.is_empty() {
+            let tags = zettel.meta.tags.iter().map(String::as_str);
+            tiddler.insert("tags".to_owned(), format_list(tags));
+        }
+        tiddler.insert(ID_FIELD.to_owned(), zettel.id.to_owned());
+        tiddlers.push(tiddler);
+    }
+    Ok(tiddlers)
+}
+
+/// write the tiddlers of `zk` as JSON TiddlyWiki can import
+pub fn write(zk: &Zettelkasten, root_dir: &Path, w: &mut impl std::io::Write) -> Result<()> {
+    serde_json::to_writer_pretty(&mut *w, &tiddlers(zk, root_dir)?)?;
+    writeln!(w)?;
+    Ok(())
+}
+
+/// tiddlers of a JSON export read from `path`
+pub fn read(path: &Path) -> Result<Vec<Tiddler>> {
+    let tiddlers: Vec<BTreeMap<String, serde_json::Value>> =
+        serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    Ok(tiddlers
+        .into_iter()
+        .map(|tiddler| {
+            tiddler
+                .into_iter()
+                .filter_map(|(field, value)| match value {
+                    serde_json::Value::String(s) => Some((field, s)),
+                    serde_json::Value::Null => None,
+                    other => Some((field, other.to_string())),
+                })
+                .collect()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn round_trip() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tiddler = |fields: &[(&str, &str)]| -> Tiddler {
+            fields
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let tiddlers = [
+            tiddler(&[
+                ("title", "Hello World"),
+                ("text", "See [[the other one|Other]] and [[Missing]]."),
+                ("tags", "greeting [[first steps]]"),
+                ("created", "20230517120000000"),
+                ("source", "web"),
+            ]),
+            tiddler(&[("title", "Other"), ("text", "Back to [[Hello World]]")]),
+            tiddler(&[("title", "$:/StoryList"), ("text", "")]),
+        ];
+        let tmp_dir = tempdir::TempDir::new("zk_tiddlywiki_test")?;
+        let root_dir = tmp_dir.path().to_path_buf();
+        let db = Database::new(root_dir.clone());
+        let mut zk = Zettelkasten::default();
+        let now = Local.ymd(2023, 6, 1).and_hms(9, 0, 0);
+        let (rows, map) = rows(&zk, &tiddlers, now);
+        assert_eq!(rows.len(), 2);
+        let ids = import::import(&db, &mut zk, &rows, &map, now)?;
+        let (hello, other) = (&ids[0], &ids[1]);
+        let meta = &zk.zettels[hello];
+        assert_eq!(meta.tags, ["greeting", "first steps"]);
+        assert!(meta.links.contains(other));
+        assert_eq!(
+            meta.created,
+            Utc.ymd(2023, 5, 17).and_hms(12, 0, 0).with_timezone(&Local)
+        );
+        let (fm, body) = frontmatter::parse_yaml_path(meta.full_path(&root_dir))?;
+        assert_eq!(fm.get(&"source".into()), Some(&"web".into()));
+        assert_eq!(
+            body,
+            format!("See [[{}|the other one]] and [[Missing]].\n", other)
+        );
+
+        let later = now + chrono::Duration::days(1);
+        let mut dup = db.new_zettel(&zk.config, "Other", "dup", later)?;
+        dup.content = "![[dup]] [[dup#part|Other]]".to_owned();
+        zk.add(db.root_dir(), &dup)?;
+        let exported = super::tiddlers(&zk, &root_dir)?;
+        let by_id = |id: &str| exported.iter().find(|t| t[ID_FIELD] == id).unwrap();
+        let hello = by_id(hello);
+        assert_eq!(hello["title"], "Hello World");
+        assert_eq!(hello["tags"], "greeting [[first steps]]");
+        assert_eq!(hello["created"], "20230517120000000");
+        assert_eq!(
+            hello["text"],
+            "See [[the other one|Other]] and [[Missing]].\n"
+        );
+        let dup = by_id("dup");
+        assert_eq!(dup["title"], "Other (dup)");
+        assert_eq!(dup["text"], "{{Other (dup)}} [[Other|Other (dup)]]\n");
+        assert_eq!(by_id(other)["text"], "Back to [[Hello World]]\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{frontmatter, link, zettel, zettelkasten::Zettelkasten};
+use std::path::Path;
+
+/// how deep embeds are expanded unless configured otherwise
+pub const DEFAULT_MAX_DEPTH: usize = 5;
+
+#[derive(Debug)]
+pub enum Error {
+    FrontmatterError(frontmatter::Error),
+    UnknownZettel(zettel::Id),
+}
+
+impl From<frontmatter::Error> for Error {
+    fn from(e: frontmatter::Error) -> Self {
+        Self::FrontmatterError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FrontmatterError(e) => e.fmt(f),
+            Self::UnknownZettel(id) => write!(f, "no zettel with id {}", id),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Inlines the bodies of zettels embedded with `![[id]]`
+pub struct Transcluder<'a> {
+    pub zk: &'a Zettelkasten,
+    pub root_dir: &'a Path,
+    /// embeds nested deeper than this are left as plain links
+    pub max_depth: usize,
+}
+
+impl<'a> Transcluder<'a> {
+    pub fn new(zk: &'a Zettelkasten, root_dir: &'a Path) -> Self {
+        Self {
+            zk,
+            root_dir,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    fn body(&self, id: &str) -> Result<String> {
+        let meta = self
+            .zk
+            .zettels
+            .get(id)
+            .ok_or_else(|| Error::UnknownZettel(id.to_owned()))?;
+        let (_, body) = frontmatter::parse_yaml_path(meta.full_path(self.root_dir))?;
+        Ok(body)
+    }
+
+    /// body of the zettel with every embed expanded
+    pub fn render(&self, id: &str) -> Result<String> {
+        let body = self.body(id)?;
+        self.expand(&body, &mut vec![id.to_owned()])
+    }
+
+    /// expand embeds in `body`, where `stack` holds the ids currently being expanded
+    pub fn expand(&self, body: &str, stack: &mut Vec<zettel::Id>) -> Result<String> {
+        let links = link::wikilinks(body);
+        let mut out = String::new();
+        for (n, line) in body.lines().enumerate() {
+            let mut last = 0;
+            for link in links.iter().filter(|l| l.line == n + 1 && l.embed) {
+                out.push_str(&line[last..link.span.start]);
+                last = link.span.end;
+                let replacement = if stack.contains(&link.target) {
+                    format!("[[{}]] (not embedded: cycle)", link.target)
+                } else if stack.len() > self.max_depth {
+                    format!("[[{}]]", link.target)
+                } else if !self.zk.zettels.contains_key(&link.target) {
+                    line[link.span.clone()].to_owned()
+                } else {
+                    let mut body = self.body(&link.target)?;
+                    if let Some(anchor) = &link.anchor {
+                        match crate::outline::anchored(&body, anchor) {
+                            Some(section) => body = section,
+                            None => {
+                                out.push_str(&line[link.span.clone()]);
+                                continue;
+                            }
+                        }
+                    }
+                    stack.push(link.target.clone());
+                    let expanded = self.expand(&body, stack)?;
+                    stack.pop();
+                    expanded.trim().to_owned()
+                };
+                out.push_str(&replacement);
+            }
+            out.push_str(&line[last..]);
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn embeds_with_cycles_and_depth() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_transclude_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let mut zk = Zettelkasten::default();
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        for (id, body) in [
+            ("a", "A starts\n![[b]]\nA ends"),
+            ("b", "B embeds ![[c]] inline"),
+            ("c", "C loops back ![[a]] and ![[missing]]"),
+            ("d", "# One\nfirst\n# Two\nsecond"),
+            ("e", "![[d#Two]] and ![[d#Three]]"),
+        ] {
+            let mut zettel = db.new_zettel(&Default::default(), id, id, dt)?;
+            zettel.content = body.to_owned();
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        let mut transcluder = Transcluder::new(&zk, db.root_dir());
+        assert_eq!(
+            transcluder.render("a")?,
+            "A starts\nB embeds C loops back [[a]] (not embedded: cycle) and ![[missing]] inline\nA ends\n"
+        );
+        assert_eq!(transcluder.render("e")?, "# Two\nsecond and ![[d#Three]]\n");
+        transcluder.max_depth = 1;
+        assert_eq!(
+            transcluder.render("a")?,
+            "A starts\nB embeds [[c]] inline\nA ends\n"
+        );
+        Ok(())
+    }
+}
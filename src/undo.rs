@@ -0,0 +1,497 @@
+//! Log of the changes commands made, kept under `.zk/undo` so the last of
+//! them can be taken back without version control
+//!
+//! each entry holds what the files a command changed held before and a hash
+//! of what they held after, along with the metadata the command changed.
+//! Only changes made through transactions are logged with their files.
+//! Entries of an encrypted database are encrypted with its key, as they hold
+//! what zettels and their metadata used to say.
+
+use crate::{
+    crypt, dates, fsutil, verify, zettel, zettelkasten, zettelkasten::Zettelkasten, DateTime,
+    ZettelMeta,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    JsonError(serde_json::Error),
+    ZettelkastenError(zettelkasten::Error),
+    CryptError(crypt::Error),
+    /// files changed since the entry was recorded
+    Changed(Vec<String>),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Self::JsonError(e)
+    }
+}
+
+impl From<zettelkasten::Error> for Error {
+    fn from(e: zettelkasten::Error) -> Self {
+        Self::ZettelkastenError(e)
+    }
+}
+
+impl From<crypt::Error> for Error {
+    fn from(e: crypt::Error) -> Self {
+        Self::CryptError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::JsonError(e) => e.fmt(f),
+            Self::ZettelkastenError(e) => e.fmt(f),
+            Self::CryptError(e) => e.fmt(f),
+            Self::Changed(paths) => write!(
+                f,
+                "changed since: {}; use --force to undo anyway",
+                paths.join(", ")
+            ),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// entries kept; older ones are dropped as new ones are recorded
+pub const MAX_ENTRIES: usize = 20;
+
+/// directory holding the entries
+pub fn dir(root_dir: &Path) -> PathBuf {
+    root_dir.join(".zk").join("undo")
+}
+
+/// file of the entry named `name` under [`dir`]
+fn path(root_dir: &Path, name: &str, encrypted: bool) -> PathBuf {
+    let extension = if encrypted { "json.age" } else { "json" };
+    dir(root_dir).join(format!("{}.{}", name, extension))
+}
+
+/// names of the entries under [`dir`] and whether they are encrypted,
+/// oldest first
+fn names(root_dir: &Path) -> Result<Vec<(String, bool)>> {
+    let dir = dir(root_dir);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(&dir)? {
+        let file_name = entry?.file_name();
+        let file_name = file_name.to_string_lossy();
+        if let Some(name) = file_name.strip_suffix(".json.age") {
+            names.push((name.to_owned(), true));
+        } else if let Some(name) = file_name.strip_suffix(".json") {
+            names.push((name.to_owned(), false));
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Contents of a file, as text when it is UTF-8 to keep entries readable
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Contents {
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl From<Vec<u8>> for Contents {
+    fn from(bytes: Vec<u8>) -> Self {
+        match String::from_utf8(bytes) {
+            Ok(text) => Self::Text(text),
+            Err(e) => Self::Bytes(e.into_bytes()),
+        }
+    }
+}
+
+impl From<Contents> for Vec<u8> {
+    fn from(contents: Contents) -> Self {
+        match contents {
+            Contents::Text(text) => text.into_bytes(),
+            Contents::Bytes(bytes) => bytes,
+        }
+    }
+}
+
+/// A change to a file, with paths relative to the root directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "kebab-case")]
+pub enum FileChange {
+    /// a file written or removed: what it held before and the hash of what
+    /// it held after, `None` where there was no file
+    Write {
+        path: String,
+        before: Option<Contents>,
+        after: Option<String>,
+    },
+    Rename {
+        from: String,
+        to: String,
+    },
+}
+
+/// What a command changed, recorded when it committed
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// file name under [`dir`], without extension
+    #[serde(skip)]
+    pub name: String,
+    /// whether the file is encrypted
+    #[serde(skip)]
+    pub encrypted: bool,
+    /// the command, as it was typed
+    pub label: String,
+    #[serde(with = "dates::utc")]
+    pub time: DateTime,
+    /// in the order the changes were made
+    pub files: Vec<FileChange>,
+    /// metadata as it was before of every zettel the command changed, `None`
+    /// for zettels it added
+    pub zettels: BTreeMap<zettel::Id, Option<ZettelMeta>>,
+}
+
+/// sha-256 of the file at `path`, `None` if there is none
+fn hash_of(path: &Path) -> std::io::Result<Option<String>> {
+    match std::fs::read(path) {
+        Ok(data) => Ok(Some(
+            verify::sha256(&data)
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        )),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn relative(root_dir: &Path, path: &Path) -> String {
+    let path = path.strip_prefix(root_dir).unwrap_or(path);
+    fsutil::to_slash(path).unwrap_or_else(|| path.display().to_string())
+}
+
+/// whether two versions of a zettel's metadata agree, whether or not
+/// their ids were filled in
+fn same(a: Option<&ZettelMeta>, b: Option<&ZettelMeta>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let mut b = b.clone();
+            b.id = a.id.clone();
+            *a == b
+        }
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+/// the entry for a commit turning zettels `before` into those of `after`
+/// with the file changes `files`, `None` if nothing changed
+pub fn entry(
+    root_dir: &Path,
+    label: &str,
+    now: DateTime,
+    before: &HashMap<zettel::Id, ZettelMeta>,
+    after: &Zettelkasten,
+    files: Vec<fsutil::Undo>,
+) -> Result<Option<Entry>> {
+    let mut changes = Vec::new();
+    for undo in files {
+        changes.push(match undo {
+            fsutil::Undo::Restore(path, previous) => FileChange::Write {
+                after: hash_of(&path)?,
+                path: relative(root_dir, &path),
+                before: previous.map(Contents::from),
+            },
+            fsutil::Undo::Rename { from, to } => FileChange::Rename {
+                from: relative(root_dir, &from),
+                to: relative(root_dir, &to),
+            },
+        });
+    }
+    let zettels: BTreeMap<zettel::Id, Option<ZettelMeta>> = before
+        .keys()
+        .chain(after.zettels.keys())
+        .filter(|id| !same(before.get(*id), after.zettels.get(*id)))
+        .map(|id| (id.clone(), before.get(id).cloned()))
+        .collect();
+    if changes.is_empty() && zettels.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Entry {
+        name: now.format("%Y%m%dT%H%M%S%.3f").to_string(),
+        encrypted: false,
+        label: label.to_owned(),
+        time: now,
+        files: changes,
+        zettels,
+    }))
+}
+
+/// write `entry` to [`dir`], encrypted with `key` if the database is
+/// encrypted, dropping the oldest entries beyond [`MAX_ENTRIES`]
+///
+/// once entries are encrypted, those written in the clear before are
+/// dropped, so what they held doesn't stay readable.
+pub fn save(root_dir: &Path, entry: &Entry, key: Option<&crypt::Age>) -> Result<()> {
+    std::fs::create_dir_all(dir(root_dir))?;
+    let mut data = serde_json::to_vec_pretty(entry)?;
+    data.push(b'\n');
+    let data = match key {
+        Some(key) => key.encrypt_bytes(&data)?,
+        None => data,
+    };
+    fsutil::write_atomic(&path(root_dir, &entry.name, key.is_some()), data)?;
+    let mut names = names(root_dir)?;
+    if key.is_some() {
+        for (name, _) in names.iter().filter(|(_, encrypted)| !encrypted) {
+            std::fs::remove_file(path(root_dir, name, false))?;
+        }
+        names.retain(|(_, encrypted)| *encrypted);
+    }
+    for (name, encrypted) in names.iter().take(names.len().saturating_sub(MAX_ENTRIES)) {
+        std::fs::remove_file(path(root_dir, name, *encrypted))?;
+    }
+    Ok(())
+}
+
+/// entries recorded for `root_dir`, oldest first, decrypting encrypted ones
+/// with `key`
+pub fn list(root_dir: &Path, key: Option<&crypt::Age>) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for (name, encrypted) in names(root_dir)? {
+        let data = std::fs::read(path(root_dir, &name, encrypted))?;
+        let data = match (encrypted, key) {
+            (false, _) => data,
+            (true, Some(key)) => key.decrypt_bytes(&data)?,
+            (true, None) => return Err(crypt::Error::NoIdentity.into()),
+        };
+        let mut entry: Entry = serde_json::from_slice(&data)?;
+        entry.name = name;
+        entry.encrypted = encrypted;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// take back the changes of `entry` in one transaction, latest first, and
+/// return the files put back
+///
+/// files changed since the entry was recorded are left alone unless
+/// `force` is set. The entry itself is kept; see [`discard`].
+pub fn undo(
+    zk: &mut Zettelkasten,
+    root_dir: &Path,
+    entry: &Entry,
+    force: bool,
+) -> Result<Vec<String>> {
+    let path = |path: &str| root_dir.join(fsutil::from_slash(path));
+    if !force {
+        // what each file held after its last change
+        let mut after = BTreeMap::new();
+        for change in &entry.files {
+            if let FileChange::Write {
+                path, after: hash, ..
+            } = change
+            {
+                after.insert(path, hash);
+            }
+        }
+        let mut changed = Vec::new();
+        for (file, hash) in after {
+            if hash_of(&path(file))? != *hash {
+                changed.push(file.clone());
+            }
+        }
+        if !changed.is_empty() {
+            return Err(Error::Changed(changed));
+        }
+    }
+    zk.transaction(|tx| {
+        let mut reverted = Vec::new();
+        for change in entry.files.iter().rev() {
+            match change {
+                FileChange::Write {
+                    path: file, before, ..
+                } => {
+                    match before {
+                        Some(before) => tx.write(path(file), before.clone()),
+                        None => tx.remove_file(path(file)),
+                    }
+                    reverted.push(file.clone());
+                }
+                FileChange::Rename { from, to } => {
+                    tx.rename(path(to), path(from));
+                    reverted.push(from.clone());
+                }
+            }
+        }
+        for (id, before) in &entry.zettels {
+            match before {
+                Some(meta) => {
+                    let mut meta = meta.clone();
+                    meta.id = id.clone();
+                    tx.zettels.insert(id.clone(), meta);
+                }
+                None => {
+                    tx.zettels.remove(id);
+                }
+            }
+        }
+        reverted.sort();
+        reverted.dedup();
+        Ok(reverted)
+    })
+}
+
+/// remove `entry` from the log once it has been undone
+pub fn discard(root_dir: &Path, entry: &Entry) -> Result<()> {
+    Ok(std::fs::remove_file(path(
+        root_dir,
+        &entry.name,
+        entry.encrypted,
+    ))?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        database::{memory::Database, Database as _},
+        testutil::{self, Kasten},
+    };
+    use chrono::prelude::*;
+
+    /// the entry for rewriting zettel `id` of `zk` with `text`
+    fn rewrite(zk: &mut Zettelkasten, root_dir: &Path, id: &str, text: &str) -> Entry {
+        let before = zk.zettels.clone();
+        let path = zk.zettels[id].full_path(root_dir);
+        zk.transaction(|tx| {
+            tx.write(&path, text);
+            Ok::<_, zettelkasten::Error>(())
+        })
+        .unwrap();
+        let files = zk.take_undo();
+        entry(root_dir, "rewrite", testutil::date(), &before, zk, files)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn records_and_undoes() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_undo_test")?;
+        let root_dir = tmp_dir.path().to_path_buf();
+        let db = Database::new(root_dir.clone());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        let old = db.new_zettel(&zk.config, "Old", "old", dt)?;
+        zk.add(db.root_dir(), &old)?;
+        let old_path = old.meta.full_path(db.root_dir());
+        let old_text = std::fs::read_to_string(&old_path)?;
+        let new = db.new_zettel(&zk.config, "New", "new", dt)?;
+        let before = zk.zettels.clone();
+        zk.transaction(|tx| {
+            tx.add(db.root_dir(), &new)?;
+            tx.write(&old_path, "changed");
+            tx.zettels.get_mut("old").unwrap().title = "Changed".to_owned();
+            Ok::<_, zettelkasten::Error>(())
+        })?;
+        let files = zk.take_undo();
+        assert!(zk.take_undo().is_empty());
+        let entry = entry(&root_dir, "test", dt, &before, &zk, files)?.unwrap();
+        assert_eq!(entry.zettels.len(), 2);
+        assert_eq!(entry.zettels["new"], None);
+        save(&root_dir, &entry, None)?;
+        let entries = list(&root_dir, None)?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, entry.name);
+        assert_eq!(entries[0].files, entry.files);
+        let entry = &entries[0];
+
+        std::fs::write(&old_path, "edited since")?;
+        assert!(matches!(
+            undo(&mut zk, &root_dir, entry, false),
+            Err(Error::Changed(_))
+        ));
+        std::fs::write(&old_path, "changed")?;
+        let reverted = undo(&mut zk, &root_dir, entry, false)?;
+        assert_eq!(reverted.len(), 2);
+        assert_eq!(std::fs::read_to_string(&old_path)?, old_text);
+        assert!(!new.meta.full_path(db.root_dir()).exists());
+        assert_eq!(zk.zettels["old"].title, "Old");
+        assert!(!zk.zettels.contains_key("new"));
+        discard(&root_dir, entry)?;
+        assert!(list(&root_dir, None)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn refuses_after_an_external_edit() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = Kasten::new();
+        let root_dir = kasten.root_dir();
+        let mut zk = kasten.add("a", "A", "first\n");
+        let path = zk.zettels["a"].full_path(root_dir);
+        let original = std::fs::read_to_string(&path)?;
+        let entry = rewrite(&mut zk, root_dir, "a", "second\n");
+        std::fs::write(&path, "edited in another program\n")?;
+        match undo(&mut zk, root_dir, &entry, false) {
+            Err(Error::Changed(paths)) => assert_eq!(paths, [relative(root_dir, &path)]),
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+        assert_eq!(
+            std::fs::read_to_string(&path)?,
+            "edited in another program\n"
+        );
+        undo(&mut zk, root_dir, &entry, true)?;
+        assert_eq!(std::fs::read_to_string(&path)?, original);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn encrypts_entries_with_the_key() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = Kasten::new();
+        let root_dir = kasten.root_dir();
+        let mut zk = kasten.add("a", "A", "secret words\n");
+        let mut age = crypt::fake_age(root_dir)?;
+        age.recipients.push("age1example".to_owned());
+        age.identity = Some(root_dir.join("key.txt"));
+        let clear = rewrite(&mut zk, root_dir, "a", "more secrets\n");
+        save(root_dir, &clear, None)?;
+        let mut encrypted = rewrite(&mut zk, root_dir, "a", "other words\n");
+        encrypted.name.push('1');
+        save(root_dir, &encrypted, Some(&age))?;
+        let files: Vec<PathBuf> = std::fs::read_dir(dir(root_dir))?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<_>>()?;
+        assert_eq!(files, [path(root_dir, &encrypted.name, true)]);
+        assert!(!std::fs::read_to_string(&files[0])?.contains("secret"));
+        assert!(matches!(
+            list(root_dir, None),
+            Err(Error::CryptError(crypt::Error::NoIdentity))
+        ));
+        let entries = list(root_dir, Some(&age))?;
+        assert_eq!(entries.len(), 1);
+        assert!(entries[0].encrypted);
+        assert_eq!(entries[0].files, encrypted.files);
+        discard(root_dir, &entries[0])?;
+        assert!(list(root_dir, None)?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use crate::{zettel, zettelkasten::Zettelkasten};
+use std::path::Path;
+
+/// round constants of sha-256
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// sha-256 digest of `data`
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut h: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = h;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = hh
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            hh = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (h, v) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+            *h = h.wrapping_add(v);
+        }
+    }
+    let mut digest = [0; 32];
+    for (out, h) in digest.chunks_exact_mut(4).zip(h) {
+        out.copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
+
+/// hash of a zettel body as stored in its metadata: the sha-256 digest in
+/// lowercase hex
+pub fn hash(body: &str) -> String {
+    sha256(body.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// What is wrong with a zettel found by [`verify`]
+#[derive(Debug, PartialEq)]
+pub enum Problem {
+    /// the file is gone
+    Missing,
+    /// the file can't be read or parsed
+    Unreadable(String),
+    /// the body differs from the one hashed on the last sync
+    Mismatch,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing => write!(f, "missing"),
+            Self::Unreadable(e) => write!(f, "unreadable ({})", e),
+            Self::Mismatch => write!(f, "changed"),
+        }
+    }
+}
+
+/// Outcome of [`verify`]
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    /// zettels whose body matched its hash
+    pub verified: usize,
+    /// zettels with no hash yet, synced before hashes were recorded
+    pub unhashed: Vec<zettel::Id>,
+    /// zettels that failed, ordered by id
+    pub problems: Vec<(zettel::Id, Problem)>,
+}
+
+/// check the body of every zettel of `zk`, or those in `ids` if any are
+/// given, against the hash recorded on the last sync
+pub fn verify(zk: &Zettelkasten, root_dir: &Path, ids: &[zettel::Id]) -> Report {
+    let mut report = Report::default();
+    let mut handles: Vec<zettel::ZettelHandle> = zk
+        .handles(root_dir)
+        .filter(|zettel| ids.is_empty() || ids.iter().any(|id| id == zettel.id))
+        .collect();
+    handles.sort_by_key(|zettel| zettel.id);
+    for zettel in handles {
+        let expected = match &zettel.meta.hash {
+            Some(hash) => hash,
+            None => {
+                report.unhashed.push(zettel.id.to_owned());
+                continue;
+            }
+        };
+        if !zettel.meta.full_path(root_dir).is_file() {
+            report
+                .problems
+                .push((zettel.id.to_owned(), Problem::Missing));
+            continue;
+        }
+        match zettel.read() {
+            Ok((_, body)) if &hash(&body) == expected => report.verified += 1,
+            Ok(_) => report
+                .problems
+                .push((zettel.id.to_owned(), Problem::Mismatch)),
+            Err(e) => report
+                .problems
+                .push((zettel.id.to_owned(), Problem::Unreadable(e.to_string()))),
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn sha256_vectors() {
+        assert_eq!(
+            hash(""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hash("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn finds_changed_and_missing() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_verify_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for id in ["a", "b", "c", "d"] {
+            let mut zettel = db.new_zettel(&zk.config, id, id, dt)?;
+            zettel.content = format!("body of {}\n", id);
+            zettel.meta.update_from_body(&zettel.content);
+            zk.add(db.root_dir(), &zettel)?;
+        }
+        let path = |id: &str| zk.zettels[id].full_path(tmp_dir.path());
+        let (fm, _) = crate::frontmatter::parse_yaml_path(path("b"))?;
+        std::fs::write(
+            path("b"),
+            crate::frontmatter::write_yaml(&fm, "body of b, flipped\n")?,
+        )?;
+        std::fs::remove_file(path("c"))?;
+        zk.zettels.get_mut("d").unwrap().hash = None;
+        let report = verify(&zk, tmp_dir.path(), &[]);
+        assert_eq!(report.verified, 1);
+        assert_eq!(report.unhashed, ["d"]);
+        assert_eq!(
+            report.problems,
+            [
+                ("b".to_owned(), Problem::Mismatch),
+                ("c".to_owned(), Problem::Missing)
+            ]
+        );
+        let report = verify(&zk, tmp_dir.path(), &["a".to_owned()]);
+        assert_eq!((report.verified, report.problems.len()), (1, 0));
+        Ok(())
+    }
+}
@@ -1,9 +1,62 @@
-use crate::{frontmatter, DateTime};
+use crate::{dates, frontmatter, fsutil, link, review, verify, DateTime};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::{BufReader, Lines, Write},
+    path::{Path, PathBuf},
+};
 
 pub type Id = String;
 
+/// generate a random alphanumeric id
+pub fn new_id() -> Id {
+    use rand::Rng;
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(18)
+        .map(char::from)
+        .collect()
+}
+
+/// How ids of new zettels are generated
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum IdScheme {
+    /// 18 random letters and digits
+    #[default]
+    Random,
+    /// creation time as `YYYYMMDDHHMMSS`, numbered from `-2` on when several
+    /// zettels are created in the same second
+    Timestamp,
+    /// random UUID
+    Uuid,
+}
+
+impl IdScheme {
+    /// id for a zettel created at `now` for which `taken` is false
+    pub fn generate(self, now: DateTime, taken: impl Fn(&str) -> bool) -> Id {
+        match self {
+            // random ids are long enough not to collide in practice
+            Self::Random => new_id(),
+            Self::Uuid => uuid::Uuid::new_v4().to_string(),
+            Self::Timestamp => {
+                let base = now.format("%Y%m%d%H%M%S").to_string();
+                let mut id = base.clone();
+                let mut n = 1;
+                while taken(&id) {
+                    n += 1;
+                    id = format!("{}-{}", base, n);
+                }
+                id
+            }
+        }
+    }
+}
+
+/// average reading speed used to estimate reading time
+pub const WORDS_PER_MINUTE: usize = 200;
+
 #[derive(Debug)]
 pub enum Error {
     UnknownField,
@@ -29,32 +82,299 @@ impl std::fmt::Display for Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// whether frontmatter marks a zettel for publishing, with `publish: true`
+/// or `visibility: public`, or keeps it private with `publish: false` or
+/// `visibility: private`
+pub fn publish(fm: &serde_yaml::Mapping) -> Option<bool> {
+    let flag = |value: &serde_yaml::Value| match value {
+        serde_yaml::Value::Bool(b) => Some(*b),
+        serde_yaml::Value::String(s) => match s.trim().to_lowercase().as_str() {
+            "true" | "yes" | "public" => Some(true),
+            "false" | "no" | "private" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    };
+    fm.get(&"publish".into())
+        .or_else(|| fm.get(&"visibility".into()))
+        .and_then(flag)
+}
+
+/// tags in frontmatter, either a list or a string separated by commas or spaces
+pub fn tags(fm: &serde_yaml::Mapping) -> Vec<String> {
+    list(fm, "tags")
+}
+
+/// citation keys or ids of literature notes under `cites`, written like tags
+pub fn cites(fm: &serde_yaml::Mapping) -> Vec<String> {
+    list(fm, "cites")
+}
+
+/// text of a string, number or boolean in frontmatter, so that tags like
+/// `2021` count as tags too
+pub fn scalar(value: &serde_yaml::Value) -> Option<String> {
+    match value {
+        serde_yaml::Value::String(s) => Some(s.clone()),
+        serde_yaml::Value::Number(n) => Some(n.to_string()),
+        serde_yaml::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn list(fm: &serde_yaml::Mapping, key: &str) -> Vec<String> {
+    match fm.get(&key.into()) {
+        Some(serde_yaml::Value::Sequence(tags)) => tags.iter().filter_map(scalar).collect(),
+        Some(serde_yaml::Value::String(tags)) => tags
+            .split([',', ' '])
+            .filter(|t| !t.is_empty())
+            .map(|t| t.to_owned())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct ZettelMeta {
+    #[serde(with = "dates::utc")]
     pub created: DateTime,
+    #[serde(with = "dates::utc")]
     pub modified: DateTime,
     pub title: String,
     /// relative path to file from directory containing _zettel
     pub path: String,
     #[serde(skip)] // stored in Zettelkasten.zettels
     pub id: Id,
+    /// number of words in the body, updated on sync
+    #[serde(default)]
+    pub word_count: usize,
+    /// sha-256 of the body, updated on sync and checked by `verify`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// spaced repetition schedule, absent until first reviewed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review: Option<review::Schedule>,
+    /// citation key of the reference this literature note is about
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cite: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// citation keys or ids of literature notes this zettel builds on, from
+    /// the `cites` frontmatter key
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cites: Vec<String>,
+    /// ids of zettels this one links to with `[[id]]`, updated on sync
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<Id>,
+    /// zettel this one continues in a sequence, mirrored in the `follows`
+    /// frontmatter key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub follows: Option<Id>,
+    /// whether the zettel may be published, from the `publish` or
+    /// `visibility` frontmatter key; zettels are private unless it is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publish: Option<bool>,
+}
+
+impl ZettelMeta {
+    /// metadata of a zettel created at `date` with no other fields set
+    pub fn new(id: &str, title: &str, path: &str, date: DateTime) -> Self {
+        Self {
+            created: date,
+            modified: date,
+            title: title.to_owned(),
+            path: path.to_owned(),
+            id: id.to_owned(),
+            word_count: 0,
+            hash: None,
+            review: None,
+            cite: None,
+            tags: Vec::new(),
+            cites: Vec::new(),
+            links: Vec::new(),
+            follows: None,
+            publish: None,
+        }
+    }
+
+    /// whether the zettel is marked for publishing
+    pub fn is_public(&self) -> bool {
+        self.publish == Some(true)
+    }
+
+    /// update fields derived from a zettel's body
+    pub fn update_from_body(&mut self, body: &str) {
+        self.update_hash(body);
+        self.word_count = body.split_whitespace().count();
+        let mut links: Vec<Id> = link::wikilinks(body)
+            .into_iter()
+            .map(|link| link.target)
+            .collect();
+        links.sort();
+        links.dedup();
+        self.links = links;
+    }
+
+    /// record the hash of `body`, which `verify` checks the file against
+    pub fn update_hash(&mut self, body: &str) {
+        self.hash = Some(verify::hash(body));
+    }
+
+    /// path relative to the root directory even if stored as an absolute path
+    pub fn relative_path(&self, root_dir: &Path) -> PathBuf {
+        let path = fsutil::from_slash(&self.path);
+        path.strip_prefix(root_dir)
+            .map(Path::to_path_buf)
+            .unwrap_or(path)
+    }
+
+    /// platform path of the zettel's file under `root_dir`
+    pub fn full_path(&self, root_dir: &Path) -> PathBuf {
+        root_dir.join(fsutil::from_slash(&self.path))
+    }
+
+    /// update fields that are mirrored in a zettel's frontmatter
+    pub fn update_from_frontmatter(&mut self, fm: &serde_yaml::Mapping) {
+        if let Some(title) = fm.get(&"title".into()).and_then(|t| t.as_str()) {
+            self.title = title.to_owned()
+        }
+        self.cite = fm
+            .get(&"cite".into())
+            .and_then(|c| c.as_str())
+            .map(|c| c.to_owned());
+        self.follows = fm
+            .get(&"follows".into())
+            .and_then(|f| f.as_str())
+            .map(|f| f.to_owned());
+        self.tags = tags(fm);
+        self.cites = cites(fm);
+        self.publish = publish(fm);
+    }
+
+    /// update fields that only the database keeps, unless the zettelkasten
+    /// keeps them in frontmatter as well; see [`Self::write_state`]
+    ///
+    /// `created` falls back to the `date` key and otherwise stays as it is,
+    /// `modified` is the time the file was last written
+    pub fn read_state(&mut self, fm: &serde_yaml::Mapping, file_modified: DateTime) {
+        let created = fm
+            .get(&"created".into())
+            .and_then(|c| c.as_str())
+            .and_then(|c| chrono::DateTime::parse_from_rfc3339(c).ok())
+            .map(|c| c.with_timezone(&chrono::Local));
+        let date = fm
+            .get(&"date".into())
+            .and_then(|d| d.as_str())
+            .and_then(|d| chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+            .and_then(|d| {
+                use chrono::TimeZone;
+                chrono::Local
+                    .from_local_datetime(&d.and_hms(0, 0, 0))
+                    .earliest()
+            });
+        if let Some(created) = created.or(date) {
+            self.created = created;
+        }
+        self.modified = file_modified;
+        self.review = fm
+            .get(&"review".into())
+            .and_then(|r| serde_yaml::from_value(r.clone()).ok());
+    }
+
+    /// write fields that only the database keeps into frontmatter, with
+    /// timestamps in `zone`
+    pub fn write_state(&self, fm: &mut serde_yaml::Mapping, zone: dates::Zone) {
+        let created = zone.show(self.created).to_rfc3339();
+        fm.insert("created".into(), created.into());
+        match self
+            .review
+            .as_ref()
+            .and_then(|r| serde_yaml::to_value(r).ok())
+        {
+            Some(review) => fm.insert("review".into(), review),
+            None => fm.remove(&"review".into()),
+        };
+    }
+
+    /// value of a `@key` frontmatter template, `None` for literal values;
+    /// dates are those in `zone`
+    ///
+    /// `@created:FORMAT` is the creation date in a strftime format, with
+    /// names in `locale`; see [`dates::format`]
+    pub fn template_value(
+        &self,
+        template: &str,
+        zone: dates::Zone,
+        locale: dates::Locale,
+    ) -> Result<Option<String>> {
+        let key = match template.strip_prefix('@') {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        Ok(Some(match key.split_once(':') {
+            None if key == "title" => self.title.clone(),
+            None if key == "id" => self.id.clone(),
+            None if key == "created" => zone.show(self.created).format("%Y-%m-%d").to_string(),
+            Some(("created", format)) => dates::format(zone.show(self.created), format, locale)
+                .unwrap_or_else(|| template.to_owned()),
+            _ => return Err(Error::UnknownField),
+        }))
+    }
+
+    /// write fields that are mirrored in frontmatter back into it, along with
+    /// the keys of `templates` that take their value from metadata
+    pub fn update_frontmatter(
+        &self,
+        fm: &mut serde_yaml::Mapping,
+        templates: &HashMap<String, String>,
+        zone: dates::Zone,
+        locale: dates::Locale,
+    ) -> Result<()> {
+        for (key, template) in templates {
+            if let Some(val) = self.template_value(template, zone, locale)? {
+                fm.insert(key.as_str().into(), val.into());
+            }
+        }
+        fm.insert("title".into(), self.title.as_str().into());
+        let mut mirror = |key: &str, val: Option<serde_yaml::Value>| match val {
+            Some(val) => fm.insert(key.into(), val),
+            None => fm.remove(&key.into()),
+        };
+        mirror("cite", self.cite.as_deref().map(Into::into));
+        mirror("follows", self.follows.as_deref().map(Into::into));
+        mirror(
+            "tags",
+            Some(self.tags.clone().into()).filter(|_| !self.tags.is_empty()),
+        );
+        mirror(
+            "cites",
+            Some(self.cites.clone().into()).filter(|_| !self.cites.is_empty()),
+        );
+        Ok(())
+    }
+
+    /// estimated reading time in whole minutes
+    pub fn reading_time(&self) -> usize {
+        self.word_count.div_ceil(WORDS_PER_MINUTE)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Zettel {
     pub meta: ZettelMeta,
     pub content: String,
+    /// frontmatter fields written in addition to the zettelkasten's defaults
+    pub extra_frontmatter: HashMap<String, String>,
 }
 
 impl AsRef<Self> for ZettelMeta {
-    fn as_ref<'a>(&'a self) -> &'a Self {
-        return &self;
+    fn as_ref(&self) -> &Self {
+        self
     }
 }
 
 impl AsRef<Self> for Zettel {
-    fn as_ref<'a>(&'a self) -> &'a Self {
-        return &self;
+    fn as_ref(&self) -> &Self {
+        self
     }
 }
 
@@ -62,26 +382,85 @@ impl Zettel {
     /// write zettel with frontmatter to string
     ///
     /// use '@key_name' to include metadata keys in fronmatter
-    /// supported key names are 'title', 'id', 'created'
-    pub fn as_string(&self, frontmatter: &HashMap<String, String>) -> Result<String> {
+    /// supported key names are 'title', 'id', 'created' and 'created:FORMAT'
+    pub fn as_string(
+        &self,
+        frontmatter: &HashMap<String, String>,
+        zone: dates::Zone,
+        locale: dates::Locale,
+        format: frontmatter::Format,
+    ) -> Result<String> {
         let mut fm = HashMap::new();
         for (key, val) in frontmatter {
-            let new_val = if !val.starts_with("@") {
-                val.to_owned()
-            } else {
-                match &val[1..] {
-                    "title" => self.meta.title.clone(),
-                    "id" => self.meta.id.clone(),
-                    "created" => self.meta.created.format("%Y-%m-%d").to_string(),
-                    _ => return Err(Error::UnknownField),
-                }
+            let new_val = match self.meta.template_value(val, zone, locale)? {
+                Some(new_val) => new_val,
+                None => val.to_owned(),
             };
             fm.insert(key.to_owned(), new_val);
         }
-        Ok(format!(
-            "{}\n---{}\n",
-            frontmatter::write_str(&fm)?,
-            self.content
-        ))
+        let mut content = self.content.clone();
+        if !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        match format {
+            frontmatter::Format::Markdown => Ok(format!(
+                "{}\n---\n{}",
+                frontmatter::write_str(&fm)?,
+                content
+            )),
+            frontmatter::Format::Org => {
+                let fm: BTreeMap<String, String> = fm.into_iter().collect();
+                let fm = fm
+                    .into_iter()
+                    .map(|(key, val)| (key.into(), val.into()))
+                    .collect();
+                Ok(frontmatter::write_org(&fm, &content)?)
+            }
+        }
+    }
+}
+
+/// A zettel known by its metadata, whose file is only read when asked for
+///
+/// listing and counting over a whole zettelkasten only needs metadata;
+/// bodies are read on demand and can be streamed rather than loaded whole
+#[derive(Debug, Clone, Copy)]
+pub struct ZettelHandle<'a> {
+    pub id: &'a str,
+    pub meta: &'a ZettelMeta,
+    root_dir: &'a Path,
+}
+
+impl<'a> ZettelHandle<'a> {
+    pub fn new(root_dir: &'a Path, id: &'a str, meta: &'a ZettelMeta) -> Self {
+        Self { id, meta, root_dir }
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.meta.full_path(self.root_dir)
+    }
+
+    /// buffered reader of the whole file, frontmatter included
+    pub fn open(&self) -> std::io::Result<BufReader<File>> {
+        Ok(BufReader::new(File::open(self.path())?))
+    }
+
+    /// copy the whole file to `out` a buffer at a time
+    pub fn copy_to(&self, out: &mut impl Write) -> std::io::Result<u64> {
+        std::io::copy(&mut self.open()?, out)
+    }
+
+    /// frontmatter and body, read in full
+    pub fn read(&self) -> std::result::Result<(serde_yaml::Mapping, String), frontmatter::Error> {
+        frontmatter::parse_yaml_path(self.path())
+    }
+
+    /// frontmatter and the lines of the body, which are read as they are
+    /// consumed
+    pub fn body_lines(
+        &self,
+    ) -> std::result::Result<(serde_yaml::Mapping, Lines<BufReader<File>>), frontmatter::Error>
+    {
+        frontmatter::Format::of(&self.path()).parse_lines(self.open()?)
     }
 }
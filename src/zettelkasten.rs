@@ -1,7 +1,16 @@
-use crate::zettel;
-use crate::{zettel::Zettel, DateTime, ZettelMeta};
+use crate::{config::Config, database, dates, events, frontmatter, fsutil, zettel};
+use crate::{
+    zettel::{Zettel, ZettelHandle},
+    DateTime, ZettelMeta,
+};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fs::File, io::prelude::*, path::Path};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fs::File,
+    io::prelude::*,
+    path::{Path, PathBuf},
+};
 
 #[derive(Debug)]
 pub enum Error {
@@ -43,17 +52,40 @@ impl From<zettel::Error> for Error {
 type Result<T> = std::result::Result<T, Error>;
 
 /// Store of zettels on the filesystem
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Zettelkasten {
     pub meta: ZkMeta,
     pub default_frontmatter: HashMap<String, String>,
+    #[serde(default)]
+    pub config: Config,
     // TODO: should be BTreeMap because ID is already totally ordered
     pub zettels: HashMap<zettel::Id, ZettelMeta>,
+    #[serde(skip)]
+    subscribers: events::Subscribers,
+    /// changes of transactions in a dry run, `None` unless in one
+    #[serde(skip)]
+    planned: Option<Vec<Planned>>,
+    /// file changes of transactions not yet recorded for `zk undo`
+    #[serde(skip)]
+    undo: RefCell<Vec<fsutil::Undo>>,
+}
+
+/// A change a transaction would have made in a dry run; see
+/// [`Zettelkasten::dry_run`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Planned {
+    Write(PathBuf),
+    Rename(PathBuf, PathBuf),
+    Remove(PathBuf),
+    /// a zettel added, moved, retitled or removed
+    Event(events::Event),
+    /// metadata of a zettel changed in some other way
+    Update(zettel::Id),
 }
 
 impl AsRef<Self> for Zettelkasten {
-    fn as_ref<'a>(&'a self) -> &'a Self {
-        return &self;
+    fn as_ref(&self) -> &Self {
+        self
     }
 }
 
@@ -62,23 +94,313 @@ impl Zettelkasten {
         Self {
             meta,
             default_frontmatter,
+            config: Config::default(),
             zettels: HashMap::new(),
+            subscribers: events::Subscribers::default(),
+            planned: None,
+            undo: RefCell::default(),
         }
     }
 
-    pub fn add(&mut self, zettel: impl AsRef<Zettel>) -> Result<()> {
+    /// receiver of the changes made to this zettelkasten from now on
+    ///
+    /// events are sent by [`Self::add`], [`Self::transaction`] and when the
+    /// database commits, and by whoever changes `zettels` directly through
+    /// [`Self::notify`]
+    pub fn subscribe(&mut self) -> std::sync::mpsc::Receiver<events::Event> {
+        self.subscribers.subscribe()
+    }
+
+    pub fn notify(&self, event: &events::Event) {
+        self.subscribers.send(event);
+    }
+
+    /// stage the changes of transactions from now on without applying them,
+    /// keeping them to be listed by [`Self::planned`]
+    pub fn dry_run(&mut self) {
+        self.planned.get_or_insert_with(Vec::new);
+    }
+
+    /// changes transactions would have made during a dry run, in order
+    pub fn planned(&self) -> &[Planned] {
+        self.planned.as_deref().unwrap_or_default()
+    }
+
+    /// file changes applied by transactions since they were last taken,
+    /// oldest first, so the database can record how to undo them
+    pub fn take_undo(&self) -> Vec<fsutil::Undo> {
+        self.undo.take()
+    }
+
+    /// write only zettel `id` to `db`, leaving the others as they are stored
+    /// there; see [`database::Database::commit_entry`]
+    pub fn commit_entry(
+        &self,
+        db: &impl database::Database,
+        id: &str,
+    ) -> std::result::Result<(), database::Error> {
+        db.commit_entry(self, id)
+    }
+
+    /// write the file of a new zettel under `root_dir` and add its metadata
+    pub fn add(&mut self, root_dir: &Path, zettel: impl AsRef<Zettel>) -> Result<()> {
         let zettel = zettel.as_ref();
-        let path = Path::new(&zettel.meta.path);
+        let path = zettel.meta.full_path(root_dir);
+        let path = path.as_path();
         if path.exists() {
             return Err(std::io::ErrorKind::AlreadyExists.into());
         }
-        let mut file = File::create(&path)?;
-        let zettel_str = zettel.as_string(&self.default_frontmatter)?;
+        if let Some(dir) = self.missing_dir(path)? {
+            std::fs::create_dir_all(dir)?;
+        }
+        let zettel_str = self.render(zettel)?;
+        let mut file = File::create(path)?;
         file.write_all(zettel_str.as_bytes())?;
+        self.zettels
+            .insert(zettel.meta.id.clone(), zettel.meta.clone());
+        self.notify(&events::Event::ZettelAdded {
+            id: zettel.meta.id.clone(),
+        });
+        Ok(())
+    }
+
+    /// the directory a new zettel at `path` goes in if it doesn't exist yet,
+    /// or an error if `config.strict_dirs` keeps it from being created
+    pub(crate) fn missing_dir<'a>(&self, path: &'a Path) -> Result<Option<&'a Path>> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() && !dir.exists() => dir,
+            _ => return Ok(None),
+        };
+        if self.config.strict_dirs.unwrap_or_default() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} doesn't exist and strict_dirs is set", dir.display()),
+            )
+            .into());
+        }
+        Ok(Some(dir))
+    }
+
+    /// handle on zettel `id` with its file under `root_dir`
+    pub fn handle<'a>(&'a self, root_dir: &'a Path, id: &str) -> Option<ZettelHandle<'a>> {
+        let (id, meta) = self.zettels.get_key_value(id)?;
+        Some(ZettelHandle::new(root_dir, id, meta))
+    }
+
+    /// handles on every zettel, in no particular order
+    pub fn handles<'a>(&'a self, root_dir: &'a Path) -> impl Iterator<Item = ZettelHandle<'a>> {
+        self.zettels
+            .iter()
+            .map(move |(id, meta)| ZettelHandle::new(root_dir, id, meta))
+    }
+
+    /// unused id for a zettel created at `now`, following the configured
+    /// scheme
+    pub fn new_id(&self, now: DateTime) -> zettel::Id {
+        let scheme = self.config.id_scheme.unwrap_or_default();
+        scheme.generate(now, |id| self.zettels.contains_key(id))
+    }
+
+    /// ids of the zettels titled `title`, in any case, by creation
+    pub fn titled(&self, title: &str) -> Vec<&zettel::Id> {
+        let title = title.to_lowercase();
+        let mut ids: Vec<&zettel::Id> = self
+            .zettels
+            .iter()
+            .filter(|(_, meta)| meta.title.to_lowercase() == title)
+            .map(|(id, _)| id)
+            .collect();
+        ids.sort_by_key(|id| (self.zettels[*id].created, *id));
+        ids
+    }
+
+    /// check that zettel `id` may be titled `title` under the title policy,
+    /// warning of the other zettels titled so when it only warns; `Err`
+    /// holds them when it refuses
+    pub fn check_title(&self, id: &str, title: &str) -> std::result::Result<(), Vec<zettel::Id>> {
+        let others: Vec<zettel::Id> = self
+            .titled(title)
+            .into_iter()
+            .filter(|other| *other != id)
+            .cloned()
+            .collect();
+        if others.is_empty() {
+            return Ok(());
+        }
+        match self.config.title_policy.unwrap_or_default() {
+            crate::config::TitlePolicy::Allow => Ok(()),
+            crate::config::TitlePolicy::Warn => {
+                tracing::warn!(
+                    "{} already titled {:?}; links by title can't tell them apart",
+                    others.join(", "),
+                    title
+                );
+                Ok(())
+            }
+            crate::config::TitlePolicy::Refuse => Err(others),
+        }
+    }
+
+    /// ids of zettels sharing a title, in any case, by title
+    pub fn duplicate_titles(&self) -> BTreeMap<String, Vec<&zettel::Id>> {
+        let mut by_title: BTreeMap<String, Vec<&zettel::Id>> = BTreeMap::new();
+        for (id, meta) in &self.zettels {
+            by_title
+                .entry(meta.title.to_lowercase())
+                .or_default()
+                .push(id);
+        }
+        by_title.retain(|_, ids| ids.len() > 1);
+        for ids in by_title.values_mut() {
+            ids.sort_by_key(|id| (self.zettels[*id].created, *id));
+        }
+        by_title
+    }
+
+    /// contents of the file for a new zettel, with the default frontmatter
+    /// under the key aliases of the config
+    pub fn render(&self, zettel: &Zettel) -> Result<String> {
+        let mut frontmatter = self.default_frontmatter.clone();
+        frontmatter.extend(zettel.extra_frontmatter.clone());
+        let aliases = &self.config.key_aliases;
+        let frontmatter = frontmatter
+            .into_iter()
+            .map(|(key, val)| match aliases.get(&key) {
+                Some(alias) => (alias.clone(), val),
+                None => (key, val),
+            })
+            .collect();
+        let zone = self.config.timezone.unwrap_or_default();
+        let format = frontmatter::Format::of(Path::new(&zettel.meta.path));
+        let locale = self.config.locale.unwrap_or_default();
+        Ok(zettel.as_string(&frontmatter, zone, locale, format)?)
+    }
+
+    /// stage changes to files and metadata with `f`, then apply all of them
+    /// or none
+    ///
+    /// nothing is touched until `f` returns successfully, and nothing at all
+    /// in a dry run. Files are then
+    /// changed in the order the changes were staged; if one fails, those
+    /// already changed are put back, created files are removed again and
+    /// the metadata is left as it was. Subscribers hear of the changes once
+    /// all of them are applied.
+    pub fn transaction<T, E: From<Error>>(
+        &mut self,
+        f: impl FnOnce(&mut Transaction) -> std::result::Result<T, E>,
+    ) -> std::result::Result<T, E> {
+        let mut tx = Transaction {
+            zk: self,
+            zettels: self.zettels.clone(),
+            changes: Vec::new(),
+        };
+        let value = f(&mut tx)?;
+        let Transaction {
+            zettels, changes, ..
+        } = tx;
+        if let Some(planned) = &mut self.planned {
+            planned.extend(changes.into_iter().map(|change| match change {
+                Change::Write(path, _) => Planned::Write(path),
+                Change::Rename(from, to) => Planned::Rename(from, to),
+                Change::Remove(path) => Planned::Remove(path),
+            }));
+            let mut ids: Vec<&zettel::Id> = self.zettels.keys().chain(zettels.keys()).collect();
+            ids.sort();
+            ids.dedup();
+            for id in ids {
+                let (before, after) = (self.zettels.get(id), zettels.get(id));
+                let events = events::Event::between(id, before, after);
+                if events.is_empty() && before != after {
+                    planned.push(Planned::Update(id.clone()));
+                }
+                planned.extend(events.into_iter().map(Planned::Event));
+            }
+            return Ok(value);
+        }
+        let mut journal = fsutil::Journal::default();
+        for change in &changes {
+            let result = match change {
+                Change::Write(path, contents) => journal.write(path, contents),
+                Change::Rename(from, to) => journal.rename(from, to),
+                Change::Remove(path) => journal.remove(path),
+            };
+            if let Err(e) = result {
+                journal.rollback();
+                return Err(Error::from(e).into());
+            }
+        }
+        self.undo.borrow_mut().extend(journal.into_undo());
+        let before = std::mem::replace(&mut self.zettels, zettels);
+        for event in events::changes(&before, &self.zettels) {
+            self.notify(&event);
+        }
+        Ok(value)
+    }
+}
+
+/// Changes staged by [`Zettelkasten::transaction`]
+pub struct Transaction<'a> {
+    zk: &'a Zettelkasten,
+    /// metadata of the zettels as it will be once the changes are applied
+    pub zettels: HashMap<zettel::Id, ZettelMeta>,
+    changes: Vec<Change>,
+}
+
+#[derive(Debug)]
+enum Change {
+    Write(PathBuf, Vec<u8>),
+    Rename(PathBuf, PathBuf),
+    Remove(PathBuf),
+}
+
+impl Transaction<'_> {
+    /// the zettelkasten as it was before the transaction
+    pub fn zk(&self) -> &Zettelkasten {
+        self.zk
+    }
+
+    /// stage the file of a new zettel and its metadata
+    pub fn add(&mut self, root_dir: &Path, zettel: impl AsRef<Zettel>) -> Result<()> {
+        let zettel = zettel.as_ref();
+        let path = zettel.meta.full_path(root_dir);
+        let staged = self
+            .changes
+            .iter()
+            .any(|c| matches!(c, Change::Write(p, _) if *p == path));
+        if path.exists() || staged {
+            return Err(std::io::ErrorKind::AlreadyExists.into());
+        }
+        // the directory is created when the write is applied
+        self.zk.missing_dir(&path)?;
+        let contents = self.zk.render(zettel)?;
+        self.changes.push(Change::Write(path, contents.into()));
         self.zettels
             .insert(zettel.meta.id.clone(), zettel.meta.clone());
         Ok(())
     }
+
+    /// stage writing `contents` to `path`, replacing what it holds
+    pub fn write(&mut self, path: impl Into<PathBuf>, contents: impl Into<Vec<u8>>) {
+        self.changes
+            .push(Change::Write(path.into(), contents.into()));
+    }
+
+    /// stage moving the file at `from` to `to`
+    pub fn rename(&mut self, from: impl Into<PathBuf>, to: impl Into<PathBuf>) {
+        self.changes.push(Change::Rename(from.into(), to.into()));
+    }
+
+    /// stage removing the file at `path`, which needn't belong to a zettel
+    pub fn remove_file(&mut self, path: impl Into<PathBuf>) {
+        self.changes.push(Change::Remove(path.into()));
+    }
+
+    /// stage removing zettel `id` along with its file under `root_dir`
+    pub fn remove(&mut self, root_dir: &Path, id: &str) -> Option<ZettelMeta> {
+        let meta = self.zettels.remove(id)?;
+        self.changes.push(Change::Remove(meta.full_path(root_dir)));
+        Some(meta)
+    }
 }
 
 impl Default for Zettelkasten {
@@ -90,19 +412,231 @@ impl Default for Zettelkasten {
         default_frontmatter.insert("date".to_owned(), "@created".to_owned());
         Zettelkasten::new(
             ZkMeta {
+                version: database::migrate::CURRENT_VERSION,
+                storage: Storage::default(),
                 created: now,
                 modified: now,
+                synced: None,
             },
             default_frontmatter,
         )
     }
 }
 
+/// Where the metadata of each zettel is kept
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Storage {
+    /// all of it in the database file
+    #[default]
+    Single,
+    /// in a small file per zettel, leaving the database file with settings
+    /// only; friendlier to version control
+    Sidecar,
+    /// in the frontmatter of each zettel, making the database a cache that
+    /// `sync` rebuilds from the files without writing to them
+    Frontmatter,
+}
+
 /// Metadata about the database
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ZkMeta {
+    /// version of the database layout, see [`database::migrate`]
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default)]
+    pub storage: Storage,
     /// database creation time
+    #[serde(with = "dates::utc")]
     pub created: DateTime,
     /// last modificiation time
+    #[serde(with = "dates::utc")]
     pub modified: DateTime,
+    /// time of the last `sync`
+    #[serde(
+        default,
+        with = "dates::utc_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub synced: Option<DateTime>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::database::{memory::Database, Database as _};
+    use chrono::prelude::*;
+
+    #[test]
+    fn title_policy() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let kasten = crate::testutil::Kasten::new();
+        kasten.add("a", "Same", "");
+        let mut zk = kasten.add("b", "same", "");
+        assert_eq!(zk.check_title("c", "Other"), Ok(()));
+        assert_eq!(zk.check_title("c", "SAME"), Ok(()));
+        zk.config.title_policy = Some(crate::config::TitlePolicy::Refuse);
+        assert_eq!(
+            zk.check_title("c", "SAME"),
+            Err(vec!["a".to_owned(), "b".to_owned()])
+        );
+        assert_eq!(zk.check_title("a", "Same"), Err(vec!["b".to_owned()]));
+        assert_eq!(zk.check_title("c", "Other"), Ok(()));
+        zk.config.title_policy = Some(crate::config::TitlePolicy::Allow);
+        assert_eq!(zk.check_title("c", "Same"), Ok(()));
+        Ok(())
+    }
+
+    #[test]
+    fn add_creates_dirs() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_add_dirs_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        let mut direct = db.new_zettel(&zk.config, "Direct", "direct", dt)?;
+        direct.meta.path = fsutil::to_slash(&tmp_dir.path().join("a/b/direct.md")).unwrap();
+        zk.add(db.root_dir(), &direct)?;
+        assert!(tmp_dir.path().join("a/b/direct.md").exists());
+        let mut staged = db.new_zettel(&zk.config, "Staged", "staged", dt)?;
+        staged.meta.path = fsutil::to_slash(&tmp_dir.path().join("c/d/staged.md")).unwrap();
+        let result = zk.transaction(|tx| {
+            tx.add(db.root_dir(), &staged)?;
+            tx.write(tmp_dir.path().join("a/b"), "fails");
+            Ok::<_, Error>(())
+        });
+        assert!(result.is_err());
+        assert!(!tmp_dir.path().join("c").exists());
+        zk.transaction(|tx| tx.add(db.root_dir(), &staged))?;
+        assert!(tmp_dir.path().join("c/d/staged.md").exists());
+        zk.config.strict_dirs = Some(true);
+        let mut strict = db.new_zettel(&zk.config, "Strict", "strict", dt)?;
+        strict.meta.path = fsutil::to_slash(&tmp_dir.path().join("e/strict.md")).unwrap();
+        assert!(zk.add(db.root_dir(), &strict).is_err());
+        assert!(zk.transaction(|tx| tx.add(db.root_dir(), &strict)).is_err());
+        assert!(!tmp_dir.path().join("e").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn transaction_rolls_back() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_transaction_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        let old = db.new_zettel(&zk.config, "Old", "old", dt)?;
+        zk.add(db.root_dir(), &old)?;
+        let old_path = old.meta.full_path(db.root_dir());
+        let before = std::fs::read_to_string(&old_path)?;
+        let new = db.new_zettel(&zk.config, "New", "new", dt)?;
+        let blocker = tmp_dir.path().join("blocker");
+        std::fs::write(&blocker, "")?;
+        let result = zk.transaction(|tx| {
+            tx.add(db.root_dir(), &new)?;
+            assert!(tx.add(db.root_dir(), &new).is_err());
+            tx.write(&old_path, "changed");
+            tx.remove(tmp_dir.path(), "old");
+            // the parent is a file, so it can't be made a directory
+            tx.write(blocker.join("dir.md"), "fails");
+            Ok::<_, Error>(())
+        });
+        assert!(result.is_err());
+        assert_eq!(std::fs::read_to_string(&old_path)?, before);
+        assert!(!new.meta.full_path(db.root_dir()).exists());
+        assert_eq!(zk.zettels.len(), 1);
+        let events = zk.subscribe();
+        zk.transaction(|tx| {
+            tx.add(db.root_dir(), &new)?;
+            tx.remove(tmp_dir.path(), "old");
+            Ok::<_, Error>(())
+        })?;
+        assert!(!old_path.exists());
+        assert!(zk.zettels.contains_key("new"));
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            vec![
+                events::Event::ZettelAdded {
+                    id: "new".to_owned()
+                },
+                events::Event::ZettelRemoved {
+                    id: "old".to_owned()
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dry_run_plans_changes() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_dry_run_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        let old = db.new_zettel(&zk.config, "Old", "old", dt)?;
+        zk.add(db.root_dir(), &old)?;
+        let old_path = old.meta.full_path(db.root_dir());
+        let new = db.new_zettel(&zk.config, "New", "new", dt)?;
+        zk.dry_run();
+        zk.transaction(|tx| {
+            tx.add(db.root_dir(), &new)?;
+            tx.write(&old_path, "changed");
+            tx.zettels.get_mut("old").unwrap().tags = vec!["a".to_owned()];
+            Ok::<_, Error>(())
+        })?;
+        assert_eq!(
+            zk.planned(),
+            [
+                Planned::Write(new.meta.full_path(db.root_dir())),
+                Planned::Write(old_path.clone()),
+                Planned::Event(events::Event::ZettelAdded {
+                    id: "new".to_owned()
+                }),
+                Planned::Update("old".to_owned()),
+            ]
+        );
+        assert!(!new.meta.full_path(db.root_dir()).exists());
+        assert_ne!(std::fs::read_to_string(&old_path)?, "changed");
+        assert_eq!(zk.zettels.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn handles_read_on_demand() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_handle_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        let mut zettel = db.new_zettel(&zk.config, "Note", "a", dt)?;
+        zettel.content = "one\ntwo\n".to_owned();
+        zk.add(db.root_dir(), &zettel)?;
+        assert!(zk.handle(tmp_dir.path(), "b").is_none());
+        let handle = zk.handle(tmp_dir.path(), "a").unwrap();
+        let (fm, lines) = handle.body_lines()?;
+        assert_eq!(fm.get(&"title".into()), Some(&"Note".into()));
+        assert_eq!(lines.collect::<std::io::Result<Vec<_>>>()?, ["one", "two"]);
+        let mut out = Vec::new();
+        handle.copy_to(&mut out)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            std::fs::read_to_string(handle.path())?
+        );
+        std::fs::remove_file(handle.path())?;
+        assert_eq!(zk.handles(tmp_dir.path()).count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn finds_duplicate_titles() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let tmp_dir = tempdir::TempDir::new("zk_titles_test")?;
+        let db = Database::new(tmp_dir.path().to_path_buf());
+        let dt = chrono::Local.ymd(2015, 5, 14).and_hms(12, 0, 0);
+        let mut zk = Zettelkasten::default();
+        for (title, id) in [("Note", "b"), ("note", "a"), ("Other", "c")] {
+            zk.add(db.root_dir(), &db.new_zettel(&zk.config, title, id, dt)?)?;
+        }
+        assert_eq!(zk.titled("NOTE"), ["a", "b"]);
+        assert!(zk.titled("missing").is_empty());
+        let duplicates = zk.duplicate_titles();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates["note"], ["a", "b"]);
+        Ok(())
+    }
 }
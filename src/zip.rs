@@ -0,0 +1,192 @@
+use std::io::Read;
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    /// not a zip archive, or a damaged one
+    Malformed,
+    /// a compression method other than stored or deflate, or zip64
+    Unsupported(String),
+    /// an entry whose checksum doesn't match its data
+    Checksum(String),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::IoError(e)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::IoError(e) => e.fmt(f),
+            Self::Malformed => write!(f, "not a zip archive"),
+            Self::Unsupported(name) => write!(f, "can't unpack {} from the archive", name),
+            Self::Checksum(name) => write!(f, "{} is damaged in the archive", name),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A file in a zip archive
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    /// path inside the archive, with `/` between components
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Result<usize> {
+    let b = bytes.get(at..at + 2).ok_or(Error::Malformed)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]) as usize)
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> Result<usize> {
+    let b = bytes.get(at..at + 4).ok_or(Error::Malformed)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+}
+
+/// the files of the zip archive `bytes`, leaving out directories
+///
+/// only archives of stored and deflated files are read, which is what
+/// exports of most apps are
+pub fn entries(bytes: &[u8]) -> Result<Vec<Entry>> {
+    const END: u32 = 0x0605_4b50;
+    const CENTRAL: usize = 0x0201_4b50;
+    const LOCAL: usize = 0x0403_4b50;
+    // the end record is last, followed by a comment of up to 64 KiB
+    let end = (0..bytes.len().saturating_sub(21))
+        .rev()
+        .take(22 + 0xffff)
+        .find(|&at| bytes[at..].starts_with(&END.to_le_bytes()))
+        .ok_or(Error::Malformed)?;
+    let count = u16_at(bytes, end + 10)?;
+    let mut at = u32_at(bytes, end + 16)?;
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(bytes, at)? != CENTRAL {
+            return Err(Error::Malformed);
+        }
+        let method = u16_at(bytes, at + 10)?;
+        let crc = u32_at(bytes, at + 16)? as u32;
+        let size = u32_at(bytes, at + 20)?;
+        let name_len = u16_at(bytes, at + 28)?;
+        let extra_len = u16_at(bytes, at + 30)?;
+        let comment_len = u16_at(bytes, at + 32)?;
+        let local = u32_at(bytes, at + 42)?;
+        let name = bytes
+            .get(at + 46..at + 46 + name_len)
+            .ok_or(Error::Malformed)?;
+        let name = String::from_utf8_lossy(name).into_owned();
+        at += 46 + name_len + extra_len + comment_len;
+        if name.ends_with('/') {
+            continue;
+        }
+        if size == 0xffff_ffff || local == 0xffff_ffff {
+            return Err(Error::Unsupported(name));
+        }
+        if u32_at(bytes, local)? != LOCAL {
+            return Err(Error::Malformed);
+        }
+        let start = local + 30 + u16_at(bytes, local + 26)? + u16_at(bytes, local + 28)?;
+        let raw = bytes.get(start..start + size).ok_or(Error::Malformed)?;
+        let data = match method {
+            0 => raw.to_vec(),
+            8 => {
+                let mut data = Vec::new();
+                flate2::read::DeflateDecoder::new(raw).read_to_end(&mut data)?;
+                data
+            }
+            _ => return Err(Error::Unsupported(name)),
+        };
+        let mut sum = flate2::Crc::new();
+        sum.update(&data);
+        if sum.sum() != crc {
+            return Err(Error::Checksum(name));
+        }
+        entries.push(Entry { name, data });
+    }
+    Ok(entries)
+}
+
+/// a zip archive of `files`, deflated, for tests of importers
+#[cfg(test)]
+pub(crate) fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut central = Vec::new();
+    for (name, data) in files {
+        use std::io::Write;
+        let mut encoder =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        let packed = encoder.finish().unwrap();
+        let mut crc = flate2::Crc::new();
+        crc.update(data);
+        let offset = out.len() as u32;
+        let common = |v: &mut Vec<u8>| {
+            v.extend(8u16.to_le_bytes());
+            v.extend([0; 4]);
+            v.extend(crc.sum().to_le_bytes());
+            v.extend((packed.len() as u32).to_le_bytes());
+            v.extend((data.len() as u32).to_le_bytes());
+            v.extend((name.len() as u16).to_le_bytes());
+            v.extend([0; 2]);
+        };
+        out.extend(0x0403_4b50u32.to_le_bytes());
+        out.extend([20, 0, 0, 0]);
+        common(&mut out);
+        out.extend(name.as_bytes());
+        out.extend(&packed);
+        central.extend(0x0201_4b50u32.to_le_bytes());
+        central.extend([20, 0, 20, 0, 0, 0]);
+        common(&mut central);
+        central.extend([0; 10]);
+        central.extend(offset.to_le_bytes());
+        central.extend(name.as_bytes());
+    }
+    let offset = out.len() as u32;
+    out.extend(&central);
+    out.extend(0x0605_4b50u32.to_le_bytes());
+    out.extend([0; 4]);
+    out.extend((files.len() as u16).to_le_bytes());
+    out.extend((files.len() as u16).to_le_bytes());
+    out.extend((central.len() as u32).to_le_bytes());
+    out.extend(offset.to_le_bytes());
+    out.extend([0; 2]);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_deflated_entries() {
+        let bytes = archive(&[("a.txt", b"hello hello hello"), ("dir/b.bin", &[0, 1, 2])]);
+        let entries = entries(&bytes).unwrap();
+        assert_eq!(
+            entries,
+            [
+                Entry {
+                    name: "a.txt".to_owned(),
+                    data: b"hello hello hello".to_vec()
+                },
+                Entry {
+                    name: "dir/b.bin".to_owned(),
+                    data: vec![0, 1, 2]
+                }
+            ]
+        );
+        assert!(matches!(
+            super::entries(b"not a zip"),
+            Err(Error::Malformed)
+        ));
+        let mut damaged = bytes.clone();
+        damaged[40] ^= 0xff;
+        assert!(super::entries(&damaged).is_err());
+    }
+}